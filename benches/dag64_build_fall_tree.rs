@@ -20,8 +20,8 @@ fn pos_query(c: &mut Criterion) {
     group.sample_size(10);
 
     let mut palette = LocalPalette::new();
-    let tree_model = MagicaVoxelModel::new("./assets/Fall_Tree.vox").unwrap();
-    let tree_grid: SharedVoxelGrid = tree_model.into_grid(&mut palette).unwrap().into();
+    let tree_model = MagicaVoxelModel::new("./assets/Fall_Tree.vox", &mut palette).unwrap();
+    let tree_grid: SharedVoxelGrid = tree_model.into_grid().unwrap().into();
 
     group.bench_with_input(
         BenchmarkId::new("dag 64 from grid", "Fall_Tree pos"), 
@@ -39,6 +39,32 @@ fn criterion_benchmark(c: &mut Criterion) {
         &csg, 
         |b, csg| 
         b.iter(|| build_from_pos_query_par(csg)));
+
+    // Same spheres as a chain of binary unions like the composer generates them.
+    let mut chain = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 10.0, 1);
+    for _ in 0..100 {
+        let pos = vec3a(fastrand::f32(), fastrand::f32(), fastrand::f32()) * 100.0;
+        let sphere = chain.add_sphere(pos, 10.0, 1);
+        let union = chain.add_union_node(vec![chain.root, sphere]);
+        chain.nodes[chain.root].parent = union;
+        chain.nodes[sphere].parent = union;
+        chain.set_root(union);
+    }
+    chain.calculate_bounds();
+
+    group.bench_with_input(
+        BenchmarkId::new("dag 64 from csg", "100 x sphere 10 chain pos"), 
+        &chain, 
+        |b, csg| 
+        b.iter(|| build_from_pos_query(csg)));
+
+    chain.optimize();
+
+    group.bench_with_input(
+        BenchmarkId::new("dag 64 from csg", "100 x sphere 10 chain optimized pos"), 
+        &chain, 
+        |b, csg| 
+        b.iter(|| build_from_pos_query(csg)));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -4,9 +4,9 @@ use itertools::Itertools;
 use octa_force::{egui::emath::Numeric, glam::Vec3A};
 use smallvec::ToSmallVec;
 
-use crate::{bvh::Bvh, csg::primitves::{CSGPrimitive, r#box::CSGBox, cylinder::CSGCylinder, sphere::CSGSphere}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::VolumeBounds, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
+use crate::{bvh::Bvh, csg::primitves::{CSGPrimitive, r#box::CSGBox, capsule::CSGCapsule, cone::CSGCone, cylinder::CSGCylinder, plane::CSGPlane, sphere::CSGSphere, torus::CSGTorus, wedge::CSGWedge}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::{heightmap::Heightmap, VolumeBounds}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
 
-use super::{tree::{CSGTreeNode, CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::{BVHNodeCSGUnion, CSGTreeUnion}};
+use super::{extrude::{CSGTreeExtrude, CSGTreeRevolve}, tree::{CSGTreeNode, CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::{BVHNodeCSGUnion, CSGTreeUnion}};
 
 impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for CSGTree<M, V, T, D> {
     fn calculate_bounds(&mut self) {
@@ -59,16 +59,69 @@ impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
                 self.calculate_bounds_index(base);
                 self.calculate_bounds_index(remove);
             },
+            CSGTreeNodeData::Transform(d) => {
+                let child = d.child;
+                self.calculate_bounds_index(child);
+            },
+            CSGTreeNodeData::Repeat(d) => {
+                let child = d.child;
+                self.calculate_bounds_index(child);
+                self.update_repeat_bounds(index);
+            },
+            CSGTreeNodeData::Displace(d) => {
+                let child = d.child;
+                self.calculate_bounds_index(child);
+            },
+            CSGTreeNodeData::Paint(d) => {
+                let base = d.base;
+                let brush = d.brush;
+                self.calculate_bounds_index(base);
+                self.calculate_bounds_index(brush);
+            },
+            CSGTreeNodeData::Intersect(d) => {
+                let indecies = d.indecies.clone();
+                for index in indecies {
+                    self.calculate_bounds_index(index);
+                }
+            },
+            CSGTreeNodeData::SmoothUnion(d) => {
+                let indecies = d.indecies.clone();
+                for index in indecies {
+                    self.calculate_bounds_index(index);
+                }
+            },
+            CSGTreeNodeData::SmoothCut(d) => {
+                let base = d.base;
+                let remove = d.remove;
+                self.calculate_bounds_index(base);
+                self.calculate_bounds_index(remove);
+            },
             CSGTreeNodeData::Box(d) 
                 => <CSGPrimitive<CSGBox, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
             CSGTreeNodeData::Sphere(d) 
                 => <CSGPrimitive<CSGSphere, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
             CSGTreeNodeData::Cylinder(d) 
                 => <CSGPrimitive<CSGCylinder, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Capsule(d) 
+                => <CSGPrimitive<CSGCapsule, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Torus(d) 
+                => <CSGPrimitive<CSGTorus, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Cone(d) 
+                => <CSGPrimitive<CSGCone, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Plane(d) 
+                => <CSGPrimitive<CSGPlane, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Wedge(d) 
+                => <CSGPrimitive<CSGWedge, M, V::VectorF, D> as VolumeBounds<V, T, D>>::calculate_bounds(d),
             CSGTreeNodeData::OffsetVoxelGrid(d) => 
             <OffsetVoxelGrid as VolumeBounds<V, T, D>>::calculate_bounds(d),
             CSGTreeNodeData::SharedVoxelGrid(d) => 
             <SharedVoxelGrid as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Heightmap(d) => 
+            <Heightmap as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Extrude(d) => 
+            <CSGTreeExtrude<M> as VolumeBounds<V, T, D>>::calculate_bounds(d),
+            CSGTreeNodeData::Revolve(d) => 
+            <CSGTreeRevolve<M> as VolumeBounds<V, T, D>>::calculate_bounds(d),
         }
     }
 
@@ -91,12 +144,27 @@ impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
                 self.nodes[index].data = CSGTreeNodeData::Union(union);
             },
             CSGTreeNodeData::Cut(csgtree_remove) => {},
+            CSGTreeNodeData::Repeat(_) => self.update_repeat_bounds(index),
+            CSGTreeNodeData::SmoothUnion(_) 
+            | CSGTreeNodeData::SmoothCut(_)
+            | CSGTreeNodeData::Intersect(_)
+            | CSGTreeNodeData::Transform(_)
+            | CSGTreeNodeData::Displace(_)
+            | CSGTreeNodeData::Paint(_) => {},
             CSGTreeNodeData::None
             | CSGTreeNodeData::Box(_)
             | CSGTreeNodeData::Sphere(_)
             | CSGTreeNodeData::Cylinder(_)
+            | CSGTreeNodeData::Capsule(_)
+            | CSGTreeNodeData::Torus(_)
+            | CSGTreeNodeData::Cone(_)
+            | CSGTreeNodeData::Plane(_)
+            | CSGTreeNodeData::Wedge(_)
             | CSGTreeNodeData::OffsetVoxelGrid(_) 
-            | CSGTreeNodeData::SharedVoxelGrid(_) => {}
+            | CSGTreeNodeData::SharedVoxelGrid(_)
+            | CSGTreeNodeData::Heightmap(_)
+            | CSGTreeNodeData::Extrude(_)
+            | CSGTreeNodeData::Revolve(_) => {}
         }
 
         if index != self.root {
@@ -114,14 +182,44 @@ impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
                 let base = csgtree_remove.base;
                 self.get_bounds_index(base)
             },
+            CSGTreeNodeData::SmoothUnion(d) => d.indecies.iter()
+                .map(|index| self.get_bounds_index(*index))
+                .fold(AABB::default(), AABB::union)
+                .expand(T::from_f32(d.blend.ceil())),
+            CSGTreeNodeData::SmoothCut(d) => self.get_bounds_index(d.base),
+            CSGTreeNodeData::Transform(d) => d.get_world_aabb(self.get_bounds_index(d.child)),
+            CSGTreeNodeData::Repeat(d) => d.get_world_aabb(),
+            CSGTreeNodeData::Displace(d) => d.get_world_aabb(self.get_bounds_index(d.child)),
+            CSGTreeNodeData::Paint(d) => self.get_bounds_index(d.base),
+            CSGTreeNodeData::Intersect(d) => d.indecies.iter()
+                .map(|index| self.get_bounds_index(*index))
+                .reduce(AABB::intersect)
+                .unwrap_or_default(),
             CSGTreeNodeData::Box(d) => d.get_bounds(),
             CSGTreeNodeData::Sphere(d) => d.get_bounds(),
             CSGTreeNodeData::Cylinder(d) => d.get_bounds(),
+            CSGTreeNodeData::Capsule(d) => d.get_bounds(),
+            CSGTreeNodeData::Torus(d) => d.get_bounds(),
+            CSGTreeNodeData::Cone(d) => d.get_bounds(),
+            CSGTreeNodeData::Plane(d) => d.get_bounds(),
+            CSGTreeNodeData::Wedge(d) => d.get_bounds(),
             CSGTreeNodeData::OffsetVoxelGrid(d) => d.get_bounds(),
             CSGTreeNodeData::SharedVoxelGrid(d) => d.get_bounds(),
+            CSGTreeNodeData::Heightmap(d) => d.get_bounds(),
+            CSGTreeNodeData::Extrude(d) => d.get_world_aabb(),
+            CSGTreeNodeData::Revolve(d) => d.get_world_aabb(),
         }
     }
 
+    // The repeat keeps the bounds of its child so queries can find the copies without the tree.
+    fn update_repeat_bounds(&mut self, index: CSGTreeIndex) {
+        let CSGTreeNodeData::Repeat(d) = &self.nodes[index].data else { unreachable!() };
+        let bounds = self.get_bounds_index(d.child).to_f();
+
+        let CSGTreeNodeData::Repeat(d) = &mut self.nodes[index].data else { unreachable!() };
+        d.child_bounds = bounds;
+    }
+
     fn calculate_bounds_union(&mut self, union: &mut CSGTreeUnion<V, T, D>) {
 
             }
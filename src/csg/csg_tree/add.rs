@@ -1,6 +1,6 @@
-use crate::{csg::{Base, csg_tree::tree::CSG_TREE_INDEX_INVALID, primitves::CSGPrimitive}, util::{number::Nu, vector::Ve}, voxel::grid::shared::SharedVoxelGrid};
+use crate::{csg::{Base, csg_tree::tree::CSG_TREE_INDEX_INVALID, primitves::CSGPrimitive}, util::{aabb::AABB, number::Nu, vector::Ve}, volume::heightmap::Heightmap, voxel::grid::shared::SharedVoxelGrid};
 
-use super::tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData};
+use super::{extrude::{CSGTreeExtrude, CSGTreeRevolve}, tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData}};
 
 
 impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
@@ -26,6 +26,103 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         self.union_at_index(index, &[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0)
     }
 
+    pub fn intersect_with_sphere(&mut self, center: V::VectorF, radius: f32, mat: M) -> IntersectResult {
+        self.intersect_at_root(&[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0)
+    }
+
+    pub fn intersect_with_sphere_at_index(&mut self, center: V::VectorF, radius: f32, mat: M, index: CSGTreeIndex) -> IntersectResult { 
+        self.intersect_at_index(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0)
+    }
+
+    pub fn intersect_with_box(&mut self, center: V::VectorF, size: V::VectorF, mat: M) -> IntersectResult { 
+        self.intersect_at_root(&[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0)
+    }
+
+    pub fn intersect_with_box_at_index(&mut self, center: V::VectorF, size: V::VectorF, mat: M, index: CSGTreeIndex) -> IntersectResult { 
+        self.intersect_at_index(index, &[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0)
+    }
+
+    pub fn union_sphere_smooth(&mut self, center: V::VectorF, radius: f32, mat: M, blend: f32) -> UnionResult {
+        self.union_at_root_smooth(&[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0, blend)
+    }
+
+    pub fn cut_with_sphere_smooth(&mut self, center: V::VectorF, radius: f32, mat: M, blend: f32) -> CutResult {
+        self.cut_at_root_smooth(&[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0, blend)
+    }
+
+    pub fn union_sphere_at_index_smooth(&mut self, center: V::VectorF, radius: f32, mat: M, blend: f32, index: CSGTreeIndex) -> UnionResult { 
+        self.union_at_index_smooth(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0, blend)
+    }
+
+    pub fn cut_with_sphere_at_index_smooth(&mut self, center: V::VectorF, radius: f32, mat: M, blend: f32, index: CSGTreeIndex) -> CutResult { 
+        self.cut_at_index_smooth(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0, blend)
+    }
+
+    pub fn union_box_smooth(&mut self, center: V::VectorF, size: V::VectorF, mat: M, blend: f32) -> UnionResult { 
+        self.union_at_root_smooth(&[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0, blend)
+    }
+
+    pub fn union_box_at_index_smooth(&mut self, center: V::VectorF, size: V::VectorF, mat: M, blend: f32, index: CSGTreeIndex) -> UnionResult { 
+        self.union_at_index_smooth(index, &[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0, blend)
+    }
+
+    pub fn union_capsule(&mut self, a: V::VectorF, b: V::VectorF, radius: f32, mat: M) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat))], 0)
+    }
+
+    pub fn cut_with_capsule(&mut self, a: V::VectorF, b: V::VectorF, radius: f32, mat: M) -> CutResult {
+        self.cut_at_root(&[CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat))], 0)
+    }
+
+    pub fn union_capsule_at_index(&mut self, a: V::VectorF, b: V::VectorF, radius: f32, mat: M, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index(index, &[CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat))], 0)
+    }
+
+    pub fn cut_with_capsule_at_index(&mut self, a: V::VectorF, b: V::VectorF, radius: f32, mat: M, index: CSGTreeIndex) -> CutResult {
+        self.cut_at_index(index, &[CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat))], 0)
+    }
+
+    pub fn union_torus(&mut self, center: V::VectorF, major_radius: f32, minor_radius: f32, mat: M) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_torus(CSGPrimitive::new_torus(center, major_radius, minor_radius, mat))], 0)
+    }
+
+    pub fn union_torus_at_index(&mut self, center: V::VectorF, major_radius: f32, minor_radius: f32, mat: M, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index(index, &[CSGTreeNode::new_torus(CSGPrimitive::new_torus(center, major_radius, minor_radius, mat))], 0)
+    }
+
+    pub fn union_cone(&mut self, base: V::VectorF, radius: f32, height: f32, mat: M) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_cone(CSGPrimitive::new_cone(base, radius, height, mat))], 0)
+    }
+
+    pub fn union_cone_at_index(&mut self, base: V::VectorF, radius: f32, height: f32, mat: M, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index(index, &[CSGTreeNode::new_cone(CSGPrimitive::new_cone(base, radius, height, mat))], 0)
+    }
+
+    pub fn union_wedge(&mut self, center: V::VectorF, size: V::VectorF, mat: M) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_wedge(CSGPrimitive::new_wedge(center, size, mat))], 0)
+    }
+
+    pub fn union_wedge_at_index(&mut self, center: V::VectorF, size: V::VectorF, mat: M, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index(index, &[CSGTreeNode::new_wedge(CSGPrimitive::new_wedge(center, size, mat))], 0)
+    }
+
+    // Removes everything on the opposite side of the normal.
+    pub fn cut_with_plane(&mut self, pos: V::VectorF, normal: V::VectorF, mat: M) -> CutResult {
+        self.cut_at_root(&[CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat))], 0)
+    }
+
+    pub fn cut_with_plane_at_index(&mut self, pos: V::VectorF, normal: V::VectorF, mat: M, index: CSGTreeIndex) -> CutResult {
+        self.cut_at_index(index, &[CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat))], 0)
+    }
+
+    // Keeps everything on the opposite side of the normal.
+    pub fn intersect_with_plane(&mut self, pos: V::VectorF, normal: V::VectorF, mat: M) -> IntersectResult {
+        self.intersect_at_root(&[CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat))], 0)
+    }
+
+    pub fn intersect_with_plane_at_index(&mut self, pos: V::VectorF, normal: V::VectorF, mat: M, index: CSGTreeIndex) -> IntersectResult {
+        self.intersect_at_index(index, &[CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat))], 0)
+    }
 }
 
 impl<M: Base + Send + Sync, V: Ve<T, 3>, T: Nu> CSGTree<M, V, T, 3> {
@@ -49,6 +146,30 @@ impl<M: Base + Send + Sync, V: Ve<T, 3>, T: Nu> CSGTree<M, V, T, 3> {
         self.cut_at_index(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0)
     }
 
+    pub fn union_disk_smooth(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M, blend: f32) -> UnionResult {
+        self.union_at_root_smooth(&[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0, blend)
+    }
+
+    pub fn cut_with_disk_smooth(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M, blend: f32) -> CutResult {
+        self.cut_at_root_smooth(&[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0, blend)
+    }
+
+    pub fn union_disk_at_index_smooth(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M, blend: f32, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index_smooth(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0, blend)
+    }
+
+    pub fn cut_with_disk_at_index_smooth(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M, blend: f32, index: CSGTreeIndex) -> CutResult {
+        self.cut_at_index_smooth(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0, blend)
+    }
+
+    pub fn intersect_with_disk(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M) -> IntersectResult {
+        self.intersect_at_root(&[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0)
+    }
+
+    pub fn intersect_with_disk_at_index(&mut self, center: V::VectorF, radius: f32, height: f32, mat: M, index: CSGTreeIndex) -> IntersectResult {
+        self.intersect_at_index(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_disk(center, radius, height, mat))], 0)
+    }
+
     pub fn add_shared_grid(&mut self, grid: SharedVoxelGrid) -> usize {
         self.add_node(CSGTreeNode::new_shared_grid(grid)) 
     }
@@ -68,6 +189,62 @@ impl<M: Base + Send + Sync, V: Ve<T, 3>, T: Nu> CSGTree<M, V, T, 3> {
     pub fn remove_shared_grid_at_index(&mut self, grid: SharedVoxelGrid, index: CSGTreeIndex) -> CutResult {
         self.cut_at_index(index, &[CSGTreeNode::new_shared_grid(grid)], 0)
     }
+
+    pub fn add_heightmap(&mut self, heightmap: Heightmap) -> usize {
+        self.add_node(CSGTreeNode::new_heightmap(heightmap)) 
+    }
+
+    pub fn union_heightmap(&mut self, heightmap: Heightmap) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_heightmap(heightmap)], 0)
+    }
+
+    pub fn cut_with_heightmap(&mut self, heightmap: Heightmap) -> CutResult {
+        self.cut_at_root(&[CSGTreeNode::new_heightmap(heightmap)], 0)
+    }
+
+    pub fn union_heightmap_at_index(&mut self, heightmap: Heightmap, index: CSGTreeIndex) -> UnionResult {
+        self.union_at_index(index, &[CSGTreeNode::new_heightmap(heightmap)], 0)
+    }
+
+    pub fn add_extrude(&mut self, extrude: CSGTreeExtrude<M>) -> usize {
+        self.add_node(CSGTreeNode::new_extrude(extrude))
+    }
+
+    pub fn union_extrude(&mut self, extrude: CSGTreeExtrude<M>) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_extrude(extrude)], 0)
+    }
+
+    pub fn cut_with_extrude(&mut self, extrude: CSGTreeExtrude<M>) -> CutResult {
+        self.cut_at_root(&[CSGTreeNode::new_extrude(extrude)], 0)
+    }
+
+    pub fn add_revolve(&mut self, revolve: CSGTreeRevolve<M>) -> usize {
+        self.add_node(CSGTreeNode::new_revolve(revolve))
+    }
+
+    pub fn union_revolve(&mut self, revolve: CSGTreeRevolve<M>) -> UnionResult {
+        self.union_at_root(&[CSGTreeNode::new_revolve(revolve)], 0)
+    }
+
+    pub fn cut_with_revolve(&mut self, revolve: CSGTreeRevolve<M>) -> CutResult {
+        self.cut_at_root(&[CSGTreeNode::new_revolve(revolve)], 0)
+    }
+
+    pub fn paint_with_sphere(&mut self, center: V::VectorF, radius: f32, mat: M) -> PaintResult {
+        self.paint_at_root(&[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0)
+    }
+
+    pub fn paint_with_sphere_at_index(&mut self, center: V::VectorF, radius: f32, mat: M, index: CSGTreeIndex) -> PaintResult {
+        self.paint_at_index(index, &[CSGTreeNode::new_sphere(CSGPrimitive::new_sphere(center, radius, mat))], 0)
+    }
+
+    pub fn paint_with_box(&mut self, center: V::VectorF, size: V::VectorF, mat: M) -> PaintResult {
+        self.paint_at_root(&[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0)
+    }
+
+    pub fn paint_with_box_at_index(&mut self, center: V::VectorF, size: V::VectorF, mat: M, index: CSGTreeIndex) -> PaintResult {
+        self.paint_at_index(index, &[CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))], 0)
+    }
 }
 
 pub struct UnionResult {
@@ -81,6 +258,18 @@ pub struct CutResult {
     pub new_object_index: CSGTreeIndex,
 }
 
+pub struct PaintResult {
+    pub paint_node_index: CSGTreeIndex,
+    pub base_index: CSGTreeIndex,
+    pub new_object_index: CSGTreeIndex,
+}
+
+pub struct IntersectResult {
+    pub intersect_node_index: CSGTreeIndex,
+    pub base_index: CSGTreeIndex,
+    pub new_object_index: CSGTreeIndex,
+}
+
 impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {   
     pub fn union_at_root(&mut self, other: &[CSGTreeNode<M, V, T, D>], other_root: usize) -> UnionResult {   
         if other.is_empty() {
@@ -198,10 +387,10 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         if let CSGTreeNodeData::Cut(cut) = &root_node.data {
             let remove_index = cut.remove;
             let base_index = cut.base;
-            let res = self.union_at_index(remove_index, other, other_root);
+            let res = self.union_at_index_internal(remove_index, other, other_root);
             self.nodes[res.union_node_index].parent = self.root;
 
-            self.index_changed(res.new_object_index); 
+            self.index_changed_in(res.new_object_index, self.get_bounds_index(base_index), 0.0); 
 
             return CutResult {
                 cut_node_index: self.root,
@@ -226,7 +415,7 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
 
         self.root = cut_index;
 
-        self.index_changed(new_index);
+        self.index_changed_in(new_index, self.get_bounds_index(base_index), 0.0);
 
         CutResult {
             cut_node_index: cut_index,
@@ -242,10 +431,10 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         if let CSGTreeNodeData::Cut(cut) = &current_node.data {
             let remove_index = cut.remove;
             let base_index = cut.base;
-            let res = self.union_at_index(remove_index, other, other_root);
+            let res = self.union_at_index_internal(remove_index, other, other_root);
             self.nodes[res.union_node_index].parent = index;
 
-            self.index_changed(res.new_object_index); 
+            self.index_changed_in(res.new_object_index, self.get_bounds_index(base_index), 0.0); 
 
             return CutResult {
                 cut_node_index: index,
@@ -272,7 +461,7 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         self.nodes[new_index].parent = cut_index;
         self.nodes[index].parent = cut_index;
 
-        self.index_changed(new_index); 
+        self.index_changed_in(new_index, self.get_bounds_index(index), 0.0); 
         
         CutResult {
             cut_node_index: cut_index,
@@ -281,7 +470,281 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         }
     }
 
-    fn update_child(&mut self, index: CSGTreeIndex, old: CSGTreeIndex, new: CSGTreeIndex) {
+    pub fn intersect_at_root(&mut self, other: &[CSGTreeNode<M, V, T, D>], other_root: usize) -> IntersectResult { 
+        // Intersecting with nothing or intersecting nothing does not change the tree.
+        if other.is_empty() || self.nodes.is_empty() || matches!(&self.nodes[self.root].data, CSGTreeNodeData::None) {
+            return IntersectResult {
+                intersect_node_index: self.root,
+                base_index: self.root,
+                new_object_index: self.root,
+            };
+        }
+
+        self.intersect_at_index(self.root, other, other_root)
+    }
+
+    pub fn intersect_at_index(&mut self, index: CSGTreeIndex, other: &[CSGTreeNode<M, V, T, D>], other_root: usize) -> IntersectResult {
+        self.needs_bounds_recompute = true;
+
+        // Everything outside of the new object gets removed.
+        let old_bounds = self.get_bounds_index(index);
+
+        let length = self.nodes.len();
+        let new_index = other_root + length;
+        self.nodes.extend_from_slice(other);
+        shift_node_indecies(&mut self.nodes[length..], length);
+
+        let current_node = &mut self.nodes[index];
+        if let CSGTreeNodeData::Intersect(intersect) = &mut current_node.data {
+            let base_index = intersect.indecies[0];
+            intersect.add_node(new_index);
+            self.nodes[new_index].parent = index;
+
+            self.index_changed_in(new_index, old_bounds, 0.0);
+            self.add_changed_bounds(self.nodes[index].parent, old_bounds);
+
+            return IntersectResult {
+                intersect_node_index: index,
+                base_index,
+                new_object_index: new_index,
+            };
+        }
+
+        let parent = self.nodes[index].parent;
+        let intersect_index = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_intersect(vec![index, new_index]));
+
+        if index == self.root {
+            self.root = intersect_index;
+        } else {
+            self.update_child(parent, index, intersect_index);
+        }
+        self.nodes[intersect_index].parent = parent;
+
+        self.nodes[new_index].parent = intersect_index;
+        self.nodes[index].parent = intersect_index;
+
+        self.index_changed_in(new_index, old_bounds, 0.0);
+        self.add_changed_bounds(parent, old_bounds);
+
+        IntersectResult {
+            intersect_node_index: intersect_index,
+            base_index: index,
+            new_object_index: new_index,
+        }
+    }
+
+    pub fn paint_at_root(&mut self, other: &[CSGTreeNode<M, V, T, D>], other_root: usize) -> PaintResult {
+        // There is no volume to paint.
+        if other.is_empty() || self.nodes.is_empty() || matches!(&self.nodes[self.root].data, CSGTreeNodeData::None) {
+            return PaintResult {
+                paint_node_index: self.root,
+                base_index: self.root,
+                new_object_index: self.root,
+            };
+        }
+
+        self.paint_at_index(self.root, other, other_root)
+    }
+
+    // Only the material inside of the base changes, so the changed bounds are clipped to it.
+    pub fn paint_at_index(&mut self, index: CSGTreeIndex, other: &[CSGTreeNode<M, V, T, D>], other_root: usize) -> PaintResult {
+        self.needs_bounds_recompute = true;
+
+        let current_node = &self.nodes[index];
+        if let CSGTreeNodeData::Paint(paint) = &current_node.data {
+            let brush_index = paint.brush;
+            let base_index = paint.base;
+            let res = self.union_at_index_internal(brush_index, other, other_root);
+            self.nodes[res.union_node_index].parent = index;
+
+            self.index_changed_in(res.new_object_index, self.get_bounds_index(base_index), 0.0);
+
+            return PaintResult {
+                paint_node_index: index,
+                base_index,
+                new_object_index: res.new_object_index,
+            };
+        }
+
+        let parent = current_node.parent;
+
+        let length = self.nodes.len();
+        let new_index = other_root + length;
+        self.nodes.extend_from_slice(other);
+        shift_node_indecies(&mut self.nodes[length..], length);
+
+        let paint_index = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_paint(index, new_index));
+
+        if index == self.root {
+            self.root = paint_index;
+        } else {
+            self.update_child(parent, index, paint_index);
+        }
+        self.nodes[paint_index].parent = parent;
+
+        self.nodes[new_index].parent = paint_index;
+        self.nodes[index].parent = paint_index;
+
+        self.index_changed_in(new_index, self.get_bounds_index(index), 0.0);
+
+        PaintResult {
+            paint_node_index: paint_index,
+            base_index: index,
+            new_object_index: new_index,
+        }
+    }
+
+    pub fn union_at_root_smooth(&mut self, other: &[CSGTreeNode<M, V, T, D>], other_root: usize, blend: f32) -> UnionResult {   
+        if other.is_empty() || self.nodes.is_empty() || matches!(&self.nodes[self.root].data, CSGTreeNodeData::None) {
+            return self.union_at_root(other, other_root);
+        }
+ 
+        let length = self.nodes.len();
+        let new_index = other_root + length;
+        self.nodes.extend_from_slice(other);
+        shift_node_indecies(&mut self.nodes[length..], length);
+
+        let root_node = &mut self.nodes[self.root];
+        if let CSGTreeNodeData::SmoothUnion(union) = &mut root_node.data {
+            if union.blend == blend {
+                union.add_node(new_index);
+                self.nodes[new_index].parent = self.root;
+
+                self.smooth_index_changed(new_index, blend);
+
+                return UnionResult {
+                    union_node_index: self.root,
+                    new_object_index: new_index,
+                };
+            }
+        }
+
+        let union_index = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_smooth_union(vec![self.root, new_index], blend));
+
+        self.nodes[new_index].parent = union_index;
+        self.nodes[self.root].parent = union_index;
+
+        self.root = union_index;
+
+        self.smooth_index_changed(new_index, blend);
+        
+        UnionResult { 
+            union_node_index: union_index, 
+            new_object_index: new_index 
+        }
+    }
+
+    pub fn union_at_index_smooth(&mut self, index: CSGTreeIndex, other: &[CSGTreeNode<M, V, T, D>], other_root: usize, blend: f32) -> UnionResult {
+        let length = self.nodes.len();
+        let new_index = other_root + length;
+        self.nodes.extend_from_slice(other);
+        shift_node_indecies(&mut self.nodes[length..], length);
+
+        let current_node = &mut self.nodes[index];
+        if let CSGTreeNodeData::SmoothUnion(union) = &mut current_node.data {
+            if union.blend == blend {
+                union.add_node(new_index);
+                self.nodes[new_index].parent = index;
+
+                self.smooth_index_changed(new_index, blend);
+
+                return UnionResult {
+                    union_node_index: index,
+                    new_object_index: new_index,
+                };
+            }
+        }
+        
+        let parent = self.nodes[index].parent;
+        let union_index = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_smooth_union(vec![index, new_index], blend));
+
+        if index == self.root {
+            self.root = union_index;
+        } else {
+            self.update_child(parent, index, union_index);
+        }
+        self.nodes[union_index].parent = parent;
+
+        self.nodes[new_index].parent = union_index;
+        self.nodes[index].parent = union_index;
+
+        self.smooth_index_changed(new_index, blend);
+
+        UnionResult {
+            union_node_index: union_index,
+            new_object_index: new_index,
+        }
+    }
+
+    pub fn cut_at_root_smooth(&mut self, other: &[CSGTreeNode<M, V, T, D>], other_root: usize, blend: f32) -> CutResult { 
+        if other.is_empty() {
+            return CutResult {
+                cut_node_index: self.root,
+                base_index: self.root,
+                new_object_index: self.root,
+            };
+        }
+
+        assert!(!self.nodes.is_empty(), "You can not remove from an empty CSGTree");
+
+        self.cut_at_index_smooth(self.root, other, other_root, blend)
+    }
+
+    pub fn cut_at_index_smooth(&mut self, index: CSGTreeIndex, other: &[CSGTreeNode<M, V, T, D>], other_root: usize, blend: f32) -> CutResult {
+        self.needs_bounds_recompute = true;
+
+        let current_node = &self.nodes[index];
+        if let CSGTreeNodeData::SmoothCut(cut) = &current_node.data {
+            if cut.blend == blend {
+                let remove_index = cut.remove;
+                let base_index = cut.base;
+                let res = self.union_at_index_internal(remove_index, other, other_root);
+                self.nodes[res.union_node_index].parent = index;
+
+                self.index_changed_in(res.new_object_index, self.get_bounds_index(base_index), blend); 
+
+                return CutResult {
+                    cut_node_index: index,
+                    base_index,
+                    new_object_index: res.new_object_index,
+                };
+            }
+        }
+
+        let parent = current_node.parent;
+
+        let length = self.nodes.len();
+        let new_index = other_root + length;
+        self.nodes.extend_from_slice(other);
+        shift_node_indecies(&mut self.nodes[length..], length);
+
+        let cut_index = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_smooth_cut(index, new_index, blend));
+
+        if index == self.root {
+            self.root = cut_index;
+        } else {
+            self.update_child(parent, index, cut_index);
+        }
+        self.nodes[cut_index].parent = parent;
+        
+        self.nodes[new_index].parent = cut_index;
+        self.nodes[index].parent = cut_index;
+
+        self.index_changed_in(new_index, self.get_bounds_index(index), blend); 
+        
+        CutResult {
+            cut_node_index: cut_index,
+            base_index: index,
+            new_object_index: new_index,
+        }
+    }
+
+    pub(super) fn update_child(&mut self, index: CSGTreeIndex, old: CSGTreeIndex, new: CSGTreeIndex) {
         let node = &mut self.nodes[index];
 
         match &mut node.data {
@@ -300,6 +763,48 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
                     panic!("Remove Parent had no child");
                 }
             },
+            CSGTreeNodeData::Intersect(intersect) => {
+                let i = intersect.indecies.iter()
+                    .position(|index| *index == old)
+                    .expect("Intersect Parent had no child");
+                intersect.indecies[i] = new;
+            },
+            CSGTreeNodeData::SmoothUnion(union) => {
+                let i = union.indecies.iter()
+                    .position(|index| *index == old)
+                    .expect("Smooth Union Parent had no child");
+                union.indecies[i] = new;
+            },
+            CSGTreeNodeData::Transform(transform) => {
+                assert_eq!(transform.child, old, "Transform Parent had no child");
+                transform.child = new;
+            },
+            CSGTreeNodeData::Repeat(repeat) => {
+                assert_eq!(repeat.child, old, "Repeat Parent had no child");
+                repeat.child = new;
+            },
+            CSGTreeNodeData::Displace(displace) => {
+                assert_eq!(displace.child, old, "Displace Parent had no child");
+                displace.child = new;
+            },
+            CSGTreeNodeData::SmoothCut(remove) => {
+                if remove.base == old {
+                    remove.base = new;
+                } else if remove.remove == old {
+                    remove.remove = new;
+                } else {
+                    panic!("Smooth Remove Parent had no child");
+                }
+            },
+            CSGTreeNodeData::Paint(paint) => {
+                if paint.base == old {
+                    paint.base = new;
+                } else if paint.brush == old {
+                    paint.brush = new;
+                } else {
+                    panic!("Paint Parent had no child");
+                }
+            },
             _ => unreachable!()
         }
     }
@@ -308,7 +813,32 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         self.calculate_bounds_index(index);
         self.calculate_bounds_parents(self.nodes[index].parent);
 
-        self.changed_bounds = self.changed_bounds.union(self.get_bounds_index(index));
+        self.add_changed_bounds(self.nodes[index].parent, self.get_bounds_index(index));
+    }
+
+    // Cuts and intersections can only change the volume inside of the bounds of their base.
+    // The bounds of the new object may be infinite (planes) so they get clipped before the blend is added.
+    fn index_changed_in(&mut self, index: usize, bounds: AABB<V, T, D>, blend: f32) {
+        self.calculate_bounds_index(index);
+        self.calculate_bounds_parents(self.nodes[index].parent);
+
+        let blend = T::from_f32(blend.ceil());
+        let changed = self.get_bounds_index(index)
+            .intersect(bounds.expand(blend))
+            .expand(blend)
+            .intersect(bounds);
+
+        if changed.valid() {
+            self.add_changed_bounds(self.nodes[index].parent, changed);
+        }
+    }
+
+    // The blend can change the surface up to blend away from the new object.
+    fn smooth_index_changed(&mut self, index: usize, blend: f32) {
+        self.index_changed(index);
+
+        let blend_bounds = self.get_bounds_index(index).expand(T::from_f32(blend.ceil()));
+        self.add_changed_bounds(self.nodes[index].parent, blend_bounds);
     }
 }
 
@@ -318,6 +848,13 @@ pub fn shift_node_indecies<M: Base, V: Ve<T, D>, T: Nu, const D: usize>(nodes: &
         match &mut node.data {
             CSGTreeNodeData::Union(csgtree_union) => csgtree_union.shift_indecies(ammount),
             CSGTreeNodeData::Cut(csgtree_remove) => csgtree_remove.shift_indecies(ammount),
+            CSGTreeNodeData::Intersect(intersect) => intersect.shift_indecies(ammount),
+            CSGTreeNodeData::Transform(transform) => transform.shift_indecies(ammount),
+            CSGTreeNodeData::Repeat(repeat) => repeat.shift_indecies(ammount),
+            CSGTreeNodeData::Displace(displace) => displace.shift_indecies(ammount),
+            CSGTreeNodeData::Paint(paint) => paint.shift_indecies(ammount),
+            CSGTreeNodeData::SmoothUnion(union) => union.shift_indecies(ammount),
+            CSGTreeNodeData::SmoothCut(remove) => remove.shift_indecies(ammount),
             _ => {}
         }
 
@@ -1,4 +1,4 @@
-use crate::{csg::{Base, csg_tree::tree::{CSGTree, CSGTreeIndex, CSGTreeNodeData}}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeChangeBounds}};
+use crate::{csg::{Base, csg_tree::{repeat::CSGTreeRepeatKind, tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}}}, util::{aabb::AABB, matrix::Ma, noise::FractalNoiseSettings, number::Nu, vector::{CastFrom, CastInto, Ve}}, volume::{VolumeBounds, VolumeChangeBounds}};
 
 
 impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeChangeBounds<V, T, D> for CSGTree<M, V, T, D> {
@@ -15,40 +15,166 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         self.changed_bounds = Default::default();
     }
 
+    /**
+    Maps bounds in the space of the children of parent through all nodes above into the space of the root.
+    A change below a repeat changes every copy, below a displace it reaches as far as the surface can move.
+    */
+    pub(super) fn get_root_space_bounds(&self, parent: CSGTreeIndex, mut bounds: AABB<V, T, D>) -> AABB<V, T, D> {
+        let mut i = parent;
+        while i != CSG_TREE_INDEX_INVALID {
+            match &self.nodes[i].data {
+                CSGTreeNodeData::Transform(d) => bounds = d.get_world_aabb(bounds),
+                CSGTreeNodeData::Repeat(d) => bounds = d.get_world_aabb_of(bounds),
+                CSGTreeNodeData::Displace(d) if bounds.valid() => bounds = bounds.expand(d.get_band()),
+                _ => {}
+            }
+            i = self.nodes[i].parent;
+        }
+
+        bounds
+    }
+
+    // The bounds are in the space of the children of parent.
+    pub(super) fn add_changed_bounds(&mut self, parent: CSGTreeIndex, bounds: AABB<V, T, D>) {
+        let bounds = self.get_root_space_bounds(parent, bounds);
+        if bounds.valid() {
+            self.changed_bounds = self.changed_bounds.union(bounds);
+        }
+    }
+
     pub fn get_mat(&self, index: CSGTreeIndex) -> V::Matrix {
         match &self.nodes[index].data {
             CSGTreeNodeData::Box(d) => d.get_mat().cast(),
             CSGTreeNodeData::Sphere(d) => d.get_mat().cast(),
             CSGTreeNodeData::Cylinder(d) => d.get_mat().cast(),
-            CSGTreeNodeData::OffsetVoxelGrid(offset_voxel_grid) => todo!(),
-            CSGTreeNodeData::SharedVoxelGrid(shared_voxel_grid) => todo!(),
+            CSGTreeNodeData::Capsule(d) => d.get_mat().cast(),
+            CSGTreeNodeData::Torus(d) => d.get_mat().cast(),
+            CSGTreeNodeData::Cone(d) => d.get_mat().cast(),
+            CSGTreeNodeData::Plane(d) => d.get_mat().cast(),
+            CSGTreeNodeData::Wedge(d) => d.get_mat().cast(),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => V::Matrix::cast_from(d.get_mat()),
+            CSGTreeNodeData::SharedVoxelGrid(d) => V::Matrix::cast_from(d.get_mat()),
+            CSGTreeNodeData::Transform(d) => d.get_mat().cast(),
             _ => unreachable!()
         }
     }
 
+    // The old and the new bounds of the node are both changed.
     pub fn set_mat(&mut self, index: CSGTreeIndex, mat: V::Matrix) {
-        let new_change_bounds = match &mut self.nodes[index].data {
-            CSGTreeNodeData::Box(d) => {
-                let aabb = d.get_bounds();
-                d.set_mat(mat.cast());
-                aabb.union(d.get_bounds())
-            },
-            CSGTreeNodeData::Sphere(d) => {
-                let aabb = d.get_bounds();
-                d.set_mat(mat.cast());
-                aabb.union(d.get_bounds())
-            },
-            CSGTreeNodeData::Cylinder(d) => {
-                let aabb = d.get_bounds();
-                d.set_mat(mat.cast());
-                aabb.union(d.get_bounds())
-            },
-            CSGTreeNodeData::OffsetVoxelGrid(offset_voxel_grid) => todo!(),
-            CSGTreeNodeData::SharedVoxelGrid(shared_voxel_grid) => todo!(),
+        let old_bounds = self.get_bounds_index(index);
+
+        match &mut self.nodes[index].data {
+            CSGTreeNodeData::Box(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Sphere(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Cylinder(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Capsule(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Torus(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Cone(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Plane(d) => d.set_mat(mat.cast()),
+            CSGTreeNodeData::Wedge(d) => d.set_mat(mat.cast()),
+            // Grids are only transformed in 3D.
+            CSGTreeNodeData::OffsetVoxelGrid(d) => d.set_mat(mat.cast_into()),
+            CSGTreeNodeData::SharedVoxelGrid(d) => d.set_mat(mat.cast_into()),
+            CSGTreeNodeData::Transform(d) => d.set_mat(mat.cast()),
+            _ => unreachable!()
+        }
+
+        self.calculate_bounds_index(index);
+        let new_bounds = self.get_bounds_index(index);
+
+        self.add_changed_bounds(self.nodes[index].parent, old_bounds.union(new_bounds));
+        self.parents_changed(index);
+    }
+    pub fn get_repeat(&self, index: CSGTreeIndex) -> CSGTreeRepeatKind<V::VectorF, D> {
+        match &self.nodes[index].data {
+            CSGTreeNodeData::Repeat(d) => d.get_kind(),
+            _ => unreachable!()
+        }
+    }
+
+    // The old and the new copies are both changed.
+    pub fn set_repeat(&mut self, index: CSGTreeIndex, kind: CSGTreeRepeatKind<V::VectorF, D>) {
+        let old_bounds = self.get_bounds_index(index);
+
+        match &mut self.nodes[index].data {
+            CSGTreeNodeData::Repeat(d) => d.set_kind(kind),
             _ => unreachable!()
-        };
+        }
+
+        let new_bounds = self.get_bounds_index(index);
+
+        self.add_changed_bounds(self.nodes[index].parent, old_bounds.union(new_bounds));
+        self.parents_changed(index);
+    }
+
+    pub fn get_displace(&self, index: CSGTreeIndex) -> FractalNoiseSettings {
+        match &self.nodes[index].data {
+            CSGTreeNodeData::Displace(d) => d.get_settings(),
+            _ => unreachable!()
+        }
+    }
+
+    // The old and the new displaced surface are both changed.
+    pub fn set_displace(&mut self, index: CSGTreeIndex, settings: FractalNoiseSettings) {
+        let old_bounds = self.get_bounds_index(index);
+
+        match &mut self.nodes[index].data {
+            CSGTreeNodeData::Displace(d) => d.set_settings(settings),
+            _ => unreachable!()
+        }
+
+        let new_bounds = self.get_bounds_index(index);
+
+        self.add_changed_bounds(self.nodes[index].parent, old_bounds.union(new_bounds));
+        self.parents_changed(index);
+    }
+
+    /**
+    Wraps the subtree at index in a transform node so the whole subtree can be moved with set_mat.
+    Returns the index of the new transform node.
+    */
+    pub fn transform_at_index(&mut self, index: CSGTreeIndex, mat: V::Matrix) -> CSGTreeIndex {
+        self.wrap_node(index, CSGTreeNode::new_transform(index, mat.cast()))
+    }
+
+    /**
+    Wraps the subtree at index in a repeat node so the subtree is evaluated as an array of copies.
+    Returns the index of the new repeat node.
+    */
+    pub fn repeat_at_index(&mut self, index: CSGTreeIndex, kind: CSGTreeRepeatKind<V::VectorF, D>) -> CSGTreeIndex {
+        self.wrap_node(index, CSGTreeNode::new_repeat(index, kind))
+    }
+
+    /**
+    Wraps the subtree at index in a displace node so its surface gets perturbed by the noise.
+    Returns the index of the new displace node.
+    */
+    pub fn displace_at_index(&mut self, index: CSGTreeIndex, settings: FractalNoiseSettings) -> CSGTreeIndex {
+        self.wrap_node(index, CSGTreeNode::new_displace(index, settings))
+    }
+
+    // Puts node with index as its only child at the place of index.
+    fn wrap_node(&mut self, index: CSGTreeIndex, mut node: CSGTreeNode<M, V, T, D>) -> CSGTreeIndex {
+        let old_bounds = self.get_bounds_index(index);
+
+        let parent = self.nodes[index].parent;
+        let new_index = self.nodes.len();
+        node.parent = parent;
+        self.nodes.push(node);
+        self.nodes[index].parent = new_index;
+
+        if index == self.root {
+            self.root = new_index;
+        } else {
+            self.update_child(parent, index, new_index);
+        }
+
+        self.calculate_bounds_index(new_index);
+        let new_bounds = self.get_bounds_index(new_index);
+
+        self.add_changed_bounds(parent, old_bounds.union(new_bounds));
+        self.parents_changed(new_index);
 
-        self.changed_bounds = self.changed_bounds.union(new_change_bounds);
-        self.calculate_bounds_parents(index);
+        new_index
     }
 }
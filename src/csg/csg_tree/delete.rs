@@ -0,0 +1,209 @@
+use std::mem;
+
+use crate::{csg::Base, util::{aabb::AABB, number::Nu, vector::Ve}, volume::VolumeBounds};
+
+use super::tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID};
+
+impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
+    /**
+    Detaches the subtree at index and adds the region it covered to the changed bounds.
+    The nodes stay in the tree until compact is called, so all indecies stay valid.
+    */
+    pub fn remove_node(&mut self, index: CSGTreeIndex) {
+        let parent = self.nodes[index].parent;
+        if index != self.root && parent == CSG_TREE_INDEX_INVALID {
+            return;
+        }
+
+        // Only the part of the subtree that was visible changes.
+        let removed = self.get_bounds_index(index);
+        let visible = self.get_root_space_bounds(parent, removed).intersect(self.get_bounds());
+        if visible.valid() {
+            self.changed_bounds = self.changed_bounds.union(visible);
+        }
+
+        self.nodes[index].parent = CSG_TREE_INDEX_INVALID;
+
+        if index == self.root {
+            self.root = self.nodes.len();
+            self.nodes.push(CSGTreeNode::new_none());
+            return;
+        }
+
+        let cut = match &mut self.nodes[parent].data {
+            CSGTreeNodeData::Union(d) => {
+                d.indecies.retain(|i| *i != index);
+                d.needs_bounds_recompute = true;
+                None
+            },
+            CSGTreeNodeData::SmoothUnion(d) => {
+                // The blend zone around the removed node changes too.
+                d.indecies.retain(|i| *i != index);
+                let blend = T::from_f32(d.blend.ceil());
+                self.add_changed_bounds(parent, removed.expand(blend));
+                None
+            },
+            CSGTreeNodeData::Intersect(d) => {
+                d.indecies.retain(|i| *i != index);
+                None
+            },
+            CSGTreeNodeData::Cut(d) => Some((d.base, d.remove)),
+            CSGTreeNodeData::SmoothCut(d) => Some((d.base, d.remove)),
+            // Without its brush a paint is only its base.
+            CSGTreeNodeData::Paint(d) => Some((d.base, d.brush)),
+            // A transform, repeat or displace without its child is as empty as a cut without its base.
+            CSGTreeNodeData::Transform(d) => Some((d.child, CSG_TREE_INDEX_INVALID)),
+            CSGTreeNodeData::Repeat(d) => Some((d.child, CSG_TREE_INDEX_INVALID)),
+            CSGTreeNodeData::Displace(d) => Some((d.child, CSG_TREE_INDEX_INVALID)),
+            _ => unreachable!()
+        };
+
+        match cut {
+            // Nothing is left of a cut without its base.
+            Some((base, _)) if base == index => {
+                self.remove_node(parent);
+            },
+            // Without the cut only the base is left.
+            Some((base, _)) => {
+                self.replace_node(parent, base);
+            },
+            None => {
+                self.parents_changed(parent);
+
+                // An intersection grows when one of its children is removed.
+                if matches!(self.nodes[parent].data, CSGTreeNodeData::Intersect(_)) {
+                    let grown = self.get_bounds_index(parent);
+                    self.add_changed_bounds(self.nodes[parent].parent, grown);
+                }
+            },
+        }
+    }
+
+    /**
+    Drops all nodes that are not reachable from the root and remaps all indecies.
+    Returns the new index of every old node or CSG_TREE_INDEX_INVALID if the node was dropped.
+    */
+    pub fn compact(&mut self) -> Vec<CSGTreeIndex> {
+        let mut map = vec![CSG_TREE_INDEX_INVALID; self.nodes.len()];
+        if self.nodes.is_empty() {
+            return map;
+        }
+
+        let mut alive = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            if alive[index] {
+                continue;
+            }
+
+            alive[index] = true;
+            stack.extend(self.nodes[index].data.get_children());
+        }
+
+        let mut next = 0;
+        for (i, alive) in alive.iter().enumerate() {
+            if *alive {
+                map[i] = next;
+                next += 1;
+            }
+        }
+
+        let nodes = mem::take(&mut self.nodes);
+        self.nodes = nodes.into_iter()
+            .zip(alive)
+            .filter(|(_, alive)| *alive)
+            .map(|(mut node, _)| {
+                node.data.map_children(|i| map[i]);
+                node.parent = map.get(node.parent).copied().unwrap_or(CSG_TREE_INDEX_INVALID);
+                node
+            })
+            .collect();
+
+        self.root = map[self.root];
+        self.nodes[self.root].parent = CSG_TREE_INDEX_INVALID;
+        self.calculate_bounds_index(self.root);
+
+        map
+    }
+
+    // Puts new at the place of old in the parent of old.
+    fn replace_node(&mut self, old: CSGTreeIndex, new: CSGTreeIndex) {
+        let parent = self.nodes[old].parent;
+        self.nodes[old].parent = CSG_TREE_INDEX_INVALID;
+        self.nodes[new].parent = parent;
+
+        if old == self.root {
+            self.root = new;
+        } else {
+            self.update_child(parent, old, new);
+            self.parents_changed(parent);
+        }
+    }
+
+    // Rebuilds the bvhs of all unions above index.
+    pub(super) fn parents_changed(&mut self, index: CSGTreeIndex) {
+        let mut i = index;
+        while i != CSG_TREE_INDEX_INVALID {
+            if let CSGTreeNodeData::Union(d) = &mut self.nodes[i].data {
+                d.needs_bounds_recompute = true;
+            }
+            i = self.nodes[i].parent;
+        }
+
+        self.calculate_bounds_parents(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::{CSGTree, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}, volume::{VolumeBounds, VolumeQureyPosValue}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    #[test]
+    fn compact_remaps_indecies() {
+        let mut csg = Tree::default();
+        let a = csg.add_sphere(Vec3A::ZERO, 4.0, 1);
+        let dead = csg.add_sphere(vec3a(20.0, 0.0, 0.0), 4.0, 3);
+        let b = csg.add_sphere(vec3a(6.0, 0.0, 0.0), 4.0, 2);
+        let union = csg.add_union_node(vec![a, b]);
+        csg.set_root(union);
+
+        let map = csg.compact();
+
+        assert_eq!(map, vec![0, CSG_TREE_INDEX_INVALID, 1, 2]);
+        assert_eq!(csg.nodes.len(), 3);
+        assert_eq!(csg.root, 2);
+        assert_eq!(csg.nodes[csg.root].parent, CSG_TREE_INDEX_INVALID);
+        let CSGTreeNodeData::Union(d) = &csg.nodes[csg.root].data else {
+            panic!("Root is not a union");
+        };
+        assert_eq!(d.indecies, vec![map[a], map[b]]);
+        assert!(map[dead] == CSG_TREE_INDEX_INVALID);
+    }
+
+    #[test]
+    fn compact_after_remove() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        let res = csg.union_sphere(vec3a(20.0, 0.0, 0.0), 4.0, 2);
+        csg.union_sphere(vec3a(0.0, 20.0, 0.0), 4.0, 3);
+        csg.remove_node(res.new_object_index);
+
+        let map = csg.compact();
+        csg.calculate_bounds();
+
+        assert_eq!(map[res.new_object_index], CSG_TREE_INDEX_INVALID);
+        assert_eq!(csg.nodes.len(), map.iter().filter(|i| **i != CSG_TREE_INDEX_INVALID).count());
+        for (i, node) in csg.nodes.iter().enumerate() {
+            for child in node.data.get_children() {
+                assert_eq!(csg.nodes[child].parent, i);
+            }
+        }
+
+        assert_eq!(csg.get_value(IVec3::ZERO), 1);
+        assert_eq!(csg.get_value(IVec3::new(20, 0, 0)), 0);
+        assert_eq!(csg.get_value(IVec3::new(0, 20, 0)), 3);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::util::{aabb::AABB, noise::{FractalNoise, FractalNoiseSettings}, number::Nu, vector::Ve};
+
+use super::tree::CSGTreeIndex;
+
+// Moves the surface of the child by up to the amplitude of the noise.
+#[derive(Debug, Clone)]
+pub struct CSGTreeDisplace {
+    pub child: CSGTreeIndex,
+    noise: FractalNoise,
+}
+
+impl CSGTreeDisplace {
+    pub fn new(child: CSGTreeIndex, settings: FractalNoiseSettings) -> Self {
+        Self {
+            child,
+            noise: FractalNoise::new(settings),
+        }
+    }
+
+    pub fn get_settings(&self) -> FractalNoiseSettings {
+        self.noise.get_settings()
+    }
+
+    pub fn set_settings(&mut self, settings: FractalNoiseSettings) {
+        self.noise = FractalNoise::new(settings);
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        self.child += ammount;
+    }
+
+    // 2D volumes sample the noise at z = 0.
+    pub fn get_offset<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        self.noise.get(pos.ve_into())
+    }
+
+    // How far the surface can move. The child is queried in the aabb grown by this.
+    pub fn get_band<T: Nu>(&self) -> T {
+        T::from_f32(self.noise.get_amplitude().ceil() + 1.0)
+    }
+
+    pub fn get_world_aabb<V: Ve<T, D>, T: Nu, const D: usize>(&self, aabb: AABB<V, T, D>) -> AABB<V, T, D> {
+        if aabb.min().cmpgt_any(aabb.max()) {
+            return AABB::default();
+        }
+
+        aabb.expand(self.get_band())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::{aabb::AABB, noise::FractalNoiseSettings}, volume::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    const AMPLITUDE: f32 = 2.5;
+
+    fn new_displaced() -> (Tree, Tree) {
+        let sphere = Tree::new_sphere_float(Vec3A::ZERO, 10.0, 1);
+
+        let mut csg = Tree::default();
+        let child = csg.add_sphere(Vec3A::ZERO, 10.0, 1);
+        let displace = csg.add_displace_node(child, FractalNoiseSettings {
+            amplitude: AMPLITUDE,
+            frequency: 0.2,
+            ..Default::default()
+        });
+        csg.set_root(displace);
+        csg.calculate_bounds();
+
+        (sphere, csg)
+    }
+
+    #[test]
+    fn bounds() {
+        let (sphere, csg) = new_displaced();
+        let bounds = csg.get_bounds();
+        let expected = sphere.get_bounds().expand(4);
+        assert_eq!((bounds.min(), bounds.max()), (expected.min(), expected.max()));
+
+        for x in -16..=16 {
+            for y in -16..=16 {
+                let pos = IVec3::new(x, y, 0);
+                if csg.get_value(pos) != 0 {
+                    assert!(bounds.min().cmple(pos).all() && bounds.max().cmpge(pos).all(), "{pos} is outside the bounds");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values() {
+        let (sphere, csg) = new_displaced();
+        assert_eq!(csg.get_value(IVec3::ZERO), 1);
+        assert_eq!(csg.get_value(IVec3::new(7, 0, 0)), 1);
+        assert_eq!(csg.get_value(IVec3::new(13, 0, 0)), 0);
+
+        // The surface moves, but never further than the amplitude.
+        let mut moved = false;
+        for x in -16..=16 {
+            for y in -16..=16 {
+                let pos = IVec3::new(x, y, 0);
+                let dist = pos.as_vec3a().length() - 10.0;
+                if dist.abs() > AMPLITUDE {
+                    assert_eq!(csg.get_value(pos), sphere.get_value(pos), "{pos} is further than the amplitude from the surface");
+                }
+                moved |= csg.get_value(pos) != sphere.get_value(pos);
+            }
+        }
+        assert!(moved);
+    }
+
+    #[test]
+    fn aabb_matches_values() {
+        let (_, csg) = new_displaced();
+        assert!(matches!(csg.get_aabb_value(AABB::new(IVec3::splat(-2), IVec3::splat(2))), VolumeQureyAABBResult::Full(1)));
+        assert!(matches!(csg.get_aabb_value(AABB::new(IVec3::splat(20), IVec3::splat(24))), VolumeQureyAABBResult::Full(0)));
+
+        for x in (-16..16).step_by(4) {
+            for y in (-16..16).step_by(4) {
+                let min = IVec3::new(x, y, 0);
+                let VolumeQureyAABBResult::Full(mat) = csg.get_aabb_value(AABB::new(min, min + 4)) else {
+                    continue;
+                };
+
+                for px in 0..4 {
+                    for py in 0..4 {
+                        let pos = min + IVec3::new(px, py, 0);
+                        assert_eq!(csg.get_value(pos), mat, "{pos} does not match its full block");
+                    }
+                }
+            }
+        }
+    }
+}
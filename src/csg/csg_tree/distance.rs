@@ -0,0 +1,256 @@
+use crate::{util::{math::{smooth_max, smooth_min}, number::Nu, vector::Ve}, volume::{VolumeQureyDistance, VolumeQureyPosValue}, voxel::{grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}, palette::palette::MATERIAL_ID_NONE}};
+
+use super::{smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, tree::{CSGTree, CSGTreeIndex, CSGTreeNodeData}, union::CSGTreeUnion};
+
+/**
+Approximate signed distances used to evaluate the smooth nodes.
+Negative inside. Voxel grids use their distance field, so they are exact up to the voxel size.
+*/
+impl<M, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
+    pub(super) fn get_distance_index(&self, index: CSGTreeIndex, pos: V::VectorF) -> f32 {
+        self.get_distance_bounded_index(index, pos, f32::MAX)
+    }
+
+    // Clamped to [-max, max]. Subtrees that are further away than max are skipped.
+    pub(super) fn get_distance_bounded_index(&self, index: CSGTreeIndex, pos: V::VectorF, max: f32) -> f32 {
+        let node = &self.nodes[index];
+        let distance = match &node.data {
+            CSGTreeNodeData::None => max,
+            CSGTreeNodeData::Union(d) => self.get_distance_union(d, pos, max),
+            CSGTreeNodeData::Cut(d) => {
+                let base = self.get_distance_bounded_index(d.base, pos, max);
+                if base >= max {
+                    return max;
+                }
+
+                let remove = self.get_distance_bounded_index(d.remove, pos, max);
+                base.max(-remove)
+            },
+            CSGTreeNodeData::SmoothUnion(d) => self.get_distance_smooth_union(d, pos),
+            CSGTreeNodeData::Intersect(d) => d.indecies.iter()
+                .map(|index| self.get_distance_bounded_index(*index, pos, max))
+                .reduce(f32::max)
+                .unwrap_or(max),
+            CSGTreeNodeData::SmoothCut(d) => self.get_distance_smooth_remove(d, pos),
+            CSGTreeNodeData::Transform(d) => {
+                let local = self.get_distance_bounded_index(d.child, d.get_local_pos_f(pos), d.get_local_distance(max));
+                d.get_world_distance(local)
+            },
+            CSGTreeNodeData::Repeat(d) => d.get_copies_at(pos).into_iter()
+                .map(|copy| self.get_distance_bounded_index(d.child, d.get_local_pos_f(copy, pos), max))
+                .fold(max, f32::min),
+            CSGTreeNodeData::Displace(d) => {
+                // The child has to be known a bit further out as the offset can pull it closer.
+                let offset = d.get_offset(pos);
+                self.get_distance_bounded_index(d.child, pos, max + offset.abs()) + offset
+            },
+            CSGTreeNodeData::Paint(d) => self.get_distance_bounded_index(d.base, pos, max),
+
+            CSGTreeNodeData::Box(d) => d.get_distance(pos),
+            CSGTreeNodeData::Sphere(d) => d.get_distance(pos),
+            CSGTreeNodeData::Cylinder(d) => d.get_distance(pos),
+            CSGTreeNodeData::Capsule(d) => d.get_distance(pos),
+            CSGTreeNodeData::Torus(d) => d.get_distance(pos),
+            CSGTreeNodeData::Cone(d) => d.get_distance(pos),
+            CSGTreeNodeData::Plane(d) => d.get_distance(pos),
+            CSGTreeNodeData::Wedge(d) => d.get_distance(pos),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => <OffsetVoxelGrid as VolumeQureyDistance<V::VectorF, D>>::get_distance_bounded(d, pos, max),
+            CSGTreeNodeData::SharedVoxelGrid(d) => <SharedVoxelGrid as VolumeQureyDistance<V::VectorF, D>>::get_distance_bounded(d, pos, max),
+            CSGTreeNodeData::Heightmap(d) => d.get_distance(pos),
+            CSGTreeNodeData::Extrude(d) => d.get_distance_bounded(pos, max),
+            CSGTreeNodeData::Revolve(d) => d.get_distance_bounded(pos, max),
+        };
+
+        distance.clamp(-max, max)
+    }
+
+    // Children whose bounds are further away than the closest distance so far can not be closer.
+    fn get_distance_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V::VectorF, max: f32) -> f32 {
+        let mut best = max;
+
+        // The bvh is only valid after the bounds were calculated.
+        if union.needs_bounds_recompute {
+            return union.indecies.iter()
+                .map(|index| self.get_distance_bounded_index(*index, pos, max))
+                .fold(best, f32::min);
+        }
+
+        let mut i = 0;
+        while i < union.bvh.nodes.len() {
+            let b = &union.bvh.nodes[i];
+
+            // Integer bounds are truncated, so they get grown by one.
+            let outside = b.aabb.expand(T::ONE).to_f::<V::VectorF>().distance_to_pos(pos);
+            if outside > 0.0 && outside >= best {
+                i = b.exit;
+                continue;
+            }
+
+            if let Some(leaf) = b.leaf {
+                best = best.min(self.get_distance_bounded_index(leaf, pos, best));
+            }
+
+            i += 1;
+        }
+
+        best
+    }
+
+    pub(super) fn get_distance_smooth_union(&self, union: &CSGTreeSmoothUnion, pos: V::VectorF) -> f32 {
+        union.indecies.iter()
+            .map(|index| self.get_distance_index(*index, pos))
+            .reduce(|a, b| smooth_min(a, b, union.blend))
+            .unwrap_or(f32::MAX)
+    }
+
+    pub(super) fn get_distance_smooth_remove(&self, remove: &CSGTreeSmoothRemove, pos: V::VectorF) -> f32 {
+        let base = self.get_distance_index(remove.base, pos);
+        let cut = self.get_distance_index(remove.remove, pos);
+
+        smooth_max(base, -cut, remove.blend)
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
+    // Returns the distance and the material of the closest surface.
+    pub(super) fn get_distance_value_index(&self, index: CSGTreeIndex, pos: V::VectorF) -> (f32, u8) {
+        let node = &self.nodes[index];
+        match &node.data {
+            CSGTreeNodeData::None => (f32::MAX, MATERIAL_ID_NONE),
+            CSGTreeNodeData::Union(d) => d.indecies.iter()
+                .map(|index| self.get_distance_value_index(*index, pos))
+                .fold((f32::MAX, MATERIAL_ID_NONE), |a, b| closer_material(a, b, a.0.min(b.0))),
+            CSGTreeNodeData::Cut(d) => {
+                let (base, mat) = self.get_distance_value_index(d.base, pos);
+                let remove = self.get_distance_index(d.remove, pos);
+                (base.max(-remove), mat)
+            },
+            CSGTreeNodeData::Intersect(d) => d.indecies.iter()
+                .map(|index| self.get_distance_value_index(*index, pos))
+                .reduce(|a, b| (a.0.max(b.0), a.1))
+                .unwrap_or((f32::MAX, MATERIAL_ID_NONE)),
+            CSGTreeNodeData::SmoothUnion(d) => d.indecies.iter()
+                .map(|index| self.get_distance_value_index(*index, pos))
+                .reduce(|a, b| closer_material(a, b, smooth_min(a.0, b.0, d.blend)))
+                .unwrap_or((f32::MAX, MATERIAL_ID_NONE)),
+            CSGTreeNodeData::SmoothCut(d) => {
+                let (base, mat) = self.get_distance_value_index(d.base, pos);
+                let remove = self.get_distance_index(d.remove, pos);
+                (smooth_max(base, -remove, d.blend), mat)
+            },
+            CSGTreeNodeData::Transform(d) => {
+                let (distance, mat) = self.get_distance_value_index(d.child, d.get_local_pos_f(pos));
+                (d.get_world_distance(distance), mat)
+            },
+            CSGTreeNodeData::Repeat(d) => d.get_copies_at(pos).into_iter()
+                .map(|copy| self.get_distance_value_index(d.child, d.get_local_pos_f(copy, pos)))
+                .fold((f32::MAX, MATERIAL_ID_NONE), |a, b| closer_material(a, b, a.0.min(b.0))),
+            CSGTreeNodeData::Displace(d) => {
+                let (distance, mat) = self.get_distance_value_index(d.child, pos);
+                (distance + d.get_offset(pos), mat)
+            },
+            CSGTreeNodeData::Paint(d) => {
+                let (distance, mat) = self.get_distance_value_index(d.base, pos);
+                let (brush, brush_mat) = self.get_distance_value_index(d.brush, pos);
+                (distance, if brush < 0.0 && brush_mat != MATERIAL_ID_NONE { brush_mat } else { mat })
+            },
+
+            CSGTreeNodeData::Box(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Sphere(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Cylinder(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Capsule(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Torus(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Cone(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Plane(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::Wedge(d) => (d.get_distance(pos), d.get_material()),
+            CSGTreeNodeData::OffsetVoxelGrid(d) =>
+                (self.get_distance_index(index, pos), <OffsetVoxelGrid as VolumeQureyPosValue<V::VectorF, f32, D>>::get_value(d, pos)),
+            CSGTreeNodeData::SharedVoxelGrid(d) =>
+                (self.get_distance_index(index, pos), <SharedVoxelGrid as VolumeQureyPosValue<V::VectorF, f32, D>>::get_value(d, pos)),
+            CSGTreeNodeData::Heightmap(d) => (d.get_distance(pos), d.material),
+            CSGTreeNodeData::Extrude(d) => d.get_distance_value(pos),
+            CSGTreeNodeData::Revolve(d) => d.get_distance_value(pos),
+        }
+    }
+}
+
+// Prefers set materials so a blend zone next to an empty voxel still gets a material.
+pub(super) fn closer_material(a: (f32, u8), b: (f32, u8), dist: f32) -> (f32, u8) {
+    let mat = if b.1 == MATERIAL_ID_NONE {
+        a.1
+    } else if a.1 == MATERIAL_ID_NONE || b.0 < a.0 {
+        b.1
+    } else {
+        a.1
+    };
+
+    (dist, mat)
+}
+
+impl<M, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyDistance<V::VectorF, D> for CSGTree<M, V, T, D> {
+    fn get_distance_bounded(&self, pos: V::VectorF, max: f32) -> f32 {
+        if self.nodes.is_empty() {
+            return max;
+        }
+
+        self.get_distance_bounded_index(self.root, pos, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, volume::{VolumeBounds, VolumeQureyDistance}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    #[test]
+    fn clamped_to_max() {
+        let csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        assert!((csg.get_distance_bounded(vec3a(10.0, 0.0, 0.0), 100.0) - 4.0).abs() < 1e-5);
+        assert!((csg.get_distance_bounded(Vec3A::ZERO, 100.0) + 6.0).abs() < 1e-5);
+        assert_eq!(csg.get_distance_bounded(vec3a(10.0, 0.0, 0.0), 2.0), 2.0);
+        assert_eq!(csg.get_distance_bounded(Vec3A::ZERO, 3.0), -3.0);
+    }
+
+    #[test]
+    fn union_matches_children() {
+        let spheres: Vec<_> = (0..5)
+            .flat_map(|x| (0..4).map(move |y| (vec3a(x as f32 * 12.0, y as f32 * 9.0, (x + y) as f32), 2.0 + (x * y % 3) as f32)))
+            .collect();
+
+        let mut csg = Tree::new_sphere_float(spheres[0].0, spheres[0].1, 1);
+        for (center, radius) in spheres[1..].iter() {
+            csg.union_sphere(*center, *radius, 1);
+        }
+        csg.calculate_bounds();
+
+        for x in (-10..60).step_by(7) {
+            for y in (-10..40).step_by(7) {
+                for z in (-10..15).step_by(5) {
+                    let pos = vec3a(x as f32, y as f32, z as f32);
+                    let expected = spheres.iter()
+                        .map(|(center, radius)| pos.distance(*center) - radius)
+                        .fold(f32::MAX, f32::min);
+
+                    assert!((csg.get_distance_bounded(pos, 1000.0) - expected).abs() < 1e-4, "Distance at {pos} differs");
+                    assert!((csg.get_distance_bounded(pos, 3.0) - expected.clamp(-3.0, 3.0)).abs() < 1e-4, "Bounded distance at {pos} differs");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cut() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        csg.cut_with_sphere(vec3a(6.0, 0.0, 0.0), 3.0, 0);
+        csg.calculate_bounds();
+
+        assert!((csg.get_distance_bounded(vec3a(2.5, 0.0, 0.0), 100.0) + 0.5).abs() < 1e-5);
+        assert!((csg.get_distance_bounded(Vec3A::ZERO, 100.0) + 3.0).abs() < 1e-5);
+        assert!((csg.get_distance_bounded(vec3a(0.0, -5.5, 0.0), 100.0) + 0.5).abs() < 1e-5);
+        assert!((csg.get_distance_bounded(vec3a(6.0, 0.0, 0.0), 100.0) - 3.0).abs() < 1e-5);
+        assert_eq!(csg.get_distance_bounded(vec3a(-20.0, 0.0, 0.0), 5.0), 5.0);
+    }
+}
@@ -0,0 +1,325 @@
+use octa_force::{anyhow::ensure, glam::{vec2, vec3a, Vec2, Vec3A}, OctaResult};
+
+use crate::{util::{aabb::AABB, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyDistance, VolumeQureyPosValid, VolumeQureyPosValue}, voxel::palette::palette::MATERIAL_ID_NONE};
+
+use super::tree::CSGTree;
+
+pub type CSGTreeProfile<M> = CSGTree<M, Vec2, f32, 2>;
+
+/**
+Pulls a 2D profile along the z axis from 0 up to height.
+The profile is scaled by taper at the top, so a taper of 0 ends in a point.
+2D queries look at the slice in the middle of the height.
+*/
+#[derive(Debug, Clone)]
+pub struct CSGTreeExtrude<M> {
+    profile: CSGTreeProfile<M>,
+    profile_bounds: AABB<Vec2, f32, 2>,
+    height: f32,
+    taper: f32,
+}
+
+/**
+Spins a 2D profile around the z axis. The x of the profile is the distance to the axis and the y is the height.
+Only the part of the profile with x >= 0 is used. 2D queries look at the slice at z = 0.
+*/
+#[derive(Debug, Clone)]
+pub struct CSGTreeRevolve<M> {
+    profile: CSGTreeProfile<M>,
+    profile_bounds: AABB<Vec2, f32, 2>,
+}
+
+impl<M: Send + Sync> CSGTreeExtrude<M> {
+    pub fn new(mut profile: CSGTreeProfile<M>, height: f32, taper: f32) -> OctaResult<Self> {
+        ensure!(!profile.nodes.is_empty(), "Extrude needs a profile");
+        ensure!(height > 0.0 && height.is_finite(), "Extrude needs a positive height, got {height}");
+        ensure!(taper >= 0.0 && taper.is_finite(), "Extrude taper can not be negative, got {taper}");
+
+        profile.calculate_bounds();
+        Ok(Self {
+            profile_bounds: profile.get_bounds(),
+            profile,
+            height,
+            taper,
+        })
+    }
+}
+
+impl<M> CSGTreeExtrude<M> {
+    pub fn get_profile(&self) -> &CSGTreeProfile<M> {
+        &self.profile
+    }
+
+    pub fn get_height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn get_taper(&self) -> f32 {
+        self.taper
+    }
+
+    fn to_local<V: Ve<T, D>, T: Nu, const D: usize>(&self, pos: V) -> Vec3A {
+        let p = pos.to_array().map(T::to_f32);
+        if D == 3 { vec3a(p[0], p[1], p[2]) } else { vec3a(p[0], p[1], self.height * 0.5) }
+    }
+
+    // Scale of the profile at the height z.
+    fn get_scale(&self, z: f32) -> f32 {
+        let t = (z / self.height).clamp(0.0, 1.0);
+        (1.0 + (self.taper - 1.0) * t).max(f32::EPSILON)
+    }
+
+    fn to_profile(&self, p: Vec3A) -> Vec2 {
+        p.truncate() / self.get_scale(p.z)
+    }
+
+    fn in_height(&self, z: f32) -> bool {
+        z >= 0.0 && z < self.height
+    }
+
+    // The taper only moves the profile between its size at the bottom and at the top.
+    pub fn get_world_aabb<V: Ve<T, D>, T: Nu, const D: usize>(&self) -> AABB<V, T, D> {
+        if !self.profile_bounds.valid() {
+            return AABB::default();
+        }
+
+        let min = self.profile_bounds.min();
+        let max = self.profile_bounds.max();
+        to_world_aabb(
+            min.min(min * self.taper).extend(0.0).into(),
+            max.max(max * self.taper).extend(self.height).into())
+    }
+
+    // The profile distance is scaled with the taper, so it is approximate for tapered extrudes.
+    fn get_distance_f<V: Ve<f32, D>, const D: usize>(&self, pos: V, max: f32) -> f32 {
+        let p = self.to_local(pos);
+        let scale = self.get_scale(p.z);
+        let profile = self.profile.get_distance_bounded_index(self.profile.root, self.to_profile(p), max / scale) * scale;
+        let height = (-p.z).max(p.z - self.height);
+
+        let w = vec2(profile, height);
+        (w.max_element().min(0.0) + w.max(Vec2::ZERO).length()).clamp(-max, max)
+    }
+}
+
+impl CSGTreeExtrude<u8> {
+    // Distance and the material of the closest surface of the profile.
+    pub fn get_distance_value<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> (f32, u8) {
+        let p = self.to_profile(self.to_local(pos));
+        (self.get_distance_f(pos, f32::MAX), self.profile.get_distance_value_index(self.profile.root, p).1)
+    }
+}
+
+impl<M: Send + Sync> CSGTreeRevolve<M> {
+    pub fn new(mut profile: CSGTreeProfile<M>) -> OctaResult<Self> {
+        ensure!(!profile.nodes.is_empty(), "Revolve needs a profile");
+
+        profile.calculate_bounds();
+        Ok(Self {
+            profile_bounds: profile.get_bounds(),
+            profile,
+        })
+    }
+}
+
+impl<M> CSGTreeRevolve<M> {
+    pub fn get_profile(&self) -> &CSGTreeProfile<M> {
+        &self.profile
+    }
+
+    fn to_profile<V: Ve<T, D>, T: Nu, const D: usize>(&self, pos: V) -> Vec2 {
+        let p = pos.to_array().map(T::to_f32);
+        let z = if D == 3 { p[2] } else { 0.0 };
+        vec2(vec2(p[0], p[1]).length(), z)
+    }
+
+    pub fn get_world_aabb<V: Ve<T, D>, T: Nu, const D: usize>(&self) -> AABB<V, T, D> {
+        if !self.profile_bounds.valid() || self.profile_bounds.max().x < 0.0 {
+            return AABB::default();
+        }
+
+        let radius = self.profile_bounds.max().x;
+        to_world_aabb(
+            vec3a(-radius, -radius, self.profile_bounds.min().y),
+            vec3a(radius, radius, self.profile_bounds.max().y))
+    }
+
+    fn get_distance_f<V: Ve<f32, D>, const D: usize>(&self, pos: V, max: f32) -> f32 {
+        self.profile.get_distance_bounded_index(self.profile.root, self.to_profile(pos), max)
+    }
+}
+
+impl CSGTreeRevolve<u8> {
+    pub fn get_distance_value<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> (f32, u8) {
+        self.profile.get_distance_value_index(self.profile.root, self.to_profile(pos))
+    }
+}
+
+impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for CSGTreeExtrude<M> {
+    fn calculate_bounds(&mut self) {
+        self.profile.calculate_bounds();
+        self.profile_bounds = self.profile.get_bounds();
+    }
+
+    fn get_bounds(&self) -> AABB<V, T, D> {
+        self.get_world_aabb()
+    }
+}
+
+impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for CSGTreeRevolve<M> {
+    fn calculate_bounds(&mut self) {
+        self.profile.calculate_bounds();
+        self.profile_bounds = self.profile.get_bounds();
+    }
+
+    fn get_bounds(&self) -> AABB<V, T, D> {
+        self.get_world_aabb()
+    }
+}
+
+impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for CSGTreeExtrude<M> {
+    fn is_position_valid(&self, pos: V) -> bool {
+        let p = self.to_local(pos);
+        self.in_height(p.z) && self.profile.is_position_valid(self.to_profile(p))
+    }
+}
+
+impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for CSGTreeRevolve<M> {
+    fn is_position_valid(&self, pos: V) -> bool {
+        self.profile.is_position_valid(self.to_profile(pos))
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for CSGTreeExtrude<u8> {
+    fn get_value(&self, pos: V) -> u8 {
+        let p = self.to_local(pos);
+        if !self.in_height(p.z) {
+            return MATERIAL_ID_NONE;
+        }
+
+        self.profile.get_value(self.to_profile(p))
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for CSGTreeRevolve<u8> {
+    fn get_value(&self, pos: V) -> u8 {
+        self.profile.get_value(self.to_profile(pos))
+    }
+}
+
+// The profile is queried in the area that the aabb covers at any of its heights.
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for CSGTreeExtrude<u8> {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let min = self.to_local(aabb.min());
+        let max = self.to_local(aabb.max());
+        if max.z < 0.0 || min.z >= self.height {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let (a, b) = (self.get_scale(min.z), self.get_scale(max.z));
+        let (small, big) = (a.min(b), a.max(b));
+        let profile_min = (min.truncate() / small).min(min.truncate() / big);
+        let profile_max = (max.truncate() / small).max(max.truncate() / big);
+
+        let result = self.profile.get_aabb_value(AABB::new(profile_min, profile_max));
+        if self.in_height(min.z) && self.in_height(max.z) {
+            return result;
+        }
+
+        match result {
+            VolumeQureyAABBResult::Full(MATERIAL_ID_NONE) => result,
+            _ => VolumeQureyAABBResult::Mixed,
+        }
+    }
+}
+
+// The aabb covers a ring of radii between its closest and furthest point to the axis.
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for CSGTreeRevolve<u8> {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let min = aabb.min().to_array().map(T::to_f32);
+        let max = aabb.max().to_array().map(T::to_f32);
+        let (min_xy, max_xy) = (vec2(min[0], min[1]), vec2(max[0], max[1]));
+        let (min_z, max_z) = if D == 3 { (min[2], max[2]) } else { (0.0, 0.0) };
+
+        let closest = Vec2::ZERO.clamp(min_xy, max_xy).length();
+        let furthest = min_xy.abs().max(max_xy.abs()).length();
+
+        self.profile.get_aabb_value(AABB::new(vec2(closest, min_z), vec2(furthest, max_z)))
+    }
+}
+
+impl<M, V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for CSGTreeExtrude<M> {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        self.get_distance_f(pos, max)
+    }
+}
+
+impl<M, V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for CSGTreeRevolve<M> {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        self.get_distance_f(pos, max)
+    }
+}
+
+fn to_world_aabb<V: Ve<T, D>, T: Nu, const D: usize>(min: Vec3A, max: Vec3A) -> AABB<V, T, D> {
+    let (min, max) = (min.to_array(), max.to_array());
+    AABB::new(
+        V::new(std::array::from_fn(|i| T::from_f32(min[i].floor()))),
+        V::new(std::array::from_fn(|i| T::from_f32(max[i].ceil()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec2, vec3a, Vec3A};
+
+    use crate::{util::aabb::AABB, volume::{VolumeBounds, VolumeQureyPosValue}};
+
+    use super::{CSGTreeExtrude, CSGTreeProfile, CSGTreeRevolve};
+
+    fn square(center_x: f32, size: f32) -> CSGTreeProfile<u8> {
+        CSGTreeProfile::new_box_float(vec2(center_x, 0.0), vec2(size, size), 1)
+    }
+
+    #[test]
+    fn reject_bad_extrude() {
+        assert!(CSGTreeExtrude::new(CSGTreeProfile::<u8>::default(), 1.0, 1.0).is_err());
+        assert!(CSGTreeExtrude::new(square(0.0, 4.0), 0.0, 1.0).is_err());
+        assert!(CSGTreeExtrude::new(square(0.0, 4.0), f32::NAN, 1.0).is_err());
+        assert!(CSGTreeExtrude::new(square(0.0, 4.0), 10.0, -1.0).is_err());
+        assert!(CSGTreeExtrude::new(square(0.0, 4.0), 10.0, f32::INFINITY).is_err());
+        assert!(CSGTreeRevolve::new(CSGTreeProfile::<u8>::default()).is_err());
+    }
+
+    #[test]
+    fn extrude_values() {
+        let extrude = CSGTreeExtrude::new(square(0.0, 4.0), 10.0, 0.0).unwrap();
+        assert_eq!(extrude.get_value(vec3a(0.0, 0.0, 5.0)), 1);
+        assert_eq!(extrude.get_value(vec3a(1.5, 0.0, 0.5)), 1);
+        assert_eq!(extrude.get_value(vec3a(1.5, 0.0, 9.5)), 0);
+        assert_eq!(extrude.get_value(vec3a(0.0, 0.0, -1.0)), 0);
+        assert_eq!(extrude.get_value(vec3a(0.0, 0.0, 11.0)), 0);
+        assert_eq!(extrude.get_value(vec3a(3.0, 0.0, 5.0)), 0);
+    }
+
+    #[test]
+    fn extrude_bounds() {
+        let extrude = CSGTreeExtrude::new(square(0.0, 4.0), 10.0, 2.0).unwrap();
+        let bounds: AABB<Vec3A, f32, 3> = extrude.get_bounds();
+        assert_eq!(bounds.min(), vec3a(-4.0, -4.0, 0.0));
+        assert_eq!(bounds.max(), vec3a(4.0, 4.0, 10.0));
+    }
+
+    #[test]
+    fn revolve_values() {
+        let revolve = CSGTreeRevolve::new(square(5.0, 2.0)).unwrap();
+        assert_eq!(revolve.get_value(vec3a(5.0, 0.0, 0.0)), 1);
+        assert_eq!(revolve.get_value(vec3a(0.0, -5.0, 0.5)), 1);
+        assert_eq!(revolve.get_value(vec3a(3.0, 3.0, 0.0)), 1);
+        assert_eq!(revolve.get_value(vec3a(0.0, 0.0, 0.0)), 0);
+        assert_eq!(revolve.get_value(vec3a(5.0, 0.0, 2.0)), 0);
+        assert_eq!(revolve.get_value(vec3a(7.0, 0.0, 0.0)), 0);
+
+        let bounds: AABB<Vec3A, f32, 3> = revolve.get_bounds();
+        assert_eq!(bounds.min(), vec3a(-6.0, -6.0, -1.0));
+        assert_eq!(bounds.max(), vec3a(6.0, 6.0, 1.0));
+    }
+}
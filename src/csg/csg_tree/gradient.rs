@@ -1,4 +1,6 @@
-use crate::{csg::csg_tree::{remove::CSGTreeRemove, tree::{CSGTree, CSGTreeIndex, CSGTreeNodeData}, union::CSGTreeUnion}, util::{number::Nu, vector::Ve}, volume::VolumeGradient};
+use crate::{csg::csg_tree::{intersect::CSGTreeIntersect, remove::CSGTreeRemove, repeat::CSGTreeRepeat, tree::{CSGTree, CSGTreeIndex, CSGTreeNodeData}, union::CSGTreeUnion}, util::{number::Nu, vector::Ve}, volume::{heightmap::Heightmap, VolumeGradient}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
+
+const GRADIENT_EPSILON: f32 = 0.5;
 
 impl<M, V: Ve<T, D>, T: Nu, const D: usize> VolumeGradient<V::VectorF, D> for CSGTree<M, V, T, D> {
     fn get_gradient_at_position(&self, pos: V::VectorF) -> V::VectorF {
@@ -7,36 +9,115 @@ impl<M, V: Ve<T, D>, T: Nu, const D: usize> VolumeGradient<V::VectorF, D> for CS
 }
 
 impl<M, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
-    fn get_gradient_at_position_internal(&self, index: CSGTreeIndex, pos: V::VectorF) -> V::VectorF {
+    pub(super) fn get_gradient_at_position_internal(&self, index: CSGTreeIndex, pos: V::VectorF) -> V::VectorF {
         let node = &self.nodes[index];
         match &node.data {
             CSGTreeNodeData::None => V::VectorF::ZERO,
             CSGTreeNodeData::Union(d) => self.get_gradient_at_position_union(d, pos),
             CSGTreeNodeData::Cut(d) => self.get_gradient_at_position_union_remove(d, pos),
+            CSGTreeNodeData::Intersect(d) => self.get_gradient_at_position_intersect(d, pos),
+            CSGTreeNodeData::Transform(d) => d.get_world_normal(self.get_gradient_at_position_internal(d.child, d.get_local_pos_f(pos))),
+            CSGTreeNodeData::Repeat(d) => self.get_gradient_at_position_repeat(d, pos),
+            CSGTreeNodeData::Paint(d) => self.get_gradient_at_position_internal(d.base, pos),
+            CSGTreeNodeData::SmoothUnion(_)
+            | CSGTreeNodeData::SmoothCut(_)
+            | CSGTreeNodeData::Displace(_)
+            | CSGTreeNodeData::Extrude(_)
+            | CSGTreeNodeData::Revolve(_) => self.get_gradient_at_position_distance(index, pos),
             
             CSGTreeNodeData::Box(d) => d.get_gradient_at_position(pos),
             CSGTreeNodeData::Sphere(d) => d.get_gradient_at_position(pos),
             CSGTreeNodeData::Cylinder(d) => d.get_gradient_at_position(pos),
-            CSGTreeNodeData::OffsetVoxelGrid(d) => todo!(),
-            CSGTreeNodeData::SharedVoxelGrid(d) => todo!(),
+            CSGTreeNodeData::Capsule(d) => d.get_gradient_at_position(pos),
+            CSGTreeNodeData::Torus(d) => d.get_gradient_at_position(pos),
+            CSGTreeNodeData::Cone(d) => d.get_gradient_at_position(pos),
+            CSGTreeNodeData::Plane(d) => d.get_gradient_at_position(pos),
+            CSGTreeNodeData::Wedge(d) => d.get_gradient_at_position(pos),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => <OffsetVoxelGrid as VolumeGradient<V::VectorF, D>>::get_gradient_at_position(d, pos),
+            CSGTreeNodeData::SharedVoxelGrid(d) => <SharedVoxelGrid as VolumeGradient<V::VectorF, D>>::get_gradient_at_position(d, pos),
+            CSGTreeNodeData::Heightmap(d) => <Heightmap as VolumeGradient<V::VectorF, D>>::get_gradient_at_position(d, pos),
         }
     }
 
+    // The closest child forms the surface.
     fn get_gradient_at_position_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V::VectorF) -> V::VectorF {
+        union.indecies.iter()
+            .map(|index| (*index, self.get_distance_index(*index, pos)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(V::VectorF::ZERO, |(index, _)| self.get_gradient_at_position_internal(index, pos))
+    }
 
-        let mut max_grad = V::VectorF::MAX;
-        for index in union.indecies.iter() {
-            let grad = self.get_gradient_at_position_internal(*index, pos);
+    // Where the removed shape dominates the surface is its inside, so the gradient flips.
+    fn get_gradient_at_position_union_remove(&self, remove: &CSGTreeRemove, pos: V::VectorF) -> V::VectorF {
+        let base = self.get_distance_index(remove.base, pos);
+        let cut = -self.get_distance_index(remove.remove, pos);
 
-            if grad.length_squared() < max_grad.length_squared() {
-                max_grad = grad;
-            }
+        if cut > base {
+            V::VectorF::ZERO - self.get_gradient_at_position_internal(remove.remove, pos)
+        } else {
+            self.get_gradient_at_position_internal(remove.base, pos)
         }
+    }
 
-        max_grad
+    // The closest copy forms the surface.
+    fn get_gradient_at_position_repeat(&self, repeat: &CSGTreeRepeat<V::VectorF, D>, pos: V::VectorF) -> V::VectorF {
+        repeat.get_copies_at(pos).into_iter()
+            .map(|copy| (copy, self.get_distance_index(repeat.child, repeat.get_local_pos_f(copy, pos))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(V::VectorF::ZERO, |(copy, _)| repeat.get_world_normal(copy, 
+                self.get_gradient_at_position_internal(repeat.child, repeat.get_local_pos_f(copy, pos))))
     }
 
-    fn get_gradient_at_position_union_remove(&self, remove: &CSGTreeRemove, pos: V::VectorF) -> V::VectorF {
-        todo!()        
+    // The child with the largest distance forms the surface.
+    fn get_gradient_at_position_intersect(&self, intersect: &CSGTreeIntersect, pos: V::VectorF) -> V::VectorF {
+        intersect.indecies.iter()
+            .map(|index| (*index, self.get_distance_index(*index, pos)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(V::VectorF::ZERO, |(index, _)| self.get_gradient_at_position_internal(index, pos))
+    }
+
+    // Central differences of the blended distance.
+    fn get_gradient_at_position_distance(&self, index: CSGTreeIndex, pos: V::VectorF) -> V::VectorF {
+        let mut grad = [0.0; D];
+        for i in 0..D {
+            let mut offset = [0.0; D];
+            offset[i] = GRADIENT_EPSILON;
+            let offset = V::VectorF::new(offset);
+
+            grad[i] = (self.get_distance_index(index, pos + offset) 
+                - self.get_distance_index(index, pos - offset)) / (2.0 * GRADIENT_EPSILON);
+        }
+
+        V::VectorF::new(grad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, volume::{VolumeBounds, VolumeGradient}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    #[test]
+    fn union() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        csg.union_sphere(vec3a(5.0, 0.0, 0.0), 4.0, 2);
+        csg.calculate_bounds();
+
+        assert!(csg.get_gradient_at_position(vec3a(10.0, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::X, 1e-5));
+        assert!(csg.get_gradient_at_position(vec3a(-7.0, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::NEG_X, 1e-5));
+    }
+
+    #[test]
+    fn cut() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        csg.cut_with_sphere(vec3a(6.0, 0.0, 0.0), 3.0, 0);
+        csg.calculate_bounds();
+
+        // Next to the hole the surface belongs to the cut sphere and faces into it.
+        assert!(csg.get_gradient_at_position(vec3a(2.5, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::X, 1e-5));
+        assert!(csg.get_gradient_at_position(vec3a(0.0, -5.5, 0.0)).normalize().abs_diff_eq(Vec3A::NEG_Y, 1e-5));
     }
 }
@@ -1,6 +1,6 @@
 use crate::{util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::{VolumeQureyAABB, VolumeQureyAABBResult}, voxel::palette::palette::MATERIAL_ID_NONE};
 
-use super::{remove::CSGTreeRemove, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
+use super::{intersect::CSGTreeIntersect, paint::CSGTreePaint, remove::CSGTreeRemove, repeat::CSGTreeRepeat, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
 
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for CSGTree<u8, V, T, D> {
     fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
@@ -16,12 +16,28 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
             CSGTreeNodeData::None => VolumeQureyAABBResult::Full(MATERIAL_ID_NONE),
             CSGTreeNodeData::Union(d) => self.get_aabb_value_union(d, aabb),
             CSGTreeNodeData::Cut(d) => self.get_aabb_value_remove(d, aabb),
+            CSGTreeNodeData::SmoothUnion(d) => self.get_aabb_value_smooth_union(d, aabb),
+            CSGTreeNodeData::SmoothCut(d) => self.get_aabb_value_smooth_remove(d, aabb),
+            CSGTreeNodeData::Intersect(d) => self.get_aabb_value_intersect(d, aabb),
+            CSGTreeNodeData::Transform(d) => self.get_aabb_value_index(d.child, d.get_local_aabb(aabb)),
+            CSGTreeNodeData::Repeat(d) => self.get_aabb_value_repeat(d, aabb),
+            // If the child is full or empty in the aabb grown by the amplitude the noise can not reach the aabb.
+            CSGTreeNodeData::Displace(d) => self.get_aabb_value_index(d.child, aabb.expand(d.get_band())),
+            CSGTreeNodeData::Paint(d) => self.get_aabb_value_paint(d, aabb),
 
             CSGTreeNodeData::Box(d) => d.get_aabb_value(aabb),
             CSGTreeNodeData::Sphere(d) => d.get_aabb_value(aabb),
             CSGTreeNodeData::Cylinder(d) => d.get_aabb_value(aabb),
-            CSGTreeNodeData::OffsetVoxelGrid(d) => todo!(),
-            CSGTreeNodeData::SharedVoxelGrid(d) => todo!(),
+            CSGTreeNodeData::Capsule(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Torus(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Cone(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Plane(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Wedge(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::SharedVoxelGrid(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Heightmap(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Extrude(d) => d.get_aabb_value(aabb),
+            CSGTreeNodeData::Revolve(d) => d.get_aabb_value(aabb),
         }
     }
 
@@ -73,4 +89,185 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
         if b != 0 { VolumeQureyAABBResult::Full(MATERIAL_ID_NONE) }
         else { VolumeQureyAABBResult::Full(a) }
     }
+
+    // Only the base decides what is empty. A full base stays full but gets mixed materials where the brush is mixed.
+    fn get_aabb_value_paint(&self, paint: &CSGTreePaint, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let base = self.get_aabb_value_index(paint.base, aabb);
+        if matches!(base, VolumeQureyAABBResult::Mixed) || base.get_value() == MATERIAL_ID_NONE {
+            return base;
+        }
+
+        match self.get_aabb_value_index(paint.brush, aabb) {
+            VolumeQureyAABBResult::Full(MATERIAL_ID_NONE) => base,
+            brush => brush,
+        }
+    }
+
+    fn get_aabb_value_intersect(&self, intersect: &CSGTreeIntersect, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        if intersect.indecies.is_empty() {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let mut mixed = false;
+        let mut value = MATERIAL_ID_NONE;
+        for (i, index) in intersect.indecies.iter().enumerate() {
+            match self.get_aabb_value_index(*index, aabb) {
+                VolumeQureyAABBResult::Full(MATERIAL_ID_NONE) => return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE),
+                VolumeQureyAABBResult::Full(v) => if i == 0 { value = v; },
+                VolumeQureyAABBResult::Mixed => mixed = true,
+            }
+        }
+
+        if mixed {
+            VolumeQureyAABBResult::Mixed
+        } else {
+            VolumeQureyAABBResult::Full(value)
+        }
+    }
+
+    // Too many copies in the aabb are not checked one by one, so the result stays O(1) in the repeat count.
+    fn get_aabb_value_repeat(&self, repeat: &CSGTreeRepeat<V::VectorF, D>, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let Some(copies) = repeat.get_copies_in(aabb.to_f()) else {
+            return VolumeQureyAABBResult::Mixed;
+        };
+
+        let mut mixed = false;
+        for copy in copies {
+            match self.get_aabb_value_index(repeat.child, repeat.get_local_aabb(copy, aabb)) {
+                VolumeQureyAABBResult::Full(MATERIAL_ID_NONE) => {},
+                VolumeQureyAABBResult::Mixed => mixed = true,
+                v => return v,
+            }
+        }
+
+        if mixed {
+            VolumeQureyAABBResult::Mixed
+        } else {
+            VolumeQureyAABBResult::Full(MATERIAL_ID_NONE)
+        }
+    }
+
+    // The smooth union contains the hard union and is contained in the hard union grown by the blend.
+    fn get_aabb_value_smooth_union(&self, union: &CSGTreeSmoothUnion, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        for index in union.indecies.iter() {
+            let v = self.get_aabb_value_index(*index, aabb);
+            if matches!(v, VolumeQureyAABBResult::Full(v) if v != MATERIAL_ID_NONE) {
+                return v;
+            }
+        }
+
+        let expanded = aabb.expand(T::from_f32(union.blend.ceil()));
+        let empty = union.indecies.iter()
+            .all(|index| matches!(self.get_aabb_value_index(*index, expanded), VolumeQureyAABBResult::Full(MATERIAL_ID_NONE)));
+
+        if empty {
+            VolumeQureyAABBResult::Full(MATERIAL_ID_NONE)
+        } else {
+            VolumeQureyAABBResult::Mixed
+        }
+    }
+
+    // The smooth cut is contained in the hard cut and contains the base shrunk by the blend minus the cut grown by the blend.
+    fn get_aabb_value_smooth_remove(&self, remove: &CSGTreeSmoothRemove, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let base = self.get_aabb_value_index(remove.base, aabb);
+        if matches!(base, VolumeQureyAABBResult::Full(MATERIAL_ID_NONE)) {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let cut = self.get_aabb_value_index(remove.remove, aabb);
+        if matches!(cut, VolumeQureyAABBResult::Full(v) if v != MATERIAL_ID_NONE) {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let expanded = aabb.expand(T::from_f32(remove.blend.ceil()));
+        let base = self.get_aabb_value_index(remove.base, expanded);
+        let cut = self.get_aabb_value_index(remove.remove, expanded);
+
+        match (base, cut) {
+            (VolumeQureyAABBResult::Full(v), VolumeQureyAABBResult::Full(MATERIAL_ID_NONE)) if v != MATERIAL_ID_NONE 
+                => VolumeQureyAABBResult::Full(v),
+            _ => VolumeQureyAABBResult::Mixed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::aabb::AABB, volume::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    fn aabb(min: [i32; 3], max: [i32; 3]) -> AABB<IVec3, i32, 3> {
+        AABB::new(IVec3::from_array(min), IVec3::from_array(max))
+    }
+
+    fn assert_full(csg: &Tree, aabb: AABB<IVec3, i32, 3>, mat: u8) {
+        assert!(matches!(csg.get_aabb_value(aabb), VolumeQureyAABBResult::Full(v) if v == mat), "{aabb:?} is not full of {mat}");
+    }
+
+    fn assert_mixed(csg: &Tree, aabb: AABB<IVec3, i32, 3>) {
+        assert!(matches!(csg.get_aabb_value(aabb), VolumeQureyAABBResult::Mixed), "{aabb:?} is not mixed");
+    }
+
+    #[test]
+    fn smooth_union() {
+        let mut csg = Tree::default();
+        let a = csg.add_sphere(vec3a(-8.0, 0.0, 0.0), 6.0, 1);
+        let b = csg.add_sphere(vec3a(8.0, 0.0, 0.0), 6.0, 2);
+        let union = csg.add_smooth_union_node(vec![a, b], 3.0);
+        csg.set_root(union);
+        csg.calculate_bounds();
+
+        assert_full(&csg, aabb([-9, -1, -1], [-7, 1, 1]), 1);
+        assert_full(&csg, aabb([7, -1, -1], [9, 1, 1]), 2);
+        assert_full(&csg, aabb([40, 40, 40], [42, 42, 42]), 0);
+        assert_mixed(&csg, aabb([-3, -1, -1], [-1, 1, 1]));
+
+        // Empty in the hard union, but the blend can fill the gap between the spheres.
+        assert_mixed(&csg, aabb([-1, -1, -1], [1, 1, 1]));
+    }
+
+    #[test]
+    fn smooth_cut() {
+        let mut csg = Tree::default();
+        let base = csg.add_sphere(Vec3A::ZERO, 12.0, 1);
+        let cut = csg.add_sphere(Vec3A::ZERO, 3.0, 2);
+        let remove = csg.add_smooth_cut_node(base, cut, 2.0);
+        csg.set_root(remove);
+        csg.calculate_bounds();
+
+        assert_full(&csg, aabb([-1, -1, -1], [1, 1, 1]), 0);
+        assert_full(&csg, aabb([6, -1, -1], [7, 1, 1]), 1);
+        assert_full(&csg, aabb([40, 40, 40], [42, 42, 42]), 0);
+
+        // Outside of the hard cut, but inside of the blend.
+        assert_mixed(&csg, aabb([3, -1, -1], [4, 1, 1]));
+    }
+
+    #[test]
+    fn intersect() {
+        let mut csg = Tree::default();
+        let a = csg.add_sphere(vec3a(-3.0, 0.0, 0.0), 6.0, 1);
+        let b = csg.add_sphere(vec3a(3.0, 0.0, 0.0), 6.0, 2);
+        let intersect = csg.add_intersect_node(vec![a, b]);
+        csg.set_root(intersect);
+        csg.calculate_bounds();
+
+        // The first child decides the material.
+        assert_full(&csg, aabb([-1, -1, -1], [1, 1, 1]), 1);
+        assert_full(&csg, aabb([-8, -1, -1], [-7, 1, 1]), 0);
+        assert_mixed(&csg, aabb([1, -1, -1], [3, 1, 1]));
+    }
+
+    #[test]
+    fn intersect_empty() {
+        let mut csg = Tree::default();
+        let intersect = csg.add_intersect_node(vec![]);
+        csg.set_root(intersect);
+        csg.calculate_bounds();
+
+        assert_full(&csg, aabb([-1, -1, -1], [1, 1, 1]), 0);
+    }
 }
@@ -0,0 +1,24 @@
+use super::tree::CSGTreeIndex;
+
+#[derive(Debug, Clone, Default)]
+pub struct CSGTreeIntersect {
+    pub indecies: Vec<CSGTreeIndex>,
+}
+
+impl CSGTreeIntersect {
+    pub fn new(indecies: Vec<CSGTreeIndex>) -> Self {
+        Self {
+            indecies,
+        }
+    }
+
+    pub fn add_node(&mut self, index: CSGTreeIndex) {
+        self.indecies.push(index);
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        for index in self.indecies.iter_mut() {
+            *(index) += ammount;
+        }
+    }
+}
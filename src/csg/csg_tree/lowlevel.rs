@@ -1,8 +1,8 @@
 use octa_force::glam::Mat4;
 
-use crate::{csg::{Base, csg_tree::tree::{CSGTree, CSGTreeNode}, primitves::{CSGPrimitive, PrimitiveType}}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
+use crate::{csg::{Base, csg_tree::tree::{CSGTree, CSGTreeNode}, primitves::{CSGPrimitive, PrimitiveType}}, util::{aabb::AABB, math_config::MC, noise::FractalNoiseSettings, number::Nu, vector::Ve}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
 
-use super::{remove::CSGTreeRemove, union::CSGTreeUnion};
+use super::{remove::CSGTreeRemove, repeat::CSGTreeRepeatKind, union::CSGTreeUnion};
 
 
 /**
@@ -32,7 +32,27 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
     }
 
     pub fn add_box(&mut self, center: V::VectorF, size: V::VectorF, mat: M) -> usize {
-        self.add_node(CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat))) 
+        self.add_node(CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat)))
+    }
+
+    pub fn add_capsule(&mut self, a: V::VectorF, b: V::VectorF, radius: f32, mat: M) -> usize {
+        self.add_node(CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat)))
+    }
+
+    pub fn add_torus(&mut self, center: V::VectorF, major_radius: f32, minor_radius: f32, mat: M) -> usize {
+        self.add_node(CSGTreeNode::new_torus(CSGPrimitive::new_torus(center, major_radius, minor_radius, mat)))
+    }
+
+    pub fn add_cone(&mut self, base: V::VectorF, radius: f32, height: f32, mat: M) -> usize {
+        self.add_node(CSGTreeNode::new_cone(CSGPrimitive::new_cone(base, radius, height, mat)))
+    }
+
+    pub fn add_plane(&mut self, pos: V::VectorF, normal: V::VectorF, mat: M) -> usize {
+        self.add_node(CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat)))
+    }
+
+    pub fn add_wedge(&mut self, center: V::VectorF, size: V::VectorF, mat: M) -> usize {
+        self.add_node(CSGTreeNode::new_wedge(CSGPrimitive::new_wedge(center, size, mat)))
     }
         
     pub fn add_node(&mut self, node: CSGTreeNode<M, V, T, D>) -> usize {
@@ -59,6 +79,62 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
         i
     }
 
+    pub fn add_intersect_node(&mut self, indecies: Vec<usize>) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_intersect(indecies)); 
+        i
+    }
+
+    pub fn add_transform_node(&mut self, child: usize, mat: <V::VectorF as MC<V::VectorF, f32, D>>::Matrix) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_transform(child, mat)); 
+        i
+    }
+
+    pub fn add_repeat_node(&mut self, child: usize, kind: CSGTreeRepeatKind<V::VectorF, D>) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_repeat(child, kind)); 
+        i
+    }
+
+    pub fn add_displace_node(&mut self, child: usize, settings: FractalNoiseSettings) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_displace(child, settings)); 
+        i
+    }
+
+    pub fn add_paint_node(&mut self, base: usize, brush: usize) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_paint(base, brush)); 
+        i
+    }
+
+    pub fn add_smooth_union_node(&mut self, indecies: Vec<usize>, blend: f32) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_smooth_union(indecies, blend)); 
+        i
+    }
+
+    pub fn add_smooth_cut_node(&mut self, base: usize, cut: usize, blend: f32) -> usize {
+        self.needs_bounds_recompute = true;
+
+        let i = self.nodes.len();
+        self.nodes.push(CSGTreeNode::new_smooth_cut(base, cut, blend)); 
+        i
+    }
+
     pub fn set_root(&mut self, root: usize) {
         self.needs_bounds_recompute = true;
 
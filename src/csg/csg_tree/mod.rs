@@ -3,10 +3,22 @@ pub mod new;
 pub mod add;
 pub mod union;
 pub mod remove;
+pub mod smooth;
+pub mod intersect;
+pub mod transform;
+pub mod repeat;
+pub mod displace;
+pub mod paint;
+pub mod extrude;
 pub mod aabb;
 pub mod pos_valid;
 pub mod pos_value;
 pub mod in_aabb;
 pub mod gradient;
+pub mod distance;
+pub mod ray;
 pub mod lowlevel;
 pub mod change;
+pub mod save;
+pub mod delete;
+pub mod optimize;
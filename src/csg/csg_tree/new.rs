@@ -1,10 +1,10 @@
 use std::any::TypeId;
 
-use octa_force::glam::{vec3, Mat4, Quat, Vec3};
+use octa_force::{glam::{vec3, Mat4, Quat, Vec3}, OctaResult};
 
-use crate::{csg::{Base, csg_tree::union, primitves::{CSGPrimitive, PrimitiveType, r#box::CSGBox, cylinder::CSGCylinder, sphere::CSGSphere}}, util::{number::Nu, vector::Ve}, voxel::grid::shared::SharedVoxelGrid};
+use crate::{csg::{Base, csg_tree::union, primitves::{CSGPrimitive, PrimitiveType, r#box::CSGBox, capsule::CSGCapsule, cone::CSGCone, cylinder::CSGCylinder, plane::CSGPlane, sphere::CSGSphere, torus::CSGTorus, wedge::CSGWedge}}, util::{math_config::MC, noise::FractalNoiseSettings, number::Nu, vector::Ve}, volume::heightmap::Heightmap, voxel::grid::shared::SharedVoxelGrid};
 
-use super::{remove::CSGTreeRemove, tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}, union::CSGTreeUnion};
+use super::{displace::CSGTreeDisplace, extrude::{CSGTreeExtrude, CSGTreeProfile, CSGTreeRevolve}, intersect::CSGTreeIntersect, paint::CSGTreePaint, remove::CSGTreeRemove, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, repeat::{CSGTreeRepeat, CSGTreeRepeatKind}, transform::CSGTreeTransform, tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}, union::CSGTreeUnion};
 
 
 impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
@@ -27,6 +27,26 @@ impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T,
     pub fn new_box_float(center: V::VectorF, size: V::VectorF, mat: M) -> Self {
         Self::from_node(CSGTreeNode::new_box(CSGPrimitive::new_box(center, size, mat)))
     }
+
+    pub fn new_capsule(a: V::VectorF, b: V::VectorF, radius: f32, mat: M) -> Self {
+        Self::from_node(CSGTreeNode::new_capsule(CSGPrimitive::new_capsule_from_a_to_b(a, b, radius, mat)))
+    }
+
+    pub fn new_torus(center: V::VectorF, major_radius: f32, minor_radius: f32, mat: M) -> Self {
+        Self::from_node(CSGTreeNode::new_torus(CSGPrimitive::new_torus(center, major_radius, minor_radius, mat)))
+    }
+
+    pub fn new_cone(base: V::VectorF, radius: f32, height: f32, mat: M) -> Self {
+        Self::from_node(CSGTreeNode::new_cone(CSGPrimitive::new_cone(base, radius, height, mat)))
+    }
+
+    pub fn new_plane(pos: V::VectorF, normal: V::VectorF, mat: M) -> Self {
+        Self::from_node(CSGTreeNode::new_plane(CSGPrimitive::new_plane(pos, normal, mat)))
+    }
+
+    pub fn new_wedge(center: V::VectorF, size: V::VectorF, mat: M) -> Self {
+        Self::from_node(CSGTreeNode::new_wedge(CSGPrimitive::new_wedge(center, size, mat)))
+    }
 }
 
 impl<M: Base + Send + Sync, V: Ve<T, 3>, T: Nu> CSGTree<M, V, T, 3> {
@@ -37,6 +57,18 @@ impl<M: Base + Send + Sync, V: Ve<T, 3>, T: Nu> CSGTree<M, V, T, 3> {
     pub fn new_shared_grid(grid: SharedVoxelGrid) -> Self {
         Self::from_node(CSGTreeNode::new_shared_grid(grid))
     }
+
+    pub fn new_heightmap(heightmap: Heightmap) -> Self {
+        Self::from_node(CSGTreeNode::new_heightmap(heightmap))
+    }
+
+    pub fn new_extrude(profile: CSGTreeProfile<M>, height: f32, taper: f32) -> OctaResult<Self> {
+        Ok(Self::from_node(CSGTreeNode::new_extrude(CSGTreeExtrude::new(profile, height, taper)?)))
+    }
+
+    pub fn new_revolve(profile: CSGTreeProfile<M>) -> OctaResult<Self> {
+        Ok(Self::from_node(CSGTreeNode::new_revolve(CSGTreeRevolve::new(profile)?)))
+    }
 }
 
 union CSGPrimitiveUnion<PA: PrimitiveType, PB: PrimitiveType, M: Copy, V: Ve<f32, D>, const D: usize> {
@@ -57,6 +89,16 @@ impl <M: Base, V: Ve<T, D>, T: Nu, const D: usize> CSGTreeNode<M, V, T, D> {
              CSGTreeNodeData::Box(unsafe { CSGPrimitiveUnion { a: p }.b })
         } else if TypeId::of::<P>() == TypeId::of::<CSGCylinder>() {
              CSGTreeNodeData::Cylinder(unsafe { CSGPrimitiveUnion { a: p }.b })
+        } else if TypeId::of::<P>() == TypeId::of::<CSGCapsule>() {
+             CSGTreeNodeData::Capsule(unsafe { CSGPrimitiveUnion { a: p }.b })
+        } else if TypeId::of::<P>() == TypeId::of::<CSGTorus>() {
+             CSGTreeNodeData::Torus(unsafe { CSGPrimitiveUnion { a: p }.b })
+        } else if TypeId::of::<P>() == TypeId::of::<CSGCone>() {
+             CSGTreeNodeData::Cone(unsafe { CSGPrimitiveUnion { a: p }.b })
+        } else if TypeId::of::<P>() == TypeId::of::<CSGPlane>() {
+             CSGTreeNodeData::Plane(unsafe { CSGPrimitiveUnion { a: p }.b })
+        } else if TypeId::of::<P>() == TypeId::of::<CSGWedge>() {
+             CSGTreeNodeData::Wedge(unsafe { CSGPrimitiveUnion { a: p }.b })
         } else {
             unreachable!()
         };
@@ -71,6 +113,26 @@ impl <M: Base, V: Ve<T, D>, T: Nu, const D: usize> CSGTreeNode<M, V, T, D> {
     pub fn new_box(p: CSGPrimitive<CSGBox, M, V::VectorF, D>) -> Self {
         CSGTreeNode::new(CSGTreeNodeData::Box(p), CSG_TREE_INDEX_INVALID)
     }
+
+    pub fn new_capsule(p: CSGPrimitive<CSGCapsule, M, V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Capsule(p), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_torus(p: CSGPrimitive<CSGTorus, M, V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Torus(p), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_cone(p: CSGPrimitive<CSGCone, M, V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Cone(p), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_plane(p: CSGPrimitive<CSGPlane, M, V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Plane(p), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_wedge(p: CSGPrimitive<CSGWedge, M, V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Wedge(p), CSG_TREE_INDEX_INVALID)
+    }
     
     pub fn new_union(nodes: Vec<CSGTreeIndex>) -> Self {
         CSGTreeNode::new(CSGTreeNodeData::Union(CSGTreeUnion::new(nodes)), CSG_TREE_INDEX_INVALID)
@@ -79,10 +141,50 @@ impl <M: Base, V: Ve<T, D>, T: Nu, const D: usize> CSGTreeNode<M, V, T, D> {
     pub fn new_cut(base: CSGTreeIndex, cut: CSGTreeIndex) -> Self {
         CSGTreeNode::new(CSGTreeNodeData::Cut(CSGTreeRemove::new(base, cut)), CSG_TREE_INDEX_INVALID)
     }
+
+    pub fn new_intersect(nodes: Vec<CSGTreeIndex>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Intersect(CSGTreeIntersect::new(nodes)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_transform(child: CSGTreeIndex, mat: <V::VectorF as MC<V::VectorF, f32, D>>::Matrix) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Transform(CSGTreeTransform::new(child, mat)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_repeat(child: CSGTreeIndex, kind: CSGTreeRepeatKind<V::VectorF, D>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Repeat(CSGTreeRepeat::new(child, kind)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_displace(child: CSGTreeIndex, settings: FractalNoiseSettings) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Displace(CSGTreeDisplace::new(child, settings)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_paint(base: CSGTreeIndex, brush: CSGTreeIndex) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Paint(CSGTreePaint::new(base, brush)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_smooth_union(nodes: Vec<CSGTreeIndex>, blend: f32) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::SmoothUnion(CSGTreeSmoothUnion::new(nodes, blend)), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_smooth_cut(base: CSGTreeIndex, cut: CSGTreeIndex, blend: f32) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::SmoothCut(CSGTreeSmoothRemove::new(base, cut, blend)), CSG_TREE_INDEX_INVALID)
+    }
 }
 
 impl <M: Base, V: Ve<T, 3>, T: Nu> CSGTreeNode<M, V, T, 3> {
     pub fn new_shared_grid(grid: SharedVoxelGrid) -> Self {
         CSGTreeNode::new(CSGTreeNodeData::SharedVoxelGrid(grid), CSG_TREE_INDEX_INVALID)
     }
+
+    pub fn new_heightmap(heightmap: Heightmap) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Heightmap(heightmap), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_extrude(extrude: CSGTreeExtrude<M>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Extrude(extrude), CSG_TREE_INDEX_INVALID)
+    }
+
+    pub fn new_revolve(revolve: CSGTreeRevolve<M>) -> Self {
+        CSGTreeNode::new(CSGTreeNodeData::Revolve(revolve), CSG_TREE_INDEX_INVALID)
+    }
 } 
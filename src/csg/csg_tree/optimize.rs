@@ -0,0 +1,323 @@
+use std::{collections::HashMap, mem, sync::Arc};
+
+use crate::{csg::{Base, primitves::{CSGPrimitive, PrimitiveType}}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::Ve}};
+
+use super::tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID};
+
+impl<M: Base + Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
+    /**
+    Simplifies the tree without changing the volume:
+    - Nested unions are flattened into one union.
+    - None nodes are removed.
+    - Unions with a single child are replaced by the child.
+    - Cuts and paints that do not touch their base are replaced by the base.
+    - Identical primitives in a union are only kept once.
+
+    Dead nodes get compacted afterwards, so all indecies change.
+    Returns the number of removed nodes.
+    */
+    pub fn optimize(&mut self) -> usize {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+
+        // Dead nodes that were already in the tree count as removed too.
+        let before = self.nodes.len();
+        self.compact();
+
+        self.root = match self.optimize_index(self.root) {
+            Some(root) => root,
+            None => {
+                self.nodes.push(CSGTreeNode::new_none());
+                self.nodes.len() - 1
+            },
+        };
+        self.nodes[self.root].parent = CSG_TREE_INDEX_INVALID;
+
+        self.compact();
+
+        before.saturating_sub(self.nodes.len())
+    }
+
+    // Returns the index that replaces the subtree or None if the subtree is empty.
+    fn optimize_index(&mut self, index: CSGTreeIndex) -> Option<CSGTreeIndex> {
+        match &self.nodes[index].data {
+            CSGTreeNodeData::None => None,
+            CSGTreeNodeData::Union(d) => {
+                let children = d.indecies.clone();
+
+                let mut indecies = vec![];
+                for child in children {
+                    let Some(child) = self.optimize_index(child) else { continue; };
+
+                    if let CSGTreeNodeData::Union(union) = &self.nodes[child].data {
+                        indecies.extend_from_slice(&union.indecies);
+                    } else {
+                        indecies.push(child);
+                    }
+                }
+
+                let indecies = self.dedup_primitives(indecies);
+                match indecies.len() {
+                    0 => None,
+                    1 => Some(indecies[0]),
+                    _ => {
+                        for child in indecies.iter() {
+                            self.nodes[*child].parent = index;
+                        }
+
+                        let CSGTreeNodeData::Union(union) = &mut self.nodes[index].data else { unreachable!() };
+                        union.indecies = indecies;
+                        union.needs_bounds_recompute = true;
+                        self.calculate_bounds_index(index);
+
+                        Some(index)
+                    },
+                }
+            },
+            CSGTreeNodeData::Cut(d) => {
+                let (base, remove) = (d.base, d.remove);
+                self.optimize_cut(index, base, remove, 0.0)
+            },
+            CSGTreeNodeData::SmoothCut(d) => {
+                let (base, remove, blend) = (d.base, d.remove, d.blend);
+                self.optimize_cut(index, base, remove, blend)
+            },
+            CSGTreeNodeData::Paint(d) => {
+                let (base, brush) = (d.base, d.brush);
+                self.optimize_cut(index, base, brush, 0.0)
+            },
+            CSGTreeNodeData::SmoothUnion(d) => {
+                let children = d.indecies.clone();
+                let indecies = self.optimize_children(children, index, false)?;
+                if indecies.len() == 1 {
+                    return Some(indecies[0]);
+                }
+
+                let CSGTreeNodeData::SmoothUnion(union) = &mut self.nodes[index].data else { unreachable!() };
+                union.indecies = indecies;
+                Some(index)
+            },
+            CSGTreeNodeData::Intersect(d) => {
+                let children = d.indecies.clone();
+                let indecies = self.optimize_children(children, index, true)?;
+                if indecies.len() == 1 {
+                    return Some(indecies[0]);
+                }
+
+                let CSGTreeNodeData::Intersect(intersect) = &mut self.nodes[index].data else { unreachable!() };
+                intersect.indecies = indecies;
+                Some(index)
+            },
+            CSGTreeNodeData::Transform(d) => {
+                let child = self.optimize_index(d.child)?;
+                self.nodes[child].parent = index;
+
+                let CSGTreeNodeData::Transform(transform) = &mut self.nodes[index].data else { unreachable!() };
+                transform.child = child;
+                Some(index)
+            },
+            CSGTreeNodeData::Repeat(d) => {
+                let child = self.optimize_index(d.child)?;
+                self.nodes[child].parent = index;
+
+                let CSGTreeNodeData::Repeat(repeat) = &mut self.nodes[index].data else { unreachable!() };
+                repeat.child = child;
+                self.calculate_bounds_index(index);
+                Some(index)
+            },
+            CSGTreeNodeData::Displace(d) => {
+                let child = self.optimize_index(d.child)?;
+                self.nodes[child].parent = index;
+
+                let CSGTreeNodeData::Displace(displace) = &mut self.nodes[index].data else { unreachable!() };
+                displace.child = child;
+                Some(index)
+            },
+            _ => Some(index),
+        }
+    }
+
+    // For intersections a single empty child makes the whole node empty.
+    fn optimize_children(&mut self, children: Vec<CSGTreeIndex>, parent: CSGTreeIndex, all_needed: bool) -> Option<Vec<CSGTreeIndex>> {
+        let mut indecies = vec![];
+        for child in children {
+            match self.optimize_index(child) {
+                Some(child) => {
+                    self.nodes[child].parent = parent;
+                    indecies.push(child);
+                },
+                None if all_needed => return None,
+                None => {},
+            }
+        }
+
+        if indecies.is_empty() {
+            None
+        } else {
+            Some(indecies)
+        }
+    }
+
+    fn optimize_cut(&mut self, index: CSGTreeIndex, base: CSGTreeIndex, remove: CSGTreeIndex, blend: f32) -> Option<CSGTreeIndex> {
+        let base = self.optimize_index(base)?;
+        let Some(remove) = self.optimize_index(remove) else { return Some(base); };
+
+        self.calculate_bounds_index(base);
+        self.calculate_bounds_index(remove);
+        let base_bounds = self.get_bounds_index(base);
+        let remove_bounds = self.get_bounds_index(remove).expand(T::from_f32(blend.ceil()));
+        if !touches(base_bounds, remove_bounds) {
+            return Some(base);
+        }
+
+        self.nodes[base].parent = index;
+        self.nodes[remove].parent = index;
+        match &mut self.nodes[index].data {
+            CSGTreeNodeData::Cut(d) => {
+                d.base = base;
+                d.remove = remove;
+            },
+            CSGTreeNodeData::SmoothCut(d) => {
+                d.base = base;
+                d.remove = remove;
+            },
+            CSGTreeNodeData::Paint(d) => {
+                d.base = base;
+                d.brush = remove;
+            },
+            _ => unreachable!()
+        }
+
+        Some(index)
+    }
+
+    // Primitives are bucketed by type and matrix so only candidates get compared.
+    fn dedup_primitives(&self, indecies: Vec<CSGTreeIndex>) -> Vec<CSGTreeIndex> {
+        let mut buckets: HashMap<_, Vec<CSGTreeIndex>> = HashMap::new();
+
+        indecies.into_iter()
+            .filter(|index| {
+                let data = &self.nodes[*index].data;
+                let Some(mat) = leaf_matrix_key(data) else { return true; };
+
+                let bucket = buckets.entry((mem::discriminant(data), mat)).or_default();
+                if bucket.iter().any(|other| same_leaf(data, &self.nodes[*other].data)) {
+                    return false;
+                }
+
+                bucket.push(*index);
+                true
+            })
+            .collect()
+    }
+}
+
+// Inclusive overlap, touching bounds still count.
+fn touches<V: Ve<T, D>, T: Nu, const D: usize>(a: AABB<V, T, D>, b: AABB<V, T, D>) -> bool {
+    (0..D).all(|i| a.min()[i] <= b.max()[i] && b.min()[i] <= a.max()[i])
+}
+
+fn matrix_key<MA: Ma<D>, const D: usize>(mat: &MA) -> Vec<u32> {
+    (0..=D).flat_map(|col| (0..=D).map(move |row| mat.index(col, row).to_bits())).collect()
+}
+
+fn leaf_matrix_key<M, V: Ve<T, D>, T: Nu, const D: usize>(data: &CSGTreeNodeData<M, V, T, D>) -> Option<Vec<u32>> {
+    match data {
+        CSGTreeNodeData::Box(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Sphere(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Cylinder(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Capsule(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Torus(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Cone(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Plane(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::Wedge(d) => Some(matrix_key(&d.get_mat())),
+        CSGTreeNodeData::SharedVoxelGrid(d) => Some(matrix_key::<_, 3>(&d.get_mat())),
+        _ => None,
+    }
+}
+
+fn same_leaf<M: Base, V: Ve<T, D>, T: Nu, const D: usize>(a: &CSGTreeNodeData<M, V, T, D>, b: &CSGTreeNodeData<M, V, T, D>) -> bool {
+    match (a, b) {
+        (CSGTreeNodeData::Box(a), CSGTreeNodeData::Box(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Sphere(a), CSGTreeNodeData::Sphere(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Cylinder(a), CSGTreeNodeData::Cylinder(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Capsule(a), CSGTreeNodeData::Capsule(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Torus(a), CSGTreeNodeData::Torus(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Cone(a), CSGTreeNodeData::Cone(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Plane(a), CSGTreeNodeData::Plane(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::Wedge(a), CSGTreeNodeData::Wedge(b)) => same_primitive(a, b),
+        (CSGTreeNodeData::SharedVoxelGrid(a), CSGTreeNodeData::SharedVoxelGrid(b)) =>
+            Arc::ptr_eq(&a.grid, &b.grid) && a.offset == b.offset && a.get_mat() == b.get_mat(),
+        _ => false,
+    }
+}
+
+fn same_primitive<P: PrimitiveType, M: Base, V: Ve<f32, D>, const D: usize>(a: &CSGPrimitive<P, M, V, D>, b: &CSGPrimitive<P, M, V, D>) -> bool {
+    a.get_primitive() == b.get_primitive()
+        && a.get_material() == b.get_material()
+        && matrix_key(&a.get_mat()) == matrix_key(&b.get_mat())
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::{CSGTree, CSGTreeNode, CSGTreeNodeData}, volume::{VolumeBounds, VolumeQureyPosValue}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    #[test]
+    fn keeps_values() {
+        let mut csg = Tree::default();
+        let a = csg.add_sphere(Vec3A::ZERO, 5.0, 1);
+        let b = csg.add_sphere(vec3a(6.0, 0.0, 0.0), 3.0, 2);
+        let duplicate = csg.add_sphere(Vec3A::ZERO, 5.0, 1);
+        let inner = csg.add_union_node(vec![a, b, duplicate]);
+        let none = csg.add_node(CSGTreeNode::new_none());
+
+        let c = csg.add_sphere(vec3a(-6.0, 0.0, 0.0), 3.0, 3);
+        let far = csg.add_sphere(vec3a(100.0, 0.0, 0.0), 2.0, 0);
+        let far_cut = csg.add_cut_node(c, far);
+        let near = csg.add_sphere(vec3a(-8.0, 0.0, 0.0), 2.0, 0);
+        let near_cut = csg.add_cut_node(far_cut, near);
+
+        let root = csg.add_union_node(vec![inner, none, near_cut]);
+        csg.set_root(root);
+        csg.calculate_bounds();
+        let original = csg.clone();
+
+        // The duplicate, the nested union, the none node and the far cut together with its sphere.
+        assert_eq!(csg.optimize(), 5);
+        assert_eq!(csg.nodes.len(), 6);
+        csg.calculate_bounds();
+
+        let CSGTreeNodeData::Union(union) = &csg.nodes[csg.root].data else {
+            panic!("Root is not a union");
+        };
+        assert_eq!(union.indecies.len(), 3);
+        let cuts = csg.nodes.iter().filter(|node| matches!(node.data, CSGTreeNodeData::Cut(_))).count();
+        assert_eq!(cuts, 1);
+
+        for x in -12..=12 {
+            for y in -6..=6 {
+                for z in -6..=6 {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(csg.get_value(pos), original.get_value(pos), "Value at {pos} differs");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_tree() {
+        let mut csg = Tree::default();
+        let none = csg.add_node(CSGTreeNode::new_none());
+        let union = csg.add_union_node(vec![none]);
+        csg.set_root(union);
+
+        assert_eq!(csg.optimize(), 1);
+        assert_eq!(csg.nodes.len(), 1);
+        assert!(matches!(csg.nodes[csg.root].data, CSGTreeNodeData::None));
+    }
+}
@@ -0,0 +1,80 @@
+use super::tree::CSGTreeIndex;
+
+// Keeps the shape of base and takes the material of brush where brush is solid.
+#[derive(Debug, Clone, Default)]
+pub struct CSGTreePaint {
+    pub base: CSGTreeIndex,
+    pub brush: CSGTreeIndex,
+}
+
+impl CSGTreePaint {
+    pub fn new(base: CSGTreeIndex, brush: CSGTreeIndex) -> Self {
+        Self {
+            base,
+            brush,
+        }
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        self.base += ammount;
+        self.brush += ammount;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::aabb::AABB, volume::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    // A box from -10 to 10 with its top from z = 6 painted.
+    fn new_painted() -> Tree {
+        let mut csg = Tree::new_box_float(Vec3A::ZERO, Vec3A::splat(20.0), 1);
+        csg.paint_with_box(vec3a(0.0, 0.0, 8.0), vec3a(20.0, 20.0, 4.0), 2);
+        csg.calculate_bounds();
+        csg
+    }
+
+    #[test]
+    fn values() {
+        let csg = new_painted();
+        assert_eq!(csg.get_value(IVec3::new(0, 0, 0)), 1);
+        assert_eq!(csg.get_value(IVec3::new(3, -3, 8)), 2);
+        assert_eq!(csg.get_value(IVec3::new(0, 0, 12)), 0);
+        assert_eq!(csg.get_value(IVec3::new(12, 0, 8)), 0);
+    }
+
+    #[test]
+    fn aabb_values() {
+        let csg = new_painted();
+        let value = |min: IVec3, max: IVec3| csg.get_aabb_value(AABB::new(min, max));
+
+        assert!(matches!(value(IVec3::splat(-4), IVec3::splat(4)), VolumeQureyAABBResult::Full(1)));
+        assert!(matches!(value(IVec3::new(-4, -4, 7), IVec3::new(4, 4, 9)), VolumeQureyAABBResult::Full(2)));
+        assert!(matches!(value(IVec3::new(-4, -4, 4), IVec3::new(4, 4, 8)), VolumeQureyAABBResult::Mixed));
+        assert!(matches!(value(IVec3::new(-4, -4, 8), IVec3::new(4, 4, 12)), VolumeQureyAABBResult::Mixed));
+        assert!(matches!(value(IVec3::splat(12), IVec3::splat(14)), VolumeQureyAABBResult::Full(0)));
+    }
+
+    #[test]
+    fn aabb_matches_values() {
+        let csg = new_painted();
+        for x in (-12..12).step_by(4) {
+            for z in (-12..12).step_by(4) {
+                let min = IVec3::new(x, 0, z);
+                let VolumeQureyAABBResult::Full(mat) = csg.get_aabb_value(AABB::new(min, min + 4)) else {
+                    continue;
+                };
+
+                for px in 0..4 {
+                    for pz in 0..4 {
+                        let pos = min + IVec3::new(px, 0, pz);
+                        assert_eq!(csg.get_value(pos), mat, "{pos} does not match its full block");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,35 +1,51 @@
-use crate::{util::{number::Nu, vector::Ve}, volume::VolumeQureyPosValid};
+use crate::{util::{number::Nu, vector::Ve}, volume::{heightmap::Heightmap, VolumeQureyPosValid}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
 
-use super::{remove::CSGTreeRemove, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
+use super::{displace::CSGTreeDisplace, extrude::{CSGTreeExtrude, CSGTreeRevolve}, intersect::CSGTreeIntersect, remove::CSGTreeRemove, repeat::CSGTreeRepeat, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, transform::from_vecf_rounded, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
 
 
 impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for CSGTree<M, V, T, D> {
     fn is_position_valid(&self, pos: V) -> bool {
-        self.is_position_valid_index(self.root, pos)
+        self.is_position_valid_index(self.root, pos.to_vecf())
     }
 }
 
+// Like get_value the position stays a float and only voxel grids round it.
 impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
-    fn is_position_valid_index(&self, index: CSGTreeIndex, pos: V) -> bool {
+    fn is_position_valid_index(&self, index: CSGTreeIndex, pos: V::VectorF) -> bool {
         let node = &self.nodes[index];
         match &node.data {
             CSGTreeNodeData::None => false,
             CSGTreeNodeData::Union(d) => self.is_position_valid_union(d, pos),
             CSGTreeNodeData::Cut(d) => self.is_position_valid_remove(d, pos),
+            CSGTreeNodeData::SmoothUnion(d) => self.is_position_valid_smooth_union(d, pos),
+            CSGTreeNodeData::SmoothCut(d) => self.is_position_valid_smooth_remove(d, pos),
+            CSGTreeNodeData::Intersect(d) => self.is_position_valid_intersect(d, pos),
+            CSGTreeNodeData::Transform(d) => self.is_position_valid_index(d.child, d.get_local_pos_f(pos)),
+            CSGTreeNodeData::Repeat(d) => self.is_position_valid_repeat(d, pos),
+            CSGTreeNodeData::Displace(d) => self.is_position_valid_displace(d, pos),
+            CSGTreeNodeData::Paint(d) => self.is_position_valid_index(d.base, pos),
             
-            CSGTreeNodeData::Box(d) => d.is_position_valid(pos),
-            CSGTreeNodeData::Sphere(d) => d.is_position_valid(pos),
-            CSGTreeNodeData::Cylinder(d) => d.is_position_valid(pos),
-            CSGTreeNodeData::OffsetVoxelGrid(d) => todo!(),
-            CSGTreeNodeData::SharedVoxelGrid(d) => todo!(),
+            CSGTreeNodeData::Box(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Sphere(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Cylinder(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Capsule(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Torus(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Cone(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Plane(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::Wedge(d) => d.is_position_valid_f(pos),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => <OffsetVoxelGrid as VolumeQureyPosValid<V, T, D>>::is_position_valid(d, from_vecf_rounded(pos, f32::round)),
+            CSGTreeNodeData::SharedVoxelGrid(d) => <SharedVoxelGrid as VolumeQureyPosValid<V, T, D>>::is_position_valid(d, from_vecf_rounded(pos, f32::round)),
+            CSGTreeNodeData::Heightmap(d) => <Heightmap as VolumeQureyPosValid<V::VectorF, f32, D>>::is_position_valid(d, pos),
+            CSGTreeNodeData::Extrude(d) => <CSGTreeExtrude<M> as VolumeQureyPosValid<V::VectorF, f32, D>>::is_position_valid(d, pos),
+            CSGTreeNodeData::Revolve(d) => <CSGTreeRevolve<M> as VolumeQureyPosValid<V::VectorF, f32, D>>::is_position_valid(d, pos),
         }
     }
 
-    fn is_position_valid_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V) -> bool {
+    fn is_position_valid_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V::VectorF) -> bool {
         let mut i = 0;
         while i < union.bvh.nodes.len() {
             let b = &union.bvh.nodes[i];
-            if b.aabb.pos_in_aabb(pos) {
+            if b.aabb.to_f::<V::VectorF>().pos_in_aabb(pos) {
                 if let Some(leaf) = b.leaf {
                     let v = self.is_position_valid_index(leaf, pos); 
                     if v {
@@ -46,10 +62,34 @@ impl<M: Send + Sync, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
         false
     }
 
-    fn is_position_valid_remove(&self, remove: &CSGTreeRemove, pos: V) -> bool {
+    fn is_position_valid_remove(&self, remove: &CSGTreeRemove, pos: V::VectorF) -> bool {
         let base = self.is_position_valid_index(remove.base, pos);
         let remove = self.is_position_valid_index(remove.remove, pos);
 
         base && !remove
     }
+
+    fn is_position_valid_intersect(&self, intersect: &CSGTreeIntersect, pos: V::VectorF) -> bool {
+        !intersect.indecies.is_empty() 
+        && intersect.indecies.iter().all(|index| self.is_position_valid_index(*index, pos))
+    }
+
+    fn is_position_valid_smooth_union(&self, union: &CSGTreeSmoothUnion, pos: V::VectorF) -> bool {
+        union.indecies.iter().any(|index| self.is_position_valid_index(*index, pos))
+        || self.get_distance_smooth_union(union, pos) < 0.0
+    }
+
+    fn is_position_valid_repeat(&self, repeat: &CSGTreeRepeat<V::VectorF, D>, pos: V::VectorF) -> bool {
+        repeat.get_copies_at(pos).into_iter()
+            .any(|copy| self.is_position_valid_index(repeat.child, repeat.get_local_pos_f(copy, pos)))
+    }
+
+    fn is_position_valid_displace(&self, displace: &CSGTreeDisplace, pos: V::VectorF) -> bool {
+        self.get_distance_index(displace.child, pos) + displace.get_offset(pos) < 0.0
+    }
+
+    fn is_position_valid_smooth_remove(&self, remove: &CSGTreeSmoothRemove, pos: V::VectorF) -> bool {
+        self.is_position_valid_index(remove.base, pos)
+        && self.get_distance_smooth_remove(remove, pos) < 0.0
+    }
 }
@@ -1,35 +1,54 @@
-use crate::{util::{number::Nu, vector::Ve}, volume::VolumeQureyPosValue, voxel::palette::palette::MATERIAL_ID_NONE};
+use crate::{util::{number::Nu, vector::Ve}, volume::{heightmap::Heightmap, VolumeQureyPosValue}, voxel::{grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}, palette::palette::MATERIAL_ID_NONE}};
 
-use super::{remove::CSGTreeRemove, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
+use super::{displace::CSGTreeDisplace, distance::closer_material, extrude::{CSGTreeExtrude, CSGTreeRevolve}, intersect::CSGTreeIntersect, paint::CSGTreePaint, remove::CSGTreeRemove, repeat::CSGTreeRepeat, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, transform::from_vecf_rounded, tree::{CSGTreeNodeData, CSGTree, CSGTreeIndex}, union::CSGTreeUnion};
 
 
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for CSGTree<u8, V, T, D> { 
     fn get_value(&self, pos: V) -> u8 {
-        self.get_value_index(self.root, pos)
+        self.get_value_index(self.root, pos.to_vecf())
     }
 }
 
+/**
+The position stays a float below the root, so transformed and repeated subtrees are sampled where the position really is.
+Only voxel grids round it to their voxels.
+*/
 impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
-    fn get_value_index(&self, index: CSGTreeIndex, pos: V) -> u8 {
+    fn get_value_index(&self, index: CSGTreeIndex, pos: V::VectorF) -> u8 {
         let node = &self.nodes[index];
         match &node.data {
             CSGTreeNodeData::None => 0,
             CSGTreeNodeData::Union(d) => self.get_value_union(d, pos),
             CSGTreeNodeData::Cut(d) => self.get_value_remove(d, pos),
+            CSGTreeNodeData::SmoothUnion(d) => self.get_value_smooth_union(d, pos),
+            CSGTreeNodeData::SmoothCut(d) => self.get_value_smooth_remove(d, pos),
+            CSGTreeNodeData::Intersect(d) => self.get_value_intersect(d, pos),
+            CSGTreeNodeData::Transform(d) => self.get_value_index(d.child, d.get_local_pos_f(pos)),
+            CSGTreeNodeData::Repeat(d) => self.get_value_repeat(d, pos),
+            CSGTreeNodeData::Displace(d) => self.get_value_displace(d, pos),
+            CSGTreeNodeData::Paint(d) => self.get_value_paint(d, pos),
             
-            CSGTreeNodeData::Box(d) => d.get_value(pos),
-            CSGTreeNodeData::Sphere(d) => d.get_value(pos),
-            CSGTreeNodeData::Cylinder(d) => d.get_value(pos),
-            CSGTreeNodeData::OffsetVoxelGrid(d) => d.get_value(pos),
-            CSGTreeNodeData::SharedVoxelGrid(d) => d.get_value(pos),
+            CSGTreeNodeData::Box(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Sphere(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Cylinder(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Capsule(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Torus(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Cone(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Plane(d) => d.get_value_f(pos),
+            CSGTreeNodeData::Wedge(d) => d.get_value_f(pos),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => <OffsetVoxelGrid as VolumeQureyPosValue<V, T, D>>::get_value(d, from_vecf_rounded(pos, f32::round)),
+            CSGTreeNodeData::SharedVoxelGrid(d) => <SharedVoxelGrid as VolumeQureyPosValue<V, T, D>>::get_value(d, from_vecf_rounded(pos, f32::round)),
+            CSGTreeNodeData::Heightmap(d) => <Heightmap as VolumeQureyPosValue<V::VectorF, f32, D>>::get_value(d, pos),
+            CSGTreeNodeData::Extrude(d) => <CSGTreeExtrude<u8> as VolumeQureyPosValue<V::VectorF, f32, D>>::get_value(d, pos),
+            CSGTreeNodeData::Revolve(d) => <CSGTreeRevolve<u8> as VolumeQureyPosValue<V::VectorF, f32, D>>::get_value(d, pos),
         }
     }
 
-    fn get_value_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V) -> u8 {
+    fn get_value_union(&self, union: &CSGTreeUnion<V, T, D>, pos: V::VectorF) -> u8 {
         let mut i = 0;
         while i < union.bvh.nodes.len() {
             let b = &union.bvh.nodes[i];
-            if b.aabb.pos_in_aabb(pos) {
+            if b.aabb.to_f::<V::VectorF>().pos_in_aabb(pos) {
                 if let Some(leaf) = b.leaf {
                     let v = self.get_value_index(leaf, pos); 
                     if v != MATERIAL_ID_NONE {
@@ -46,7 +65,7 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
         MATERIAL_ID_NONE
     }
 
-    fn get_value_remove(&self, remove: &CSGTreeRemove, pos: V) -> u8 {
+    fn get_value_remove(&self, remove: &CSGTreeRemove, pos: V::VectorF) -> u8 {
         let base = self.get_value_index(remove.base, pos);
         let remove = self.get_value_index(remove.remove, pos);
 
@@ -56,4 +75,81 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
             base
         }
     }
+
+    // The material is taken from the first child.
+    fn get_value_intersect(&self, intersect: &CSGTreeIntersect, pos: V::VectorF) -> u8 {
+        let mut value = MATERIAL_ID_NONE;
+        for (i, index) in intersect.indecies.iter().enumerate() {
+            let v = self.get_value_index(*index, pos);
+            if v == MATERIAL_ID_NONE {
+                return MATERIAL_ID_NONE;
+            }
+
+            if i == 0 {
+                value = v;
+            }
+        }
+
+        value
+    }
+
+    fn get_value_repeat(&self, repeat: &CSGTreeRepeat<V::VectorF, D>, pos: V::VectorF) -> u8 {
+        repeat.get_copies_at(pos).into_iter()
+            .map(|copy| self.get_value_index(repeat.child, repeat.get_local_pos_f(copy, pos)))
+            .find(|v| *v != MATERIAL_ID_NONE)
+            .unwrap_or(MATERIAL_ID_NONE)
+    }
+
+    // Where the noise grows the child the material of the closest surface is used.
+    fn get_value_displace(&self, displace: &CSGTreeDisplace, pos: V::VectorF) -> u8 {
+        if self.get_distance_index(displace.child, pos) + displace.get_offset(pos) >= 0.0 {
+            return MATERIAL_ID_NONE;
+        }
+
+        let v = self.get_value_index(displace.child, pos);
+        if v != MATERIAL_ID_NONE {
+            return v;
+        }
+
+        self.get_distance_value_index(displace.child, pos).1
+    }
+
+    fn get_value_paint(&self, paint: &CSGTreePaint, pos: V::VectorF) -> u8 {
+        let base = self.get_value_index(paint.base, pos);
+        if base == MATERIAL_ID_NONE {
+            return MATERIAL_ID_NONE;
+        }
+
+        let brush = self.get_value_index(paint.brush, pos);
+        if brush != MATERIAL_ID_NONE { brush } else { base }
+    }
+
+    // Inside of a child the hard union decides the material so it matches get_aabb_value.
+    // Only the blend zone takes the material of the closest child.
+    fn get_value_smooth_union(&self, union: &CSGTreeSmoothUnion, pos: V::VectorF) -> u8 {
+        for index in union.indecies.iter() {
+            let v = self.get_value_index(*index, pos);
+            if v != MATERIAL_ID_NONE {
+                return v;
+            }
+        }
+
+        if self.get_distance_smooth_union(union, pos) >= 0.0 {
+            return MATERIAL_ID_NONE;
+        }
+
+        union.indecies.iter()
+            .map(|index| self.get_distance_value_index(*index, pos))
+            .reduce(|a, b| closer_material(a, b, a.0.min(b.0)))
+            .map_or(MATERIAL_ID_NONE, |(_, mat)| mat)
+    }
+
+    fn get_value_smooth_remove(&self, remove: &CSGTreeSmoothRemove, pos: V::VectorF) -> u8 {
+        let base = self.get_value_index(remove.base, pos);
+        if base == MATERIAL_ID_NONE || self.get_distance_smooth_remove(remove, pos) >= 0.0 {
+            MATERIAL_ID_NONE
+        } else {
+            base
+        }
+    }
 }
@@ -0,0 +1,155 @@
+use crate::{csg::primitves::normalize_or_zero, util::{number::Nu, vector::Ve}, volume::{VolumeQureyRay, VolumeRayHit}, voxel::palette::palette::MATERIAL_ID_NONE};
+
+use super::{paint::CSGTreePaint, tree::{CSGTree, CSGTreeIndex, CSGTreeNodeData}, union::CSGTreeUnion};
+
+const RAY_MAX_STEPS: usize = 256;
+const RAY_BISECT_STEPS: usize = 8;
+const RAY_EPSILON: f32 = 0.01;
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyRay<V::VectorF, D> for CSGTree<u8, V, T, D> {
+    fn get_ray_hit(&self, origin: V::VectorF, dir: V::VectorF, max_t: f32) -> Option<VolumeRayHit<V::VectorF>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        self.get_ray_hit_index(self.root, origin, dir, 0.0, max_t)
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> CSGTree<u8, V, T, D> {
+    // The hit is in the space of the node. Nodes without a closed form get sphere traced.
+    fn get_ray_hit_index(&self, index: CSGTreeIndex, origin: V::VectorF, dir: V::VectorF, min: f32, max: f32) -> Option<VolumeRayHit<V::VectorF>> {
+        let node = &self.nodes[index];
+        match &node.data {
+            CSGTreeNodeData::None => None,
+            CSGTreeNodeData::Union(d) => self.get_ray_hit_union(d, origin, dir, min, max),
+            CSGTreeNodeData::Transform(d) => {
+                // The ray parameter does not change with the space.
+                let hit = self.get_ray_hit_index(d.child, d.get_local_pos_f(origin), d.get_local_dir(dir), min, max)?;
+                Some(VolumeRayHit {
+                    pos: origin + dir * hit.t,
+                    normal: normalize_or_zero(d.get_world_normal(hit.normal)),
+                    ..hit
+                })
+            },
+            CSGTreeNodeData::Paint(d) => self.get_ray_hit_paint(d, origin, dir, min, max),
+            CSGTreeNodeData::Cut(_)
+            | CSGTreeNodeData::SmoothUnion(_)
+            | CSGTreeNodeData::SmoothCut(_)
+            | CSGTreeNodeData::Intersect(_)
+            | CSGTreeNodeData::Repeat(_)
+            | CSGTreeNodeData::Displace(_)
+            | CSGTreeNodeData::Heightmap(_)
+            | CSGTreeNodeData::Extrude(_)
+            | CSGTreeNodeData::Revolve(_) => self.get_ray_hit_traced(index, origin, dir, min, max),
+
+            CSGTreeNodeData::Box(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Sphere(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Cylinder(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Capsule(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Torus(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Cone(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Plane(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::Wedge(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::OffsetVoxelGrid(d) => d.get_ray_hit_in(origin, dir, min, max),
+            CSGTreeNodeData::SharedVoxelGrid(d) => d.get_ray_hit_in(origin, dir, min, max),
+        }
+    }
+
+    // The bvh is not ordered along the ray, so every child the ray reaches before the closest hit is tested.
+    fn get_ray_hit_union(&self, union: &CSGTreeUnion<V, T, D>, origin: V::VectorF, dir: V::VectorF, min: f32, max: f32) -> Option<VolumeRayHit<V::VectorF>> {
+        let mut best: Option<VolumeRayHit<V::VectorF>> = None;
+
+        let mut i = 0;
+        while i < union.bvh.nodes.len() {
+            let b = &union.bvh.nodes[i];
+            let max = best.map_or(max, |hit| hit.t);
+
+            // Integer bounds are truncated, so they get grown by one.
+            let reached = b.aabb.expand(T::ONE).ray_range(origin, dir)
+                .is_some_and(|(enter, exit)| enter <= max && exit >= min);
+
+            if reached {
+                if let Some(leaf) = b.leaf {
+                    if let Some(hit) = self.get_ray_hit_index(leaf, origin, dir, min, max) {
+                        best = Some(hit);
+                    }
+                }
+
+                i += 1;
+            } else {
+                i = b.exit;
+            }
+        }
+
+        best
+    }
+
+    fn get_ray_hit_paint(&self, paint: &CSGTreePaint, origin: V::VectorF, dir: V::VectorF, min: f32, max: f32) -> Option<VolumeRayHit<V::VectorF>> {
+        let mut hit = self.get_ray_hit_index(paint.base, origin, dir, min, max)?;
+
+        let (distance, mat) = self.get_distance_value_index(paint.brush, hit.pos);
+        if distance <= 0.0 && mat != MATERIAL_ID_NONE {
+            hit.material = mat;
+        }
+
+        Some(hit)
+    }
+
+    // Sphere tracing inside of the bounds of the node. The step that ends inside gets bisected to find the surface.
+    fn get_ray_hit_traced(&self, index: CSGTreeIndex, origin: V::VectorF, dir: V::VectorF, min: f32, max: f32) -> Option<VolumeRayHit<V::VectorF>> {
+        let (enter, exit) = self.get_bounds_index(index).expand(T::ONE).ray_range(origin, dir)?;
+        let len = dir.length();
+        if len == 0.0 {
+            return None;
+        }
+
+        let end = exit.min(max);
+        let mut t = enter.max(min);
+        let mut last = t;
+        for _ in 0..RAY_MAX_STEPS {
+            if t > end {
+                return None;
+            }
+
+            let distance = self.get_distance_index(index, origin + dir * t);
+            if distance < 0.0 {
+                let t = if t > last { self.bisect_ray(index, origin, dir, last, t) } else { t };
+                return Some(self.get_traced_hit(index, origin, dir, t));
+            }
+
+            if distance < RAY_EPSILON {
+                return Some(self.get_traced_hit(index, origin, dir, t));
+            }
+
+            last = t;
+            t += distance / len;
+        }
+
+        None
+    }
+
+    fn bisect_ray(&self, index: CSGTreeIndex, origin: V::VectorF, dir: V::VectorF, mut outside: f32, mut inside: f32) -> f32 {
+        for _ in 0..RAY_BISECT_STEPS {
+            let t = (outside + inside) * 0.5;
+            if self.get_distance_index(index, origin + dir * t) < 0.0 {
+                inside = t;
+            } else {
+                outside = t;
+            }
+        }
+
+        inside
+    }
+
+    fn get_traced_hit(&self, index: CSGTreeIndex, origin: V::VectorF, dir: V::VectorF, t: f32) -> VolumeRayHit<V::VectorF> {
+        let pos = origin + dir * t;
+
+        VolumeRayHit {
+            t,
+            pos,
+            normal: normalize_or_zero(self.get_gradient_at_position_internal(index, pos)),
+            material: self.get_distance_value_index(index, pos).1,
+        }
+    }
+}
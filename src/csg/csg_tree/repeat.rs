@@ -0,0 +1,420 @@
+use std::f32::consts::{PI, TAU};
+
+use smallvec::SmallVec;
+
+use crate::util::{aabb::AABB, number::Nu, vector::Ve};
+
+use super::{transform::from_vecf_rounded, tree::CSGTreeIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CSGTreeRepeatKind<V: Ve<f32, D>, const D: usize> {
+    // count copies, each moved by step from the one before.
+    Linear { step: V, count: u32 },
+    // count[i] copies along axis i, spaced by step[i].
+    Grid { step: V, count: [u32; D] },
+    // count copies evenly rotated around center. In 3D the rotation is around the z axis.
+    Radial { center: V, count: u32 },
+}
+
+// One copy of the child. The offset is applied first, the rotation is around the center of a radial repeat.
+#[derive(Debug, Clone, Copy)]
+pub struct CSGTreeRepeatCopy<V: Ve<f32, D>, const D: usize> {
+    offset: V,
+    angle: f32,
+}
+
+pub type CSGTreeRepeatCopies<V, const D: usize> = SmallVec<[CSGTreeRepeatCopy<V, D>; 8]>;
+
+/**
+Evaluates the child as a finite array of copies by folding the query into the space of the child.
+Queries only look at the copies next to the position, so the child should not reach further than one step into its neighbours.
+*/
+#[derive(Debug, Clone)]
+pub struct CSGTreeRepeat<V: Ve<f32, D>, const D: usize> {
+    pub child: CSGTreeIndex,
+    kind: CSGTreeRepeatKind<V, D>,
+    pub(super) child_bounds: AABB<V, f32, D>,
+}
+
+impl<V: Ve<f32, D>, const D: usize> CSGTreeRepeatKind<V, D> {
+    pub fn get_count(&self) -> u32 {
+        match self {
+            CSGTreeRepeatKind::Linear { count, .. } => *count,
+            CSGTreeRepeatKind::Grid { count, .. } => count.iter().product(),
+            CSGTreeRepeatKind::Radial { count, .. } => *count,
+        }
+    }
+}
+
+impl<VF: Ve<f32, D>, const D: usize> CSGTreeRepeat<VF, D> {
+    pub fn new(child: CSGTreeIndex, kind: CSGTreeRepeatKind<VF, D>) -> Self {
+        assert!(kind.get_count() > 0, "Repeat needs at least one copy");
+
+        Self {
+            child,
+            kind,
+            child_bounds: AABB::default(),
+        }
+    }
+
+    pub fn get_kind(&self) -> CSGTreeRepeatKind<VF, D> {
+        self.kind
+    }
+
+    pub fn set_kind(&mut self, kind: CSGTreeRepeatKind<VF, D>) {
+        assert!(kind.get_count() > 0, "Repeat needs at least one copy");
+        self.kind = kind;
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        self.child += ammount;
+    }
+
+    // The copies that can contain pos.
+    pub fn get_copies_at(&self, pos: VF) -> CSGTreeRepeatCopies<VF, D> {
+        let child_center = self.child_bounds.center();
+
+        match self.kind {
+            CSGTreeRepeatKind::Linear { step, count } => {
+                let len = step.length_squared();
+                let t = if len == 0.0 { 0.0 } else { (pos - child_center).dot(step) / len };
+                linear_copies(step, nearest_range(t, count))
+            },
+            CSGTreeRepeatKind::Grid { step, count } => {
+                let ranges = std::array::from_fn(|i| {
+                    let t = if step[i] == 0.0 { 0.0 } else { (pos[i] - child_center[i]) / step[i] };
+                    nearest_range(t, count[i])
+                });
+                grid_copies(step, ranges)
+            },
+            CSGTreeRepeatKind::Radial { center, count } => {
+                let step = TAU / count as f32;
+                let t = wrap_angle(angle_of(pos - center) - angle_of(child_center - center)) / step;
+
+                // The copies wrap around, so the indecies are not clamped.
+                let i = t.round() as i32;
+                let j = if t >= i as f32 { i + 1 } else { i - 1 };
+                radial_copies(count, (i.min(j), i.max(j)))
+            },
+        }
+    }
+
+    // The copies whose bounds collide with the aabb or None if there are too many to check them one by one.
+    pub fn get_copies_in(&self, aabb: AABB<VF, f32, D>) -> Option<CSGTreeRepeatCopies<VF, D>> {
+        let child = self.child_bounds;
+        if child.min().cmpgt_any(child.max()) {
+            return Some(SmallVec::new());
+        }
+
+        match self.kind {
+            CSGTreeRepeatKind::Linear { step, count } => {
+                let mut range = (0, count as i32 - 1);
+                for i in 0..D {
+                    let Some(r) = overlap_range(aabb.min()[i], aabb.max()[i], child.min()[i], child.max()[i], step[i], count) else {
+                        return Some(SmallVec::new());
+                    };
+                    range = (range.0.max(r.0), range.1.min(r.1));
+                }
+
+                if range.0 > range.1 {
+                    return Some(SmallVec::new());
+                }
+
+                (range.1 - range.0 < 2).then(|| linear_copies(step, range))
+            },
+            CSGTreeRepeatKind::Grid { step, count } => {
+                let mut ranges = [(0, 0); D];
+                for i in 0..D {
+                    let Some(r) = overlap_range(aabb.min()[i], aabb.max()[i], child.min()[i], child.max()[i], step[i], count[i]) else {
+                        return Some(SmallVec::new());
+                    };
+
+                    if r.1 - r.0 >= 2 {
+                        return None;
+                    }
+                    ranges[i] = r;
+                }
+
+                Some(grid_copies(step, ranges))
+            },
+            CSGTreeRepeatKind::Radial { center, count } => {
+                let all = (count <= 2).then(|| radial_copies(count, (0, count as i32 - 1)));
+
+                let (Some(query), Some(child)) = (angle_range(aabb, center), angle_range(child, center)) else {
+                    return all;
+                };
+
+                let step = TAU / count as f32;
+                let range = (
+                    ((query.0 - child.1) / step).ceil() as i32,
+                    ((query.1 - child.0) / step).floor() as i32,
+                );
+
+                if range.0 > range.1 {
+                    Some(SmallVec::new())
+                } else if range.1 - range.0 < 2 {
+                    Some(radial_copies(count, range))
+                } else {
+                    all
+                }
+            },
+        }
+    }
+
+    pub fn get_local_pos_f(&self, copy: CSGTreeRepeatCopy<VF, D>, pos: VF) -> VF {
+        if copy.angle == 0.0 {
+            return pos - copy.offset;
+        }
+
+        let center = self.get_center();
+        rotate(pos - copy.offset - center, -copy.angle) + center
+    }
+
+    // The local aabb contains the folded aabb, so Full and Empty results stay correct.
+    pub fn get_local_aabb<V: Ve<T, D>, T: Nu>(&self, copy: CSGTreeRepeatCopy<VF, D>, aabb: AABB<V, T, D>) -> AABB<V, T, D> {
+        let aabb: AABB<VF, f32, D> = aabb.to_f();
+        let aabb = get_corners(aabb)
+            .map(|corner| self.get_local_pos_f(copy, corner))
+            .fold(AABB::default(), AABB::union_point);
+
+        AABB::new(from_vecf_rounded(aabb.min(), f32::floor), from_vecf_rounded(aabb.max(), f32::ceil))
+    }
+
+    pub fn get_world_normal(&self, copy: CSGTreeRepeatCopy<VF, D>, normal: VF) -> VF {
+        rotate(normal, copy.angle)
+    }
+
+    // Covers all copies.
+    pub fn get_world_aabb<V: Ve<T, D>, T: Nu>(&self) -> AABB<V, T, D> {
+        self.get_copies_aabb(self.child_bounds)
+    }
+
+    // Covers the aabb in the space of the child in all copies.
+    pub fn get_world_aabb_of<V: Ve<T, D>, T: Nu>(&self, aabb: AABB<V, T, D>) -> AABB<V, T, D> {
+        if aabb.min().cmpgt_any(aabb.max()) {
+            return AABB::default();
+        }
+
+        self.get_copies_aabb(aabb.to_f())
+    }
+
+    fn get_copies_aabb<V: Ve<T, D>, T: Nu>(&self, child: AABB<VF, f32, D>) -> AABB<V, T, D> {
+        if child.min().cmpgt_any(child.max()) {
+            return AABB::default();
+        }
+
+        let aabb = match self.kind {
+            CSGTreeRepeatKind::Linear { step, count } => {
+                let last = step * (count - 1) as f32;
+                AABB::new(child.min().min(child.min() + last), child.max().max(child.max() + last))
+            },
+            CSGTreeRepeatKind::Grid { step, count } => {
+                let last = step * VF::new(count.map(|c| (c - 1) as f32));
+                AABB::new(child.min().min(child.min() + last), child.max().max(child.max() + last))
+            },
+            CSGTreeRepeatKind::Radial { center, count } => {
+                if count == 1 {
+                    child
+                } else {
+                    // Every copy lies in the cylinder around the center that contains the child.
+                    let radius = get_corners(child)
+                        .map(|corner| plane_length(corner - center))
+                        .fold(0.0, f32::max);
+
+                    let mut min = child.min().to_array();
+                    let mut max = child.max().to_array();
+                    for i in 0..2 {
+                        min[i] = center[i] - radius;
+                        max[i] = center[i] + radius;
+                    }
+
+                    AABB::new(VF::new(min), VF::new(max))
+                }
+            },
+        };
+
+        AABB::new(from_vecf_rounded(aabb.min(), f32::floor), from_vecf_rounded(aabb.max(), f32::ceil))
+    }
+
+    fn get_center(&self) -> VF {
+        match self.kind {
+            CSGTreeRepeatKind::Radial { center, .. } => center,
+            _ => VF::ZERO,
+        }
+    }
+}
+
+// The index closest to t and its neighbour on the side of t.
+fn nearest_range(t: f32, count: u32) -> (i32, i32) {
+    let last = count as i32 - 1;
+    let i = (t.round() as i32).clamp(0, last);
+    let j = (if t >= i as f32 { i + 1 } else { i - 1 }).clamp(0, last);
+
+    (i.min(j), i.max(j))
+}
+
+// The indecies i where [child_min, child_max] + i * step collides with [min, max].
+fn overlap_range(min: f32, max: f32, child_min: f32, child_max: f32, step: f32, count: u32) -> Option<(i32, i32)> {
+    let last = count as i32 - 1;
+    if step == 0.0 {
+        return (child_min <= max && min <= child_max).then_some((0, last));
+    }
+
+    let a = (min - child_max) / step;
+    let b = (max - child_min) / step;
+    let (a, b) = if step > 0.0 { (a, b) } else { (b, a) };
+
+    let range = ((a.ceil() as i32).max(0), (b.floor() as i32).min(last));
+    (range.0 <= range.1).then_some(range)
+}
+
+fn linear_copies<V: Ve<f32, D>, const D: usize>(step: V, range: (i32, i32)) -> CSGTreeRepeatCopies<V, D> {
+    (range.0..=range.1)
+        .map(|i| CSGTreeRepeatCopy { offset: step * i as f32, angle: 0.0 })
+        .collect()
+}
+
+fn grid_copies<V: Ve<f32, D>, const D: usize>(step: V, ranges: [(i32, i32); D]) -> CSGTreeRepeatCopies<V, D> {
+    let sizes = ranges.map(|(a, b)| (b - a + 1) as usize);
+    let total = sizes.iter().product::<usize>();
+
+    (0..total)
+        .map(|mut n| {
+            let index = V::new(std::array::from_fn(|i| {
+                let v = ranges[i].0 + (n % sizes[i]) as i32;
+                n /= sizes[i];
+                v as f32
+            }));
+
+            CSGTreeRepeatCopy { offset: step * index, angle: 0.0 }
+        })
+        .collect()
+}
+
+fn radial_copies<V: Ve<f32, D>, const D: usize>(count: u32, range: (i32, i32)) -> CSGTreeRepeatCopies<V, D> {
+    let step = TAU / count as f32;
+    let len = (range.1 - range.0 + 1).min(count as i32);
+
+    (range.0..range.0 + len)
+        .map(|i| CSGTreeRepeatCopy { offset: V::ZERO, angle: i.rem_euclid(count as i32) as f32 * step })
+        .collect()
+}
+
+// The angles covered by the aabb seen from the center or None if the aabb contains the center.
+fn angle_range<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>, center: V) -> Option<(f32, f32)> {
+    let (min, max) = (aabb.min() - center, aabb.max() - center);
+    if min[0] <= 0.0 && max[0] >= 0.0 && min[1] <= 0.0 && max[1] >= 0.0 {
+        return None;
+    }
+
+    let mid = angle_of(aabb.center() - center);
+    let (a, b) = get_corners(aabb)
+        .map(|corner| wrap_angle(angle_of(corner - center) - mid))
+        .fold((0.0, 0.0), |(a, b), angle: f32| (a.min(angle), b.max(angle)));
+
+    Some((mid + a, mid + b))
+}
+
+fn get_corners<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> impl Iterator<Item = V> {
+    (0..1 << D).map(move |mask: usize| {
+        V::new(std::array::from_fn(|i| if mask >> i & 1 == 1 { aabb.max()[i] } else { aabb.min()[i] }))
+    })
+}
+
+// Rotation in the xy plane.
+fn rotate<V: Ve<f32, D>, const D: usize>(v: V, angle: f32) -> V {
+    let (sin, cos) = angle.sin_cos();
+    let mut a = v.to_array();
+    a[0] = v[0] * cos - v[1] * sin;
+    a[1] = v[0] * sin + v[1] * cos;
+    V::new(a)
+}
+
+fn angle_of<V: Ve<f32, D>, const D: usize>(v: V) -> f32 {
+    v[1].atan2(v[0])
+}
+
+fn plane_length<V: Ve<f32, D>, const D: usize>(v: V) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::aabb::AABB, volume::{VolumeBounds, VolumeQureyPosValue}};
+
+    use super::{CSGTreeRepeat, CSGTreeRepeatKind};
+
+    fn new_repeat(kind: CSGTreeRepeatKind<Vec3A, 3>) -> CSGTreeRepeat<Vec3A, 3> {
+        let mut repeat = CSGTreeRepeat::new(0, kind);
+        repeat.child_bounds = AABB::new(Vec3A::splat(-1.0), Vec3A::splat(1.0));
+        repeat
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(CSGTreeRepeatKind::Linear { step: Vec3A::X, count: 5 }.get_count(), 5);
+        assert_eq!(CSGTreeRepeatKind::Grid { step: Vec3A::ONE, count: [2, 3, 4] }.get_count(), 24);
+        assert_eq!(CSGTreeRepeatKind::Radial { center: Vec3A::ZERO, count: 7 }.get_count(), 7);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_count() {
+        CSGTreeRepeat::new(0, CSGTreeRepeatKind::Linear { step: Vec3A::X, count: 0 });
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_grid_axis() {
+        CSGTreeRepeat::new(0, CSGTreeRepeatKind::Grid { step: Vec3A::ONE, count: [2, 0, 2] });
+    }
+
+    #[test]
+    fn world_aabb() {
+        let repeat = new_repeat(CSGTreeRepeatKind::Linear { step: vec3a(4.0, 0.0, 0.0), count: 3 });
+        let aabb: AABB<IVec3, i32, 3> = repeat.get_world_aabb();
+        assert_eq!(aabb.min(), IVec3::new(-1, -1, -1));
+        assert_eq!(aabb.max(), IVec3::new(9, 1, 1));
+
+        let repeat = new_repeat(CSGTreeRepeatKind::Grid { step: vec3a(4.0, -4.0, 0.0), count: [2, 3, 1] });
+        let aabb: AABB<IVec3, i32, 3> = repeat.get_world_aabb();
+        assert_eq!(aabb.min(), IVec3::new(-1, -9, -1));
+        assert_eq!(aabb.max(), IVec3::new(5, 1, 1));
+    }
+
+    #[test]
+    fn copies_in_limit() {
+        let repeat = new_repeat(CSGTreeRepeatKind::Linear { step: vec3a(4.0, 0.0, 0.0), count: 100 });
+
+        // Too many copies are not listed.
+        assert!(repeat.get_copies_in(AABB::new(Vec3A::splat(-10.0), Vec3A::splat(400.0))).is_none());
+
+        let copies = repeat.get_copies_in(AABB::new(vec3a(19.5, -0.5, -0.5), vec3a(20.5, 0.5, 0.5))).unwrap();
+        assert_eq!(copies.len(), 1);
+
+        let copies = repeat.get_copies_in(AABB::new(vec3a(500.0, -0.5, -0.5), vec3a(501.0, 0.5, 0.5))).unwrap();
+        assert!(copies.is_empty());
+    }
+
+    #[test]
+    fn values() {
+        let mut csg = CSGTree::<u8, IVec3, i32, 3>::default();
+        let sphere = csg.add_sphere(Vec3A::ZERO, 1.5, 1);
+        let repeat = csg.add_repeat_node(sphere, CSGTreeRepeatKind::Linear { step: vec3a(4.0, 0.0, 0.0), count: 3 });
+        csg.set_root(repeat);
+        csg.calculate_bounds();
+
+        for x in [0, 4, 8] {
+            assert_eq!(csg.get_value(IVec3::new(x, 0, 0)), 1);
+        }
+        for x in [-4, 2, 6, 12] {
+            assert_eq!(csg.get_value(IVec3::new(x, 0, 0)), 0);
+        }
+    }
+}
@@ -0,0 +1,686 @@
+use std::{fs, path::Path, sync::Arc};
+
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::{IVec3, Mat3, Mat4, UVec2, UVec3, Vec3A}, OctaResult};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{csg::{Base, primitves::{CSGPrimitive, PrimitiveType, r#box::CSGBox, capsule::CSGCapsule, cone::CSGCone, cylinder::CSGCylinder, plane::CSGPlane, sphere::CSGSphere, torus::CSGTorus, wedge::CSGWedge}}, util::{aabb::AABB, math_config::MC, matrix::Ma, noise::FractalNoiseSettings, number::Nu, vector::{CastFrom, CastInto, Ve}}, volume::heightmap::{Heightmap, HeightmapData, HeightmapSampling}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid, VoxelGrid}};
+
+use super::{displace::CSGTreeDisplace, extrude::{CSGTreeExtrude, CSGTreeProfile, CSGTreeRevolve}, intersect::CSGTreeIntersect, paint::CSGTreePaint, remove::CSGTreeRemove, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, repeat::{CSGTreeRepeat, CSGTreeRepeatKind}, transform::CSGTreeTransform, tree::{CSGTree, CSGTreeIndex, CSGTreeNode, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}, union::CSGTreeUnion};
+
+// Version 2 added the transform, repeat, displace, heightmap, paint, extrude and revolve nodes.
+// Version 3 added referenced voxel grids.
+// The old variants kept their indecies, so version 1 saves still load.
+pub const CSG_TREE_SAVE_VERSION: u32 = 3;
+const CSG_TREE_SAVE_MIN_VERSION: u32 = 1;
+const CSG_TREE_BINARY_MAGIC: [u8; 4] = *b"CSGT";
+
+/**
+On disk layout of a CSGTree.
+The BVHs and bounds are not stored, they are rebuild when loading.
+Voxel grids are stored once in grids and referenced by index, so shared grids stay shared.
+Shared grids that live outside of the tree, like imported models, can be stored as a name instead.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CSGTreeSave<M> {
+    pub version: u32,
+    pub dimensions: usize,
+    pub root: CSGTreeIndex,
+    pub nodes: Vec<CSGTreeNodeSave<M>>,
+    pub grids: Vec<VoxelGridSave>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CSGTreeNodeSave<M> {
+    pub data: CSGTreeNodeDataSave<M>,
+    pub parent: CSGTreeIndex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CSGTreeNodeDataSave<M> {
+    Union { indecies: Vec<CSGTreeIndex> },
+    Cut { base: CSGTreeIndex, remove: CSGTreeIndex },
+    SmoothUnion { indecies: Vec<CSGTreeIndex>, blend: f32 },
+    SmoothCut { base: CSGTreeIndex, remove: CSGTreeIndex, blend: f32 },
+    Intersect { indecies: Vec<CSGTreeIndex> },
+
+    None,
+    Box(PrimitiveSave<CSGBox, M>),
+    Sphere(PrimitiveSave<CSGSphere, M>),
+    Cylinder(PrimitiveSave<CSGCylinder, M>),
+    Capsule(PrimitiveSave<CSGCapsule, M>),
+    Torus(PrimitiveSave<CSGTorus, M>),
+    Cone(PrimitiveSave<CSGCone, M>),
+    Plane(PrimitiveSave<CSGPlane, M>),
+    Wedge(PrimitiveSave<CSGWedge, M>),
+    OffsetVoxelGrid(VoxelGridNodeSave),
+    SharedVoxelGrid(VoxelGridNodeSave),
+
+    // New node types are added at the end so the variant indecies of binary saves stay the same.
+    Transform { child: CSGTreeIndex, mat: Vec<f32> },
+    Repeat { child: CSGTreeIndex, kind: RepeatKindSave },
+    Displace { child: CSGTreeIndex, noise: FractalNoiseSettings },
+    Heightmap(HeightmapNodeSave),
+    Paint { base: CSGTreeIndex, brush: CSGTreeIndex },
+    Extrude { profile: CSGTreeSave<M>, height: f32, taper: f32 },
+    Revolve { profile: CSGTreeSave<M> },
+    ReferencedVoxelGrid(ReferencedVoxelGridNodeSave),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RepeatKindSave {
+    Linear { step: Vec<f32>, count: u32 },
+    Grid { step: Vec<f32>, count: Vec<u32> },
+    Radial { center: Vec<f32>, count: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrimitiveSave<P, M> {
+    pub primitive: P,
+    // Columns of the matrix.
+    pub mat: Vec<f32>,
+    pub material: M,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoxelGridNodeSave {
+    pub grid: usize,
+    pub offset: [i32; 3],
+    pub mat: Option<[f32; 16]>,
+}
+
+// A shared grid that is not part of the save, the name is resolved by the caller when loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferencedVoxelGridNodeSave {
+    pub name: String,
+    pub offset: [i32; 3],
+    pub mat: Option<[f32; 16]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeightmapNodeSave {
+    pub size: [u32; 2],
+    pub heights: Vec<f32>,
+    pub offset: [f32; 3],
+    pub horizontal_scale: f32,
+    pub vertical_scale: f32,
+    pub sampling: HeightmapSampling,
+    pub material: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoxelGridSave {
+    pub size: [u32; 3],
+    pub data: Vec<u8>,
+}
+
+impl<M: Base + Send + Sync + Serialize + DeserializeOwned, V: Ve<T, D>, T: Nu, const D: usize> CSGTree<M, V, T, D> {
+    pub fn to_save(&self) -> CSGTreeSave<M> {
+        self.to_save_with(|_| None)
+    }
+
+    // Shared grids that get a name from get_reference are stored as that name instead of their voxels.
+    pub fn to_save_with(&self, get_reference: impl Fn(&Arc<VoxelGrid>) -> Option<String>) -> CSGTreeSave<M> {
+        let mut grids = vec![];
+        let mut shared_grids: Vec<(*const VoxelGrid, usize)> = vec![];
+
+        let nodes = self.nodes.iter()
+            .map(|node| {
+                let data = match &node.data {
+                    CSGTreeNodeData::Union(d) => CSGTreeNodeDataSave::Union { indecies: d.indecies.clone() },
+                    CSGTreeNodeData::Cut(d) => CSGTreeNodeDataSave::Cut { base: d.base, remove: d.remove },
+                    CSGTreeNodeData::SmoothUnion(d) => CSGTreeNodeDataSave::SmoothUnion { indecies: d.indecies.clone(), blend: d.blend },
+                    CSGTreeNodeData::SmoothCut(d) => CSGTreeNodeDataSave::SmoothCut { base: d.base, remove: d.remove, blend: d.blend },
+                    CSGTreeNodeData::Intersect(d) => CSGTreeNodeDataSave::Intersect { indecies: d.indecies.clone() },
+                    CSGTreeNodeData::Transform(d) => CSGTreeNodeDataSave::Transform { child: d.child, mat: mat_to_save::<V::VectorF, D>(&d.get_mat()) },
+                    CSGTreeNodeData::Repeat(d) => CSGTreeNodeDataSave::Repeat { child: d.child, kind: RepeatKindSave::new(d.get_kind()) },
+                    CSGTreeNodeData::Displace(d) => CSGTreeNodeDataSave::Displace { child: d.child, noise: d.get_settings() },
+                    CSGTreeNodeData::Heightmap(d) => CSGTreeNodeDataSave::Heightmap(HeightmapNodeSave::new(d)),
+                    CSGTreeNodeData::Paint(d) => CSGTreeNodeDataSave::Paint { base: d.base, brush: d.brush },
+                    CSGTreeNodeData::Extrude(d) => CSGTreeNodeDataSave::Extrude { profile: d.get_profile().to_save(), height: d.get_height(), taper: d.get_taper() },
+                    CSGTreeNodeData::Revolve(d) => CSGTreeNodeDataSave::Revolve { profile: d.get_profile().to_save() },
+
+                    CSGTreeNodeData::None => CSGTreeNodeDataSave::None,
+                    CSGTreeNodeData::Box(d) => CSGTreeNodeDataSave::Box(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Sphere(d) => CSGTreeNodeDataSave::Sphere(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Cylinder(d) => CSGTreeNodeDataSave::Cylinder(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Capsule(d) => CSGTreeNodeDataSave::Capsule(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Torus(d) => CSGTreeNodeDataSave::Torus(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Cone(d) => CSGTreeNodeDataSave::Cone(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Plane(d) => CSGTreeNodeDataSave::Plane(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::Wedge(d) => CSGTreeNodeDataSave::Wedge(PrimitiveSave::new(d)),
+                    CSGTreeNodeData::OffsetVoxelGrid(d) => {
+                        grids.push(VoxelGridSave::new(&d.grid));
+                        CSGTreeNodeDataSave::OffsetVoxelGrid(VoxelGridNodeSave::new(grids.len() - 1, d.offset, d.get_mat()))
+                    },
+                    CSGTreeNodeData::SharedVoxelGrid(d) => {
+                        if let Some(name) = get_reference(&d.grid) {
+                            return CSGTreeNodeSave {
+                                data: CSGTreeNodeDataSave::ReferencedVoxelGrid(ReferencedVoxelGridNodeSave {
+                                    name,
+                                    offset: d.offset.to_array(),
+                                    mat: mat_to_grid_save(d.get_mat()),
+                                }),
+                                parent: node.parent,
+                            };
+                        }
+
+                        let ptr = Arc::as_ptr(&d.grid);
+                        let grid = if let Some((_, i)) = shared_grids.iter().find(|(p, _)| *p == ptr) {
+                            *i
+                        } else {
+                            grids.push(VoxelGridSave::new(&d.grid));
+                            shared_grids.push((ptr, grids.len() - 1));
+                            grids.len() - 1
+                        };
+
+                        CSGTreeNodeDataSave::SharedVoxelGrid(VoxelGridNodeSave::new(grid, d.offset, d.get_mat()))
+                    },
+                };
+
+                CSGTreeNodeSave { data, parent: node.parent }
+            })
+            .collect();
+
+        CSGTreeSave {
+            version: CSG_TREE_SAVE_VERSION,
+            dimensions: D,
+            root: self.root,
+            nodes,
+            grids,
+        }
+    }
+
+    pub fn from_save(save: CSGTreeSave<M>) -> OctaResult<Self> {
+        Self::from_save_with(save, |_| None)
+    }
+
+    // Referenced grids are looked up by name, a name that get_grid does not know is an error.
+    pub fn from_save_with(save: CSGTreeSave<M>, get_reference: impl Fn(&str) -> Option<Arc<VoxelGrid>>) -> OctaResult<Self> {
+        check_version(save.version)?;
+
+        if save.dimensions != D {
+            bail!("CSG tree save has {} dimensions but {} were expected", save.dimensions, D);
+        }
+
+        let node_count = save.nodes.len();
+        let check_index = |index: CSGTreeIndex| -> OctaResult<CSGTreeIndex> {
+            ensure!(index < node_count, "CSG tree save references node {index} but only has {node_count} nodes");
+            Ok(index)
+        };
+        let check_indecies = |indecies: Vec<CSGTreeIndex>| -> OctaResult<Vec<CSGTreeIndex>> {
+            indecies.into_iter().map(check_index).collect()
+        };
+
+        let grids: Vec<Arc<VoxelGrid>> = save.grids.into_iter()
+            .map(|grid| grid.into_grid().map(Arc::new))
+            .collect::<OctaResult<_>>()?;
+        let get_grid = |index: usize| -> OctaResult<Arc<VoxelGrid>> {
+            match grids.get(index) {
+                Some(grid) => Ok(grid.clone()),
+                None => bail!("CSG tree save references grid {index} but only has {} grids", grids.len()),
+            }
+        };
+
+        let nodes = save.nodes.into_iter()
+            .map(|node| -> OctaResult<CSGTreeNode<M, V, T, D>> {
+                let data = match node.data {
+                    CSGTreeNodeDataSave::Union { indecies } => CSGTreeNodeData::Union(CSGTreeUnion::new(check_indecies(indecies)?)),
+                    CSGTreeNodeDataSave::Cut { base, remove } => CSGTreeNodeData::Cut(CSGTreeRemove::new(check_index(base)?, check_index(remove)?)),
+                    CSGTreeNodeDataSave::SmoothUnion { indecies, blend } => CSGTreeNodeData::SmoothUnion(CSGTreeSmoothUnion::new(check_indecies(indecies)?, blend)),
+                    CSGTreeNodeDataSave::SmoothCut { base, remove, blend } => CSGTreeNodeData::SmoothCut(CSGTreeSmoothRemove::new(check_index(base)?, check_index(remove)?, blend)),
+                    CSGTreeNodeDataSave::Intersect { indecies } => CSGTreeNodeData::Intersect(CSGTreeIntersect::new(check_indecies(indecies)?)),
+                    CSGTreeNodeDataSave::Transform { child, mat } => CSGTreeNodeData::Transform(CSGTreeTransform::new(check_index(child)?, mat_from_save::<V::VectorF, D>(&mat)?)),
+                    CSGTreeNodeDataSave::Repeat { child, kind } => CSGTreeNodeData::Repeat(CSGTreeRepeat::new(check_index(child)?, kind.into_kind()?)),
+                    CSGTreeNodeDataSave::Displace { child, noise } => CSGTreeNodeData::Displace(CSGTreeDisplace::new(check_index(child)?, noise)),
+                    CSGTreeNodeDataSave::Heightmap(d) => CSGTreeNodeData::Heightmap(d.into_heightmap()?),
+                    CSGTreeNodeDataSave::Paint { base, brush } => CSGTreeNodeData::Paint(CSGTreePaint::new(check_index(base)?, check_index(brush)?)),
+                    CSGTreeNodeDataSave::Extrude { profile, height, taper } => CSGTreeNodeData::Extrude(CSGTreeExtrude::new(profile_from_save(profile)?, height, taper)?),
+                    CSGTreeNodeDataSave::Revolve { profile } => CSGTreeNodeData::Revolve(CSGTreeRevolve::new(profile_from_save(profile)?)?),
+
+                    CSGTreeNodeDataSave::None => CSGTreeNodeData::None,
+                    CSGTreeNodeDataSave::Box(d) => CSGTreeNodeData::Box(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Sphere(d) => CSGTreeNodeData::Sphere(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Cylinder(d) => CSGTreeNodeData::Cylinder(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Capsule(d) => CSGTreeNodeData::Capsule(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Torus(d) => CSGTreeNodeData::Torus(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Cone(d) => CSGTreeNodeData::Cone(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Plane(d) => CSGTreeNodeData::Plane(d.into_primitive()?),
+                    CSGTreeNodeDataSave::Wedge(d) => CSGTreeNodeData::Wedge(d.into_primitive()?),
+                    CSGTreeNodeDataSave::OffsetVoxelGrid(d) => {
+                        let grid = Arc::unwrap_or_clone(get_grid(d.grid)?);
+                        let mut grid = OffsetVoxelGrid::from_grid(grid, IVec3::from_array(d.offset));
+                        grid.set_mat(d.get_mat());
+                        CSGTreeNodeData::OffsetVoxelGrid(grid)
+                    },
+                    CSGTreeNodeDataSave::SharedVoxelGrid(d) => {
+                        let mut grid = SharedVoxelGrid {
+                            grid: get_grid(d.grid)?,
+                            offset: IVec3::from_array(d.offset),
+                            transform: None,
+                        };
+                        grid.set_mat(d.get_mat());
+                        CSGTreeNodeData::SharedVoxelGrid(grid)
+                    },
+                    CSGTreeNodeDataSave::ReferencedVoxelGrid(d) => {
+                        let mut grid = SharedVoxelGrid {
+                            grid: get_reference(&d.name).ok_or_else(|| anyhow!("CSG tree save references the unknown grid {}", d.name))?,
+                            offset: IVec3::from_array(d.offset),
+                            transform: None,
+                        };
+                        grid.set_mat(mat_from_grid_save(d.mat));
+                        CSGTreeNodeData::SharedVoxelGrid(grid)
+                    },
+                };
+
+                if node.parent != CSG_TREE_INDEX_INVALID {
+                    check_index(node.parent)?;
+                }
+
+                Ok(CSGTreeNode::new(data, node.parent))
+            })
+            .collect::<OctaResult<_>>()?;
+
+        let mut tree = Self {
+            nodes,
+            root: save.root,
+            needs_bounds_recompute: false,
+            changed_bounds: AABB::default(),
+        };
+
+        if !tree.nodes.is_empty() {
+            check_index(tree.root)?;
+            tree.check_reachable_nodes()?;
+            tree.calculate_bounds_index(tree.root);
+            tree.changed_bounds = tree.get_bounds_index(tree.root);
+        }
+
+        Ok(tree)
+    }
+
+    pub fn to_json(&self) -> OctaResult<String> {
+        self.to_save().to_json()
+    }
+
+    pub fn from_json(json: &str) -> OctaResult<Self> {
+        Self::from_save(CSGTreeSave::from_json(json)?)
+    }
+
+    pub fn to_binary(&self) -> OctaResult<Vec<u8>> {
+        self.to_save().to_binary()
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> OctaResult<Self> {
+        Self::from_save(CSGTreeSave::from_binary(bytes)?)
+    }
+
+    // The bounds are calculated recursively, so a node that is reached twice could be part of a cycle.
+    fn check_reachable_nodes(&self) -> OctaResult<()> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            ensure!(!visited[index], "CSG tree save reaches node {index} more than once");
+            visited[index] = true;
+            stack.extend(self.nodes[index].data.get_children());
+        }
+
+        Ok(())
+    }
+
+    // Files ending in .json are stored as json, everything else in the binary format.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> OctaResult<()> {
+        let path = path.as_ref();
+        if is_json_path(path) {
+            fs::write(path, self.to_json()?)?;
+        } else {
+            fs::write(path, self.to_binary()?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> OctaResult<Self> {
+        let path = path.as_ref();
+        if is_json_path(path) {
+            Self::from_json(&fs::read_to_string(path)?)
+        } else {
+            Self::from_binary(&fs::read(path)?)
+        }
+    }
+}
+
+// Saves with referenced grids are encoded and decoded here and converted with to_save_with and from_save_with.
+impl<M: Serialize + DeserializeOwned> CSGTreeSave<M> {
+    pub fn to_json(&self) -> OctaResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> OctaResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    // Magic, version and then the bincode encoded save.
+    pub fn to_binary(&self) -> OctaResult<Vec<u8>> {
+        let mut bytes = CSG_TREE_BINARY_MAGIC.to_vec();
+        bytes.extend_from_slice(&CSG_TREE_SAVE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> OctaResult<Self> {
+        if bytes.len() < 8 || bytes[0..4] != CSG_TREE_BINARY_MAGIC {
+            bail!("Not a binary CSG tree save");
+        }
+
+        check_version(u32::from_le_bytes(bytes[4..8].try_into().unwrap()))?;
+
+        Ok(bincode::deserialize(&bytes[8..])?)
+    }
+}
+
+fn check_version(version: u32) -> OctaResult<()> {
+    if !(CSG_TREE_SAVE_MIN_VERSION..=CSG_TREE_SAVE_VERSION).contains(&version) {
+        bail!("CSG tree save version {} is not supported. Expected {} to {}", version, CSG_TREE_SAVE_MIN_VERSION, CSG_TREE_SAVE_VERSION);
+    }
+
+    Ok(())
+}
+
+fn profile_from_save<M: Base + Send + Sync + Serialize + DeserializeOwned>(save: CSGTreeSave<M>) -> OctaResult<CSGTreeProfile<M>> {
+    ensure!(!save.nodes.is_empty(), "Profile of a CSG tree save is empty");
+    CSGTreeProfile::from_save(save)
+}
+
+fn is_json_path(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
+
+impl<P: PrimitiveType, M: Copy> PrimitiveSave<P, M> {
+    fn new<V: Ve<f32, D>, const D: usize>(primitive: &CSGPrimitive<P, M, V, D>) -> Self {
+        Self {
+            primitive: primitive.get_primitive(),
+            mat: mat_to_save::<V, D>(&primitive.get_mat()),
+            material: primitive.get_material(),
+        }
+    }
+
+    fn into_primitive<V: Ve<f32, D>, const D: usize>(self) -> OctaResult<CSGPrimitive<P, M, V, D>> {
+        let mat = mat_from_save::<V, D>(&self.mat)?;
+
+        Ok(CSGPrimitive::new(self.primitive, mat, self.material))
+    }
+}
+
+impl HeightmapNodeSave {
+    fn new(heightmap: &Heightmap) -> Self {
+        Self {
+            size: heightmap.data.get_size().to_array(),
+            heights: heightmap.data.get_heights().to_vec(),
+            offset: heightmap.offset.to_array(),
+            horizontal_scale: heightmap.horizontal_scale,
+            vertical_scale: heightmap.vertical_scale,
+            sampling: heightmap.sampling,
+            material: heightmap.material,
+        }
+    }
+
+    fn into_heightmap(self) -> OctaResult<Heightmap> {
+        let data = HeightmapData::new(UVec2::from_array(self.size), self.heights)?;
+        Ok(Heightmap::new(Arc::new(data), Vec3A::from_array(self.offset), self.horizontal_scale, self.vertical_scale, self.sampling, self.material))
+    }
+}
+
+impl RepeatKindSave {
+    fn new<V: Ve<f32, D>, const D: usize>(kind: CSGTreeRepeatKind<V, D>) -> Self {
+        match kind {
+            CSGTreeRepeatKind::Linear { step, count } => RepeatKindSave::Linear { step: step.to_array().to_vec(), count },
+            CSGTreeRepeatKind::Grid { step, count } => RepeatKindSave::Grid { step: step.to_array().to_vec(), count: count.to_vec() },
+            CSGTreeRepeatKind::Radial { center, count } => RepeatKindSave::Radial { center: center.to_array().to_vec(), count },
+        }
+    }
+
+    fn into_kind<V: Ve<f32, D>, const D: usize>(self) -> OctaResult<CSGTreeRepeatKind<V, D>> {
+        let kind = match self {
+            RepeatKindSave::Linear { step, count } => CSGTreeRepeatKind::Linear { step: vec_from_save(&step)?, count },
+            RepeatKindSave::Grid { step, count } => {
+                let count: [u32; D] = count.try_into()
+                    .map_err(|count: Vec<u32>| anyhow!("CSG tree save has a repeat count with {} entries, expected {}", count.len(), D))?;
+
+                CSGTreeRepeatKind::Grid { step: vec_from_save(&step)?, count }
+            },
+            RepeatKindSave::Radial { center, count } => CSGTreeRepeatKind::Radial { center: vec_from_save(&center)?, count },
+        };
+
+        ensure!(kind.get_count() > 0, "CSG tree save has a repeat without copies");
+        Ok(kind)
+    }
+}
+
+fn vec_from_save<V: Ve<f32, D>, const D: usize>(v: &[f32]) -> OctaResult<V> {
+    ensure!(v.len() == D, "CSG tree save has a vector with {} entries, expected {}", v.len(), D);
+    Ok(V::new(std::array::from_fn(|i| v[i])))
+}
+
+fn mat_to_save<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> Vec<f32> {
+    match D {
+        2 => CastInto::<Mat3>::cast_into(*mat).to_cols_array().to_vec(),
+        3 => CastInto::<Mat4>::cast_into(*mat).to_cols_array().to_vec(),
+        _ => unreachable!(),
+    }
+}
+
+fn mat_from_save<V: Ve<f32, D>, const D: usize>(mat: &[f32]) -> OctaResult<V::Matrix> {
+    ensure!(mat.len() == (D + 1) * (D + 1), "CSG tree save has a matrix with {} entries, expected {}", mat.len(), (D + 1) * (D + 1));
+
+    Ok(match D {
+        2 => V::Matrix::cast_from(Mat3::from_cols_slice(mat)),
+        3 => V::Matrix::cast_from(Mat4::from_cols_slice(mat)),
+        _ => unreachable!(),
+    })
+}
+
+impl VoxelGridNodeSave {
+    fn new(grid: usize, offset: IVec3, mat: Mat4) -> Self {
+        Self {
+            grid,
+            offset: offset.to_array(),
+            mat: mat_to_grid_save(mat),
+        }
+    }
+
+    fn get_mat(&self) -> Mat4 {
+        mat_from_grid_save(self.mat)
+    }
+}
+
+// Grids without a transform store no matrix.
+fn mat_to_grid_save(mat: Mat4) -> Option<[f32; 16]> {
+    (mat != Mat4::IDENTITY).then(|| mat.to_cols_array())
+}
+
+fn mat_from_grid_save(mat: Option<[f32; 16]>) -> Mat4 {
+    mat.map(|mat| Mat4::from_cols_array(&mat)).unwrap_or(Mat4::IDENTITY)
+}
+
+impl VoxelGridSave {
+    fn new(grid: &VoxelGrid) -> Self {
+        Self {
+            size: grid.size.to_array(),
+            data: grid.data.clone(),
+        }
+    }
+
+    fn into_grid(self) -> OctaResult<VoxelGrid> {
+        let size = UVec3::from_array(self.size);
+        let count = size.x as usize * size.y as usize * size.z as usize;
+        ensure!(self.data.len() == count, "CSG tree save has a grid of size {size} with {} voxels", self.data.len());
+
+        Ok(VoxelGrid::from_data(size, self.data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use octa_force::glam::{vec2, vec3a, IVec3, UVec3, Vec3A};
+
+    use crate::{csg::csg_tree::{extrude::CSGTreeProfile, tree::{CSGTree, CSGTreeNodeData, CSG_TREE_INDEX_INVALID}}, volume::{VolumeBounds, VolumeQureyPosValue}, voxel::grid::{shared::SharedVoxelGrid, VoxelGrid}};
+
+    use super::{CSGTreeNodeDataSave, CSGTreeNodeSave, CSGTreeSave, CSG_TREE_SAVE_VERSION};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    fn new_tree() -> Tree {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        csg.union_sphere(vec3a(5.0, 0.0, 0.0), 4.0, 2);
+        csg.cut_with_sphere(vec3a(0.0, 3.0, 0.0), 3.0, 0);
+        csg.calculate_bounds();
+        csg
+    }
+
+    fn assert_same_values(a: &Tree, b: &Tree) {
+        for x in -10..=10 {
+            for y in -10..=10 {
+                for z in -10..=10 {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(a.get_value(pos), b.get_value(pos), "Value at {pos} differs");
+                }
+            }
+        }
+    }
+
+    fn new_save(nodes: Vec<CSGTreeNodeSave<u8>>) -> CSGTreeSave<u8> {
+        CSGTreeSave {
+            version: CSG_TREE_SAVE_VERSION,
+            dimensions: 3,
+            root: 0,
+            nodes,
+            grids: vec![],
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let csg = new_tree();
+        let loaded = Tree::from_binary(&csg.to_binary().unwrap()).unwrap();
+
+        assert_eq!(csg.nodes.len(), loaded.nodes.len());
+        assert_eq!(csg.root, loaded.root);
+        assert_same_values(&csg, &loaded);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let csg = new_tree();
+        let loaded = Tree::from_json(&csg.to_json().unwrap()).unwrap();
+
+        assert_eq!(csg.nodes.len(), loaded.nodes.len());
+        assert_same_values(&csg, &loaded);
+    }
+
+    fn new_grid_tree() -> (Tree, Arc<VoxelGrid>) {
+        let grid = Arc::new(VoxelGrid::from_data(UVec3::splat(4), vec![3; 64]));
+        let mut csg = Tree::new_shared_grid(SharedVoxelGrid {
+            grid: grid.clone(),
+            offset: IVec3::splat(-2),
+            transform: None,
+        });
+        csg.union_sphere(vec3a(5.0, 0.0, 0.0), 3.0, 1);
+        csg.calculate_bounds();
+
+        (csg, grid)
+    }
+
+    #[test]
+    fn referenced_grid_round_trip() {
+        let (csg, grid) = new_grid_tree();
+        let save = csg.to_save_with(|g| Arc::ptr_eq(g, &grid).then(|| "rock".to_string()));
+        assert!(save.grids.is_empty());
+
+        let save = CSGTreeSave::from_binary(&save.to_binary().unwrap()).unwrap();
+        let loaded = Tree::from_save_with(save, |name| (name == "rock").then(|| grid.clone())).unwrap();
+        assert_same_values(&csg, &loaded);
+
+        let shared = loaded.nodes.iter().any(|node| matches!(&node.data, CSGTreeNodeData::SharedVoxelGrid(d) if Arc::ptr_eq(&d.grid, &grid)));
+        assert!(shared);
+    }
+
+    #[test]
+    fn reject_unresolved_grid() {
+        let (csg, grid) = new_grid_tree();
+        let save = csg.to_save_with(|_| Some("rock".to_string()));
+
+        assert!(Tree::from_save(save.clone()).is_err());
+        assert!(Tree::from_save_with(save, |name| (name == "tree").then(|| grid.clone())).is_err());
+    }
+
+    #[test]
+    fn embedded_grid_round_trip() {
+        let (csg, _) = new_grid_tree();
+        let save = csg.to_save();
+        assert_eq!(save.grids.len(), 1);
+
+        let loaded = Tree::from_json(&csg.to_json().unwrap()).unwrap();
+        assert_same_values(&csg, &loaded);
+    }
+
+    #[test]
+    fn extrude_round_trip() {
+        let profile = CSGTreeProfile::new_box_float(vec2(0.0, 0.0), vec2(6.0, 4.0), 1);
+        let mut csg = Tree::new_extrude(profile, 8.0, 0.5).unwrap();
+        csg.calculate_bounds();
+
+        let loaded = Tree::from_binary(&csg.to_binary().unwrap()).unwrap();
+        assert_same_values(&csg, &loaded);
+
+        let mut save = csg.to_save();
+        for node in save.nodes.iter_mut() {
+            if let CSGTreeNodeDataSave::Extrude { height, .. } = &mut node.data {
+                *height = -1.0;
+            }
+        }
+        assert!(Tree::from_save(save).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_version() {
+        let mut bytes = new_tree().to_binary().unwrap();
+        bytes[4..8].copy_from_slice(&(CSG_TREE_SAVE_VERSION + 1).to_le_bytes());
+        assert!(Tree::from_binary(&bytes).is_err());
+
+        let mut save = new_tree().to_save();
+        save.version = 0;
+        assert!(Tree::from_save(save).is_err());
+    }
+
+    #[test]
+    fn reject_bad_magic() {
+        let mut bytes = new_tree().to_binary().unwrap();
+        bytes[0] = b'X';
+        assert!(Tree::from_binary(&bytes).is_err());
+        assert!(Tree::from_binary(&bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn reject_out_of_range_indecies() {
+        let save = new_save(vec![
+            CSGTreeNodeSave { data: CSGTreeNodeDataSave::Union { indecies: vec![1] }, parent: CSG_TREE_INDEX_INVALID },
+        ]);
+        assert!(Tree::from_save(save).is_err());
+
+        let save = new_save(vec![
+            CSGTreeNodeSave { data: CSGTreeNodeDataSave::None, parent: 5 },
+        ]);
+        assert!(Tree::from_save(save).is_err());
+    }
+
+    #[test]
+    fn reject_cycles() {
+        let save = new_save(vec![
+            CSGTreeNodeSave { data: CSGTreeNodeDataSave::Union { indecies: vec![1] }, parent: 1 },
+            CSGTreeNodeSave { data: CSGTreeNodeDataSave::Union { indecies: vec![0] }, parent: 0 },
+        ]);
+        assert!(Tree::from_save(save).is_err());
+    }
+}
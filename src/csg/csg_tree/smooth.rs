@@ -0,0 +1,49 @@
+use super::tree::CSGTreeIndex;
+
+// The blend zone never reaches further than blend from the hard union or cut.
+#[derive(Debug, Clone, Default)]
+pub struct CSGTreeSmoothUnion {
+    pub indecies: Vec<CSGTreeIndex>,
+    pub blend: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CSGTreeSmoothRemove {
+    pub base: CSGTreeIndex,
+    pub remove: CSGTreeIndex,
+    pub blend: f32,
+}
+
+impl CSGTreeSmoothUnion {
+    pub fn new(indecies: Vec<CSGTreeIndex>, blend: f32) -> Self {
+        Self {
+            indecies,
+            blend,
+        }
+    }
+
+    pub fn add_node(&mut self, index: CSGTreeIndex) {
+        self.indecies.push(index);
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        for index in self.indecies.iter_mut() {
+            *(index) += ammount;
+        }
+    }
+}
+
+impl CSGTreeSmoothRemove {
+    pub fn new(base: CSGTreeIndex, remove: CSGTreeIndex, blend: f32) -> Self {
+        Self {
+            base,
+            remove,
+            blend,
+        }
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        self.base += ammount;
+        self.remove += ammount;
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{csg::primitves::get_distance_scale, util::{aabb::AABB, aabb_transformer::AABBTransformer, matrix::Ma, number::Nu, vector::Ve}};
+
+use super::tree::CSGTreeIndex;
+
+// Applies a matrix to the whole subtree below child.
+#[derive(Debug, Clone)]
+pub struct CSGTreeTransform<V: Ve<f32, D>, const D: usize> {
+    pub child: CSGTreeIndex,
+    matrix: V::Matrix,
+    inverse_transfomer: AABBTransformer<V::Matrix, V, D>,
+    distance_scale: f32,
+}
+
+impl<VF: Ve<f32, D>, const D: usize> CSGTreeTransform<VF, D> {
+    pub fn new(child: CSGTreeIndex, matrix: VF::Matrix) -> Self {
+        Self {
+            child,
+            matrix,
+            inverse_transfomer: AABBTransformer::new(matrix.inverse()),
+            distance_scale: get_distance_scale::<VF, D>(&matrix),
+        }
+    }
+
+    pub fn get_mat(&self) -> VF::Matrix {
+        self.matrix
+    }
+
+    pub fn set_mat(&mut self, mat: VF::Matrix) {
+        self.inverse_transfomer = AABBTransformer::new(mat.inverse());
+        self.distance_scale = get_distance_scale::<VF, D>(&mat);
+        self.matrix = mat;
+    }
+
+    pub fn shift_indecies(&mut self, ammount: usize) {
+        self.child += ammount;
+    }
+
+    pub fn get_local_pos_f(&self, pos: VF) -> VF {
+        self.inverse_transfomer.transform_pos(pos)
+    }
+
+    pub fn get_local_dir(&self, dir: VF) -> VF {
+        self.inverse_transfomer.transform_dir(dir)
+    }
+
+    // The local aabb contains the transformed aabb, so Full and Empty results stay correct.
+    pub fn get_local_aabb<V: Ve<T, D>, T: Nu>(&self, aabb: AABB<V, T, D>) -> AABB<V, T, D> {
+        let aabb = self.inverse_transfomer.transform_aabb(aabb.to_f());
+        AABB::new(from_vecf_rounded(aabb.min(), f32::floor), from_vecf_rounded(aabb.max(), f32::ceil))
+    }
+
+    pub fn get_world_aabb<V: Ve<T, D>, T: Nu>(&self, aabb: AABB<V, T, D>) -> AABB<V, T, D> {
+        if aabb.min().cmpgt_any(aabb.max()) {
+            return AABB::default();
+        }
+
+        let aabb: AABB<VF, f32, D> = aabb.to_f::<VF>().mul_mat(&self.matrix);
+        AABB::new(from_vecf_rounded(aabb.min(), f32::floor), from_vecf_rounded(aabb.max(), f32::ceil))
+    }
+
+    pub fn get_world_normal(&self, normal: VF) -> VF {
+        self.inverse_transfomer.transform_normal(normal)
+    }
+
+    pub fn get_world_distance(&self, distance: f32) -> f32 {
+        distance * self.distance_scale
+    }
+
+    pub fn get_local_distance(&self, distance: f32) -> f32 {
+        distance / self.distance_scale
+    }
+}
+
+// Integer positions can not hold the fraction after a transform, so they get rounded.
+// Only bounds and voxel grids are rounded, everything else is sampled at the float position.
+pub(super) fn from_vecf_rounded<V: Ve<T, D>, T: Nu, VF: Ve<f32, D>, const D: usize>(v: VF, round: fn(f32) -> f32) -> V {
+    if T::EPSILON == T::ZERO {
+        V::new(v.to_array().map(|x| T::from_f32(round(x))))
+    } else {
+        V::from_vecf(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3, IVec3, Mat4, Quat, Vec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, volume::{VolumeBounds, VolumeQureyPosValue}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    #[test]
+    fn sample_at_float_position() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 1.2, 1);
+        csg.transform_at_index(0, Mat4::from_scale(Vec3::splat(4.0)));
+        csg.calculate_bounds();
+
+        // In the child 5 is at 1.25, which would be inside if it was rounded to 1.
+        assert_eq!(csg.get_value(IVec3::new(4, 0, 0)), 1);
+        assert_eq!(csg.get_value(IVec3::new(5, 0, 0)), 0);
+    }
+
+    #[test]
+    fn changed_bounds_in_root_space() {
+        let mut csg = Tree::new_sphere_float(Vec3A::ZERO, 2.0, 1);
+        csg.transform_at_index(0, Mat4::from_translation(vec3(10.0, 0.0, 0.0)));
+        csg.calculate_bounds();
+        csg.reset_changed_bounds();
+
+        csg.set_mat(0, Mat4::from_scale_rotation_translation(Vec3::splat(2.0), Quat::IDENTITY, vec3(3.0, 0.0, 0.0)));
+
+        // The sphere moved from 8..12 to 11..15 in the space of the root.
+        let changed = csg.changed_bounds;
+        assert!(changed.min().x >= 6 && changed.min().x <= 8, "{changed:?}");
+        assert!(changed.max().x >= 15 && changed.max().x <= 17, "{changed:?}");
+    }
+}
@@ -1,8 +1,8 @@
 use octa_force::glam::Mat4;
 
-use crate::{csg::{Base, primitves::{CSGPrimitive, r#box::CSGBox, cylinder::CSGCylinder, sphere::CSGSphere}}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
+use crate::{csg::{Base, primitves::{CSGPrimitive, r#box::CSGBox, capsule::CSGCapsule, cone::CSGCone, cylinder::CSGCylinder, plane::CSGPlane, sphere::CSGSphere, torus::CSGTorus, wedge::CSGWedge}}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::heightmap::Heightmap, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
 
-use super::{remove::CSGTreeRemove, union::CSGTreeUnion};
+use super::{displace::CSGTreeDisplace, extrude::{CSGTreeExtrude, CSGTreeRevolve}, intersect::CSGTreeIntersect, paint::CSGTreePaint, remove::CSGTreeRemove, smooth::{CSGTreeSmoothRemove, CSGTreeSmoothUnion}, repeat::CSGTreeRepeat, transform::CSGTreeTransform, union::CSGTreeUnion};
 
 pub type CSGTreeIndex = usize; 
 pub const CSG_TREE_INDEX_INVALID: CSGTreeIndex = CSGTreeIndex::MAX;
@@ -11,13 +11,28 @@ pub const CSG_TREE_INDEX_INVALID: CSGTreeIndex = CSGTreeIndex::MAX;
 pub enum CSGTreeNodeData<M, V: Ve<T, D>, T: Nu, const D: usize> {
     Union(CSGTreeUnion<V, T, D>),
     Cut(CSGTreeRemove),
+    SmoothUnion(CSGTreeSmoothUnion),
+    SmoothCut(CSGTreeSmoothRemove),
+    Intersect(CSGTreeIntersect),
+    Transform(CSGTreeTransform<V::VectorF, D>),
+    Repeat(CSGTreeRepeat<V::VectorF, D>),
+    Displace(CSGTreeDisplace),
+    Paint(CSGTreePaint),
    
     None,
     Box(CSGPrimitive<CSGBox, M, V::VectorF, D>),
     Sphere(CSGPrimitive<CSGSphere, M, V::VectorF, D>),
     Cylinder(CSGPrimitive<CSGCylinder, M, V::VectorF, D>),
+    Capsule(CSGPrimitive<CSGCapsule, M, V::VectorF, D>),
+    Torus(CSGPrimitive<CSGTorus, M, V::VectorF, D>),
+    Cone(CSGPrimitive<CSGCone, M, V::VectorF, D>),
+    Plane(CSGPrimitive<CSGPlane, M, V::VectorF, D>),
+    Wedge(CSGPrimitive<CSGWedge, M, V::VectorF, D>),
     OffsetVoxelGrid(OffsetVoxelGrid),
     SharedVoxelGrid(SharedVoxelGrid),
+    Heightmap(Heightmap),
+    Extrude(CSGTreeExtrude<M>),
+    Revolve(CSGTreeRevolve<M>),
 }
 
 #[derive(Debug, Clone)]
@@ -44,3 +59,47 @@ impl<M, V: Ve<T, D>, T: Nu, const D: usize> CSGTreeNode<M, V, T, D> {
         }
     }
 }
+
+impl<M, V: Ve<T, D>, T: Nu, const D: usize> CSGTreeNodeData<M, V, T, D> {
+    pub fn get_children(&self) -> Vec<CSGTreeIndex> {
+        match self {
+            CSGTreeNodeData::Union(d) => d.indecies.clone(),
+            CSGTreeNodeData::Cut(d) => vec![d.base, d.remove],
+            CSGTreeNodeData::SmoothUnion(d) => d.indecies.clone(),
+            CSGTreeNodeData::SmoothCut(d) => vec![d.base, d.remove],
+            CSGTreeNodeData::Intersect(d) => d.indecies.clone(),
+            CSGTreeNodeData::Transform(d) => vec![d.child],
+            CSGTreeNodeData::Repeat(d) => vec![d.child],
+            CSGTreeNodeData::Displace(d) => vec![d.child],
+            CSGTreeNodeData::Paint(d) => vec![d.base, d.brush],
+            _ => vec![],
+        }
+    }
+
+    pub fn map_children(&mut self, mut f: impl FnMut(CSGTreeIndex) -> CSGTreeIndex) {
+        match self {
+            CSGTreeNodeData::Union(d) => {
+                d.indecies.iter_mut().for_each(|index| *index = f(*index));
+                d.needs_bounds_recompute = true;
+            },
+            CSGTreeNodeData::Cut(d) => {
+                d.base = f(d.base);
+                d.remove = f(d.remove);
+            },
+            CSGTreeNodeData::SmoothUnion(d) => d.indecies.iter_mut().for_each(|index| *index = f(*index)),
+            CSGTreeNodeData::SmoothCut(d) => {
+                d.base = f(d.base);
+                d.remove = f(d.remove);
+            },
+            CSGTreeNodeData::Intersect(d) => d.indecies.iter_mut().for_each(|index| *index = f(*index)),
+            CSGTreeNodeData::Transform(d) => d.child = f(d.child),
+            CSGTreeNodeData::Repeat(d) => d.child = f(d.child),
+            CSGTreeNodeData::Displace(d) => d.child = f(d.child),
+            CSGTreeNodeData::Paint(d) => {
+                d.base = f(d.base);
+                d.brush = f(d.brush);
+            },
+            _ => {},
+        }
+    }
+}
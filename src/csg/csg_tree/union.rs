@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use crate::{bvh::{Bvh, node::BHNode, shape::{BHShape, Shapes}}, csg::primitves::{CSGPrimitive, r#box::CSGBox, cylinder::CSGCylinder, sphere::CSGSphere}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeQureyPosValid}};
+use crate::{bvh::{Bvh, node::BHNode, shape::{BHShape, Shapes}}, csg::primitves::{CSGPrimitive, r#box::CSGBox, capsule::CSGCapsule, cone::CSGCone, cylinder::CSGCylinder, plane::CSGPlane, sphere::CSGSphere, torus::CSGTorus, wedge::CSGWedge}, util::{aabb::AABB, math_config::MC, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeQureyPosValid}};
 
 use super::tree::{CSGTreeNode, CSGTreeNodeData, CSGTree, CSGTreeIndex};
 
@@ -59,14 +59,40 @@ impl<M, V: Ve<T, D>, T: Nu, const D: usize> BHShape<(), V::VectorF, f32, D> for
                 let base = csgtree_remove.base;
                 shapes.aabb(base)
             },
+            CSGTreeNodeData::SmoothUnion(d) => d.indecies.iter()
+                .map(|index| shapes.aabb(*index))
+                .fold(AABB::default(), AABB::union)
+                .expand(d.blend.ceil()),
+            CSGTreeNodeData::SmoothCut(d) => shapes.aabb(d.base),
+            CSGTreeNodeData::Transform(d) => d.get_world_aabb(shapes.aabb(d.child)),
+            CSGTreeNodeData::Repeat(d) => d.get_world_aabb(),
+            CSGTreeNodeData::Displace(d) => d.get_world_aabb(shapes.aabb(d.child)),
+            CSGTreeNodeData::Paint(d) => shapes.aabb(d.base),
+            CSGTreeNodeData::Intersect(d) => d.indecies.iter()
+                .map(|index| shapes.aabb(*index))
+                .reduce(AABB::intersect)
+                .unwrap_or_default(),
             CSGTreeNodeData::Box(d) 
                 => <CSGPrimitive<CSGBox, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
             CSGTreeNodeData::Sphere(d) 
                 => <CSGPrimitive<CSGSphere, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
             CSGTreeNodeData::Cylinder(d) 
                 => <CSGPrimitive<CSGCylinder, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
+            CSGTreeNodeData::Capsule(d) 
+                => <CSGPrimitive<CSGCapsule, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
+            CSGTreeNodeData::Torus(d) 
+                => <CSGPrimitive<CSGTorus, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
+            CSGTreeNodeData::Cone(d) 
+                => <CSGPrimitive<CSGCone, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
+            CSGTreeNodeData::Plane(d) 
+                => <CSGPrimitive<CSGPlane, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
+            CSGTreeNodeData::Wedge(d) 
+                => <CSGPrimitive<CSGWedge, M, V::VectorF, D> as VolumeBounds<V, T, D>>::get_bounds(d).to_f(),
             CSGTreeNodeData::OffsetVoxelGrid(d) => d.get_bounds(),
             CSGTreeNodeData::SharedVoxelGrid(d) => d.get_bounds(),
+            CSGTreeNodeData::Heightmap(d) => d.get_bounds(),
+            CSGTreeNodeData::Extrude(d) => d.get_world_aabb(),
+            CSGTreeNodeData::Revolve(d) => d.get_world_aabb(),
         }
     }
 
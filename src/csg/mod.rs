@@ -5,7 +5,7 @@ use crate::voxel::palette::palette::MATERIAL_ID_BASE;
 pub mod csg_tree;
 pub mod primitves;
 
-pub trait Base: Copy + Default + fmt::Debug + 'static {
+pub trait Base: Copy + Default + fmt::Debug + PartialEq + 'static {
     fn base() -> Self;
 }
 
@@ -1,18 +1,18 @@
 use octa_force::{egui::Vec2, glam::{IVec3, Mat3, Mat4, Quat, UVec3, Vec3, Vec3A, Vec4, vec3, vec3a, vec4}};
 
-use crate::{csg::primitves::{CSGPrimitive, PrimitiveType}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, Ve}}};
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, first_in_range, normalize_or_zero, unit_axis}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, Ve}}};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct CSGBox {}
 
 impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGBox, M, V, D> {
     pub fn new_box(pos: V, size: V, mat: M) -> Self {
-        CSGPrimitive::new(V::Matrix::from_scale_translation(size, pos), mat)
+        CSGPrimitive::new(CSGBox {}, V::Matrix::from_scale_translation(size, pos), mat)
     }
 }
 
 impl PrimitiveType for CSGBox {
-    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> AABB<V, f32, D> {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
         match D {
             2 => {
                 let min = Vec2::splat(-0.5);
@@ -70,7 +70,7 @@ impl PrimitiveType for CSGBox {
         }
     }
 
-    fn sample_pos<V: Ve<f32, D>, const D: usize>(pos: V) -> bool {
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
         let aabb = AABB::<V, f32, D>::new(
             V::new([-0.5; D]), 
             V::new([0.5; D]));
@@ -78,7 +78,7 @@ impl PrimitiveType for CSGBox {
         aabb.pos_in_aabb(pos)
     }
 
-    fn sample_aabb<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
 
         let b = AABB::<V, f32, D>::new(
             V::new([-0.5; D]), 
@@ -92,9 +92,44 @@ impl PrimitiveType for CSGBox {
             super::SampleAABBResult::Empty
         }
     }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let q = pos.abs() - 0.5;
+        let outside = q.max(V::ZERO).length();
+        let inside = q.to_array().into_iter().fold(f32::MIN, f32::max).min(0.0);
+
+        outside + inside
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        box_gradient(pos)
+    }
+
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let range = AABB::<V, f32, D>::new(
+            V::new([-0.5; D]), 
+            V::new([0.5; D])).ray_range(origin, dir);
+
+        first_in_range(range, min, max)
+    }
 }
 
 
+// Outside the closest point on the surface is used, inside the face of the closest side.
+pub fn box_gradient<V: Ve<f32, D>, const D: usize>(pos: V) -> V {
+    let q = pos.abs() - 0.5;
+    let outside = q.max(V::ZERO);
+
+    if outside.cmpgt_any(V::ZERO) {
+        return normalize_or_zero(outside * pos.signum());
+    }
+
+    // Inside all values of q are negative, the largest one belongs to the closest face.
+    let q = q.to_array();
+    let axis = (0..D).max_by(|a, b| q[*a].total_cmp(&q[*b])).unwrap();
+    unit_axis::<V, D>(axis) * pos[axis].signum()
+}
+
 /*
 
 
@@ -0,0 +1,169 @@
+use octa_force::glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, SampleAABBResult, contains_aabb_convex, first_in_range, height_range, intersect_range, normalize_or_zero, quadratic_range, radial_range, transformed_sphere_bounds}, util::{aabb::AABB, vector::{CastFrom, Ve}}};
+
+// Unit radius capsule around the segment from -half_length to half_length on the last axis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CSGCapsule {
+    pub half_length: f32,
+}
+
+impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGCapsule, M, V, D> {
+    pub fn new_capsule_from_a_to_b(a: V, b: V, r: f32, mat: M) -> Self {
+        let (matrix, half_length) = capsule_between(a, b, r);
+        CSGPrimitive::new(CSGCapsule { half_length }, matrix, mat)
+    }
+}
+
+pub fn capsule_between<V: Ve<f32, D>, const D: usize>(a: V, b: V, r: f32) -> (V::Matrix, f32) {
+    let axis = b - a;
+    let len = axis.length();
+    let dir = if len > 0.0 { axis / len } else { V::new(std::array::from_fn(|i| if i == D - 1 { 1.0 } else { 0.0 })) };
+
+    let center = (a + b) * 0.5;
+
+    let mat = match D {
+        2 => {
+            let dir: Vec2 = dir.ve_into();
+            let center: Vec2 = center.ve_into();
+
+            V::Matrix::cast_from(Mat3::from_scale_angle_translation(
+                Vec2::splat(r),
+                f32::atan2(-dir.x, dir.y),
+                center))
+        },
+        3 => {
+            let rot = Quat::from_rotation_arc(Vec3::Z, dir.ve_into());
+
+            V::Matrix::cast_from(Mat4::from_translation(center.ve_into())
+                * Mat4::from_quat(rot)
+                * Mat4::from_scale(Vec3::splat(r)))
+        },
+        _ => unreachable!()
+    };
+
+    (mat, len * 0.5 / r)
+}
+
+impl CSGCapsule {
+    fn segment_end<V: Ve<f32, D>, const D: usize>(&self, sign: f32) -> V {
+        V::new(std::array::from_fn(|i| if i == D - 1 { self.half_length * sign } else { 0.0 }))
+    }
+}
+
+impl PrimitiveType for CSGCapsule {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
+        transformed_sphere_bounds(mat, self.segment_end::<V, D>(-1.0), 1.0)
+            .union(transformed_sphere_bounds(mat, self.segment_end::<V, D>(1.0), 1.0))
+    }
+
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
+        let mut arr = pos.to_array();
+        arr[D-1] -= arr[D-1].clamp(-self.half_length, self.half_length);
+
+        V::new(arr).length_squared() < 1.0
+    }
+
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult {
+        // The segment is axis aligned so the distance to the aabb is the distance between two aabbs.
+        let segment = AABB::new(self.segment_end::<V, D>(-1.0), self.segment_end::<V, D>(1.0));
+        let min = aabb.min().to_array();
+        let max = aabb.max().to_array();
+
+        let dist: f32 = (0..D)
+            .map(|i| {
+                let d = (min[i] - segment.max()[i]).max(segment.min()[i] - max[i]).max(0.0);
+                d * d
+            })
+            .sum();
+
+        if dist >= 1.0 {
+            SampleAABBResult::Empty
+        } else if contains_aabb_convex(self, aabb) {
+            SampleAABBResult::Full
+        } else {
+            SampleAABBResult::Mixed
+        }
+    }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let mut arr = pos.to_array();
+        arr[D-1] -= arr[D-1].clamp(-self.half_length, self.half_length);
+
+        V::new(arr).length() - 1.0
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        let mut arr = pos.to_array();
+        arr[D-1] -= arr[D-1].clamp(-self.half_length, self.half_length);
+
+        normalize_or_zero(V::new(arr))
+    }
+
+    // The capsule is convex, so the ranges of the body and the two caps merge into one.
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let body = intersect_range(radial_range(origin, dir), height_range(origin, dir, self.half_length));
+        let cap = |sign: f32| {
+            let origin = origin - self.segment_end::<V, D>(sign);
+            quadratic_range(dir.dot(dir), 2.0 * origin.dot(dir), origin.dot(origin) - 1.0)
+        };
+
+        let range = [body, cap(-1.0), cap(1.0)].into_iter()
+            .flatten()
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)));
+
+        first_in_range(range, min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, Vec3A};
+
+    use crate::{csg::primitves::CSGPrimitive, volume::{VolumeGradient, VolumeQureyRay}};
+
+    use super::CSGCapsule;
+
+    fn new_capsule() -> CSGPrimitive<CSGCapsule, u8, Vec3A, 3> {
+        CSGPrimitive::new_capsule_from_a_to_b(vec3a(0.0, 0.0, -2.0), vec3a(0.0, 0.0, 2.0), 1.0, 1)
+    }
+
+    #[test]
+    fn distance() {
+        let capsule = new_capsule();
+        assert!((capsule.get_distance(vec3a(3.0, 0.0, 0.0)) - 2.0).abs() < 1e-5);
+        assert!((capsule.get_distance(vec3a(0.0, 0.0, 5.0)) - 2.0).abs() < 1e-5);
+        assert!((capsule.get_distance(Vec3A::ZERO) + 1.0).abs() < 1e-5);
+
+        // Rotated onto the x axis and scaled by the radius.
+        let capsule = CSGPrimitive::<CSGCapsule, u8, Vec3A, 3>::new_capsule_from_a_to_b(Vec3A::ZERO, vec3a(10.0, 0.0, 0.0), 2.0, 1);
+        assert!((capsule.get_distance(vec3a(5.0, 5.0, 0.0)) - 3.0).abs() < 1e-4);
+        assert!((capsule.get_distance(vec3a(13.0, 0.0, 0.0)) - 1.0).abs() < 1e-4);
+        assert_eq!(capsule.get_value_f(vec3a(11.5, 0.0, 0.0)), 1);
+        assert_eq!(capsule.get_value_f(vec3a(5.0, 2.5, 0.0)), 0);
+    }
+
+    #[test]
+    fn ray() {
+        let capsule = new_capsule();
+        let hit = capsule.get_ray_hit(vec3a(-5.0, 0.0, 0.0), Vec3A::X, 100.0).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+
+        let hit = capsule.get_ray_hit(vec3a(0.0, 0.0, 10.0), Vec3A::NEG_Z, 100.0).unwrap();
+        assert!((hit.t - 7.0).abs() < 1e-5);
+
+        assert!(capsule.get_ray_hit(vec3a(-5.0, 2.0, 0.0), Vec3A::X, 100.0).is_none());
+        assert!(capsule.get_ray_hit(vec3a(-5.0, 0.0, 0.0), Vec3A::X, 3.0).is_none());
+    }
+
+    #[test]
+    fn gradient() {
+        let capsule = new_capsule();
+        assert!(capsule.get_gradient_at_position(vec3a(3.0, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::X, 1e-5));
+        assert!(capsule.get_gradient_at_position(vec3a(0.0, 0.0, 5.0)).normalize().abs_diff_eq(Vec3A::Z, 1e-5));
+        assert!(capsule.get_gradient_at_position(vec3a(1.0, 0.0, 3.0)).normalize().abs_diff_eq(vec3a(1.0, 0.0, 1.0).normalize(), 1e-5));
+
+        let hit = capsule.get_ray_hit(vec3a(0.0, 0.0, 10.0), Vec3A::NEG_Z, 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(Vec3A::Z, 1e-5));
+    }
+}
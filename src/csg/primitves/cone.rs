@@ -0,0 +1,222 @@
+use octa_force::glam::{Vec2, vec2};
+
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, SampleAABBResult, contains_aabb_convex, first_in_range, height_range, intersect_range, linear_range, normalize_or_zero, quadratic_range, unit_axis}, util::{aabb::AABB, matrix::Ma, vector::Ve}};
+
+// Unit cone with the base of radius 1 at -1 and the tip at 1 on the last axis.
+// In 2D this is a triangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CSGCone {}
+
+impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGCone, M, V, D> {
+    pub fn new_cone(base: V, radius: f32, height: f32, mat: M) -> Self {
+        let scale = V::new(std::array::from_fn(|i| if i == D - 1 { height * 0.5 } else { radius }));
+        let center = base + V::new(std::array::from_fn(|i| if i == D - 1 { height * 0.5 } else { 0.0 }));
+
+        CSGPrimitive::new(CSGCone {}, V::Matrix::from_scale_translation(scale, center), mat)
+    }
+}
+
+impl CSGCone {
+    fn radial_and_height<V: Ve<f32, D>, const D: usize>(pos: V) -> (f32, f32) {
+        let arr = pos.to_array();
+        let radial = arr[..(D-1)].iter()
+            .map(|i| i * i)
+            .sum::<f32>()
+            .sqrt();
+
+        (radial, arr[D-1])
+    }
+}
+
+impl PrimitiveType for CSGCone {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
+        // The base circle and the tip.
+        let mut aabb = AABB::<V, f32, D>::new(
+            V::new([-1.0; D]),
+            V::new(std::array::from_fn(|i| if i == D - 1 { -1.0 } else { 1.0 })),
+        ).mul_mat::<V::Matrix, V>(mat);
+        aabb.union_point_mut(mat.mul_vector(V::new(std::array::from_fn(|i| if i == D - 1 { 1.0 } else { 0.0 }))));
+
+        aabb
+    }
+
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
+        let (radial, height) = Self::radial_and_height(pos);
+
+        height >= -1.0 && height <= 1.0 && radial <= (1.0 - height) * 0.5
+    }
+
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult {
+        let min = aabb.min().to_array();
+        let max = aabb.max().to_array();
+        if max[D-1] < -1.0 || min[D-1] > 1.0 {
+            return SampleAABBResult::Empty;
+        }
+
+        // Closest radial distance and the widest part of the cone inside of the aabb height.
+        let radial: f32 = (0..(D-1))
+            .map(|i| (min[i].max(0.0) - max[i].min(0.0)).max(0.0).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        if radial > (1.0 - min[D-1].max(-1.0)) * 0.5 {
+            return SampleAABBResult::Empty;
+        }
+
+        if contains_aabb_convex(self, aabb) {
+            SampleAABBResult::Full
+        } else {
+            SampleAABBResult::Mixed
+        }
+    }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let (radial, height) = Self::radial_and_height(pos);
+
+        triangle_distance(vec2(radial, height), vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(0.0, 1.0))
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        let (radial, height) = Self::radial_and_height(pos);
+        let grad = triangle_gradient(vec2(radial, height), vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(0.0, 1.0));
+
+        let mut radial_dir = pos.to_array();
+        radial_dir[D-1] = 0.0;
+        let radial_dir = normalize_or_zero(V::new(radial_dir));
+
+        radial_dir * grad.x + unit_axis::<V, D>(D-1) * grad.y
+    }
+
+    // Inside of the height range the cone is radial^2 <= ((1 - height) / 2)^2.
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let height = height_range(origin, dir, 1.0);
+        let to_tip = 1.0 - origin[D-1];
+
+        let a = (0..(D-1)).map(|i| dir[i] * dir[i]).sum::<f32>() - 0.25 * dir[D-1] * dir[D-1];
+        let b = (0..(D-1)).map(|i| 2.0 * origin[i] * dir[i]).sum::<f32>() + 0.5 * to_tip * dir[D-1];
+        let c = (0..(D-1)).map(|i| origin[i] * origin[i]).sum::<f32>() - 0.25 * to_tip * to_tip;
+
+        let range = if a >= -f32::EPSILON {
+            intersect_range(quadratic_range(a.max(0.0), b, c), height)
+        } else {
+            // The ray passes both halfs of the double cone, only one of them lies in the height range.
+            let disc = b * b - 4.0 * a * c;
+            if disc < 0.0 {
+                height
+            } else {
+                let s = disc.sqrt();
+                let (lo, hi) = ((-b + s) / (2.0 * a), (-b - s) / (2.0 * a));
+                [(f32::NEG_INFINITY, lo), (hi, f32::INFINITY)].into_iter()
+                    .find_map(|range| intersect_range(Some(range), height))
+            }
+        };
+
+        first_in_range(range, min, max)
+    }
+}
+
+// Signed distance to a 2D triangle from https://iquilezles.org/articles/distfunctions2d/
+pub fn triangle_distance(p: Vec2, p0: Vec2, p1: Vec2, p2: Vec2) -> f32 {
+    let e0 = p1 - p0;
+    let e1 = p2 - p1;
+    let e2 = p0 - p2;
+    let v0 = p - p0;
+    let v1 = p - p1;
+    let v2 = p - p2;
+
+    let pq0 = v0 - e0 * (v0.dot(e0) / e0.dot(e0)).clamp(0.0, 1.0);
+    let pq1 = v1 - e1 * (v1.dot(e1) / e1.dot(e1)).clamp(0.0, 1.0);
+    let pq2 = v2 - e2 * (v2.dot(e2) / e2.dot(e2)).clamp(0.0, 1.0);
+
+    let s = (e0.x * e2.y - e0.y * e2.x).signum();
+    let d = vec2(pq0.dot(pq0), s * (v0.x * e0.y - v0.y * e0.x))
+        .min(vec2(pq1.dot(pq1), s * (v1.x * e1.y - v1.y * e1.x)))
+        .min(vec2(pq2.dot(pq2), s * (v2.x * e2.y - v2.y * e2.x)));
+
+    -d.x.sqrt() * d.y.signum()
+}
+
+// Gradient of triangle_distance, the direction from the closest point on the edges.
+// On an edge that direction is zero, so the outward normal of the edge is used instead.
+pub fn triangle_gradient(p: Vec2, p0: Vec2, p1: Vec2, p2: Vec2) -> Vec2 {
+    let (closest, a, e) = [(p0, p1), (p1, p2), (p2, p0)]
+        .into_iter()
+        .map(|(a, b)| {
+            let e = b - a;
+            let v = p - a;
+            (v - e * (v.dot(e) / e.dot(e)).clamp(0.0, 1.0), a, e)
+        })
+        .min_by(|a, b| a.0.length_squared().total_cmp(&b.0.length_squared()))
+        .unwrap();
+
+    if closest.length_squared() > 0.0 {
+        return closest.normalize() * triangle_distance(p, p0, p1, p2).signum();
+    }
+
+    let normal = vec2(e.y, -e.x).normalize_or_zero();
+    let center = (p0 + p1 + p2) / 3.0;
+    if normal.dot(a - center) < 0.0 { -normal } else { normal }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec2, vec3a, Vec3A};
+
+    use crate::{csg::primitves::CSGPrimitive, volume::{VolumeGradient, VolumeQureyRay}};
+
+    use super::{triangle_gradient, CSGCone};
+
+    // Base of radius 2 at z = 0 and the tip at z = 4.
+    fn new_cone() -> CSGPrimitive<CSGCone, u8, Vec3A, 3> {
+        CSGPrimitive::new_cone(Vec3A::ZERO, 2.0, 4.0, 1)
+    }
+
+    #[test]
+    fn distance() {
+        let cone = new_cone();
+        assert!((cone.get_distance(vec3a(0.0, 0.0, -1.0)) - 1.0).abs() < 1e-5);
+        assert!((cone.get_distance(vec3a(0.0, 0.0, 6.0)) - 2.0).abs() < 1e-5);
+        assert!(cone.get_distance(vec3a(0.0, 0.0, 1.0)) < 0.0);
+        assert_eq!(cone.get_value_f(vec3a(1.4, 0.0, 1.0)), 1);
+        assert_eq!(cone.get_value_f(vec3a(1.6, 0.0, 1.0)), 0);
+        assert_eq!(cone.get_value_f(vec3a(0.0, 0.0, -0.5)), 0);
+    }
+
+    #[test]
+    fn ray() {
+        let cone = new_cone();
+        let hit = cone.get_ray_hit(vec3a(0.0, 0.0, -5.0), Vec3A::Z, 100.0).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+
+        // At z = 1 the radius is 1.5.
+        let hit = cone.get_ray_hit(vec3a(-5.0, 0.0, 1.0), Vec3A::X, 100.0).unwrap();
+        assert!((hit.t - 3.5).abs() < 1e-5);
+
+        let hit = cone.get_ray_hit(vec3a(0.0, 0.0, 10.0), Vec3A::NEG_Z, 100.0).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-5);
+
+        assert!(cone.get_ray_hit(vec3a(-5.0, 0.0, 5.0), Vec3A::X, 100.0).is_none());
+    }
+
+    #[test]
+    fn gradient() {
+        let cone = new_cone();
+        assert!(cone.get_gradient_at_position(vec3a(0.0, 0.0, -1.0)).normalize().abs_diff_eq(Vec3A::NEG_Z, 1e-5));
+        assert!(cone.get_gradient_at_position(vec3a(0.0, 0.0, 6.0)).normalize().abs_diff_eq(Vec3A::Z, 1e-5));
+
+        // Hits lie on the surface, where the direction to the closest point is zero.
+        let hit = cone.get_ray_hit(vec3a(0.0, 0.0, -5.0), Vec3A::Z, 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(Vec3A::NEG_Z, 1e-5));
+
+        let hit = cone.get_ray_hit(vec3a(-5.0, 0.0, 1.0), Vec3A::X, 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(vec3a(-2.0, 0.0, 1.0).normalize(), 1e-4));
+    }
+
+    #[test]
+    fn triangle_gradient_on_edges() {
+        let (p0, p1, p2) = (vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(0.0, 1.0));
+        assert!(triangle_gradient(vec2(0.0, -1.0), p0, p1, p2).abs_diff_eq(vec2(0.0, -1.0), 1e-5));
+        assert!(triangle_gradient(vec2(0.5, 0.0), p0, p1, p2).abs_diff_eq(vec2(2.0, 1.0).normalize(), 1e-5));
+        assert!(triangle_gradient(vec2(0.0, -2.0), p0, p1, p2).abs_diff_eq(vec2(0.0, -1.0), 1e-5));
+        assert!(triangle_gradient(vec2(0.0, -0.9), p0, p1, p2).abs_diff_eq(vec2(0.0, -1.0), 1e-5));
+    }
+}
@@ -1,14 +1,14 @@
 use octa_force::glam::{IVec3, Mat3, Mat4, Quat, UVec3, Vec2, Vec3, Vec3A, Vec4, vec3, vec3a, vec4};
 
-use crate::{csg::primitves::{CSGPrimitive, PrimitiveType}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, IntoT, Ve}}};
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, first_in_range, height_range, intersect_range, normalize_or_zero, radial_range, unit_axis}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, IntoT, Ve}}};
 use crate::util::vector::CastFrom;   
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct CSGCylinder {}
 
 impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGCylinder, M, V, D> {
     pub fn new_cylinder_from_a_to_b(a: V, b: V, r: f32, mat: M) -> Self {
-         CSGPrimitive::new( cylinder_between(a, b, r), mat)
+         CSGPrimitive::new(CSGCylinder {}, cylinder_between(a, b, r), mat)
     }
 }
 
@@ -33,7 +33,7 @@ pub fn cylinder_between<V: Ve<f32, D>, const D: usize>(a: V, b: V, r: f32) -> V:
 }
 
 impl PrimitiveType for CSGCylinder {
-    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> AABB<V, f32, D> {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
         match D {
             2 => {
                 let mat: Mat3 = mat.cast_into();
@@ -69,7 +69,7 @@ impl PrimitiveType for CSGCylinder {
         }
     }
 
-    fn sample_pos<V: Ve<f32, D>, const D: usize>(pos: V) -> bool {
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
         let arr = pos.to_array();
 
         // height check
@@ -86,7 +86,7 @@ impl PrimitiveType for CSGCylinder {
         radial <= 1.0
     }
 
-    fn sample_aabb<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
         let min = aabb.min().to_array();
         let max = aabb.max().to_array();
         // height check
@@ -124,5 +124,46 @@ impl PrimitiveType for CSGCylinder {
 
         super::SampleAABBResult::Mixed
     }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let arr = pos.to_array();
+
+        let radial = arr[..(D-1)].iter()
+            .map(|i| i * i)
+            .sum::<f32>()
+            .sqrt() - 1.0;
+        let height = arr[D-1].abs() - 1.0;
+
+        let outside = (radial.max(0.0).powi(2) + height.max(0.0).powi(2)).sqrt();
+        let inside = radial.max(height).min(0.0);
+
+        outside + inside
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        let mut radial_dir = pos.to_array();
+        radial_dir[D-1] = 0.0;
+        let radial_dir = V::new(radial_dir);
+
+        let radial_len = radial_dir.length();
+        let radial_dir = normalize_or_zero(radial_dir);
+        let height_dir = unit_axis::<V, D>(D-1) * pos[D-1].signum();
+
+        let radial = radial_len - 1.0;
+        let height = pos[D-1].abs() - 1.0;
+
+        if radial > 0.0 || height > 0.0 {
+            normalize_or_zero(radial_dir * radial.max(0.0) + height_dir * height.max(0.0))
+        } else if radial > height {
+            radial_dir
+        } else {
+            height_dir
+        }
+    }
+
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let range = intersect_range(radial_range(origin, dir), height_range(origin, dir, 1.0));
+        first_in_range(range, min, max)
+    }
 }
 
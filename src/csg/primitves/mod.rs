@@ -1,20 +1,24 @@
-use std::marker::PhantomData;
-
-use crate::{util::{aabb::AABB, aabb_transformer::AABBTransformer, matrix::Ma, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeGradient, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValid, VolumeQureyPosValue}, voxel::palette::palette::MATERIAL_ID_NONE};
+use crate::{util::{aabb::AABB, aabb_transformer::AABBTransformer, matrix::Ma, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeGradient, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyDistance, VolumeQureyPosValid, VolumeQureyPosValue, VolumeQureyRay, VolumeRayHit}, voxel::palette::palette::MATERIAL_ID_NONE};
 
 pub mod r#box;
 pub mod sphere;
 pub mod all;
 pub mod cylinder;
+pub mod capsule;
+pub mod torus;
+pub mod cone;
+pub mod plane;
+pub mod wedge;
 
 #[derive(Debug, Copy, Clone)]
 pub struct CSGPrimitive<P: PrimitiveType, M, V: Ve<f32, D>, const D: usize> {
     matrix: V::Matrix,
     inverse_transfomer: AABBTransformer<V::Matrix, V, D>,
     material: M,
+    distance_scale: f32,
     aabb: AABB<V, f32, D>,
     needs_aabb_recompute: bool,
-    primitive_type: PhantomData<P>,
+    primitive: P,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -24,35 +28,183 @@ pub enum SampleAABBResult {
     Mixed,
 }
 
-pub trait PrimitiveType: Copy {
-    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> AABB<V, f32, D>;
+// Shape parameters that a matrix can not express (like the length of a capsule) live in the primitive itself.
+pub trait PrimitiveType: Copy + PartialEq {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D>;
 
-    fn sample_pos<V: Ve<f32, D>, const D: usize>(pos: V) -> bool;
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool;
     
-    fn sample_aabb<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> SampleAABBResult;
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult;
+
+    // Signed distance in primitive space. Negative inside.
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32;
+
+    // Gradient of the distance in primitive space. Points outwards.
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V;
+
+    // First ray parameter in [min, max] that is inside of the primitive.
+    // The direction is not normalized, so the parameter is the same in world space.
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32>;
 }
 
 impl<P: PrimitiveType, M, V: Ve<f32, D>, const D: usize> CSGPrimitive<P, M, V, D> {
-    pub fn new(matrix: V::Matrix, material: M) -> Self {
+    pub fn new(primitive: P, matrix: V::Matrix, material: M) -> Self {
         Self {
             inverse_transfomer: AABBTransformer::new(matrix.inverse()),
+            distance_scale: get_distance_scale::<V, D>(&matrix),
             matrix,
             material,
             aabb: AABB::default(),
             needs_aabb_recompute: true,
-            primitive_type: PhantomData,
+            primitive,
         }
     }
 
+    pub fn get_primitive(&self) -> P {
+        self.primitive
+    }
+
+    pub fn set_primitive(&mut self, primitive: P) {
+        self.primitive = primitive;
+        self.needs_aabb_recompute = true;
+    }
+
     pub fn get_mat(&self) -> V::Matrix {
         self.matrix
     }
 
     pub fn set_mat(&mut self, mat: V::Matrix) {
         self.inverse_transfomer = AABBTransformer::new(mat.inverse());
+        self.distance_scale = get_distance_scale::<V, D>(&mat);
         self.matrix = mat;
         self.needs_aabb_recompute = true;
     }
+
+    pub fn get_distance(&self, pos: V) -> f32 {
+        let pos = self.inverse_transfomer.transform_pos(pos);
+
+        self.primitive.sample_distance(pos) * self.distance_scale
+    }
+
+    pub fn is_position_valid_f(&self, pos: V) -> bool {
+        let pos = self.inverse_transfomer.transform_pos(pos);
+
+        self.primitive.sample_pos(pos)
+    }
+}
+
+impl<P: PrimitiveType, M: Copy, V: Ve<f32, D>, const D: usize> CSGPrimitive<P, M, V, D> {
+    pub fn get_material(&self) -> M {
+        self.material
+    }
+}
+
+// The primitive space distance is scaled by the smallest axis scale.
+// For non uniform scales this underestimates the real distance.
+pub(crate) fn get_distance_scale<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> f32 {
+    (0..D)
+        .map(|i| V::new(mat.truc_col(i)).length())
+        .fold(f32::MAX, f32::min)
+}
+
+pub(super) fn aabb_corners<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> impl Iterator<Item = V> {
+    let min = aabb.min().to_array();
+    let max = aabb.max().to_array();
+
+    (0..(1usize << D)).map(move |c| {
+        let mut corner = min;
+        for i in 0..D {
+            if c & (1 << i) != 0 {
+                corner[i] = max[i];
+            }
+        }
+        V::new(corner)
+    })
+}
+
+pub(super) fn unit_axis<V: Ve<f32, D>, const D: usize>(axis: usize) -> V {
+    V::new(std::array::from_fn(|i| if i == axis { 1.0 } else { 0.0 }))
+}
+
+pub(super) fn normalize_or_zero<V: Ve<f32, D>, const D: usize>(v: V) -> V {
+    let len = v.length();
+    if len > 0.0 {
+        v / len
+    } else {
+        V::ZERO
+    }
+}
+
+// Convex shapes overlap a ray in a single range, so the first hit is its start.
+pub(super) fn first_in_range(range: Option<(f32, f32)>, min: f32, max: f32) -> Option<f32> {
+    let (enter, exit) = range?;
+    let t = enter.max(min);
+    (t <= exit.min(max)).then_some(t)
+}
+
+pub(super) fn intersect_range(a: Option<(f32, f32)>, b: Option<(f32, f32)>) -> Option<(f32, f32)> {
+    let (a, b) = (a?, b?);
+    let range = (a.0.max(b.0), a.1.min(b.1));
+    (range.0 <= range.1).then_some(range)
+}
+
+// Range where b * t + c <= 0.
+pub(super) fn linear_range(b: f32, c: f32) -> Option<(f32, f32)> {
+    if b == 0.0 {
+        (c <= 0.0).then_some((f32::NEG_INFINITY, f32::INFINITY))
+    } else if b > 0.0 {
+        Some((f32::NEG_INFINITY, -c / b))
+    } else {
+        Some((-c / b, f32::INFINITY))
+    }
+}
+
+// Range where a * t^2 + b * t + c <= 0 for a >= 0.
+pub(super) fn quadratic_range(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    if a <= f32::EPSILON {
+        return linear_range(b, c);
+    }
+
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+
+    let s = disc.sqrt();
+    Some(((-b - s) / (2.0 * a), (-b + s) / (2.0 * a)))
+}
+
+// Range inside of the unit radius around the last axis.
+pub(super) fn radial_range<V: Ve<f32, D>, const D: usize>(origin: V, dir: V) -> Option<(f32, f32)> {
+    quadratic_range(
+        (0..(D-1)).map(|i| dir[i] * dir[i]).sum(),
+        (0..(D-1)).map(|i| 2.0 * origin[i] * dir[i]).sum(),
+        (0..(D-1)).map(|i| origin[i] * origin[i]).sum::<f32>() - 1.0)
+}
+
+// Range between -half and half on the last axis.
+pub(super) fn height_range<V: Ve<f32, D>, const D: usize>(origin: V, dir: V, half: f32) -> Option<(f32, f32)> {
+    intersect_range(
+        linear_range(dir[D-1], origin[D-1] - half),
+        linear_range(-dir[D-1], -origin[D-1] - half))
+}
+
+// A convex shape contains the aabb if it contains all corners.
+pub(super) fn contains_aabb_convex<P: PrimitiveType, V: Ve<f32, D>, const D: usize>(primitive: &P, aabb: AABB<V, f32, D>) -> bool {
+    aabb_corners(aabb).all(|corner| primitive.sample_pos(corner))
+}
+
+// Bounds of a sphere in primitive space after it got transformed by mat.
+pub(super) fn transformed_sphere_bounds<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix, center: V, radius: f32) -> AABB<V, f32, D> {
+    let center = mat.mul_vector(center);
+    let extent = V::new(std::array::from_fn(|row| {
+        (0..D)
+            .map(|col| mat.index(col, row).powi(2))
+            .sum::<f32>()
+            .sqrt() * radius
+    }));
+
+    AABB::new(center - extent, center + extent)
 }
 
 impl<P: PrimitiveType, M, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for CSGPrimitive<P, M, V::VectorF, D> {
@@ -61,7 +213,7 @@ impl<P: PrimitiveType, M, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T,
             return;
         }
 
-        self.aabb = P::calculate_bounds(&self.matrix);
+        self.aabb = self.primitive.calculate_bounds(&self.matrix);
         self.needs_aabb_recompute = false;
     }
 
@@ -72,28 +224,20 @@ impl<P: PrimitiveType, M, V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T,
 
 impl<P: PrimitiveType, M, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for CSGPrimitive<P, M, V::VectorF, D> {
     fn is_position_valid(&self, pos: V) -> bool {
-        let pos = self.inverse_transfomer.transform_pos(pos.to_vecf());
-
-        P::sample_pos(pos)
+        self.is_position_valid_f(pos.to_vecf())
     }
 }
 
 impl<P: PrimitiveType, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for CSGPrimitive<P, u8, V::VectorF, D> {
     fn get_value(&self, pos: V) -> u8 {
-        let pos = self.inverse_transfomer.transform_pos(pos.to_vecf());
-
-        if P::sample_pos(pos) {
-            self.material
-        } else {
-            MATERIAL_ID_NONE
-        }
+        self.get_value_f(pos.to_vecf())
     }
 }
 
 impl<P: PrimitiveType, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for CSGPrimitive<P, u8, V::VectorF, D> {
     fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
         let aabb = self.inverse_transfomer.transform_aabb(aabb.to_f());
-        match P::sample_aabb(aabb) {
+        match self.primitive.sample_aabb(aabb) {
             SampleAABBResult::Full => VolumeQureyAABBResult::Full(self.material),
             SampleAABBResult::Empty => VolumeQureyAABBResult::Full(MATERIAL_ID_NONE),
             SampleAABBResult::Mixed => VolumeQureyAABBResult::Mixed,
@@ -101,9 +245,50 @@ impl<P: PrimitiveType, V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T,
     }
 }
 
+impl<P: PrimitiveType, V: Ve<f32, D>, const D: usize> CSGPrimitive<P, u8, V, D> {
+    pub fn get_value_f(&self, pos: V) -> u8 {
+        if self.is_position_valid_f(pos) {
+            self.material
+        } else {
+            MATERIAL_ID_NONE
+        }
+    }
+
+    pub fn get_ray_hit_in(&self, origin: V, dir: V, min: f32, max: f32) -> Option<VolumeRayHit<V>> {
+        let local_origin = self.inverse_transfomer.transform_pos(origin);
+        let local_dir = self.inverse_transfomer.transform_dir(dir);
+
+        let t = self.primitive.ray_hit(local_origin, local_dir, min, max)?;
+        let grad = self.primitive.sample_gradient(local_origin + local_dir * t);
+
+        Some(VolumeRayHit {
+            t,
+            pos: origin + dir * t,
+            normal: normalize_or_zero(self.inverse_transfomer.transform_normal(grad)),
+            material: self.material,
+        })
+    }
+}
+
+impl<P: PrimitiveType, V: Ve<f32, D>, const D: usize> VolumeQureyRay<V, D> for CSGPrimitive<P, u8, V, D> {
+    fn get_ray_hit(&self, origin: V, dir: V, max_t: f32) -> Option<VolumeRayHit<V>> {
+        self.get_ray_hit_in(origin, dir, 0.0, max_t)
+    }
+}
+
+impl<P: PrimitiveType, M, V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for CSGPrimitive<P, M, V, D> {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        self.get_distance(pos).clamp(-max, max)
+    }
+}
+
+// Normals are evaluated in primitive space and then transformed by the transpose of the inverse matrix.
 impl<P: PrimitiveType, M, V: Ve<f32, D>, const D: usize> VolumeGradient<V, D> for CSGPrimitive<P, M, V, D> {
     fn get_gradient_at_position(&self, pos: V) -> V {
-        todo!()        
+        let local = self.inverse_transfomer.transform_pos(pos);
+        let grad = self.primitive.sample_gradient(local);
+
+        self.inverse_transfomer.transform_normal(grad)
     }
 }
 
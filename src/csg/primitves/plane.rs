@@ -0,0 +1,140 @@
+use octa_force::glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, SampleAABBResult, first_in_range, linear_range, unit_axis}, util::{aabb::AABB, matrix::Ma, vector::{CastFrom, Ve}}};
+
+// Half space below 0 on the last axis.
+// The bounds are infinite unless the plane is axis aligned, so it is meant to be used to cut or intersect other nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CSGPlane {}
+
+impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGPlane, M, V, D> {
+    // Everything on the opposite side of the normal is solid.
+    pub fn new_plane(pos: V, normal: V, mat: M) -> Self {
+        let normal = normal.normalize();
+
+        let matrix = match D {
+            2 => {
+                let normal: Vec2 = normal.ve_into();
+                V::Matrix::cast_from(Mat3::from_scale_angle_translation(
+                    Vec2::ONE,
+                    f32::atan2(-normal.x, normal.y),
+                    pos.ve_into()))
+            },
+            3 => {
+                let rot = Quat::from_rotation_arc(Vec3::Z, normal.ve_into());
+                V::Matrix::cast_from(Mat4::from_rotation_translation(rot, pos.ve_into()))
+            },
+            _ => unreachable!()
+        };
+
+        CSGPrimitive::new(CSGPlane {}, matrix, mat)
+    }
+}
+
+impl PrimitiveType for CSGPlane {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
+        // In world space the half space is n * pos + c < 0.
+        let inv = mat.inverse();
+        let n: [f32; D] = std::array::from_fn(|i| inv.index(i, D - 1));
+        let c = inv.index(D, D - 1);
+
+        let mut min = [f32::MIN; D];
+        let mut max = [f32::MAX; D];
+
+        let mut axes = (0..D).filter(|i| n[*i] != 0.0);
+        if let (Some(axis), None) = (axes.next(), axes.next()) {
+            if n[axis] > 0.0 {
+                max[axis] = -c / n[axis];
+            } else {
+                min[axis] = -c / n[axis];
+            }
+        }
+
+        AABB::new(V::new(min), V::new(max))
+    }
+
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
+        pos[D-1] < 0.0
+    }
+
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult {
+        if aabb.max()[D-1] <= 0.0 {
+            SampleAABBResult::Full
+        } else if aabb.min()[D-1] >= 0.0 {
+            SampleAABBResult::Empty
+        } else {
+            SampleAABBResult::Mixed
+        }
+    }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        pos[D-1]
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        unit_axis(D-1)
+    }
+
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        first_in_range(linear_range(dir[D-1], origin[D-1]), min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, Vec3A};
+
+    use crate::{csg::primitves::CSGPrimitive, util::aabb::AABB, volume::{VolumeBounds, VolumeGradient, VolumeQureyRay}};
+
+    use super::CSGPlane;
+
+    #[test]
+    fn distance() {
+        let plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(vec3a(0.0, 0.0, 1.0), Vec3A::Z, 1);
+        assert!((plane.get_distance(vec3a(0.0, 0.0, 3.0)) - 2.0).abs() < 1e-5);
+        assert!((plane.get_distance(vec3a(5.0, 5.0, -1.0)) + 2.0).abs() < 1e-5);
+        assert_eq!(plane.get_value_f(vec3a(100.0, 0.0, 0.5)), 1);
+        assert_eq!(plane.get_value_f(vec3a(0.0, 0.0, 1.5)), 0);
+
+        let plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(Vec3A::ZERO, vec3a(1.0, 0.0, 1.0), 1);
+        assert!((plane.get_distance(vec3a(1.0, 0.0, 1.0)) - 2.0_f32.sqrt()).abs() < 1e-5);
+        assert!(plane.get_distance(vec3a(1.0, 0.0, -1.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(vec3a(0.0, 0.0, 1.0), Vec3A::Z, 1);
+        VolumeBounds::<Vec3A, f32, 3>::calculate_bounds(&mut plane);
+        let bounds: AABB<Vec3A, f32, 3> = plane.get_bounds();
+        assert!((bounds.max().z - 1.0).abs() < 1e-5);
+        assert_eq!(bounds.min().z, f32::MIN);
+        assert_eq!(bounds.max().x, f32::MAX);
+    }
+
+    #[test]
+    fn ray() {
+        let plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(vec3a(0.0, 0.0, 1.0), Vec3A::Z, 1);
+        let hit = plane.get_ray_hit(vec3a(0.0, 0.0, 5.0), Vec3A::NEG_Z, 100.0).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+
+        let hit = plane.get_ray_hit(vec3a(0.0, 0.0, -5.0), Vec3A::Z, 100.0).unwrap();
+        assert_eq!(hit.t, 0.0);
+
+        assert!(plane.get_ray_hit(vec3a(0.0, 0.0, 5.0), Vec3A::X, 100.0).is_none());
+        assert!(plane.get_ray_hit(vec3a(0.0, 0.0, 5.0), Vec3A::Z, 100.0).is_none());
+    }
+
+    #[test]
+    fn gradient() {
+        let plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(vec3a(0.0, 0.0, 1.0), Vec3A::Z, 1);
+        assert!(plane.get_gradient_at_position(vec3a(3.0, -2.0, 0.0)).normalize().abs_diff_eq(Vec3A::Z, 1e-5));
+
+        let normal = vec3a(1.0, 0.0, 1.0).normalize();
+        let plane = CSGPrimitive::<CSGPlane, u8, Vec3A, 3>::new_plane(Vec3A::ZERO, normal, 1);
+        assert!(plane.get_gradient_at_position(vec3a(1.0, 0.0, 1.0)).normalize().abs_diff_eq(normal, 1e-5));
+
+        let hit = plane.get_ray_hit(vec3a(5.0, 0.0, 5.0), Vec3A::NEG_X, 100.0).unwrap();
+        assert!((hit.t - 10.0).abs() < 1e-4);
+        assert!(hit.normal.abs_diff_eq(normal, 1e-5));
+    }
+}
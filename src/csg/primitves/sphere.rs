@@ -1,14 +1,14 @@
 use octa_force::glam::{IVec3, Mat3, Mat4, Quat, UVec3, Vec3, Vec3A, Vec4, vec2, vec3, vec3a, vec4};
 
-use crate::{csg::primitves::{CSGPrimitive, PrimitiveType}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, Ve}}};
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, first_in_range, normalize_or_zero, quadratic_range}, util::{aabb::AABB, matrix::Ma, number::Nu, vector::{CastInto, Ve}}};
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct CSGSphere {}
 
 
 impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGSphere, M, V, D> {
     pub fn new_sphere(center: V, radius: f32, mat: M) -> Self {
-        CSGPrimitive::new(V::Matrix::from_scale_translation(
+        CSGPrimitive::new(CSGSphere {}, V::Matrix::from_scale_translation(
                 V::ONE * radius,
                 center,
             ), mat)
@@ -17,7 +17,7 @@ impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGSphere, M, V, D> {
 
 impl<M, V: Ve<f32, 3>> CSGPrimitive<CSGSphere, M, V, 3> {
     pub fn new_disk(center: V, radius: f32, height: f32, mat: M) -> Self {
-        CSGPrimitive::new( V::Matrix::from_scale_translation(
+        CSGPrimitive::new(CSGSphere {}, V::Matrix::from_scale_translation(
                 V::new([radius, radius, height]),
                 center,
             ), mat)
@@ -25,7 +25,7 @@ impl<M, V: Ve<f32, 3>> CSGPrimitive<CSGSphere, M, V, 3> {
 }
 
 impl PrimitiveType for CSGSphere {
-    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(mat: &V::Matrix) -> AABB<V, f32, D> {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
         match D {
             2 => {
                 let mat: Mat3 = mat.cast_into();
@@ -53,11 +53,11 @@ impl PrimitiveType for CSGSphere {
         }
     }
 
-    fn sample_pos<V: Ve<f32, D>, const D: usize>(pos: V) -> bool {
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
         pos.length_squared() < 1.0
     }
 
-    fn sample_aabb<V: Ve<f32, D>, const D: usize>(aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> super::SampleAABBResult {
         let a = aabb.min() * aabb.min();
         let b = aabb.max() * aabb.max();
         let dmax = a.max(b).element_sum();
@@ -71,6 +71,19 @@ impl PrimitiveType for CSGSphere {
             super::SampleAABBResult::Empty
         }
     }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        pos.length() - 1.0
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        normalize_or_zero(pos)
+    }
+
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let range = quadratic_range(dir.dot(dir), 2.0 * origin.dot(dir), origin.dot(origin) - 1.0);
+        first_in_range(range, min, max)
+    }
 }
 
 
@@ -0,0 +1,156 @@
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, SampleAABBResult, normalize_or_zero}, util::{aabb::AABB, matrix::Ma, vector::Ve}};
+
+const TORUS_RAY_STEPS: usize = 64;
+const TORUS_RAY_EPSILON: f32 = 1e-4;
+
+// Ring with major radius 1 in the plane of the first two axes.
+// In 3D the last axis is the axis of the torus, in 2D this is a flat ring.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CSGTorus {
+    pub minor_radius: f32,
+}
+
+impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGTorus, M, V, D> {
+    pub fn new_torus(center: V, major_radius: f32, minor_radius: f32, mat: M) -> Self {
+        CSGPrimitive::new(
+            CSGTorus { minor_radius: minor_radius / major_radius },
+            V::Matrix::from_scale_translation(V::ONE * major_radius, center),
+            mat)
+    }
+}
+
+impl CSGTorus {
+    // Distance to the ring in the plane and along the axis.
+    fn ring_and_height<V: Ve<f32, D>, const D: usize>(pos: V) -> (f32, f32) {
+        let arr = pos.to_array();
+        let ring = (arr[0] * arr[0] + arr[1] * arr[1]).sqrt() - 1.0;
+        let height = if D == 3 { arr[2] } else { 0.0 };
+
+        (ring, height)
+    }
+}
+
+impl PrimitiveType for CSGTorus {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
+        let extent = V::new(std::array::from_fn(|i| if i < 2 { 1.0 + self.minor_radius } else { self.minor_radius }));
+
+        AABB::<V, f32, D>::new(V::ZERO - extent, extent).mul_mat(mat)
+    }
+
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
+        let (ring, height) = Self::ring_and_height(pos);
+
+        ring * ring + height * height < self.minor_radius * self.minor_radius
+    }
+
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult {
+        let min = aabb.min().to_array();
+        let max = aabb.max().to_array();
+
+        let closest = |i: usize| (min[i].max(0.0) - max[i].min(0.0)).max(0.0);
+        let furthest = |i: usize| min[i].abs().max(max[i].abs());
+
+        // Range of the distance to the torus axis inside of the aabb.
+        let radial_min = (closest(0).powi(2) + closest(1).powi(2)).sqrt();
+        let radial_max = (furthest(0).powi(2) + furthest(1).powi(2)).sqrt();
+        let (height_min, height_max) = if D == 3 { (closest(2), furthest(2)) } else { (0.0, 0.0) };
+
+        let ring_min = (radial_min - 1.0).max(1.0 - radial_max).max(0.0);
+        if ring_min * ring_min + height_min * height_min >= self.minor_radius * self.minor_radius {
+            return SampleAABBResult::Empty;
+        }
+
+        let ring_max = (radial_min - 1.0).abs().max((radial_max - 1.0).abs());
+        if ring_max * ring_max + height_max * height_max < self.minor_radius * self.minor_radius {
+            return SampleAABBResult::Full;
+        }
+
+        SampleAABBResult::Mixed
+    }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let (ring, height) = Self::ring_and_height(pos);
+
+        (ring * ring + height * height).sqrt() - self.minor_radius
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        // Direction from the closest point on the ring.
+        let mut ring = pos.to_array();
+        for i in 2..D {
+            ring[i] = 0.0;
+        }
+        let ring = normalize_or_zero(V::new(ring));
+
+        normalize_or_zero(pos - ring)
+    }
+
+    // The exact intersection needs a quartic, so the exact distance is sphere traced instead.
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let extent = V::new(std::array::from_fn(|i| if i < 2 { 1.0 + self.minor_radius } else { self.minor_radius }));
+        let (enter, exit) = AABB::<V, f32, D>::new(V::ZERO - extent, extent).ray_range(origin, dir)?;
+
+        let len = dir.length();
+        let mut t = enter.max(min);
+        let end = exit.min(max);
+        for _ in 0..TORUS_RAY_STEPS {
+            if t > end {
+                return None;
+            }
+
+            let dist = self.sample_distance(origin + dir * t);
+            if dist < TORUS_RAY_EPSILON {
+                return Some(t);
+            }
+            t += dist / len;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, Vec3A};
+
+    use crate::{csg::primitves::CSGPrimitive, volume::{VolumeGradient, VolumeQureyRay}};
+
+    use super::CSGTorus;
+
+    fn new_torus() -> CSGPrimitive<CSGTorus, u8, Vec3A, 3> {
+        CSGPrimitive::new_torus(Vec3A::ZERO, 4.0, 1.0, 1)
+    }
+
+    #[test]
+    fn distance() {
+        let torus = new_torus();
+        assert!((torus.get_distance(vec3a(4.0, 0.0, 0.0)) + 1.0).abs() < 1e-5);
+        assert!((torus.get_distance(vec3a(6.0, 0.0, 0.0)) - 1.0).abs() < 1e-5);
+        assert!((torus.get_distance(vec3a(0.0, 4.0, 2.0)) - 1.0).abs() < 1e-5);
+        assert!((torus.get_distance(Vec3A::ZERO) - 3.0).abs() < 1e-5);
+        assert_eq!(torus.get_value_f(vec3a(0.0, -4.5, 0.0)), 1);
+        assert_eq!(torus.get_value_f(Vec3A::ZERO), 0);
+    }
+
+    #[test]
+    fn ray() {
+        let torus = new_torus();
+        let hit = torus.get_ray_hit(vec3a(-10.0, 0.0, 0.0), Vec3A::X, 100.0).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-2);
+
+        // Straight through the hole.
+        assert!(torus.get_ray_hit(vec3a(0.0, 0.0, 10.0), Vec3A::NEG_Z, 100.0).is_none());
+        assert!(torus.get_ray_hit(vec3a(-10.0, 0.0, 2.0), Vec3A::X, 100.0).is_none());
+    }
+
+    #[test]
+    fn gradient() {
+        let torus = new_torus();
+        assert!(torus.get_gradient_at_position(vec3a(6.0, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::X, 1e-5));
+        assert!(torus.get_gradient_at_position(vec3a(2.0, 0.0, 0.0)).normalize().abs_diff_eq(Vec3A::NEG_X, 1e-5));
+        assert!(torus.get_gradient_at_position(vec3a(0.0, 4.0, 2.0)).normalize().abs_diff_eq(Vec3A::Z, 1e-5));
+
+        let hit = torus.get_ray_hit(vec3a(-10.0, 0.0, 0.0), Vec3A::X, 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(Vec3A::NEG_X, 1e-3));
+    }
+}
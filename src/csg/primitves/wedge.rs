@@ -0,0 +1,115 @@
+use crate::{csg::primitves::{CSGPrimitive, PrimitiveType, SampleAABBResult, aabb_corners, contains_aabb_convex, first_in_range, intersect_range, linear_range, unit_axis, r#box::{CSGBox, box_gradient}}, util::{aabb::AABB, matrix::Ma, vector::Ve}};
+
+// Half of the unit box below the diagonal between the first and the last axis.
+// The slope rises towards -x, in 2D this is a right triangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CSGWedge {}
+
+impl<M, V: Ve<f32, D>, const D: usize> CSGPrimitive<CSGWedge, M, V, D> {
+    pub fn new_wedge(pos: V, size: V, mat: M) -> Self {
+        CSGPrimitive::new(CSGWedge {}, V::Matrix::from_scale_translation(size, pos), mat)
+    }
+}
+
+fn unit_box<V: Ve<f32, D>, const D: usize>() -> AABB<V, f32, D> {
+    AABB::new(V::new([-0.5; D]), V::new([0.5; D]))
+}
+
+impl PrimitiveType for CSGWedge {
+    fn calculate_bounds<V: Ve<f32, D>, const D: usize>(&self, mat: &V::Matrix) -> AABB<V, f32, D> {
+        aabb_corners(unit_box::<V, D>())
+            .filter(|corner| corner[0] + corner[D-1] <= 0.0)
+            .fold(AABB::default(), |aabb, corner| aabb.union_point(mat.mul_vector(corner)))
+    }
+
+    fn sample_pos<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> bool {
+        unit_box::<V, D>().pos_in_aabb(pos) && pos[0] + pos[D-1] <= 0.0
+    }
+
+    fn sample_aabb<V: Ve<f32, D>, const D: usize>(&self, aabb: AABB<V, f32, D>) -> SampleAABBResult {
+        if !unit_box::<V, D>().collides_aabb(aabb) || aabb.min()[0] + aabb.min()[D-1] >= 0.0 {
+            SampleAABBResult::Empty
+        } else if contains_aabb_convex(self, aabb) {
+            SampleAABBResult::Full
+        } else {
+            SampleAABBResult::Mixed
+        }
+    }
+
+    fn sample_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let q = pos.abs() - 0.5;
+        let outside = q.max(V::ZERO).length();
+        let inside = q.to_array().into_iter().fold(f32::MIN, f32::max).min(0.0);
+
+        (outside + inside).max((pos[0] + pos[D-1]) * std::f32::consts::FRAC_1_SQRT_2)
+    }
+
+    fn sample_gradient<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> V {
+        let slope = (pos[0] + pos[D-1]) * std::f32::consts::FRAC_1_SQRT_2;
+        let box_distance = CSGBox {}.sample_distance(pos);
+        if slope > box_distance {
+            (unit_axis::<V, D>(0) + unit_axis::<V, D>(D-1)) * std::f32::consts::FRAC_1_SQRT_2
+        } else {
+            box_gradient(pos)
+        }
+    }
+
+    fn ray_hit<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<f32> {
+        let range = intersect_range(
+            unit_box::<V, D>().ray_range(origin, dir),
+            linear_range(dir[0] + dir[D-1], origin[0] + origin[D-1]));
+
+        first_in_range(range, min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, Vec3A};
+
+    use crate::{csg::primitves::CSGPrimitive, volume::{VolumeGradient, VolumeQureyRay}};
+
+    use super::CSGWedge;
+
+    // Fills the part of the box from -1 to 1 where x + z <= 0.
+    fn new_wedge() -> CSGPrimitive<CSGWedge, u8, Vec3A, 3> {
+        CSGPrimitive::new_wedge(Vec3A::ZERO, Vec3A::splat(2.0), 1)
+    }
+
+    #[test]
+    fn distance() {
+        let wedge = new_wedge();
+        assert!((wedge.get_distance(vec3a(1.0, 0.0, 1.0)) - 2.0_f32.sqrt()).abs() < 1e-5);
+        assert!((wedge.get_distance(vec3a(0.0, 0.0, -3.0)) - 2.0).abs() < 1e-5);
+        assert!(wedge.get_distance(vec3a(-0.5, 0.0, -0.5)) < 0.0);
+        assert_eq!(wedge.get_value_f(vec3a(-0.5, 0.5, -0.5)), 1);
+        assert_eq!(wedge.get_value_f(vec3a(0.5, 0.0, 0.5)), 0);
+    }
+
+    #[test]
+    fn ray() {
+        let wedge = new_wedge();
+        let hit = wedge.get_ray_hit(vec3a(5.0, 0.0, 5.0), vec3a(-1.0, 0.0, -1.0), 100.0).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+
+        let hit = wedge.get_ray_hit(vec3a(-5.0, 0.0, -0.5), Vec3A::X, 100.0).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+
+        assert!(wedge.get_ray_hit(vec3a(-5.0, 0.0, 0.9), vec3a(1.0, 0.0, 0.0), 100.0).is_some());
+        assert!(wedge.get_ray_hit(vec3a(5.0, 0.0, 0.5), Vec3A::Y, 100.0).is_none());
+    }
+
+    #[test]
+    fn gradient() {
+        let wedge = new_wedge();
+        let slope = vec3a(1.0, 0.0, 1.0).normalize();
+        assert!(wedge.get_gradient_at_position(vec3a(1.0, 0.0, 1.0)).normalize().abs_diff_eq(slope, 1e-5));
+        assert!(wedge.get_gradient_at_position(vec3a(0.0, 0.0, -3.0)).normalize().abs_diff_eq(Vec3A::NEG_Z, 1e-5));
+
+        let hit = wedge.get_ray_hit(vec3a(5.0, 0.0, 5.0), vec3a(-1.0, 0.0, -1.0), 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(slope, 1e-5));
+
+        let hit = wedge.get_ray_hit(vec3a(-5.0, 0.0, -0.5), Vec3A::X, 100.0).unwrap();
+        assert!(hit.normal.abs_diff_eq(Vec3A::NEG_X, 1e-5));
+    }
+}
@@ -0,0 +1,386 @@
+use std::{collections::BTreeMap, fs::File, io::{BufWriter, Write}, path::Path};
+
+use octa_force::{anyhow::{anyhow, ensure}, glam::{Vec3, Vec3Swizzles}, OctaResult};
+use serde_json::json;
+
+use crate::{mesh::Mesh, util::shader_constants::METERS_PER_SHADER_UNIT, voxel::palette::Palette};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+/**
+The vertices are stored in shader units, files get meters.
+from_volume swaps x and y of the positions, the files get the z up volume axes again turned to y up.
+Undoing the swap mirrors the mesh, so the triangles are flipped back as well.
+*/
+impl Mesh {
+    fn get_position_in_meters(&self, index: usize) -> Vec3 {
+        to_y_up(self.vertices[index].pos.yxz()) * METERS_PER_SHADER_UNIT as f32
+    }
+
+    // The normals are not swapped by from_volume.
+    fn get_normal(&self, index: usize) -> Vec3 {
+        to_y_up(self.vertices[index].normal)
+    }
+
+    fn get_triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[2], t[1]])
+    }
+
+    // Material of the first vertex of every triangle.
+    fn get_triangle_material(&self, triangle: &[u32]) -> u8 {
+        self.vertices[triangle[0] as usize].material_id as u8
+    }
+
+    /**
+    Writes the mtl file next to the obj with one material per used palette entry.
+    The faces are grouped by their material.
+    */
+    pub fn save_obj<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let path = path.as_ref();
+        let mtl_path = path.with_extension("mtl");
+        let mtl_name = mtl_path.file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid obj path {}", path.display()))?;
+
+        let mut groups: BTreeMap<u8, Vec<[u32; 3]>> = BTreeMap::new();
+        for triangle in self.get_triangles() {
+            groups.entry(self.get_triangle_material(&triangle))
+                .or_default()
+                .push(triangle);
+        }
+
+        let mut obj = BufWriter::new(File::create(path)?);
+        writeln!(obj, "mtllib {mtl_name}")?;
+        for i in 0..self.vertices.len() {
+            let p = self.get_position_in_meters(i);
+            writeln!(obj, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for i in 0..self.vertices.len() {
+            let n = self.get_normal(i);
+            writeln!(obj, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for (mat, triangles) in groups.iter() {
+            writeln!(obj, "usemtl mat_{mat}")?;
+            for t in triangles {
+                writeln!(obj, "f {0}//{0} {1}//{1} {2}//{2}", t[0] + 1, t[1] + 1, t[2] + 1)?;
+            }
+        }
+        obj.flush()?;
+
+        let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+        for mat in groups.keys() {
+            let [r, g, b] = palette.get_color(*mat).map(|c| c as f32 / 255.0);
+            writeln!(mtl, "newmtl mat_{mat}")?;
+            writeln!(mtl, "Kd {r} {g} {b}")?;
+            writeln!(mtl)?;
+        }
+        mtl.flush()?;
+
+        Ok(())
+    }
+
+    pub fn save_ply<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let mut ply = BufWriter::new(File::create(path)?);
+
+        write!(ply, "ply\n\
+            format binary_little_endian 1.0\n\
+            element vertex {}\n\
+            property float x\n\
+            property float y\n\
+            property float z\n\
+            property float nx\n\
+            property float ny\n\
+            property float nz\n\
+            property uchar red\n\
+            property uchar green\n\
+            property uchar blue\n\
+            element face {}\n\
+            property list uchar uint vertex_indices\n\
+            end_header\n", self.vertices.len(), self.indices.len() / 3)?;
+
+        for (i, v) in self.vertices.iter().enumerate() {
+            for f in self.get_position_in_meters(i).to_array().into_iter().chain(self.get_normal(i).to_array()) {
+                ply.write_all(&f.to_le_bytes())?;
+            }
+            ply.write_all(&palette.get_color(v.material_id as u8))?;
+        }
+
+        for triangle in self.get_triangles() {
+            ply.write_all(&[3])?;
+            for i in triangle {
+                ply.write_all(&i.to_le_bytes())?;
+            }
+        }
+
+        ply.flush()?;
+        Ok(())
+    }
+
+    /**
+    One mesh with positions, normals, linear vertex colors and indecies in a single buffer.
+    The vertex colors multiply a white base color, so viewers show the palette colors.
+    */
+    pub fn save_glb<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        // Accessors need a min and max and buffer views can not be empty.
+        ensure!(!self.vertices.is_empty() && !self.indices.is_empty(), "Can not save an empty mesh as glb");
+
+        let vertex_count = self.vertices.len();
+
+        let mut bin = vec![];
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for i in 0..vertex_count {
+            let p = self.get_position_in_meters(i);
+            min = min.min(p);
+            max = max.max(p);
+            bin.extend(p.to_array().map(f32::to_le_bytes).concat());
+        }
+
+        let normals_offset = bin.len();
+        for i in 0..vertex_count {
+            bin.extend(self.get_normal(i).to_array().map(f32::to_le_bytes).concat());
+        }
+
+        let colors_offset = bin.len();
+        for v in self.vertices.iter() {
+            let color = palette.get_color(v.material_id as u8).map(srgb_to_linear);
+            bin.extend(color.map(f32::to_le_bytes).concat());
+        }
+
+        let indices_offset = bin.len();
+        for i in self.get_triangles().flatten() {
+            bin.extend(i.to_le_bytes());
+        }
+        let indices_len = bin.len() - indices_offset;
+
+        pad_to_4(&mut bin, 0);
+
+        let vec3_view = |offset: usize| json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": vertex_count * 12,
+            "target": 34962,
+        });
+
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "dynamic_voxels" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "NORMAL": 1, "COLOR_0": 2 },
+                    "indices": 3,
+                    "material": 0,
+                }],
+            }],
+            "materials": [{
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [1.0, 1.0, 1.0, 1.0],
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+            }],
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": [
+                vec3_view(0),
+                vec3_view(normals_offset),
+                vec3_view(colors_offset),
+                { "buffer": 0, "byteOffset": indices_offset, "byteLength": indices_len, "target": 34963 },
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": vertex_count, "type": "VEC3",
+                    "min": min.to_array(), "max": max.to_array() },
+                { "bufferView": 1, "componentType": 5126, "count": vertex_count, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": vertex_count, "type": "VEC3" },
+                { "bufferView": 3, "componentType": 5125, "count": self.indices.len(), "type": "SCALAR" },
+            ],
+        });
+
+        let mut json = serde_json::to_vec(&gltf)?;
+        pad_to_4(&mut json, b' ');
+
+        let total_len = 12 + 8 + json.len() + 8 + bin.len();
+
+        let mut glb = BufWriter::new(File::create(path)?);
+        glb.write_all(GLB_MAGIC)?;
+        glb.write_all(&2u32.to_le_bytes())?;
+        glb.write_all(&(total_len as u32).to_le_bytes())?;
+
+        glb.write_all(&(json.len() as u32).to_le_bytes())?;
+        glb.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+        glb.write_all(&json)?;
+
+        glb.write_all(&(bin.len() as u32).to_le_bytes())?;
+        glb.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+        glb.write_all(&bin)?;
+
+        glb.flush()?;
+        Ok(())
+    }
+}
+
+// Inverse of the turn from_obj does when loading.
+fn to_y_up(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.z, -v.y)
+}
+
+fn pad_to_4(data: &mut Vec<u8>, value: u8) {
+    while data.len() % 4 != 0 {
+        data.push(value);
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use octa_force::glam::Vec3;
+
+    use crate::{mesh::{Mesh, Vertex}, util::shader_constants::METERS_PER_SHADER_UNIT, voxel::palette::{palette::LocalPalette, Palette}};
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dynamic_voxels_{}_{name}", std::process::id()))
+    }
+
+    // Two triangles, the first one red and the second one blue.
+    fn new_mesh(palette: &mut LocalPalette) -> Mesh {
+        let red = palette.get_index_simple_color(RED).unwrap() as u32;
+        let blue = palette.get_index_simple_color(BLUE).unwrap() as u32;
+        let vertex = |pos: Vec3, material_id: u32| Vertex { pos, material_id, normal: Vec3::Z };
+
+        let mut mesh = Mesh::default();
+        mesh.vertices = vec![
+            vertex(Vec3::new(0.0, 0.0, 0.0), red),
+            vertex(Vec3::new(1.0, 0.0, 0.0), red),
+            vertex(Vec3::new(0.0, 1.0, 0.0), red),
+            vertex(Vec3::new(1.0, 1.0, 2.0), blue),
+        ];
+        mesh.indices = vec![0, 1, 2, 3, 2, 1];
+        mesh
+    }
+
+    // Shader units with x and y swapped by from_volume turned into y up meters.
+    fn expected_position(pos: Vec3) -> Vec3 {
+        Vec3::new(pos.y, pos.z, -pos.x) * METERS_PER_SHADER_UNIT as f32
+    }
+
+    #[test]
+    fn obj() {
+        let mut palette = LocalPalette::new();
+        let mesh = new_mesh(&mut palette);
+        let red = palette.get_index_simple_color(RED).unwrap();
+        let blue = palette.get_index_simple_color(BLUE).unwrap();
+
+        let path = temp_path("mesh.obj");
+        mesh.save_obj(&path, &palette).unwrap();
+        let obj = fs::read_to_string(&path).unwrap();
+        let mtl = fs::read_to_string(path.with_extension("mtl")).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("mtl")).unwrap();
+
+        let lines: Vec<_> = obj.lines().collect();
+        assert_eq!(lines[0], format!("mtllib {}", path.with_extension("mtl").file_name().unwrap().to_str().unwrap()));
+
+        let positions: Vec<Vec3> = lines.iter()
+            .filter_map(|l| l.strip_prefix("v "))
+            .map(|l| Vec3::from_slice(&l.split(' ').map(|v| v.parse().unwrap()).collect::<Vec<f32>>()))
+            .collect();
+        assert_eq!(positions.len(), 4);
+        for (p, v) in positions.iter().zip(mesh.vertices.iter()) {
+            assert!(p.abs_diff_eq(expected_position(v.pos), 1e-5));
+        }
+        assert_eq!(lines.iter().filter(|l| l.starts_with("vn ")).count(), 4);
+
+        let faces: Vec<_> = lines.iter().skip_while(|l| !l.starts_with("usemtl")).copied().collect();
+        assert_eq!(faces, vec![
+            format!("usemtl mat_{red}"),
+            "f 1//1 3//3 2//2".to_string(),
+            format!("usemtl mat_{blue}"),
+            "f 4//4 2//2 3//3".to_string(),
+        ]);
+
+        assert!(mtl.contains(&format!("newmtl mat_{red}\nKd 1 0 0\n")));
+        assert!(mtl.contains(&format!("newmtl mat_{blue}\nKd 0 0 1\n")));
+    }
+
+    #[test]
+    fn ply() {
+        let mut palette = LocalPalette::new();
+        let mesh = new_mesh(&mut palette);
+
+        let path = temp_path("mesh.ply");
+        mesh.save_ply(&path, &palette).unwrap();
+        let ply = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header_end = b"end_header\n";
+        let body_start = ply.windows(header_end.len()).position(|w| w == header_end).unwrap() + header_end.len();
+        let header = std::str::from_utf8(&ply[..body_start]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains("element vertex 4\n"));
+        assert!(header.contains("element face 2\n"));
+
+        // Six floats and three color bytes per vertex, a count and three indecies per face.
+        let vertex_size = 6 * 4 + 3;
+        let body = &ply[body_start..];
+        assert_eq!(body.len(), 4 * vertex_size + 2 * 13);
+
+        let float = |offset: usize| f32::from_le_bytes(body[offset..offset + 4].try_into().unwrap());
+        let second = vertex_size;
+        let pos = Vec3::new(float(second), float(second + 4), float(second + 8));
+        assert!(pos.abs_diff_eq(expected_position(mesh.vertices[1].pos), 1e-5));
+        assert_eq!(&body[second + 24..second + 27], &RED);
+        assert_eq!(&body[3 * vertex_size + 24..3 * vertex_size + 27], &BLUE);
+
+        let faces = &body[4 * vertex_size..];
+        let index = |offset: usize| u32::from_le_bytes(faces[offset..offset + 4].try_into().unwrap());
+        assert_eq!(faces[0], 3);
+        assert_eq!([index(1), index(5), index(9)], [0, 2, 1]);
+        assert_eq!(faces[13], 3);
+        assert_eq!([index(14), index(18), index(22)], [3, 1, 2]);
+    }
+
+    #[test]
+    fn glb() {
+        let mut palette = LocalPalette::new();
+        let mesh = new_mesh(&mut palette);
+
+        let path = temp_path("mesh.glb");
+        mesh.save_glb(&path, &palette).unwrap();
+        let glb = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let word = |offset: usize| u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap()) as usize;
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8), glb.len());
+
+        let json_len = word(12);
+        assert_eq!(json_len % 4, 0);
+        let json: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        assert_eq!(json["accessors"][0]["count"], 4);
+        assert_eq!(json["accessors"][3]["count"], 6);
+
+        let bin_len = word(20 + json_len);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(json["buffers"][0]["byteLength"], bin_len);
+        assert_eq!(20 + json_len + 8 + bin_len, glb.len());
+
+        assert!(Mesh::default().save_glb(temp_path("empty.glb"), &palette).is_err());
+    }
+}
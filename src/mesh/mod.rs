@@ -7,6 +7,7 @@ pub mod from_volume;
 pub mod renderer;
 pub mod gpu_mesh;
 pub mod scene;
+pub mod export;
 
 #[derive(Debug, Clone, Default)]
 pub struct Mesh {
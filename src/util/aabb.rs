@@ -80,6 +80,29 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> AABB<V, T, D>  {
         self.max = self.max.max(point);
     }
 
+    pub fn expand(self, amount: T) -> Self {
+        if !self.valid() {
+            return self;
+        }
+
+        Self::new(self.min - amount, self.max + amount)
+    }
+
+    pub fn distance_to_pos(self, pos: V) -> f32 {
+        let mut dist = 0.0;
+        for i in 0..D {
+            let d = if pos[i] < self.min[i] {
+                self.min[i].to_f32() - pos[i].to_f32()
+            } else if pos[i] > self.max[i] {
+                pos[i].to_f32() - self.max[i].to_f32()
+            } else {
+                0.0
+            };
+            dist += d * d;
+        }
+        dist.sqrt()
+    }
+
     pub fn largest_axis(self) -> usize {
         self.size().max_value().0
     }
@@ -90,6 +113,32 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> AABB<V, T, D>  {
         })
     }
 
+    // Ray parameters where the ray enters and leaves the aabb. Infinite bounds work as well.
+    pub fn ray_range<VF: Ve<f32, D>>(&self, origin: VF, dir: VF) -> Option<(f32, f32)> {
+        let mut range = (f32::NEG_INFINITY, f32::INFINITY);
+        for i in 0..D {
+            let min = self.min[i].to_f32();
+            let max = self.max[i].to_f32();
+
+            if dir[i] == 0.0 {
+                if origin[i] < min || origin[i] > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let a = (min - origin[i]) / dir[i];
+            let b = (max - origin[i]) / dir[i];
+            range = (range.0.max(a.min(b)), range.1.min(a.max(b)));
+        }
+
+        if range.0 <= range.1 {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
     pub fn surface_area(self) -> T {
         let size = self.size();
         T::TWO * size.dot(size)
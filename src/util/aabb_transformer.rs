@@ -11,9 +11,10 @@ pub struct AABBTransformer<M: Ma<D>, V: Ve<f32, D>, const D: usize> {
 impl<M: Ma<D>, V: Ve<f32, D>, const D: usize> AABBTransformer<M, V, D> {
     pub fn new(mat: M) -> Self {
 
+        // Rows of the matrix, the extent on an axis depends on the row of that axis.
         let mut abs = [V::ZERO; D];
         for i in 0..D {
-            abs[i] = V::new(mat.truc_col(i)).abs();
+            abs[i] = V::new(std::array::from_fn(|j| mat.index(j, i))).abs();
         }
 
         Self {
@@ -27,6 +28,18 @@ impl<M: Ma<D>, V: Ve<f32, D>, const D: usize> AABBTransformer<M, V, D> {
         self.center_mat.mul_vector(pos)
     }
 
+    // Directions ignore the translation.
+    #[inline(always)]
+    pub fn transform_dir(&self, dir: V) -> V {
+        V::new(std::array::from_fn(|row| (0..D).map(|col| self.center_mat.index(col, row) * dir[col]).sum()))
+    }
+
+    // Normals need the transpose of the matrix to stay orthogonal to the surface.
+    #[inline(always)]
+    pub fn transform_normal(&self, normal: V) -> V {
+        V::new(std::array::from_fn(|i| V::new(self.center_mat.truc_col(i)).dot(normal)))
+    }
+
     #[inline(always)]
     pub fn transform_aabb(&self, aabb: AABB<V, f32, D>) -> AABB<V, f32, D> {
         let center = (aabb.min() + aabb.max()) * 0.5;
@@ -43,3 +56,41 @@ impl<M: Ma<D>, V: Ve<f32, D>, const D: usize> AABBTransformer<M, V, D> {
         AABB::new(new_center - new_extent, new_center + new_extent)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{EulerRot, Mat4, Quat, Vec3, Vec3A};
+
+    use crate::util::aabb::AABB;
+
+    use super::AABBTransformer;
+
+    #[test]
+    fn rotated_box() {
+        let mat = Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 1.0, 0.5),
+            Quat::from_euler(EulerRot::XYZ, 0.3, 0.5, 0.7),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+        let min = Vec3A::new(-1.0, 0.0, 2.0);
+        let max = Vec3A::new(3.0, 1.0, 4.0);
+
+        // The bounds of the transformed corners.
+        let mut expected_min = Vec3A::splat(f32::MAX);
+        let mut expected_max = Vec3A::splat(f32::MIN);
+        for i in 0..8 {
+            let corner = Vec3A::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let p = mat.transform_point3a(corner);
+            expected_min = expected_min.min(p);
+            expected_max = expected_max.max(p);
+        }
+
+        let aabb = AABBTransformer::<Mat4, Vec3A, 3>::new(mat).transform_aabb(AABB::new(min, max));
+        assert!(aabb.min().abs_diff_eq(expected_min, 1e-4), "{} != {expected_min}", aabb.min());
+        assert!(aabb.max().abs_diff_eq(expected_max, 1e-4), "{} != {expected_max}", aabb.max());
+    }
+}
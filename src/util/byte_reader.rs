@@ -0,0 +1,69 @@
+use octa_force::{anyhow::ensure, OctaResult};
+
+// Reads the binary model formats. Every read checks that enough bytes are left.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn bytes(&mut self, ammount: usize) -> OctaResult<&'a [u8]> {
+        ensure!(self.data.len() - self.pos >= ammount, "Unexpected end of data at byte {}", self.pos);
+
+        let bytes = &self.data[self.pos..self.pos + ammount];
+        self.pos += ammount;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> OctaResult<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> OctaResult<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u32_le(&mut self) -> OctaResult<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i32_le(&mut self) -> OctaResult<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64_le(&mut self) -> OctaResult<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn u16_le(&mut self) -> OctaResult<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32_le(&mut self) -> OctaResult<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i16_be(&mut self) -> OctaResult<i16> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u16_be(&mut self) -> OctaResult<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn i32_be(&mut self) -> OctaResult<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    pub fn i64_be(&mut self) -> OctaResult<i64> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+}
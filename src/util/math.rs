@@ -1,8 +1,23 @@
 use std::iter;
 
-use octa_force::glam::{ivec3, uvec3, vec3a, IVec3, UVec3, Vec3A};
+use octa_force::{anyhow::{anyhow, ensure}, glam::{ivec3, uvec3, vec3a, IVec3, UVec3, Vec3A}, OctaResult};
 use rayon::iter::IntoParallelRefIterator;
 
+// Sizes read from files are bounded, so a broken header can not ask for all of the memory.
+pub const MAX_IMPORT_VOXELS: u32 = 1 << 30;
+
+
+// The voxel count of a size read from a file. to_1d stays in u32 for every position inside of it.
+pub fn get_import_voxel_count(size: UVec3) -> OctaResult<usize> {
+    ensure!(size.cmpgt(UVec3::ZERO).all(), "Size {size} has an empty axis");
+
+    let count = size.x.checked_mul(size.y)
+        .and_then(|v| v.checked_mul(size.z))
+        .filter(|v| *v <= MAX_IMPORT_VOXELS)
+        .ok_or_else(|| anyhow!("Size {size} has more than {MAX_IMPORT_VOXELS} voxels"))?;
+
+    Ok(count as usize)
+}
 
 pub fn to_1d(pos: UVec3, size: UVec3) -> usize {
     (pos.x * size.y * size.z + pos.y * size.z + pos.z) as usize
@@ -61,6 +76,21 @@ pub fn to_kb(size: usize) -> f32 {
     size as f32 * 0.001
 }
 
+// Polynomial smooth min from https://iquilezles.org/articles/smin/
+// The result is never more than k / 4 below min(a, b).
+pub fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+pub fn smooth_max(a: f32, b: f32, k: f32) -> f32 {
+    -smooth_min(-a, -b, k)
+}
+
 /*
 pub fn sphere_aabb_intersection(aabb: AABB)
 {
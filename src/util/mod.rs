@@ -6,6 +6,7 @@ pub mod math_config;
 pub mod default_types;
 
 pub mod math;
+pub mod noise;
 pub mod profiler;
 pub mod state_saver;
 pub mod worker_response;
@@ -15,3 +16,4 @@ pub mod aabb_transformer;
 pub mod parallel_reuse_buffer;
 pub mod reuse_buffer;
 pub mod shader_constants;
+pub mod byte_reader;
@@ -0,0 +1,129 @@
+use octa_force::glam::Vec3A;
+use serde::{Deserialize, Serialize};
+
+// Seeded 3D gradient noise after Ken Perlins improved noise. Values are in [-1, 1].
+#[derive(Debug, Clone)]
+pub struct PerlinNoise {
+    perm: [u8; 512],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FractalNoiseSettings {
+    pub seed: u64,
+    pub octaves: u32,
+    pub frequency: f32,
+    pub amplitude: f32,
+    // Frequency multiplier from one octave to the next.
+    pub lacunarity: f32,
+    // Amplitude multiplier from one octave to the next.
+    pub gain: f32,
+}
+
+// Sum of perlin octaves. Values are in [-amplitude, amplitude].
+#[derive(Debug, Clone)]
+pub struct FractalNoise {
+    settings: FractalNoiseSettings,
+    noise: PerlinNoise,
+    norm: f32,
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> Self {
+        let mut p: [u8; 256] = std::array::from_fn(|i| i as u8);
+        fastrand::Rng::with_seed(seed).shuffle(&mut p);
+
+        Self {
+            perm: std::array::from_fn(|i| p[i & 255]),
+        }
+    }
+
+    pub fn get(&self, pos: Vec3A) -> f32 {
+        let cell = pos.floor();
+        let [x, y, z] = (cell.as_ivec3() & 255).to_array().map(|v| v as usize);
+        let [fx, fy, fz] = (pos - cell).to_array();
+        let [u, v, w] = [fade(fx), fade(fy), fade(fz)];
+
+        let p = &self.perm;
+        let a = p[x] as usize + y;
+        let aa = p[a] as usize + z;
+        let ab = p[a + 1] as usize + z;
+        let b = p[x + 1] as usize + y;
+        let ba = p[b] as usize + z;
+        let bb = p[b + 1] as usize + z;
+
+        let value = lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], fx, fy, fz), grad(p[ba], fx - 1.0, fy, fz)),
+                lerp(u, grad(p[ab], fx, fy - 1.0, fz), grad(p[bb], fx - 1.0, fy - 1.0, fz))),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], fx, fy, fz - 1.0), grad(p[ba + 1], fx - 1.0, fy, fz - 1.0)),
+                lerp(u, grad(p[ab + 1], fx, fy - 1.0, fz - 1.0), grad(p[bb + 1], fx - 1.0, fy - 1.0, fz - 1.0))));
+
+        // The gradients are not normalized, so the peaks can slightly overshoot.
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for FractalNoiseSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            octaves: 4,
+            frequency: 0.05,
+            amplitude: 4.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl FractalNoise {
+    pub fn new(settings: FractalNoiseSettings) -> Self {
+        let norm = (0..settings.octaves)
+            .map(|i| settings.gain.abs().powi(i as i32))
+            .sum::<f32>();
+
+        Self {
+            settings,
+            noise: PerlinNoise::new(settings.seed),
+            norm: if norm > 0.0 { norm.recip() } else { 0.0 },
+        }
+    }
+
+    pub fn get_settings(&self) -> FractalNoiseSettings {
+        self.settings
+    }
+
+    pub fn get_amplitude(&self) -> f32 {
+        self.settings.amplitude.abs()
+    }
+
+    pub fn get(&self, pos: Vec3A) -> f32 {
+        let mut frequency = self.settings.frequency;
+        let mut amplitude = 1.0;
+        let mut value = 0.0;
+        for _ in 0..self.settings.octaves {
+            value += self.noise.get(pos * frequency) * amplitude;
+            frequency *= self.settings.lacunarity;
+            amplitude *= self.settings.gain;
+        }
+
+        value * self.norm * self.settings.amplitude
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -29,6 +29,38 @@ impl<T: Copy + Default + fmt::Debug + Eq + std::hash::Hash> ParallelReUseBuffer<
         }
     }
 
+    // Everything before the write head counts as written and is flushed again. The cache starts empty, see rebuild_cache.
+    pub fn from_data(mut data: Vec<T>, size: usize, write_head: usize) -> ParallelReUseBuffer<T> {
+        data.resize(size, T::default());
+        ParallelReUseBuffer { 
+            data: UnsafeCell::new(data), 
+            write_head: AtomicUsize::new(write_head),
+            flushed: AtomicUsize::new(0),
+            cache: DashMap::with_hasher(nohash_hasher::BuildNoHashHasher::new()),
+        }
+    }
+
+    pub fn rebuild_cache(&self, ranges: impl Iterator<Item = std::ops::Range<usize>>) {
+        let hasher = fnv::FnvBuildHasher::default();
+        let data = self.get_data();
+        for r in ranges {
+            let values = &data[r.clone()];
+            let mut vec = self.cache.entry(hasher.hash_one(values)).or_default();
+            if vec.iter().any(|c| &data[c.as_range()] == values) {
+                continue;
+            }
+
+            vec.push(CompactRange {
+                start: r.start as _,
+                length: r.len() as _,
+            });
+        }
+    }
+
+    pub fn get_write_head(&self) -> usize {
+        self.write_head.load(Ordering::Relaxed)
+    }
+
     fn get_data(&self) -> &mut [T] {
         unsafe { &mut *self.data.get() }
     }
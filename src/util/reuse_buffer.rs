@@ -39,6 +39,35 @@ impl<T: Copy + Default + fmt::Debug + Eq + std::hash::Hash, Hasher: std::hash::B
         }
     } 
 
+    // The cache starts empty, see rebuild_cache.
+    pub fn from_data(mut data: Vec<T>, size: usize, used_ranges: Vec<(usize, usize)>) -> Self {
+        data.resize(size, T::default());
+        Self { 
+            data,
+            used_ranges,
+            cache: Default::default(),
+            _phantom: Default::default()
+        }
+    }
+
+    // Adds the ranges that later pushes should be able to reuse.
+    pub fn rebuild_cache(&mut self, ranges: impl Iterator<Item = std::ops::Range<usize>>) {
+        let hasher = Hasher::default();
+        for r in ranges {
+            let values = &self.data[r.clone()];
+            let hash = hasher.hash_one(values);
+            if self.cache.find(hash, |c| &self.data[c.as_range()] == values).is_some() {
+                continue;
+            }
+
+            let range = CompactRange {
+                start: r.start as u32,
+                length: r.len() as u8,
+            };
+            self.cache.insert_unique(hash, range, |c| hasher.hash_one(&self.data[c.as_range()]));
+        }
+    }
+
     pub fn push(&mut self, mut values: &[T]) -> OctaResult<u32> {
         if values.is_empty() {
             return Ok(0);
@@ -0,0 +1,158 @@
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::{IVec3, UVec3, Vec3}, OctaResult};
+
+use crate::{util::math::{get_import_voxel_count, to_1d}, voxel::{grid::offset::OffsetVoxelGrid, palette::Palette}};
+
+/**
+Binvox files only store which voxels are filled, so the whole model gets one color.
+The file is y up, the depth axis is flipped when swapping y and z so the model stays right handed.
+*/
+#[derive(Debug, Clone)]
+pub struct BinvoxModel {
+    // In our axes.
+    pub size: UVec3,
+    pub filled: Vec<bool>,
+    // Where the voxelizer placed the model, the voxels cover scale in every axis.
+    pub translate: Vec3,
+    pub scale: f32,
+}
+
+impl BinvoxModel {
+    pub fn new(path: &str) -> OctaResult<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> OctaResult<Self> {
+        let mut dims = None;
+        let mut translate = Vec3::ZERO;
+        let mut scale = 1.0;
+
+        let mut pos = 0;
+        let mut first = true;
+        loop {
+            let end = data[pos..].iter()
+                .position(|b| *b == b'\n')
+                .ok_or_else(|| anyhow!("Binvox header has no data line"))?;
+            let line = std::str::from_utf8(&data[pos..pos + end])?.trim();
+            pos += end + 1;
+
+            if first {
+                ensure!(line.starts_with("#binvox"), "Not a binvox file");
+                first = false;
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let values = |parts: std::str::SplitWhitespace| parts
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>();
+
+            match parts.next() {
+                Some("dim") => {
+                    let v = values(parts)?;
+                    ensure!(v.len() == 3, "Binvox dim needs 3 values");
+                    dims = Some(UVec3::new(v[0] as u32, v[1] as u32, v[2] as u32));
+                }
+                Some("translate") => {
+                    let v = values(parts)?;
+                    ensure!(v.len() == 3, "Binvox translate needs 3 values");
+                    translate = Vec3::new(v[0], v[1], v[2]);
+                }
+                Some("scale") => {
+                    scale = values(parts)?.first().copied().unwrap_or(1.0);
+                }
+                Some("data") => break,
+                _ => {}
+            }
+        }
+
+        let Some(dims) = dims else {
+            bail!("Binvox header has no dim line");
+        };
+
+        // y runs fastest, then z, then x.
+        let size = UVec3::new(dims.x, dims.z, dims.y);
+        let total = get_import_voxel_count(size)? as u32;
+        let mut filled = vec![false; total as usize];
+
+        // Counts past the end are ignored, so the index can not overflow.
+        let mut index = 0;
+        for pair in data[pos..].chunks_exact(2) {
+            if index >= total {
+                break;
+            }
+
+            let (value, count) = (pair[0], pair[1] as u32);
+            if value != 0 {
+                for i in index..(index + count).min(total) {
+                    let y = i % dims.y;
+                    let z = (i / dims.y) % dims.z;
+                    let x = i / (dims.y * dims.z);
+                    filled[to_1d(UVec3::new(x, dims.z - 1 - z, y), size)] = true;
+                }
+            }
+
+            index += count;
+        }
+
+        ensure!(index >= total, "Binvox data ended after {index} of {total} voxels");
+
+        Ok(Self {
+            size,
+            filled,
+            translate,
+            scale,
+        })
+    }
+
+    pub fn into_grid<P: Palette>(self, palette: &mut P, color: [u8; 3]) -> OctaResult<OffsetVoxelGrid> {
+        let mat = palette.get_index_simple_color(color)?;
+        let data = self.filled.iter()
+            .map(|f| if *f { mat } else { 0 })
+            .collect();
+
+        Ok(OffsetVoxelGrid::from_data(self.size, data, IVec3::ZERO))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{UVec3, Vec3};
+
+    use crate::util::math::to_1d;
+
+    use super::BinvoxModel;
+
+    fn new_file(dim: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("#binvox 1\ndim {dim}\ntranslate 1 2 3\nscale 0.5\ndata\n").into_bytes();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn load() {
+        let model = BinvoxModel::from_bytes(&new_file("2 2 2", &[1, 1, 0, 7])).unwrap();
+
+        assert_eq!(model.size, UVec3::new(2, 2, 2));
+        assert_eq!(model.translate, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(model.scale, 0.5);
+        assert_eq!(model.filled.iter().filter(|f| **f).count(), 1);
+
+        // The first voxel of the file is at the flipped end of the depth axis.
+        assert!(model.filled[to_1d(UVec3::new(0, 1, 0), model.size)]);
+    }
+
+    #[test]
+    fn ignore_counts_past_the_end() {
+        let model = BinvoxModel::from_bytes(&new_file("2 2 2", &[1, 255, 1, 255])).unwrap();
+        assert!(model.filled.iter().all(|f| *f));
+    }
+
+    #[test]
+    fn reject_bad_files() {
+        assert!(BinvoxModel::from_bytes(b"#notbinvox\ndata\n").is_err());
+        assert!(BinvoxModel::from_bytes(&new_file("0 2 2", &[])).is_err());
+        assert!(BinvoxModel::from_bytes(&new_file("2 2 2", &[1, 4])).is_err());
+        assert!(BinvoxModel::from_bytes(&new_file("2 2 2", &[])).is_err());
+    }
+}
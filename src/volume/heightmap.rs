@@ -0,0 +1,353 @@
+use std::{path::Path, sync::Arc};
+
+use octa_force::{anyhow::{anyhow, ensure}, glam::{uvec2, vec2, UVec2, Vec2, Vec3A}, image::ImageReader, OctaResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{util::{aabb::AABB, number::Nu, vector::Ve}, voxel::palette::palette::MATERIAL_ID_NONE};
+
+use super::{VolumeBounds, VolumeGradient, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValid, VolumeQureyPosValue};
+
+pub const HEIGHTMAP_TILE_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeightmapSampling {
+    Nearest,
+    Bilinear,
+}
+
+// Heights are normalized to [0, 1]. Every tile knows the min and max of its pixels including the border to the next tile.
+#[derive(Debug)]
+pub struct HeightmapData {
+    size: UVec2,
+    heights: Vec<f32>,
+    tile_count: UVec2,
+    tiles: Vec<(f32, f32)>,
+}
+
+/**
+Solid from offset.z up to the sampled height. The image x and y map to the world x and y.
+Pixel centers are horizontal_scale apart and a height of 1 is vertical_scale high.
+2D queries look at the first image row with y as the height.
+*/
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub data: Arc<HeightmapData>,
+    pub offset: Vec3A,
+    pub horizontal_scale: f32,
+    pub vertical_scale: f32,
+    pub sampling: HeightmapSampling,
+    pub material: u8,
+}
+
+impl HeightmapData {
+    pub fn new(size: UVec2, heights: Vec<f32>) -> OctaResult<Self> {
+        ensure!(size.x > 0 && size.y > 0, "Heightmap can not be empty");
+        let count = size.x.checked_mul(size.y)
+            .ok_or_else(|| anyhow!("Heightmap size {size} is too large"))?;
+        ensure!(heights.len() == count as usize, "Heightmap has {} heights but a size of {size}", heights.len());
+
+        let tile_count = ((size - 1 + HEIGHTMAP_TILE_SIZE - 1) / HEIGHTMAP_TILE_SIZE).max(UVec2::ONE);
+        let mut data = Self {
+            size,
+            heights,
+            tile_count,
+            tiles: vec![],
+        };
+
+        let tiles = (0..tile_count.y)
+            .flat_map(|y| (0..tile_count.x).map(move |x| uvec2(x, y)))
+            .map(|tile| {
+                let min = tile * HEIGHTMAP_TILE_SIZE;
+                let max = ((tile + 1) * HEIGHTMAP_TILE_SIZE).min(size - 1);
+                data.get_pixel_range(min, max)
+            })
+            .collect();
+        data.tiles = tiles;
+
+        Ok(data)
+    }
+
+    // Grayscale of the image, 16 bit images keep their precision.
+    pub fn from_image<P: AsRef<Path>>(path: P) -> OctaResult<Self> {
+        let img = ImageReader::open(path)?.decode()?.into_luma16();
+        let size = uvec2(img.width(), img.height());
+        let heights = img.pixels()
+            .map(|p| p.0[0] as f32 / u16::MAX as f32)
+            .collect();
+
+        Self::new(size, heights)
+    }
+
+    pub fn get_size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn get_heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    pub fn get_pixel(&self, pixel: UVec2) -> f32 {
+        self.heights[(pixel.x + pixel.y * self.size.x) as usize]
+    }
+
+    // Pixel coordinates are clamped to the image.
+    pub fn sample(&self, pixel: Vec2, sampling: HeightmapSampling) -> f32 {
+        let pixel = pixel.clamp(Vec2::ZERO, (self.size - 1).as_vec2());
+
+        match sampling {
+            HeightmapSampling::Nearest => self.get_pixel(pixel.round().as_uvec2()),
+            HeightmapSampling::Bilinear => {
+                let a = pixel.floor().as_uvec2();
+                let b = (a + 1).min(self.size - 1);
+                let t = pixel - pixel.floor();
+
+                let top = lerp(self.get_pixel(a), self.get_pixel(uvec2(b.x, a.y)), t.x);
+                let bottom = lerp(self.get_pixel(uvec2(a.x, b.y)), self.get_pixel(b), t.x);
+                lerp(top, bottom, t.y)
+            },
+        }
+    }
+
+    // Min and max height of all samples in the pixel area.
+    pub fn get_range(&self, min: Vec2, max: Vec2) -> (f32, f32) {
+        let last = self.size - 1;
+        let min = min.floor().max(Vec2::ZERO).as_uvec2().min(last);
+        let max = max.ceil().max(Vec2::ZERO).as_uvec2().min(last);
+
+        let tile_min = (min / HEIGHTMAP_TILE_SIZE).min(self.tile_count - 1);
+        // A range on the border between two tiles only needs the tile after it.
+        let tile_max = (uvec2(max.x.saturating_sub(1), max.y.saturating_sub(1)) / HEIGHTMAP_TILE_SIZE).min(self.tile_count - 1).max(tile_min);
+
+        let mut range = (f32::MAX, f32::MIN);
+        for y in tile_min.y..=tile_max.y {
+            for x in tile_min.x..=tile_max.x {
+                let tile = self.tiles[(x + y * self.tile_count.x) as usize];
+                range = (range.0.min(tile.0), range.1.max(tile.1));
+            }
+        }
+
+        range
+    }
+
+    pub fn get_max(&self) -> f32 {
+        self.tiles.iter().fold(0.0, |max, tile| max.max(tile.1))
+    }
+
+    fn get_pixel_range(&self, min: UVec2, max: UVec2) -> (f32, f32) {
+        let mut range = (f32::MAX, f32::MIN);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let h = self.get_pixel(uvec2(x, y));
+                range = (range.0.min(h), range.1.max(h));
+            }
+        }
+
+        range
+    }
+}
+
+impl Heightmap {
+    pub fn new(data: Arc<HeightmapData>, offset: Vec3A, horizontal_scale: f32, vertical_scale: f32, sampling: HeightmapSampling, material: u8) -> Self {
+        Self {
+            data,
+            offset,
+            horizontal_scale,
+            vertical_scale,
+            sampling,
+            material,
+        }
+    }
+
+    pub fn from_image<P: AsRef<Path>>(path: P, offset: Vec3A, horizontal_scale: f32, vertical_scale: f32, sampling: HeightmapSampling, material: u8) -> OctaResult<Self> {
+        let data = HeightmapData::from_image(path)?;
+        Ok(Self::new(Arc::new(data), offset, horizontal_scale, vertical_scale, sampling, material))
+    }
+
+    // Returns the pixel position and the height above the base.
+    fn to_local<V: Ve<T, D>, T: Nu, const D: usize>(&self, pos: V) -> (Vec2, f32) {
+        let p = pos.to_array().map(T::to_f32);
+        let (x, y, z) = if D == 3 { (p[0], p[1], p[2]) } else { (p[0], self.offset.y, p[1]) };
+
+        ((vec2(x, y) - self.offset.truncate()) / self.horizontal_scale, z - self.offset.z)
+    }
+
+    fn from_local<V: Ve<T, D>, T: Nu, const D: usize>(&self, pixel: Vec2, height: f32, round: fn(f32) -> f32) -> V {
+        let p = pixel * self.horizontal_scale + self.offset.truncate();
+        let z = height + self.offset.z;
+        let p = if D == 3 { [p.x, p.y, z] } else { [p.x, z, 0.0] };
+
+        V::new(std::array::from_fn(|i| T::from_f32(round(p[i]))))
+    }
+
+    fn in_image(&self, pixel: Vec2) -> bool {
+        pixel.cmpge(Vec2::ZERO).all() && pixel.cmple((self.data.size - 1).as_vec2()).all()
+    }
+
+    pub fn get_height(&self, pixel: Vec2) -> f32 {
+        self.data.sample(pixel, self.sampling) * self.vertical_scale
+    }
+
+    pub fn is_inside<V: Ve<T, D>, T: Nu, const D: usize>(&self, pos: V) -> bool {
+        let (pixel, z) = self.to_local(pos);
+        self.in_image(pixel) && z >= 0.0 && z < self.get_height(pixel)
+    }
+
+    // Vertical distance to the surface, so it overestimates on steep slopes.
+    pub fn get_distance<V: Ve<f32, D>, const D: usize>(&self, pos: V) -> f32 {
+        let (pixel, z) = self.to_local(pos);
+        let last = (self.data.size - 1).as_vec2();
+
+        let outside = (Vec2::ZERO - pixel).max(pixel - last).max(Vec2::ZERO).length() * self.horizontal_scale;
+        let vertical = (z - self.get_height(pixel.clamp(Vec2::ZERO, last))).max(-z);
+
+        if outside > 0.0 {
+            (outside * outside + vertical.max(0.0).powi(2)).sqrt()
+        } else {
+            vertical
+        }
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for Heightmap {
+    fn calculate_bounds(&mut self) {}
+
+    // The max is exclusive, so it is one pixel beyond the last one that is_inside accepts.
+    fn get_bounds(&self) -> AABB<V, T, D> {
+        let max = self.data.size.as_vec2();
+        AABB::new(
+            self.from_local(Vec2::ZERO, 0.0, f32::floor),
+            self.from_local(max, self.data.get_max() * self.vertical_scale, f32::ceil))
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for Heightmap {
+    fn get_value(&self, pos: V) -> u8 {
+        if self.is_inside(pos) {
+            self.material
+        } else {
+            MATERIAL_ID_NONE
+        }
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for Heightmap {
+    fn is_position_valid(&self, pos: V) -> bool {
+        self.is_inside(pos)
+    }
+}
+
+// The tiles give the height range below the aabb, so whole columns are classified without sampling.
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for Heightmap {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        let (min, z_min) = self.to_local(aabb.min());
+        let (max, z_max) = self.to_local(aabb.max());
+        let last = (self.data.size - 1).as_vec2();
+
+        if max.cmplt(Vec2::ZERO).any() || min.cmpgt(last).any() || z_max < 0.0 {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let (h_min, h_max) = self.data.get_range(min, max);
+        if z_min >= h_max * self.vertical_scale {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        if self.in_image(min) && self.in_image(max) && z_min >= 0.0 && z_max < h_min * self.vertical_scale {
+            return VolumeQureyAABBResult::Full(self.material);
+        }
+
+        VolumeQureyAABBResult::Mixed
+    }
+}
+
+// Points up and away from the slope.
+impl<V: Ve<f32, D>, const D: usize> VolumeGradient<V, D> for Heightmap {
+    fn get_gradient_at_position(&self, pos: V) -> V {
+        let (pixel, _) = self.to_local(pos);
+        let slope = |offset: Vec2| (self.get_height(pixel + offset) - self.get_height(pixel - offset)) / self.horizontal_scale;
+        let dx = slope(vec2(0.5, 0.0));
+        let dy = slope(vec2(0.0, 0.5));
+
+        let grad = if D == 3 { [-dx, -dy, 1.0] } else { [-dx, 1.0, 0.0] };
+        V::new(std::array::from_fn(|i| grad[i]))
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use octa_force::glam::{uvec2, vec2, vec3a, IVec3, UVec2};
+
+    use crate::{util::aabb::AABB, volume::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue}};
+
+    use super::{Heightmap, HeightmapData, HeightmapSampling};
+
+    // 3 x 3 tiles with a height of 0.5, a peak of 1 at (20, 5) and a dip of 0.25 at (35, 35).
+    fn new_data() -> HeightmapData {
+        let size = UVec2::splat(40);
+        let mut heights = vec![0.5; 40 * 40];
+        heights[20 + 5 * 40] = 1.0;
+        heights[35 + 35 * 40] = 0.25;
+
+        HeightmapData::new(size, heights).unwrap()
+    }
+
+    // Pixel centers are 2 apart starting at x = 10, a height of 1 is 8 high.
+    fn new_heightmap() -> Heightmap {
+        Heightmap::new(Arc::new(new_data()), vec3a(10.0, 0.0, 0.0), 2.0, 8.0, HeightmapSampling::Nearest, 1)
+    }
+
+    #[test]
+    fn reject_bad_size() {
+        assert!(HeightmapData::new(uvec2(0, 3), vec![]).is_err());
+        assert!(HeightmapData::new(uvec2(2, 2), vec![0.0; 3]).is_err());
+        assert!(HeightmapData::new(uvec2(u32::MAX, 2), vec![0.0; 4]).is_err());
+    }
+
+    #[test]
+    fn tile_ranges() {
+        let data = new_data();
+        assert_eq!(data.get_range(vec2(0.0, 0.0), vec2(10.0, 10.0)), (0.5, 0.5));
+        assert_eq!(data.get_range(vec2(18.0, 2.0), vec2(22.0, 8.0)), (0.5, 1.0));
+        assert_eq!(data.get_range(vec2(30.0, 30.0), vec2(39.0, 39.0)), (0.25, 0.5));
+        assert_eq!(data.get_range(vec2(0.0, 0.0), vec2(39.0, 39.0)), (0.25, 1.0));
+        assert_eq!(data.get_range(vec2(16.0, 16.0), vec2(16.0, 16.0)), (0.5, 0.5));
+        assert_eq!(data.get_range(vec2(-5.0, -5.0), vec2(100.0, 2.0)), (0.5, 1.0));
+        assert_eq!(data.get_max(), 1.0);
+    }
+
+    #[test]
+    fn values() {
+        let heightmap = new_heightmap();
+        assert_eq!(heightmap.get_value(IVec3::new(50, 10, 7)), 1);
+        assert_eq!(heightmap.get_value(IVec3::new(52, 10, 7)), 0);
+        assert_eq!(heightmap.get_value(IVec3::new(40, 10, 3)), 1);
+        assert_eq!(heightmap.get_value(IVec3::new(40, 10, 4)), 0);
+        assert_eq!(heightmap.get_value(IVec3::new(40, 10, -1)), 0);
+        assert_eq!(heightmap.get_value(IVec3::new(8, 10, 1)), 0);
+    }
+
+    #[test]
+    fn bounds() {
+        let bounds: AABB<IVec3, i32, 3> = new_heightmap().get_bounds();
+        assert_eq!(bounds.min(), IVec3::new(10, 0, 0));
+        assert_eq!(bounds.max(), IVec3::new(90, 80, 8));
+    }
+
+    #[test]
+    fn aabb_values() {
+        let heightmap = new_heightmap();
+        let value = |min: IVec3, max: IVec3| heightmap.get_aabb_value(AABB::new(min, max));
+
+        assert!(matches!(value(IVec3::new(12, 2, 0), IVec3::new(20, 8, 3)), VolumeQureyAABBResult::Full(1)));
+        assert!(matches!(value(IVec3::new(12, 2, 5), IVec3::new(20, 8, 7)), VolumeQureyAABBResult::Full(0)));
+        assert!(matches!(value(IVec3::new(46, 6, 5), IVec3::new(54, 14, 7)), VolumeQureyAABBResult::Mixed));
+        assert!(matches!(value(IVec3::new(0, 2, 0), IVec3::new(5, 8, 3)), VolumeQureyAABBResult::Full(0)));
+        assert!(matches!(value(IVec3::new(12, 2, -5), IVec3::new(20, 8, -1)), VolumeQureyAABBResult::Full(0)));
+    }
+}
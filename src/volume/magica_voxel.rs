@@ -1,22 +1,45 @@
-use octa_force::{anyhow::anyhow, glam::{IVec3, Mat3, UVec3, Vec3, Vec3A}, OctaResult};
+use std::collections::HashMap;
+
+use octa_force::{anyhow::{anyhow, ensure}, glam::{IVec3, Mat3, UVec3, Vec3, Vec3A}, OctaResult};
 use dot_vox::*;
 
-use crate::{util::{aabb::IAABB3, math::to_1d}, voxel::{grid::offset::OffsetVoxelGrid, palette::Palette}};
+use crate::{util::{aabb::{IAABB3, AABB3}, math::{get_import_voxel_count, to_1d}}, voxel::{grid::{offset::OffsetVoxelGrid, summary::merge_value}, palette::{palette::MATERIAL_ID_NONE, Palette}}};
+
+use super::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue, VolumeQureyRay, VolumeRayHit};
 
-use super::{VolumeBounds, VolumeQureyPosValue};
+pub const VOX_BRICK_SIZE: i32 = 8;
+const VOX_BRICK_VOLUME: usize = (VOX_BRICK_SIZE * VOX_BRICK_SIZE * VOX_BRICK_SIZE) as usize;
 
+/**
+Queries are answered from a sparse map of 8x8x8 bricks, so only the voxels of the models take memory.
+The voxels store the color index of the vox file starting at 1, so 0 stays empty.
+The used colors are added to the palette when the model is loaded, queries return their materials.
+*/
 pub struct MagicaVoxelModel {
     min: IVec3,
     max: IVec3,
     vox: DotVoxData,
     models_and_positions: Vec<(IVec3, UVec3, Rotation, u32)>, 
+    bricks: HashMap<IVec3, MagicaVoxelBrick>,
+    used_colors: [bool; 256],
+    materials: [u8; 256],
+}
+
+struct MagicaVoxelBrick {
+    voxels: Box<[u8; VOX_BRICK_VOLUME]>,
+    // Set if all voxels have the same color.
+    value: Option<u8>,
 }
 
 impl MagicaVoxelModel {
-    pub fn new(path: &str) -> OctaResult<Self> {
+    pub fn new<P: Palette>(path: &str, palette: &mut P) -> OctaResult<Self> {
         let vox = load(path)
             .map_err(|e| anyhow!(e))?;
 
+        Self::from_vox(vox, palette)
+    }
+
+    pub fn from_vox<P: Palette>(vox: DotVoxData, palette: &mut P) -> OctaResult<Self> {
         let mut stack = vec![(0, IVec3::ZERO, dot_vox::Rotation::IDENTITY)];
         let mut models_and_positions = Vec::new();
 
@@ -77,6 +100,8 @@ impl MagicaVoxelModel {
             }
         }
 
+        ensure!(!models_and_positions.is_empty(), "The vox file has no models");
+
         let mut min = IVec3::splat(i32::MAX);
         let mut max = IVec3::splat(i32::MIN);
 
@@ -85,13 +110,117 @@ impl MagicaVoxelModel {
             max = max.max(pos + (model_size / 2).as_ivec3());
         }
 
-        Ok(Self {
+        let mut model = Self {
             min, 
             max, 
             vox,
-            models_and_positions
-        })
+            models_and_positions,
+            bricks: HashMap::new(),
+            used_colors: [false; 256],
+            materials: [MATERIAL_ID_NONE; 256],
+        };
+        model.build_bricks();
+        model.map_palette(palette)?;
+
+        Ok(model)
     }
+
+    // Later models overwrite earlier ones like in into_grid.
+    fn build_bricks(&mut self) {
+        let mut bricks: HashMap<IVec3, MagicaVoxelBrick> = HashMap::new();
+        let mut used_colors = [false; 256];
+
+        for placed in self.models_and_positions.iter() {
+            for (voxel_pos, color_index) in self.placed_voxels(*placed) {
+                let color = color_index.saturating_add(1);
+                used_colors[color as usize] = true;
+
+                let brick = voxel_pos.div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+                bricks.entry(brick)
+                    .or_insert_with(MagicaVoxelBrick::empty)
+                    .voxels[brick_index(voxel_pos - brick * VOX_BRICK_SIZE)] = color;
+            }
+        }
+
+        for brick in bricks.values_mut() {
+            brick.update_value();
+        }
+
+        self.bricks = bricks;
+        self.used_colors = used_colors;
+    }
+
+    fn map_palette<P: Palette>(&mut self, palette: &mut P) -> OctaResult<()> {
+        for color in 1..256 {
+            if !self.used_colors[color] {
+                continue;
+            }
+
+            let c = self.vox.palette[color - 1];
+            self.materials[color] = palette.get_index_simple_color([c.r, c.g, c.b])?;
+        }
+
+        Ok(())
+    }
+
+    fn get_color(&self, pos: IVec3) -> u8 {
+        let brick = pos.div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+        match self.bricks.get(&brick) {
+            Some(b) => b.voxels[brick_index(pos - brick * VOX_BRICK_SIZE)],
+            None => 0,
+        }
+    }
+
+    // Merges the materials of the brick in [min, max). Returns false if they differ.
+    fn merge_brick(&self, brick_pos: IVec3, brick: &MagicaVoxelBrick, min: IVec3, max: IVec3, value: &mut Option<u8>, covered: &mut u64) -> bool {
+        let brick_min = brick_pos * VOX_BRICK_SIZE;
+        let lo = brick_min.max(min);
+        let hi = (brick_min + VOX_BRICK_SIZE).min(max);
+        if lo.cmpge(hi).any() {
+            return true;
+        }
+
+        *covered += voxel_count(hi - lo);
+
+        if let Some(color) = brick.value {
+            return merge_value(value, self.materials[color as usize]);
+        }
+
+        for x in lo.x..hi.x {
+            for y in lo.y..hi.y {
+                for z in lo.z..hi.z {
+                    let color = brick.voxels[brick_index(IVec3::new(x, y, z) - brick_min)];
+                    if !merge_value(value, self.materials[color as usize]) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl MagicaVoxelBrick {
+    fn empty() -> Self {
+        Self {
+            voxels: Box::new([0; VOX_BRICK_VOLUME]),
+            value: None,
+        }
+    }
+
+    fn update_value(&mut self) {
+        let first = self.voxels[0];
+        self.value = self.voxels.iter().all(|v| *v == first).then_some(first);
+    }
+}
+
+fn voxel_count(size: IVec3) -> u64 {
+    size.x as u64 * size.y as u64 * size.z as u64
+}
+
+fn brick_index(local: IVec3) -> usize {
+    to_1d(local.as_uvec3(), UVec3::splat(VOX_BRICK_SIZE as u32))
 }
 
 impl VolumeBounds<IVec3, i32, 3> for MagicaVoxelModel {
@@ -102,42 +231,195 @@ impl VolumeBounds<IVec3, i32, 3> for MagicaVoxelModel {
     }
 }
 
+impl VolumeQureyPosValue<IVec3, i32, 3> for MagicaVoxelModel {
+    fn get_value(&self, pos: IVec3) -> u8 {
+        self.materials[self.get_color(pos) as usize]
+    }
+}
+
+/**
+The aabb covers the voxels in [min, max).
+Small aabbs look up the bricks they touch, large ones walk all bricks, so sparse scenes stay fast at the top of a DAG.
+*/
+impl VolumeQureyAABB<IVec3, i32, 3> for MagicaVoxelModel {
+    fn get_aabb_value(&self, aabb: IAABB3) -> VolumeQureyAABBResult {
+        let (min, max) = (aabb.min(), aabb.max());
+        if min.cmpge(max).any() {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let brick_min = min.div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+        let brick_max = (max - 1).div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+        let brick_count = voxel_count(brick_max - brick_min + 1);
+
+        let mut value = None;
+        let mut covered = 0;
+        let same = if brick_count <= self.bricks.len() as u64 {
+            (brick_min.x..=brick_max.x).all(|x| {
+                (brick_min.y..=brick_max.y).all(|y| {
+                    (brick_min.z..=brick_max.z).all(|z| {
+                        let brick_pos = IVec3::new(x, y, z);
+                        self.bricks.get(&brick_pos)
+                            .map_or(true, |brick| self.merge_brick(brick_pos, brick, min, max, &mut value, &mut covered))
+                    })
+                })
+            })
+        } else {
+            self.bricks.iter()
+                .all(|(brick_pos, brick)| self.merge_brick(*brick_pos, brick, min, max, &mut value, &mut covered))
+        };
+
+        // Voxels without a brick are empty.
+        if !same || (covered < voxel_count(max - min) && !merge_value(&mut value, MATERIAL_ID_NONE)) {
+            return VolumeQureyAABBResult::Mixed;
+        }
+
+        VolumeQureyAABBResult::Full(value.unwrap_or(MATERIAL_ID_NONE))
+    }
+}
+
 impl MagicaVoxelModel {
-    pub fn into_grid<P: Palette>(self, palette: &mut P) -> OctaResult<OffsetVoxelGrid> { 
+    pub fn into_grid(self) -> OctaResult<OffsetVoxelGrid> { 
 
         let size = (self.max - self.min + 1).as_uvec3();
 
-        let mut array = vec![0; size.x as usize * size.y as usize * size.z as usize];
+        let mut array = vec![0; get_import_voxel_count(size)?];
+
+        for placed in self.models_and_positions.iter() {
+            for (voxel_pos, color_index) in self.placed_voxels(*placed) {
+                array[to_1d((voxel_pos - self.min).as_uvec3(), size)] = self.materials[color_index.saturating_add(1) as usize];
+            }
+        }
+
+        Ok(OffsetVoxelGrid::from_data(size, array, self.min))
+    }
 
-        for (pos, model_size, rotation, model_id) in self.models_and_positions {
-            let offset = pos - self.min - (model_size / 2).as_ivec3();
+    // World position and palette index of every voxel of a placed model.
+    fn placed_voxels(&self, (pos, model_size, rotation, model_id): (IVec3, UVec3, Rotation, u32)) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        let offset = pos - (model_size / 2).as_ivec3();
 
-            let channel_reordering = (Mat3::from_cols_array_2d(&rotation.to_cols_array_2d())
-                * Vec3::new(1.0, 2.0, 3.0))
-                .as_ivec3();
-            let model_size_swizzled = swizzle(model_size.as_ivec3(), channel_reordering.abs());
+        let channel_reordering = (Mat3::from_cols_array_2d(&rotation.to_cols_array_2d())
+            * Vec3::new(1.0, 2.0, 3.0))
+            .as_ivec3();
+        let model_size_swizzled = swizzle(model_size.as_ivec3(), channel_reordering.abs());
 
-            let model = &self.vox.models[model_id as usize];
-            for voxel in &model.voxels {
-                let voxel_pos = IVec3::new(voxel.x as i32, voxel.y as i32, voxel.z as i32);
+        let model = &self.vox.models[model_id as usize];
+        model.voxels.iter().map(move |voxel| {
+            let voxel_pos = IVec3::new(voxel.x as i32, voxel.y as i32, voxel.z as i32);
 
-                let mut voxel_pos_swizzled = swizzle(voxel_pos, channel_reordering);
+            let mut voxel_pos_swizzled = swizzle(voxel_pos, channel_reordering);
 
-                for i in 0..3 {
-                    if channel_reordering[i] < 0 {
-                        voxel_pos_swizzled[i] = model_size_swizzled[i] - 1 - voxel_pos_swizzled[i];
-                    }
+            for i in 0..3 {
+                if channel_reordering[i] < 0 {
+                    voxel_pos_swizzled[i] = model_size_swizzled[i] - 1 - voxel_pos_swizzled[i];
                 }
+            }
 
-                let color = self.vox.palette[voxel.i as usize];
-                let mat_nr = palette.get_index_simple_color([color.r, color.g, color.b])?;
+            (offset + voxel_pos_swizzled, voxel.i)
+        })
+    }
+}
+
+/**
+Walks the bricks along the ray and inside of bricks that are not uniform their voxels, both with a DDA.
+Missing bricks are empty, so sparse models skip most of the ray.
+*/
+impl VolumeQureyRay<Vec3A, 3> for MagicaVoxelModel {
+    fn get_ray_hit(&self, origin: Vec3A, dir: Vec3A, max_t: f32) -> Option<VolumeRayHit<Vec3A>> {
+        let bounds = AABB3::new(self.min.as_vec3a(), (self.max + 1).as_vec3a());
+        let (enter, exit) = bounds.ray_range(origin, dir)?;
+        let start = enter.max(0.0);
+        let end = exit.min(max_t);
+        if start > end {
+            return None;
+        }
 
-                let voxel_pos = offset + voxel_pos_swizzled;
-                array[to_1d(voxel_pos.as_uvec3(), size)] = mat_nr;
+        let brick_min = self.min.div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+        let brick_max = self.max.div_euclid(IVec3::splat(VOX_BRICK_SIZE));
+        dda(origin, dir, VOX_BRICK_SIZE, start, end, brick_min, brick_max, |brick_pos, t, t_exit, axis| {
+            let brick = self.bricks.get(&brick_pos)?;
+            let voxel_min = brick_pos * VOX_BRICK_SIZE;
+            let voxel_max = voxel_min + VOX_BRICK_SIZE - 1;
+
+            match brick.value {
+                Some(0) => None,
+                Some(color) => Some(self.get_hit(origin, dir, t, axis, voxel_min, voxel_max, color)),
+                None => dda(origin, dir, 1, t, t_exit, voxel_min, voxel_max, |voxel_pos, t, _, voxel_axis| {
+                    let color = brick.voxels[brick_index(voxel_pos - voxel_min)];
+                    // The first voxel is entered through the face of the brick.
+                    let voxel_axis = voxel_axis.or(axis);
+                    (color != 0).then(|| self.get_hit(origin, dir, t, voxel_axis, voxel_pos, voxel_pos, color))
+                }),
             }
+        })
+    }
+}
+
+impl MagicaVoxelModel {
+    // The hit voxel lies in [min, max]. Without the axis the ray was entered through the face closest to the hit is used.
+    fn get_hit(&self, origin: Vec3A, dir: Vec3A, t: f32, axis: Option<usize>, min: IVec3, max: IVec3, color: u8) -> VolumeRayHit<Vec3A> {
+        let pos = origin + dir * t;
+
+        let mut normal = Vec3A::ZERO;
+        match axis {
+            Some(axis) => normal[axis] = -dir[axis].signum(),
+            None => {
+                let voxel_pos = pos.floor().as_ivec3().clamp(min, max);
+                let q = pos - (voxel_pos.as_vec3a() + 0.5);
+                let axis = (0..3).max_by(|a, b| q[*a].abs().total_cmp(&q[*b].abs())).unwrap();
+                normal[axis] = q[axis].signum();
+            },
         }
 
-        Ok(OffsetVoxelGrid::from_data(size, array, self.min))
+        VolumeRayHit {
+            t,
+            pos,
+            normal,
+            material: self.materials[color as usize],
+        }
+    }
+}
+
+/**
+Steps through the cells of size cell_size in [min, max] that the ray passes in [t, end].
+f gets the cell, the t where the ray enters and leaves it and the axis it was entered through.
+*/
+fn dda<R>(origin: Vec3A, dir: Vec3A, cell_size: i32, mut t: f32, end: f32, min: IVec3, max: IVec3, mut f: impl FnMut(IVec3, f32, f32, Option<usize>) -> Option<R>) -> Option<R> {
+    let o = origin / cell_size as f32;
+    let d = dir / cell_size as f32;
+    let step = IVec3::from_array(dir.to_array().map(|v| if v < 0.0 { -1 } else { 1 }));
+
+    let t_delta = d.abs().recip();
+    let mut cell = (o + d * t).floor().as_ivec3().clamp(min, max);
+    let mut t_next: [f32; 3] = std::array::from_fn(|i| {
+        if d[i] > 0.0 {
+            ((cell[i] + 1) as f32 - o[i]) / d[i]
+        } else if d[i] < 0.0 {
+            (cell[i] as f32 - o[i]) / d[i]
+        } else {
+            f32::INFINITY
+        }
+    });
+
+    let mut axis = None;
+    loop {
+        let next_axis = (0..3).min_by(|a, b| t_next[*a].total_cmp(&t_next[*b])).unwrap();
+        if let Some(r) = f(cell, t, t_next[next_axis].min(end), axis) {
+            return Some(r);
+        }
+
+        t = t_next[next_axis];
+        if t > end {
+            return None;
+        }
+
+        cell[next_axis] += step[next_axis];
+        if cell[next_axis] < min[next_axis] || cell[next_axis] > max[next_axis] {
+            return None;
+        }
+
+        t_next[next_axis] += t_delta[next_axis];
+        axis = Some(next_axis);
     }
 }
 
@@ -150,3 +432,132 @@ fn swizzle(v: IVec3, indices: IVec3) -> IVec3 {
         v[indices.z as usize],
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, UVec3, Vec3A};
+
+    use crate::{util::aabb::IAABB3, volume::{vox_writer::VoxWriter, VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue}, voxel::palette::{palette::LocalPalette, Palette}};
+
+    use super::{dda, MagicaVoxelModel};
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    // Written with the vox writer, so the vox color indecies are 1 for red and 2 for blue.
+    fn new_model(palette: &mut LocalPalette) -> MagicaVoxelModel {
+        let mut writer_palette = LocalPalette::new();
+        let red = writer_palette.get_index_simple_color(RED).unwrap();
+        let blue = writer_palette.get_index_simple_color(BLUE).unwrap();
+
+        let mut writer = VoxWriter::new();
+        writer.add_voxel(IVec3::new(0, 0, 0), red);
+        writer.add_voxel(IVec3::new(1, 0, 0), blue);
+        writer.add_voxel(IVec3::new(300, 2, 1), red);
+
+        let mut bytes = vec![];
+        writer.write(&mut bytes, &writer_palette).unwrap();
+        MagicaVoxelModel::from_vox(dot_vox::load_bytes(&bytes).unwrap(), palette).unwrap()
+    }
+
+    #[test]
+    fn values_use_the_palette() {
+        let mut palette = LocalPalette::new();
+        for i in 0..5 {
+            palette.get_index_simple_color([i, i, i]).unwrap();
+        }
+
+        let model = new_model(&mut palette);
+        let red = palette.get_index_simple_color(RED).unwrap();
+        let blue = palette.get_index_simple_color(BLUE).unwrap();
+        assert!(red > 2 && blue > 2);
+
+        assert_eq!(model.get_value(IVec3::new(0, 0, 0)), red);
+        assert_eq!(model.get_value(IVec3::new(1, 0, 0)), blue);
+        assert_eq!(model.get_value(IVec3::new(2, 0, 0)), 0);
+        assert_eq!(model.get_value(IVec3::new(300, 2, 1)), red);
+
+        let bounds = model.get_bounds();
+        assert_eq!((bounds.min(), bounds.max()), (IVec3::ZERO, IVec3::new(301, 3, 2)));
+    }
+
+    #[test]
+    fn aabb_values() {
+        let mut palette = LocalPalette::new();
+        let model = new_model(&mut palette);
+        let red = palette.get_index_simple_color(RED).unwrap();
+
+        let full = |min: [i32; 3], max: [i32; 3]| match model.get_aabb_value(IAABB3::new(IVec3::from_array(min), IVec3::from_array(max))) {
+            VolumeQureyAABBResult::Full(v) => Some(v),
+            VolumeQureyAABBResult::Mixed => None,
+        };
+
+        assert_eq!(full([0, 0, 0], [1, 1, 1]), Some(red));
+        assert_eq!(full([300, 2, 1], [301, 3, 2]), Some(red));
+        assert_eq!(full([0, 0, 0], [2, 1, 1]), None);
+        assert_eq!(full([0, 0, 0], [1, 2, 1]), None);
+        assert_eq!(full([2, 0, 0], [200, 64, 64]), Some(0));
+        assert_eq!(full([-64, -64, -64], [400, 64, 64]), None);
+    }
+
+    #[test]
+    fn into_grid() {
+        let mut palette = LocalPalette::new();
+        let model = new_model(&mut palette);
+        let blue = palette.get_index_simple_color(BLUE).unwrap();
+        let values: Vec<_> = [IVec3::new(0, 0, 0), IVec3::new(1, 0, 0), IVec3::new(300, 2, 1), IVec3::new(150, 1, 1)].iter()
+            .map(|pos| model.get_value(*pos))
+            .collect();
+
+        let grid = model.into_grid().unwrap();
+        assert_eq!(grid.offset, IVec3::ZERO);
+        assert_eq!(grid.grid.size, UVec3::new(301, 3, 2));
+        assert_eq!(grid.grid.get(UVec3::new(1, 0, 0)), blue);
+
+        let grid_values: Vec<_> = [UVec3::new(0, 0, 0), UVec3::new(1, 0, 0), UVec3::new(300, 2, 1), UVec3::new(150, 1, 1)].iter()
+            .map(|pos| grid.grid.get(*pos))
+            .collect();
+        assert_eq!(grid_values, values);
+    }
+
+    fn collect_cells(origin: Vec3A, dir: Vec3A, cell_size: i32, t: f32, end: f32, min: IVec3, max: IVec3) -> Vec<(IVec3, Option<usize>)> {
+        let mut cells = vec![];
+        dda::<()>(origin, dir, cell_size, t, end, min, max, |cell, _, _, axis| {
+            cells.push((cell, axis));
+            None
+        });
+        cells
+    }
+
+    #[test]
+    fn straight() {
+        let cells = collect_cells(Vec3A::new(0.5, 0.5, -2.0), Vec3A::Z, 1, 2.0, 100.0, IVec3::ZERO, IVec3::new(0, 0, 3));
+        assert_eq!(cells, (0..4).map(|z| (IVec3::new(0, 0, z), (z > 0).then_some(2))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bricks() {
+        let cells = collect_cells(Vec3A::new(-1.0, 1.0, 1.0), -Vec3A::X, 4, 0.0, 100.0, IVec3::new(-3, 0, 0), IVec3::ZERO);
+        let x: Vec<_> = cells.iter().map(|(c, _)| c.x).collect();
+        assert_eq!(x, vec![-1, -2, -3]);
+    }
+
+    #[test]
+    fn stops_at_end() {
+        let cells = collect_cells(Vec3A::new(0.5, 0.5, 0.5), Vec3A::Z, 1, 0.0, 1.7, IVec3::ZERO, IVec3::splat(10));
+        assert_eq!(cells.len(), 3);
+    }
+
+    #[test]
+    fn diagonal_cells_touch() {
+        let dir = Vec3A::new(1.0, 0.7, 0.3).normalize();
+        let cells = collect_cells(Vec3A::splat(0.1), dir, 1, 0.0, 100.0, IVec3::ZERO, IVec3::splat(7));
+
+        assert_eq!(cells[0].0, IVec3::ZERO);
+        assert_eq!(cells.last().unwrap().0.x, 7);
+        for pair in cells.windows(2) {
+            let step = pair[1].0 - pair[0].0;
+            assert_eq!(step.abs().element_sum(), 1, "{:?} to {:?}", pair[0].0, pair[1].0);
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use octa_force::{anyhow::{anyhow, ensure}, glam::{Mat3, Mat4, UVec3, Vec3}, OctaResult};
+use dot_vox::*;
+
+use crate::{util::math::{get_import_voxel_count, to_1d}, voxel::{grid::offset::OffsetVoxelGrid, palette::{material::Material as VoxelMaterial, Palette}}};
+
+// Deeper scene graphs are treated as broken, this also stops nodes that reference themselves.
+const MAX_SCENE_DEPTH: u32 = 256;
+
+/**
+Keeps the scene graph of a vox file instead of flattening it like MagicaVoxelModel.
+Every transform node of the file becomes a node and the models of a shape become grids in the space of that node.
+Nodes that are hidden or sit on a hidden layer are left out together with their children.
+*/
+#[derive(Debug, Clone)]
+pub struct MagicaVoxelScene {
+    pub root: MagicaVoxelSceneNode,
+}
+
+#[derive(Debug, Clone)]
+pub struct MagicaVoxelSceneNode {
+    pub name: Option<String>,
+    pub layer: Option<String>,
+    // Relative to the parent node.
+    pub mat: Mat4,
+    // Centered on the node like in MagicaVoxel.
+    pub grids: Vec<OffsetVoxelGrid>,
+    pub children: Vec<MagicaVoxelSceneNode>,
+}
+
+struct MagicaVoxelSceneBuilder<'a, P: Palette> {
+    vox: &'a DotVoxData,
+    frame: u32,
+    palette: &'a mut P,
+    materials: [Option<u8>; 256],
+}
+
+impl MagicaVoxelScene {
+    pub fn new<P: Palette>(path: &str, palette: &mut P) -> OctaResult<Self> {
+        Self::new_frame(path, 0, palette)
+    }
+
+    // Uses the last keyframe of every transform at or before the frame.
+    pub fn new_frame<P: Palette>(path: &str, frame: u32, palette: &mut P) -> OctaResult<Self> {
+        let vox = load(path)
+            .map_err(|e| anyhow!(e))?;
+
+        Self::from_vox(&vox, frame, palette)
+    }
+
+    pub fn from_vox<P: Palette>(vox: &DotVoxData, frame: u32, palette: &mut P) -> OctaResult<Self> {
+        let mut builder = MagicaVoxelSceneBuilder {
+            vox,
+            frame,
+            palette,
+            materials: [None; 256],
+        };
+
+        // Files without a scene graph just list their models.
+        let root = if vox.scenes.is_empty() {
+            let grids = (0..vox.models.len())
+                .map(|i| builder.model_grid(i as u32))
+                .collect::<OctaResult<_>>()?;
+
+            MagicaVoxelSceneNode {
+                name: None,
+                layer: None,
+                mat: Mat4::IDENTITY,
+                grids,
+                children: vec![],
+            }
+        } else {
+            builder.node(0, 0)?.unwrap_or(MagicaVoxelSceneNode {
+                name: None,
+                layer: None,
+                mat: Mat4::IDENTITY,
+                grids: vec![],
+                children: vec![],
+            })
+        };
+
+        Ok(Self { root })
+    }
+
+    // Every grid with the transforms of its parents applied.
+    pub fn world_grids(&self) -> Vec<(Option<&str>, OffsetVoxelGrid)> {
+        let mut grids = vec![];
+        let mut stack = vec![(&self.root, Mat4::IDENTITY)];
+        while let Some((node, parent_mat)) = stack.pop() {
+            let mat = parent_mat * node.mat;
+            for grid in node.grids.iter() {
+                let mut grid = grid.clone();
+                grid.set_mat(mat);
+                grids.push((node.name.as_deref(), grid));
+            }
+
+            stack.extend(node.children.iter().map(|child| (child, mat)));
+        }
+
+        grids
+    }
+
+    pub fn find(&self, name: &str) -> Option<&MagicaVoxelSceneNode> {
+        self.root.find(name)
+    }
+}
+
+impl MagicaVoxelSceneNode {
+    pub fn find(&self, name: &str) -> Option<&MagicaVoxelSceneNode> {
+        if self.name.as_deref() == Some(name) {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(name))
+    }
+}
+
+impl<'a, P: Palette> MagicaVoxelSceneBuilder<'a, P> {
+    fn node(&mut self, index: u32, depth: u32) -> OctaResult<Option<MagicaVoxelSceneNode>> {
+        ensure!(depth < MAX_SCENE_DEPTH, "Vox scene is deeper than {MAX_SCENE_DEPTH} nodes");
+
+        let vox = self.vox;
+        let SceneNode::Transform { attributes, frames, child, layer_id } = self.scene(index)? else {
+            return Err(anyhow!("Vox scene node {index} should be a transform"));
+        };
+
+        let layer = vox.layers.get(*layer_id as usize);
+        if is_hidden(attributes) || layer.is_some_and(|l| is_hidden(&l.attributes)) {
+            return Ok(None);
+        }
+
+        let mut node = MagicaVoxelSceneNode {
+            name: attributes.get("_name").cloned(),
+            layer: layer.and_then(|l| l.attributes.get("_name").cloned()),
+            mat: self.frame_mat(frames),
+            grids: vec![],
+            children: vec![],
+        };
+
+        match self.scene(*child)? {
+            SceneNode::Group { children, .. } => {
+                for child in children {
+                    if let Some(child) = self.node(*child, depth + 1)? {
+                        node.children.push(child);
+                    }
+                }
+            }
+            SceneNode::Shape { models, .. } => {
+                for model in models {
+                    node.grids.push(self.model_grid(model.model_id)?);
+                }
+            }
+            SceneNode::Transform { .. } => {
+                if let Some(child) = self.node(*child, depth + 1)? {
+                    node.children.push(child);
+                }
+            }
+        }
+
+        Ok(Some(node))
+    }
+
+    fn scene(&self, index: u32) -> OctaResult<&'a SceneNode> {
+        self.vox.scenes.get(index as usize)
+            .ok_or_else(|| anyhow!("Vox scene node {index} does not exist"))
+    }
+
+    fn frame_mat(&self, frames: &[Frame]) -> Mat4 {
+        let frame_nr = |f: &Frame| f.attributes.get("_f").and_then(|f| f.parse::<u32>().ok()).unwrap_or(0);
+
+        let Some(frame) = frames.iter()
+            .filter(|f| frame_nr(f) <= self.frame)
+            .max_by_key(|f| frame_nr(f))
+            .or(frames.first()) else {
+            return Mat4::IDENTITY;
+        };
+
+        let translation = frame.attributes.get("_t")
+            .map(|t| t.split(" ")
+                .map(|x| x.parse().unwrap_or(0.0))
+                .collect::<Vec<f32>>())
+            .filter(|v| v.len() == 3)
+            .map(|v| Vec3::new(v[0], v[1], v[2]))
+            .unwrap_or(Vec3::ZERO);
+
+        let rotation = frame.attributes.get("_r")
+            .and_then(|r| r.parse().ok())
+            .map(|r| Mat3::from_cols_array_2d(&Rotation::from_byte(r).to_cols_array_2d()))
+            .unwrap_or(Mat3::IDENTITY);
+
+        Mat4::from_translation(translation) * Mat4::from_mat3(rotation)
+    }
+
+    fn model_grid(&mut self, model_id: u32) -> OctaResult<OffsetVoxelGrid> {
+        let vox = self.vox;
+        let model = vox.models.get(model_id as usize)
+            .ok_or_else(|| anyhow!("Vox model {model_id} does not exist"))?;
+        let size = UVec3::new(model.size.x, model.size.y, model.size.z);
+
+        let mut data = vec![0; get_import_voxel_count(size)?];
+        for voxel in model.voxels.iter() {
+            let pos = UVec3::new(voxel.x as u32, voxel.y as u32, voxel.z as u32);
+            ensure!(pos.cmplt(size).all(), "Voxel {pos} is outside of vox model {model_id} with size {size}");
+            data[to_1d(pos, size)] = self.material(voxel.i)?;
+        }
+
+        Ok(OffsetVoxelGrid::from_data(size, data, -(size / 2).as_ivec3()))
+    }
+
+    /**
+    Voxels store the color index starting at 0, the materials of the file start at 1.
+    Emissive materials scale their emission with the flux, metal materials keep their roughness as fuzziness.
+    */
+    fn material(&mut self, color_index: u8) -> OctaResult<u8> {
+        if let Some(mat) = self.materials[color_index as usize] {
+            return Ok(mat);
+        }
+
+        let c = self.vox.palette.get(color_index as usize)
+            .ok_or_else(|| anyhow!("Vox color {color_index} is not in the palette"))?;
+        let mut material = VoxelMaterial::default();
+        material.set_simple_color([c.r, c.g, c.b]);
+
+        let properties = self.vox.materials.iter()
+            .find(|m| m.id == color_index as u32 + 1)
+            .map(|m| &m.properties);
+
+        if let Some(properties) = properties {
+            let get = |key: &str| properties.get(key).and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.0);
+
+            match properties.get("_type").map(|t| t.as_str()) {
+                Some("_emit") => {
+                    material.emission = half::f16::from_f32(get("_emit") * (1.0 + get("_flux")));
+                }
+                Some("_metal") => {
+                    material.metal_fuzziness = (1.0 + get("_rough").clamp(0.0, 1.0) * 254.0).round() as u8;
+                }
+                _ => {}
+            }
+        }
+
+        let mat = self.palette.get_index_material(material)?;
+        self.materials[color_index as usize] = Some(mat);
+        Ok(mat)
+    }
+}
+
+fn is_hidden(attributes: &Dict) -> bool {
+    attributes.get("_hidden").is_some_and(|h| h == "1")
+}
+
+#[cfg(test)]
+mod tests {
+    use dot_vox::{DotVoxData, Frame, Layer, Material, SceneNode};
+    use octa_force::glam::{IVec3, Mat4, Vec3};
+
+    use crate::{volume::{vox_writer::VoxWriter, VolumeQureyPosValue}, voxel::palette::{palette::LocalPalette, Palette}};
+
+    use super::MagicaVoxelScene;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    /**
+    Written with the vox writer, so the scene nodes are: 0 root transform, 1 group, 2 and 4 transforms, 3 and 5 shapes.
+    Node 2 holds the red model translated to (1, 0, 0), node 4 the blue one at (300, 2, 1).
+    */
+    fn new_vox() -> DotVoxData {
+        let mut palette = LocalPalette::new();
+        let red = palette.get_index_simple_color(RED).unwrap();
+        let blue = palette.get_index_simple_color(BLUE).unwrap();
+
+        let mut writer = VoxWriter::new();
+        writer.add_voxel(IVec3::new(0, 0, 0), red);
+        writer.add_voxel(IVec3::new(1, 0, 0), red);
+        writer.add_voxel(IVec3::new(300, 2, 1), blue);
+
+        let mut bytes = vec![];
+        writer.write(&mut bytes, &palette).unwrap();
+        let mut vox = dot_vox::load_bytes(&bytes).unwrap();
+
+        set_attribute(&mut vox, 2, "_name", "a");
+        set_attribute(&mut vox, 4, "_name", "b");
+        vox
+    }
+
+    fn transform(vox: &mut DotVoxData, index: usize) -> (&mut dot_vox::Dict, &mut Vec<Frame>, &mut u32, &mut u32) {
+        let SceneNode::Transform { attributes, frames, child, layer_id } = &mut vox.scenes[index] else {
+            panic!("Node {index} should be a transform");
+        };
+        (attributes, frames, child, layer_id)
+    }
+
+    fn set_attribute(vox: &mut DotVoxData, index: usize, key: &str, value: &str) {
+        transform(vox, index).0.insert(key.to_string(), value.to_string());
+    }
+
+    #[test]
+    fn names_and_transforms() {
+        let mut palette = LocalPalette::new();
+        let scene = MagicaVoxelScene::from_vox(&new_vox(), 0, &mut palette).unwrap();
+        let red = palette.get_index_simple_color(RED).unwrap();
+        let blue = palette.get_index_simple_color(BLUE).unwrap();
+
+        assert_eq!(scene.root.children.len(), 2);
+        let a = scene.find("a").unwrap();
+        let b = scene.find("b").unwrap();
+        assert_eq!(a.mat, Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(b.mat, Mat4::from_translation(Vec3::new(300.0, 2.0, 1.0)));
+        assert_eq!(a.grids[0].offset, IVec3::new(-1, 0, 0));
+
+        let grids = scene.world_grids();
+        assert_eq!(grids.len(), 2);
+        let (_, a) = grids.iter().find(|(name, _)| *name == Some("a")).unwrap();
+        let (_, b) = grids.iter().find(|(name, _)| *name == Some("b")).unwrap();
+        assert_eq!(a.get_value(IVec3::new(0, 0, 0)), red);
+        assert_eq!(a.get_value(IVec3::new(1, 0, 0)), red);
+        assert_eq!(a.get_value(IVec3::new(2, 0, 0)), 0);
+        assert_eq!(b.get_value(IVec3::new(300, 2, 1)), blue);
+    }
+
+    #[test]
+    fn frames() {
+        let mut vox = new_vox();
+        let frames = transform(&mut vox, 2).1;
+        frames.push(Frame {
+            attributes: [("_f", "10"), ("_t", "5 6 7")].into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+
+        let mut palette = LocalPalette::new();
+        let scene = MagicaVoxelScene::from_vox(&vox, 9, &mut palette).unwrap();
+        assert_eq!(scene.find("a").unwrap().mat, Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0)));
+
+        let scene = MagicaVoxelScene::from_vox(&vox, 12, &mut palette).unwrap();
+        assert_eq!(scene.find("a").unwrap().mat, Mat4::from_translation(Vec3::new(5.0, 6.0, 7.0)));
+    }
+
+    #[test]
+    fn hidden_nodes_and_layers() {
+        let mut vox = new_vox();
+        set_attribute(&mut vox, 4, "_hidden", "1");
+
+        let mut palette = LocalPalette::new();
+        let scene = MagicaVoxelScene::from_vox(&vox, 0, &mut palette).unwrap();
+        assert!(scene.find("a").is_some());
+        assert!(scene.find("b").is_none());
+        assert_eq!(scene.world_grids().len(), 1);
+
+        let mut vox = new_vox();
+        vox.layers = [("_name", "shown"), ("_hidden", "1")].into_iter()
+            .map(|(k, v)| Layer { attributes: [(k.to_string(), v.to_string())].into_iter().collect() })
+            .collect();
+        *transform(&mut vox, 4).3 = 1;
+
+        let scene = MagicaVoxelScene::from_vox(&vox, 0, &mut palette).unwrap();
+        assert_eq!(scene.find("a").unwrap().layer.as_deref(), Some("shown"));
+        assert!(scene.find("b").is_none());
+    }
+
+    #[test]
+    fn materials() {
+        // The writer numbers red as vox color index 1 and blue as 2.
+        let mut vox = new_vox();
+        let properties = |entries: &[(&str, &str)]| entries.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        vox.materials = vec![
+            Material { id: 1, properties: properties(&[("_type", "_emit"), ("_emit", "0.5"), ("_flux", "1")]) },
+            Material { id: 2, properties: properties(&[("_type", "_metal"), ("_rough", "1")]) },
+        ];
+
+        let mut palette = LocalPalette::new();
+        let scene = MagicaVoxelScene::from_vox(&vox, 0, &mut palette).unwrap();
+        let grids = scene.world_grids();
+        let (_, a) = grids.iter().find(|(name, _)| *name == Some("a")).unwrap();
+        let (_, b) = grids.iter().find(|(name, _)| *name == Some("b")).unwrap();
+
+        let red = palette.materials[a.get_value(IVec3::new(0, 0, 0)) as usize];
+        assert_eq!(red.color, RED);
+        assert_eq!(red.emission, half::f16::from_f32(1.0));
+        assert_eq!(red.metal_fuzziness, 0);
+
+        let blue = palette.materials[b.get_value(IVec3::new(300, 2, 1)) as usize];
+        assert_eq!(blue.color, BLUE);
+        assert_eq!(blue.emission, half::f16::ZERO);
+        assert_eq!(blue.metal_fuzziness, 255);
+    }
+
+    #[test]
+    fn reject_bad_references() {
+        let mut palette = LocalPalette::new();
+
+        let mut vox = new_vox();
+        *transform(&mut vox, 2).2 = 99;
+        assert!(MagicaVoxelScene::from_vox(&vox, 0, &mut palette).is_err());
+
+        let mut vox = new_vox();
+        let SceneNode::Shape { models, .. } = &mut vox.scenes[3] else {
+            panic!("Node 3 should be a shape");
+        };
+        models[0].model_id = 9;
+        assert!(MagicaVoxelScene::from_vox(&vox, 0, &mut palette).is_err());
+
+        let mut vox = new_vox();
+        vox.scenes.swap(0, 1);
+        assert!(MagicaVoxelScene::from_vox(&vox, 0, &mut palette).is_err());
+
+        let mut vox = new_vox();
+        vox.models[0].voxels[0].x = 10;
+        assert!(MagicaVoxelScene::from_vox(&vox, 0, &mut palette).is_err());
+    }
+
+    #[test]
+    fn reject_cycles() {
+        let mut vox = new_vox();
+        *transform(&mut vox, 2).2 = 0;
+
+        let mut palette = LocalPalette::new();
+        assert!(MagicaVoxelScene::from_vox(&vox, 0, &mut palette).is_err());
+    }
+}
@@ -1,4 +1,11 @@
 pub mod magica_voxel;
+pub mod magica_voxel_scene;
+pub mod qubicle;
+pub mod binvox;
+pub mod schematic;
+pub mod triangle_mesh;
+pub mod vox_writer;
+pub mod heightmap;
 pub mod remove_trait;
 
 use std::{fmt::Debug, marker::PhantomData};
@@ -51,6 +58,29 @@ pub trait VolumeQureyAABB<V: Ve<T, D>, T: Nu, const D: usize>: VolumeQureyPosVal
     fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult;
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct VolumeRayHit<V> {
+    // Ray parameter of the hit, measured in lengths of the ray direction.
+    pub t: f32,
+    pub pos: V,
+    // Normalized and pointing out of the volume.
+    pub normal: V,
+    pub material: u8,
+}
+
+// Rays that start inside of the volume hit at their origin.
+pub trait VolumeQureyRay<V: Ve<f32, D>, const D: usize> {
+    fn get_ray_hit(&self, origin: V, dir: V, max_t: f32) -> Option<VolumeRayHit<V>>;
+}
+
+/**
+Signed distance to the surface, negative inside. The result is clamped to [-max, max],
+so parts of the volume that are further away than max do not have to be looked at.
+*/
+pub trait VolumeQureyDistance<V: Ve<f32, D>, const D: usize> {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32;
+}
+
 impl VolumeQureyAABBResult {
     pub fn get_value(self) -> u8 {
         match self {
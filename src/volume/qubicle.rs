@@ -0,0 +1,273 @@
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::{IVec3, UVec3}, OctaResult};
+
+use crate::{util::{byte_reader::ByteReader, math::{get_import_voxel_count, to_1d}}, voxel::{grid::offset::OffsetVoxelGrid, palette::Palette}};
+
+const QB_CODE_FLAG: u32 = 2;
+const QB_NEXT_SLICE_FLAG: u32 = 6;
+
+/**
+Qubicle .qb files with all their matrices.
+Qubicle is y up, so y and z are swapped to get our z up.
+Right handed files also flip their depth axis to stay right handed after the swap.
+*/
+#[derive(Debug, Clone)]
+pub struct QubicleModel {
+    pub matrices: Vec<QubicleMatrix>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QubicleMatrix {
+    pub name: String,
+    pub size: UVec3,
+    pub offset: IVec3,
+    // In our axes, None is empty.
+    pub colors: Vec<Option<[u8; 3]>>,
+}
+
+impl QubicleModel {
+    pub fn new(path: &str) -> OctaResult<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> OctaResult<Self> {
+        let mut reader = ByteReader::new(data);
+
+        let _version = reader.u32_le()?;
+        let color_format = reader.u32_le()?;
+        let z_axis_orientation = reader.u32_le()?;
+        let compressed = reader.u32_le()?;
+        let _visibility_mask_encoded = reader.u32_le()?;
+        let num_matrices = reader.u32_le()?;
+
+        ensure!(color_format <= 1, "Unknown qb color format {color_format}");
+        let bgra = color_format == 1;
+        let right_handed = z_axis_orientation == 1;
+
+        let mut matrices = Vec::new();
+        for _ in 0..num_matrices {
+            let name_len = reader.u8()? as usize;
+            let name = String::from_utf8_lossy(reader.bytes(name_len)?).into_owned();
+
+            let qb_size = UVec3::new(reader.u32_le()?, reader.u32_le()?, reader.u32_le()?);
+            let qb_pos = IVec3::new(reader.i32_le()?, reader.i32_le()?, reader.i32_le()?);
+
+            let size = UVec3::new(qb_size.x, qb_size.z, qb_size.y);
+            let mut colors = vec![None; get_import_voxel_count(size)?];
+
+            let offset = if right_handed {
+                let depth = qb_pos.z.checked_add_unsigned(qb_size.z)
+                    .and_then(i32::checked_neg)
+                    .ok_or_else(|| anyhow!("The qb matrix {name} is out of range"))?;
+                IVec3::new(qb_pos.x, depth, qb_pos.y)
+            } else {
+                IVec3::new(qb_pos.x, qb_pos.z, qb_pos.y)
+            };
+            let mut set = |x: u32, y: u32, z: u32, color: u32| {
+                if x >= qb_size.x || y >= qb_size.y || z >= qb_size.z {
+                    return;
+                }
+
+                // Only a zero alpha means invisible, with the visibility mask the other values mark visible sides.
+                let [a, b, c, alpha] = color.to_le_bytes();
+                if alpha == 0 {
+                    return;
+                }
+
+                let depth = if right_handed { qb_size.z - 1 - z } else { z };
+                let rgb = if bgra { [c, b, a] } else { [a, b, c] };
+                colors[to_1d(UVec3::new(x, depth, y), size)] = Some(rgb);
+            };
+
+            if compressed == 0 {
+                for z in 0..qb_size.z {
+                    for y in 0..qb_size.y {
+                        for x in 0..qb_size.x {
+                            set(x, y, z, reader.u32_le()?);
+                        }
+                    }
+                }
+            } else {
+                // Run length encoded slices along z.
+                for z in 0..qb_size.z {
+                    let mut index = 0;
+                    loop {
+                        let data = reader.u32_le()?;
+                        if data == QB_NEXT_SLICE_FLAG {
+                            break;
+                        }
+
+                        let (count, color) = if data == QB_CODE_FLAG {
+                            (reader.u32_le()?, reader.u32_le()?)
+                        } else {
+                            (1, data)
+                        };
+
+                        // Voxels past the end of the slice are skipped.
+                        let end = index.checked_add(count)
+                            .ok_or_else(|| anyhow!("The qb slice {z} of {name} has too many voxels"))?;
+                        for i in index..end.min(qb_size.x * qb_size.y) {
+                            set(i % qb_size.x, i / qb_size.x, z, color);
+                        }
+                        index = end;
+                    }
+                }
+            }
+
+            matrices.push(QubicleMatrix {
+                name,
+                size,
+                offset,
+                colors,
+            });
+        }
+
+        if matrices.is_empty() {
+            bail!("The qb file has no matrices");
+        }
+
+        Ok(Self { matrices })
+    }
+
+    pub fn into_grids<P: Palette>(self, palette: &mut P) -> OctaResult<Vec<(String, OffsetVoxelGrid)>> {
+        self.matrices.into_iter()
+            .map(|matrix| {
+                let data = matrix.colors.iter()
+                    .map(|c| c.map_or(Ok(0), |c| palette.get_index_simple_color(c)))
+                    .collect::<OctaResult<_>>()?;
+
+                Ok((matrix.name, OffsetVoxelGrid::from_data(matrix.size, data, matrix.offset)))
+            })
+            .collect()
+    }
+
+    // All matrices in one grid, later matrices overwrite earlier ones.
+    pub fn into_grid<P: Palette>(self, palette: &mut P) -> OctaResult<OffsetVoxelGrid> {
+        let min = self.matrices.iter()
+            .map(|m| m.offset)
+            .reduce(IVec3::min)
+            .unwrap();
+
+        let mut size = UVec3::ZERO;
+        for m in self.matrices.iter() {
+            for i in 0..3 {
+                let end = m.offset[i] as i64 + m.size[i] as i64 - min[i] as i64;
+                size[i] = size[i].max(u32::try_from(end)
+                    .map_err(|_| anyhow!("The qb matrices span more than {} voxels", u32::MAX))?);
+            }
+        }
+
+        let mut data = vec![0; get_import_voxel_count(size)?];
+        for (_, grid) in self.into_grids(palette)? {
+            let local = (grid.offset - min).as_uvec3();
+            for x in 0..grid.grid.size.x {
+                for y in 0..grid.grid.size.y {
+                    for z in 0..grid.grid.size.z {
+                        let pos = UVec3::new(x, y, z);
+                        let mat = grid.grid.get(pos);
+                        if mat != 0 {
+                            data[to_1d(local + pos, size)] = mat;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(OffsetVoxelGrid::from_data(size, data, min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, UVec3};
+
+    use crate::{util::math::to_1d, voxel::palette::palette::LocalPalette};
+
+    use super::{QubicleMatrix, QubicleModel, QB_CODE_FLAG, QB_NEXT_SLICE_FLAG};
+
+    fn new_file(compressed: bool, size: [u32; 3], voxels: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for v in [257, 0, 0, compressed as u32, 0, 1] {
+            bytes.extend_from_slice(&u32::to_le_bytes(v));
+        }
+
+        bytes.push(4);
+        bytes.extend_from_slice(b"test");
+        for v in size.into_iter().chain([0, 0, 0]) {
+            bytes.extend_from_slice(&u32::to_le_bytes(v));
+        }
+        for v in voxels {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    fn color(r: u8, g: u8, b: u8) -> u32 {
+        u32::from_le_bytes([r, g, b, 255])
+    }
+
+    #[test]
+    fn uncompressed() {
+        let bytes = new_file(false, [2, 1, 1], &[color(10, 20, 30), 0]);
+        let model = QubicleModel::from_bytes(&bytes).unwrap();
+
+        let matrix = &model.matrices[0];
+        assert_eq!(matrix.name, "test");
+        assert_eq!(matrix.size, UVec3::new(2, 1, 1));
+        assert_eq!(matrix.colors[to_1d(UVec3::new(0, 0, 0), matrix.size)], Some([10, 20, 30]));
+        assert_eq!(matrix.colors[to_1d(UVec3::new(1, 0, 0), matrix.size)], None);
+    }
+
+    #[test]
+    fn compressed() {
+        let bytes = new_file(true, [3, 1, 1], &[QB_CODE_FLAG, 2, color(1, 2, 3), color(4, 5, 6), QB_NEXT_SLICE_FLAG]);
+        let model = QubicleModel::from_bytes(&bytes).unwrap();
+
+        let matrix = &model.matrices[0];
+        let colors: Vec<_> = (0..3).map(|x| matrix.colors[to_1d(UVec3::new(x, 0, 0), matrix.size)]).collect();
+        assert_eq!(colors, vec![Some([1, 2, 3]), Some([1, 2, 3]), Some([4, 5, 6])]);
+    }
+
+    #[test]
+    fn reject_overflowing_run() {
+        let bytes = new_file(true, [3, 1, 1], &[color(1, 2, 3), QB_CODE_FLAG, u32::MAX, color(1, 2, 3), QB_NEXT_SLICE_FLAG]);
+        assert!(QubicleModel::from_bytes(&bytes).is_err());
+    }
+
+    fn new_matrix(offset: IVec3) -> QubicleMatrix {
+        QubicleMatrix {
+            name: "test".to_string(),
+            size: UVec3::ONE,
+            offset,
+            colors: vec![Some([255, 255, 255])],
+        }
+    }
+
+    #[test]
+    fn merge_matrices() {
+        let model = QubicleModel { matrices: vec![new_matrix(IVec3::new(-1, 0, 0)), new_matrix(IVec3::new(2, 1, 0))] };
+        let grid = model.into_grid(&mut LocalPalette::new()).unwrap();
+
+        assert_eq!(grid.offset, IVec3::new(-1, 0, 0));
+        assert_eq!(grid.grid.size, UVec3::new(4, 2, 1));
+        assert_ne!(grid.grid.get(UVec3::new(0, 0, 0)), 0);
+        assert_ne!(grid.grid.get(UVec3::new(3, 1, 0)), 0);
+        assert_eq!(grid.grid.get(UVec3::new(1, 0, 0)), 0);
+    }
+
+    #[test]
+    fn reject_far_apart_matrices() {
+        let model = QubicleModel { matrices: vec![new_matrix(IVec3::splat(i32::MIN)), new_matrix(IVec3::splat(i32::MAX))] };
+        assert!(model.into_grid(&mut LocalPalette::new()).is_err());
+
+        let model = QubicleModel { matrices: vec![new_matrix(IVec3::ZERO), new_matrix(IVec3::splat(100_000))] };
+        assert!(model.into_grid(&mut LocalPalette::new()).is_err());
+    }
+
+    #[test]
+    fn reject_empty_and_truncated() {
+        assert!(QubicleModel::from_bytes(&new_file(false, [0, 1, 1], &[])).is_err());
+        assert!(QubicleModel::from_bytes(&new_file(false, [2, 1, 1], &[color(10, 20, 30)])).is_err());
+    }
+}
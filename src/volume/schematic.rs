@@ -0,0 +1,409 @@
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::GzDecoder;
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::{IVec3, UVec3}, OctaResult};
+
+use crate::{util::{byte_reader::ByteReader, math::{get_import_voxel_count, to_1d}}, voxel::{grid::offset::OffsetVoxelGrid, palette::Palette}};
+
+// Same limit as minecraft, deeper files are rejected instead of overflowing the stack.
+const MAX_NBT_DEPTH: usize = 512;
+
+/**
+Sponge schematics (.schem) in version 1 to 3.
+Minecraft is y up, the depth axis is flipped when swapping y and z so the model stays right handed.
+*/
+#[derive(Debug, Clone)]
+pub struct SpongeSchematic {
+    // In our axes.
+    pub size: UVec3,
+    pub offset: IVec3,
+    // Block states like minecraft:oak_stairs[facing=north].
+    pub block_states: Vec<String>,
+    // Index into block_states for every voxel.
+    pub blocks: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(HashMap<String, Nbt>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl SpongeSchematic {
+    pub fn new(path: &str) -> OctaResult<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> OctaResult<Self> {
+        // Schematics are normally gzip compressed.
+        let mut unpacked = vec![];
+        let data = if data.starts_with(&[0x1f, 0x8b]) {
+            GzDecoder::new(data).read_to_end(&mut unpacked)?;
+            &unpacked[..]
+        } else {
+            data
+        };
+
+        let mut reader = ByteReader::new(data);
+        ensure!(reader.u8()? == 10, "Schematic has to start with a compound");
+        let _name = read_nbt_string(&mut reader)?;
+        let root = read_nbt_compound(&mut reader, 0)?;
+
+        // Version 3 wraps everything in a Schematic compound.
+        let schematic = match root.get("Schematic") {
+            Some(Nbt::Compound(schematic)) => schematic,
+            _ => &root,
+        };
+
+        let short = |key: &str| match schematic.get(key) {
+            Some(Nbt::Short(v)) => Ok(*v as u16 as u32),
+            _ => Err(anyhow!("Schematic has no {key}")),
+        };
+        let width = short("Width")?;
+        let height = short("Height")?;
+        let length = short("Length")?;
+
+        let (palette, block_data) = match schematic.get("Blocks") {
+            Some(Nbt::Compound(blocks)) => (blocks.get("Palette"), blocks.get("Data")),
+            _ => (schematic.get("Palette"), schematic.get("BlockData")),
+        };
+
+        let Some(Nbt::Compound(palette)) = palette else {
+            bail!("Schematic has no block palette");
+        };
+        let Some(Nbt::ByteArray(block_data)) = block_data else {
+            bail!("Schematic has no block data");
+        };
+
+        let mut block_states = vec![String::new(); palette.len()];
+        for (state, index) in palette.iter() {
+            let Nbt::Int(index) = index else {
+                bail!("Schematic palette entry {state} is not an int");
+            };
+
+            let index = *index as usize;
+            ensure!(index < block_states.len(), "Schematic palette index {index} out of range");
+            block_states[index] = state.to_owned();
+        }
+
+        let size = UVec3::new(width, length, height);
+        let mut blocks = vec![0; get_import_voxel_count(size)?];
+
+        // Indecies are varints in y, z, x order with x running fastest.
+        let mut data = ByteReader::new(block_data);
+        for y in 0..height {
+            for z in 0..length {
+                for x in 0..width {
+                    let block = read_varint(&mut data)?;
+                    ensure!((block as usize) < block_states.len(), "Schematic block {block} is not in the palette");
+                    blocks[to_1d(UVec3::new(x, length - 1 - z, y), size)] = block;
+                }
+            }
+        }
+
+        let offset = match schematic.get("Offset") {
+            Some(Nbt::IntArray(o)) if o.len() == 3 => {
+                let depth = o[2].checked_add_unsigned(length)
+                    .and_then(i32::checked_neg)
+                    .ok_or_else(|| anyhow!("Schematic offset {o:?} is out of range"))?;
+                IVec3::new(o[0], depth, o[1])
+            }
+            _ => IVec3::new(0, -(length as i32), 0),
+        };
+
+        Ok(Self {
+            size,
+            offset,
+            block_states,
+            blocks,
+        })
+    }
+
+    pub fn into_grid<P: Palette>(self, palette: &mut P) -> OctaResult<OffsetVoxelGrid> {
+        self.into_grid_with(palette, get_block_color)
+    }
+
+    // The mapping gets the full block state and returns None for blocks that should stay empty.
+    pub fn into_grid_with<P: Palette>(self, palette: &mut P, mapping: impl Fn(&str) -> Option<[u8; 3]>) -> OctaResult<OffsetVoxelGrid> {
+        let materials = self.block_states.iter()
+            .map(|state| mapping(state).map_or(Ok(0), |c| palette.get_index_simple_color(c)))
+            .collect::<OctaResult<Vec<_>>>()?;
+
+        let data = self.blocks.iter()
+            .map(|b| materials[*b as usize])
+            .collect();
+
+        Ok(OffsetVoxelGrid::from_data(self.size, data, self.offset))
+    }
+}
+
+/**
+Rough colors for common blocks. The properties in brackets and the namespace are ignored.
+Unknown blocks get a gray that depends on their name, so different blocks stay distinguishable.
+*/
+pub fn get_block_color(state: &str) -> Option<[u8; 3]> {
+    let name = state.split('[').next().unwrap_or(state);
+    let name = name.rsplit(':').next().unwrap_or(name);
+
+    let color = match name {
+        "air" | "cave_air" | "void_air" | "structure_void" | "barrier" | "light" => return None,
+        "stone" | "stone_bricks" | "cobblestone" | "andesite" => [125, 125, 125],
+        "granite" => [149, 103, 85],
+        "diorite" => [188, 188, 188],
+        "deepslate" | "cobbled_deepslate" => [80, 80, 82],
+        "dirt" | "coarse_dirt" | "rooted_dirt" => [134, 96, 67],
+        "grass_block" => [95, 159, 53],
+        "sand" => [219, 207, 163],
+        "red_sand" => [190, 102, 33],
+        "sandstone" => [216, 203, 155],
+        "gravel" => [131, 127, 126],
+        "clay" => [160, 166, 179],
+        "snow" | "snow_block" | "powder_snow" => [249, 254, 254],
+        "ice" | "packed_ice" | "blue_ice" => [145, 183, 253],
+        "water" => [63, 118, 228],
+        "lava" => [207, 92, 15],
+        "oak_log" | "oak_wood" => [109, 85, 50],
+        "oak_planks" => [162, 130, 78],
+        "spruce_log" | "spruce_wood" => [58, 37, 16],
+        "spruce_planks" => [114, 84, 48],
+        "birch_log" | "birch_wood" => [216, 215, 210],
+        "birch_planks" => [192, 175, 121],
+        "dark_oak_planks" => [66, 43, 20],
+        "oak_leaves" | "birch_leaves" | "jungle_leaves" => [60, 120, 30],
+        "spruce_leaves" => [52, 88, 52],
+        "glass" => [175, 213, 219],
+        "bricks" => [150, 97, 83],
+        "terracotta" => [152, 94, 67],
+        "white_wool" | "white_concrete" => [233, 236, 236],
+        "black_wool" | "black_concrete" => [20, 21, 25],
+        "red_wool" | "red_concrete" => [160, 39, 34],
+        "blue_wool" | "blue_concrete" => [53, 57, 157],
+        "green_wool" | "green_concrete" => [84, 109, 27],
+        "yellow_wool" | "yellow_concrete" => [248, 197, 39],
+        "obsidian" => [15, 10, 24],
+        "netherrack" => [97, 38, 38],
+        "glowstone" => [171, 131, 84],
+        "iron_block" => [220, 220, 220],
+        "gold_block" => [246, 208, 61],
+        "quartz_block" => [235, 229, 222],
+        _ => {
+            let hash = name.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+            let v = 96 + (hash % 96) as u8;
+            [v, v, v]
+        }
+    };
+
+    Some(color)
+}
+
+fn read_varint(reader: &mut ByteReader) -> OctaResult<u32> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = reader.u8()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("Schematic varint is too long")
+}
+
+fn read_nbt_string(reader: &mut ByteReader) -> OctaResult<String> {
+    let len = reader.u16_be()? as usize;
+    Ok(String::from_utf8_lossy(reader.bytes(len)?).into_owned())
+}
+
+fn read_nbt_compound(reader: &mut ByteReader, depth: usize) -> OctaResult<HashMap<String, Nbt>> {
+    ensure!(depth < MAX_NBT_DEPTH, "Nbt is nested deeper than {MAX_NBT_DEPTH}");
+
+    let mut compound = HashMap::new();
+    loop {
+        let tag = reader.u8()?;
+        if tag == 0 {
+            return Ok(compound);
+        }
+
+        let name = read_nbt_string(reader)?;
+        compound.insert(name, read_nbt(reader, tag, depth + 1)?);
+    }
+}
+
+fn read_nbt_len(reader: &mut ByteReader) -> OctaResult<usize> {
+    let len = reader.i32_be()?;
+    ensure!(len >= 0, "Negative nbt length");
+    Ok(len as usize)
+}
+
+fn read_nbt(reader: &mut ByteReader, tag: u8, depth: usize) -> OctaResult<Nbt> {
+    ensure!(depth < MAX_NBT_DEPTH, "Nbt is nested deeper than {MAX_NBT_DEPTH}");
+
+    Ok(match tag {
+        1 => Nbt::Byte(reader.u8()? as i8),
+        2 => Nbt::Short(reader.i16_be()?),
+        3 => Nbt::Int(reader.i32_be()?),
+        4 => Nbt::Long(reader.i64_be()?),
+        5 => Nbt::Float(f32::from_bits(reader.i32_be()? as u32)),
+        6 => Nbt::Double(f64::from_bits(reader.i64_be()? as u64)),
+        7 => {
+            let len = read_nbt_len(reader)?;
+            Nbt::ByteArray(reader.bytes(len)?.to_vec())
+        }
+        8 => Nbt::String(read_nbt_string(reader)?),
+        9 => {
+            let tag = reader.u8()?;
+            let len = read_nbt_len(reader)?;
+            Nbt::List((0..len).map(|_| read_nbt(reader, tag, depth + 1)).collect::<OctaResult<_>>()?)
+        }
+        10 => Nbt::Compound(read_nbt_compound(reader, depth)?),
+        11 => {
+            let len = read_nbt_len(reader)?;
+            Nbt::IntArray((0..len).map(|_| reader.i32_be()).collect::<OctaResult<_>>()?)
+        }
+        12 => {
+            let len = read_nbt_len(reader)?;
+            Nbt::LongArray((0..len).map(|_| reader.i64_be()).collect::<OctaResult<_>>()?)
+        }
+        _ => bail!("Unknown nbt tag {tag}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use octa_force::glam::{IVec3, UVec3};
+
+    use crate::util::{byte_reader::ByteReader, math::to_1d};
+
+    use super::{read_varint, SpongeSchematic, MAX_NBT_DEPTH};
+
+    fn tag(bytes: &mut Vec<u8>, tag: u8, name: &str) {
+        bytes.push(tag);
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    fn short(bytes: &mut Vec<u8>, name: &str, v: i16) {
+        tag(bytes, 2, name);
+        bytes.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn palette(bytes: &mut Vec<u8>, entries: &[(&str, i32)]) {
+        tag(bytes, 10, "Palette");
+        for (state, index) in entries {
+            tag(bytes, 3, state);
+            bytes.extend_from_slice(&index.to_be_bytes());
+        }
+        bytes.push(0);
+    }
+
+    fn byte_array(bytes: &mut Vec<u8>, name: &str, data: &[u8]) {
+        tag(bytes, 7, name);
+        bytes.extend_from_slice(&(data.len() as i32).to_be_bytes());
+        bytes.extend_from_slice(data);
+    }
+
+    fn size(bytes: &mut Vec<u8>) {
+        short(bytes, "Width", 2);
+        short(bytes, "Height", 1);
+        short(bytes, "Length", 1);
+    }
+
+    // Two blocks along x, stone and then air.
+    fn new_v2(entries: &[(&str, i32)], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        tag(&mut bytes, 10, "Schematic");
+        size(&mut bytes);
+        palette(&mut bytes, entries);
+        byte_array(&mut bytes, "BlockData", data);
+
+        tag(&mut bytes, 11, "Offset");
+        bytes.extend_from_slice(&3i32.to_be_bytes());
+        for v in [1i32, 2, 3] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.push(0);
+        bytes
+    }
+
+    fn new_v3() -> Vec<u8> {
+        let mut bytes = vec![];
+        tag(&mut bytes, 10, "");
+        tag(&mut bytes, 10, "Schematic");
+        size(&mut bytes);
+        tag(&mut bytes, 10, "Blocks");
+        palette(&mut bytes, &[("minecraft:air", 0), ("minecraft:stone", 1)]);
+        byte_array(&mut bytes, "Data", &[1, 0]);
+        bytes.extend_from_slice(&[0, 0, 0]);
+        bytes
+    }
+
+    fn assert_blocks(schematic: &SpongeSchematic) {
+        assert_eq!(schematic.size, UVec3::new(2, 1, 1));
+
+        let state = |x| &schematic.block_states[schematic.blocks[to_1d(UVec3::new(x, 0, 0), schematic.size)] as usize];
+        assert_eq!(state(0), "minecraft:stone");
+        assert_eq!(state(1), "minecraft:air");
+    }
+
+    #[test]
+    fn version_2() {
+        let schematic = SpongeSchematic::from_bytes(&new_v2(&[("minecraft:air", 0), ("minecraft:stone", 1)], &[1, 0])).unwrap();
+        assert_blocks(&schematic);
+        assert_eq!(schematic.offset, IVec3::new(1, -4, 2));
+    }
+
+    #[test]
+    fn version_3() {
+        let schematic = SpongeSchematic::from_bytes(&new_v3()).unwrap();
+        assert_blocks(&schematic);
+        assert_eq!(schematic.offset, IVec3::new(0, -1, 0));
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&new_v3()).unwrap();
+
+        let schematic = SpongeSchematic::from_bytes(&encoder.finish().unwrap()).unwrap();
+        assert_blocks(&schematic);
+    }
+
+    #[test]
+    fn reject_bad_palette() {
+        assert!(SpongeSchematic::from_bytes(&new_v2(&[("minecraft:air", 0), ("minecraft:stone", 5)], &[1, 0])).is_err());
+        assert!(SpongeSchematic::from_bytes(&new_v2(&[("minecraft:air", 0), ("minecraft:stone", 1)], &[2, 0])).is_err());
+    }
+
+    #[test]
+    fn reject_deep_nesting() {
+        let mut bytes = vec![];
+        for _ in 0..MAX_NBT_DEPTH * 2 {
+            tag(&mut bytes, 10, "a");
+        }
+        bytes.extend(std::iter::repeat(0).take(MAX_NBT_DEPTH * 2));
+
+        assert!(SpongeSchematic::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn varint() {
+        assert_eq!(read_varint(&mut ByteReader::new(&[0x05])).unwrap(), 5);
+        assert_eq!(read_varint(&mut ByteReader::new(&[0xac, 0x02])).unwrap(), 300);
+        assert!(read_varint(&mut ByteReader::new(&[0xff; 6])).is_err());
+        assert!(read_varint(&mut ByteReader::new(&[0x80])).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+use std::{collections::HashMap, path::Path};
+
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::{vec2, IVec3, UVec3, Vec2, Vec3}, OctaResult};
+
+use crate::{util::{byte_reader::ByteReader, math::{get_import_voxel_count, to_1d}, shader_constants::VOXELS_PER_METER}, voxel::{grid::offset::OffsetVoxelGrid, palette::{palette::MATERIAL_ID_NONE, Palette}}};
+
+pub const DEFAULT_MESH_COLOR: [u8; 3] = [255, 255, 255];
+
+// Grows the voxels a bit in the overlap test so triangles exactly on a voxel border hit both sides.
+const OVERLAP_EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoxelizeFill {
+    // Only the voxels the triangles touch.
+    Surface,
+    // Inside where a ray along z crossed an odd number of triangles.
+    Parity,
+    // Inside where the triangles wind around the voxel, works for overlapping closed shells.
+    Winding,
+}
+
+/**
+Triangle soup in meters with one color per triangle.
+Positions are in our z up axes, OBJ files are y up and get converted while loading.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct TriangleMesh {
+    pub triangles: Vec<[Vec3; 3]>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl TriangleMesh {
+    pub fn load(path: &str) -> OctaResult<Self> {
+        let extension = Path::new(path).extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("obj") => Self::from_obj(path),
+            Some("stl") => Self::from_stl(path),
+            _ => bail!("Can not voxelize {path}, only obj and stl are supported"),
+        }
+    }
+
+    /**
+    Uses the Kd color of the material from the mtl file.
+    Faces without a material use the average of their vertex colors if the file has them.
+    Polygons are split into fans.
+    */
+    pub fn from_obj(path: &str) -> OctaResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut positions = vec![];
+        let mut vertex_colors = vec![];
+        let mut materials = HashMap::new();
+        let mut material = None;
+        let mut mesh = Self::default();
+
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => {
+                    let v = parse_floats(parts)?;
+                    ensure!(v.len() >= 3, "Obj vertex needs 3 values: {line}");
+                    positions.push(Vec3::new(v[0], -v[2], v[1]));
+                    vertex_colors.push((v.len() >= 6).then(|| Vec3::new(v[3], v[4], v[5])));
+                }
+                Some("mtllib") => {
+                    let file = parts.collect::<Vec<_>>().join(" ");
+                    materials.extend(load_mtl(&dir.join(file))?);
+                }
+                Some("usemtl") => {
+                    material = parts.next().and_then(|name| materials.get(name).copied());
+                }
+                Some("f") => {
+                    let indecies = parts
+                        .map(|p| {
+                            let i: i64 = p.split('/').next().unwrap_or("").parse()?;
+                            let i = if i < 0 { positions.len() as i64 + i } else { i - 1 };
+                            ensure!(i >= 0 && (i as usize) < positions.len(), "Obj face index out of range: {line}");
+                            Ok(i as usize)
+                        })
+                        .collect::<OctaResult<Vec<_>>>()?;
+
+                    for i in 1..indecies.len().saturating_sub(1) {
+                        let face = [indecies[0], indecies[i], indecies[i + 1]];
+
+                        let color = material.unwrap_or_else(|| {
+                            let colors = face.map(|i| vertex_colors[i]);
+                            if colors.iter().all(|c| c.is_some()) {
+                                let c = colors.iter().flatten().sum::<Vec3>() / 3.0;
+                                to_rgb(c)
+                            } else {
+                                DEFAULT_MESH_COLOR
+                            }
+                        });
+
+                        mesh.triangles.push(face.map(|i| positions[i]));
+                        mesh.colors.push(color);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    /**
+    Binary files can store a color per face in the attribute bytes like VisCAM and SolidView do.
+    The red, green and blue 5 bit values are only used when the highest bit is set.
+    */
+    pub fn from_stl(path: &str) -> OctaResult<Self> {
+        let data = std::fs::read(path)?;
+
+        // Some binary files start with "solid" too, so the size decides.
+        let is_binary = data.len() >= 84
+            && 84 + 50 * u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize == data.len();
+
+        if !is_binary {
+            let text = std::str::from_utf8(&data)?;
+            let vertices = text.lines()
+                .map(|l| l.trim())
+                .filter_map(|l| l.strip_prefix("vertex"))
+                .map(|l| {
+                    let v = parse_floats(l.split_whitespace())?;
+                    ensure!(v.len() == 3, "Stl vertex needs 3 values");
+                    Ok(Vec3::new(v[0], v[1], v[2]))
+                })
+                .collect::<OctaResult<Vec<_>>>()?;
+
+            return Ok(Self {
+                colors: vec![DEFAULT_MESH_COLOR; vertices.len() / 3],
+                triangles: vertices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            });
+        }
+
+        let mut reader = ByteReader::new(&data);
+        reader.bytes(80)?;
+        let count = reader.u32_le()?;
+
+        let mut mesh = Self::default();
+        for _ in 0..count {
+            let mut v = [0.0; 12];
+            for f in v.iter_mut() {
+                *f = reader.f32_le()?;
+            }
+            let attribute = reader.u16_le()?;
+
+            let color = if attribute & 0x8000 != 0 {
+                let c = |shift: u16| (((attribute >> shift) & 31) * 255 / 31) as u8;
+                [c(10), c(5), c(0)]
+            } else {
+                DEFAULT_MESH_COLOR
+            };
+
+            mesh.triangles.push([
+                Vec3::new(v[3], v[4], v[5]),
+                Vec3::new(v[6], v[7], v[8]),
+                Vec3::new(v[9], v[10], v[11]),
+            ]);
+            mesh.colors.push(color);
+        }
+
+        Ok(mesh)
+    }
+
+    pub fn voxelize_default<P: Palette>(&self, palette: &mut P, fill: VoxelizeFill) -> OctaResult<OffsetVoxelGrid> {
+        self.voxelize(palette, VOXELS_PER_METER as f32, fill)
+    }
+
+    /**
+    Every voxel that a triangle overlaps gets the color of the first such triangle.
+    Filled voxels take the color of the last triangle the fill ray entered through.
+    */
+    pub fn voxelize<P: Palette>(&self, palette: &mut P, voxels_per_meter: f32, fill: VoxelizeFill) -> OctaResult<OffsetVoxelGrid> {
+        ensure!(!self.triangles.is_empty(), "Can not voxelize an empty mesh");
+        ensure!(voxels_per_meter > 0.0, "Voxels per meter has to be positive");
+
+        let mut mats = HashMap::new();
+        let mut get_mat = |color: [u8; 3]| -> OctaResult<u8> {
+            if let Some(mat) = mats.get(&color) {
+                return Ok(*mat);
+            }
+
+            let mat = palette.get_index_simple_color(color)?;
+            mats.insert(color, mat);
+            Ok(mat)
+        };
+
+        let triangles = self.triangles.iter()
+            .map(|t| t.map(|p| p * voxels_per_meter))
+            .collect::<Vec<_>>();
+
+        let min = triangles.iter().flatten().copied().reduce(Vec3::min).unwrap();
+        let max = triangles.iter().flatten().copied().reduce(Vec3::max).unwrap();
+        ensure!(min.is_finite() && max.is_finite(), "Can not voxelize a mesh with non finite positions");
+        ensure!(min.cmpgt(Vec3::splat(i32::MIN as f32)).all() && max.cmplt(Vec3::splat(i32::MAX as f32)).all(),
+            "The mesh from {min} to {max} is out of range");
+
+        let offset = min.floor().as_ivec3();
+        let size = (max.floor() - min.floor() + 1.0).as_uvec3();
+        let count = get_import_voxel_count(size)?;
+
+        let triangles = triangles.iter()
+            .map(|t| t.map(|p| p - offset.as_vec3()))
+            .collect::<Vec<_>>();
+
+        let mut data = vec![MATERIAL_ID_NONE; count];
+        let half = Vec3::splat(0.5 + OVERLAP_EPSILON);
+
+        for (t, color) in triangles.iter().zip(self.colors.iter()) {
+            let mat = get_mat(*color)?;
+            let t_min = t[0].min(t[1]).min(t[2]).floor().as_uvec3().min(size - 1);
+            let t_max = t[0].max(t[1]).max(t[2]).floor().as_uvec3().min(size - 1);
+
+            for x in t_min.x..=t_max.x {
+                for y in t_min.y..=t_max.y {
+                    for z in t_min.z..=t_max.z {
+                        let pos = UVec3::new(x, y, z);
+                        let index = to_1d(pos, size);
+                        if data[index] == MATERIAL_ID_NONE && triangle_box_overlap(pos.as_vec3() + 0.5, half, t) {
+                            data[index] = mat;
+                        }
+                    }
+                }
+            }
+        }
+
+        if fill != VoxelizeFill::Surface {
+            self.fill_inside(&triangles, &mut data, size, fill, &mut get_mat)?;
+        }
+
+        Ok(OffsetVoxelGrid::from_data(size, data, offset))
+    }
+
+    // Casts a ray along z through the center of every column and counts the triangles it crosses.
+    fn fill_inside(&self, triangles: &[[Vec3; 3]], data: &mut [u8], size: UVec3, fill: VoxelizeFill, get_mat: &mut impl FnMut([u8; 3]) -> OctaResult<u8>) -> OctaResult<()> {
+        let mut columns: Vec<Vec<(f32, i32, u8)>> = vec![vec![]; get_import_voxel_count(UVec3::new(size.x, size.y, 1))?];
+
+        for (t, color) in triangles.iter().zip(self.colors.iter()) {
+            let normal = (t[1] - t[0]).cross(t[2] - t[0]);
+            if normal.z == 0.0 {
+                continue;
+            }
+
+            // Rays go up, so they enter through triangles that face down.
+            let dir = if normal.z < 0.0 { 1 } else { -1 };
+            let mat = get_mat(*color)?;
+            let flat = t.map(|p| p.truncate());
+
+            // Columns whose centers lie in the bounds of the triangle.
+            let c_min = (flat[0].min(flat[1]).min(flat[2]) - 0.5).ceil().max(Vec2::ZERO);
+            let c_max = (flat[0].max(flat[1]).max(flat[2]) - 0.5).floor().min(vec2(size.x as f32, size.y as f32) - 1.0);
+            if c_max.cmplt(c_min).any() {
+                continue;
+            }
+
+            for x in c_min.x as u32..=c_max.x as u32 {
+                for y in c_min.y as u32..=c_max.y as u32 {
+                    let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                    if !inside_triangle_xy(flat, p) {
+                        continue;
+                    }
+
+                    let z = t[0].z - (normal.x * (p.x - t[0].x) + normal.y * (p.y - t[0].y)) / normal.z;
+                    columns[(x * size.y + y) as usize].push((z, dir, mat));
+                }
+            }
+        }
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let column = &mut columns[(x * size.y + y) as usize];
+                if column.is_empty() {
+                    continue;
+                }
+                column.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut next = 0;
+                let mut winding = 0;
+                let mut crossings = 0;
+                let mut mat = MATERIAL_ID_NONE;
+                for z in 0..size.z {
+                    let center = z as f32 + 0.5;
+                    while next < column.len() && column[next].0 < center {
+                        winding += column[next].1;
+                        crossings += 1;
+                        if column[next].1 > 0 || mat == MATERIAL_ID_NONE {
+                            mat = column[next].2;
+                        }
+                        next += 1;
+                    }
+
+                    let inside = match fill {
+                        VoxelizeFill::Parity => crossings % 2 == 1,
+                        _ => winding != 0,
+                    };
+
+                    let index = to_1d(UVec3::new(x, y, z), size);
+                    if inside && data[index] == MATERIAL_ID_NONE {
+                        data[index] = mat;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_floats<'a>(parts: impl Iterator<Item = &'a str>) -> OctaResult<Vec<f32>> {
+    parts.map(|v| v.parse::<f32>().map_err(|e| anyhow!("{e}: {v}"))).collect()
+}
+
+fn to_rgb(c: Vec3) -> [u8; 3] {
+    let c = (c * 255.0).clamp(Vec3::ZERO, Vec3::splat(255.0)).round();
+    [c.x as u8, c.y as u8, c.z as u8]
+}
+
+fn load_mtl(path: &Path) -> OctaResult<HashMap<String, [u8; 3]>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read mtl file {}: {e}", path.display()))?;
+
+    let mut materials = HashMap::new();
+    let mut current = None;
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("newmtl") => {
+                current = parts.next().map(|s| s.to_owned());
+            }
+            Some("Kd") => {
+                let v = parse_floats(parts)?;
+                if let (Some(name), true) = (&current, v.len() >= 3) {
+                    materials.insert(name.to_owned(), to_rgb(Vec3::new(v[0], v[1], v[2])));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+// Points on shared edges only count for one of the triangles, so rays through edges are not counted twice.
+fn inside_triangle_xy(t: [Vec2; 3], p: Vec2) -> bool {
+    let [a, mut b, mut c] = t;
+    let area = edge(a, b, c);
+    if area == 0.0 {
+        return false;
+    }
+
+    if area < 0.0 {
+        std::mem::swap(&mut b, &mut c);
+    }
+
+    [(a, b), (b, c), (c, a)].into_iter().all(|(e0, e1)| {
+        let w = edge(e0, e1, p);
+        let d = e1 - e0;
+        w > 0.0 || (w == 0.0 && (d.y < 0.0 || (d.y == 0.0 && d.x > 0.0)))
+    })
+}
+
+// Separating axis test after Akenine-Moeller.
+fn triangle_box_overlap(center: Vec3, half: Vec3, t: &[Vec3; 3]) -> bool {
+    let v = t.map(|p| p - center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+
+    let separated = |axis: Vec3| {
+        let p = v.map(|p| p.dot(axis));
+        let r = half.dot(axis.abs());
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+
+    for e in edges {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            if separated(axis.cross(e)) {
+                return false;
+            }
+        }
+    }
+
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+        if separated(axis) {
+            return false;
+        }
+    }
+
+    !separated(edges[0].cross(edges[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, UVec3, Vec3};
+
+    use crate::voxel::palette::{palette::{LocalPalette, MATERIAL_ID_NONE}, Palette};
+
+    use super::{TriangleMesh, VoxelizeFill, DEFAULT_MESH_COLOR};
+
+    // Counter clockwise seen from outside.
+    fn add_box(mesh: &mut TriangleMesh, min: f32, max: f32, color: [u8; 3]) {
+        let c = |x: bool, y: bool, z: bool| Vec3::new(
+            if x { max } else { min },
+            if y { max } else { min },
+            if z { max } else { min },
+        );
+
+        let quads = [
+            [c(false, false, false), c(false, true, false), c(true, true, false), c(true, false, false)],
+            [c(false, false, true), c(true, false, true), c(true, true, true), c(false, true, true)],
+            [c(false, false, false), c(true, false, false), c(true, false, true), c(false, false, true)],
+            [c(false, true, false), c(false, true, true), c(true, true, true), c(true, true, false)],
+            [c(false, false, false), c(false, false, true), c(false, true, true), c(false, true, false)],
+            [c(true, false, false), c(true, true, false), c(true, true, true), c(true, false, true)],
+        ];
+
+        for [a, b, c, d] in quads {
+            mesh.triangles.push([a, b, c]);
+            mesh.triangles.push([a, c, d]);
+            mesh.colors.extend([color; 2]);
+        }
+    }
+
+    fn write_temp_file(name: &str, data: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("dynamic_voxels_{}_{name}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn voxelize_box() {
+        let mut mesh = TriangleMesh::default();
+        add_box(&mut mesh, 0.25, 7.75, DEFAULT_MESH_COLOR);
+        let mut palette = LocalPalette::new();
+        let white = palette.get_index_simple_color(DEFAULT_MESH_COLOR).unwrap();
+
+        let surface = mesh.voxelize(&mut palette, 1.0, VoxelizeFill::Surface).unwrap();
+        assert_eq!(surface.offset, IVec3::ZERO);
+        assert_eq!(surface.grid.size, UVec3::splat(8));
+        assert_eq!(surface.grid.get(UVec3::new(0, 4, 4)), white);
+        assert_eq!(surface.grid.get(UVec3::new(7, 4, 4)), white);
+        assert_eq!(surface.grid.get(UVec3::new(4, 4, 4)), MATERIAL_ID_NONE);
+
+        for fill in [VoxelizeFill::Parity, VoxelizeFill::Winding] {
+            let solid = mesh.voxelize(&mut palette, 1.0, fill).unwrap();
+            assert!(solid.grid.data.iter().all(|v| *v == white), "{fill:?} did not fill the box");
+        }
+    }
+
+    #[test]
+    fn voxelize_nested_boxes() {
+        let mut mesh = TriangleMesh::default();
+        add_box(&mut mesh, 0.25, 7.75, DEFAULT_MESH_COLOR);
+        add_box(&mut mesh, 2.25, 5.75, [255, 0, 0]);
+        let mut palette = LocalPalette::new();
+        let red = palette.get_index_simple_color([255, 0, 0]).unwrap();
+
+        // The inner box is a hole for the parity fill but not for the winding fill.
+        let parity = mesh.voxelize(&mut palette, 1.0, VoxelizeFill::Parity).unwrap();
+        assert_eq!(parity.grid.get(UVec3::new(4, 4, 4)), MATERIAL_ID_NONE);
+
+        let winding = mesh.voxelize(&mut palette, 1.0, VoxelizeFill::Winding).unwrap();
+        assert_eq!(winding.grid.get(UVec3::new(4, 4, 4)), red);
+    }
+
+    #[test]
+    fn voxelize_rejects_bad_input() {
+        let mut palette = LocalPalette::new();
+        assert!(TriangleMesh::default().voxelize(&mut palette, 1.0, VoxelizeFill::Surface).is_err());
+
+        let mut mesh = TriangleMesh::default();
+        add_box(&mut mesh, 0.0, 1.0, DEFAULT_MESH_COLOR);
+        assert!(mesh.voxelize(&mut palette, 0.0, VoxelizeFill::Surface).is_err());
+
+        // Far too many voxels and positions that do not fit into the grid.
+        assert!(mesh.voxelize(&mut palette, 1e6, VoxelizeFill::Parity).is_err());
+        assert!(mesh.voxelize(&mut palette, 1e20, VoxelizeFill::Surface).is_err());
+
+        mesh.triangles[0][0] = Vec3::NAN;
+        assert!(mesh.voxelize(&mut palette, 1.0, VoxelizeFill::Surface).is_err());
+    }
+
+    #[test]
+    fn load_obj() {
+        let path = write_temp_file("mesh.obj", b"v 0 0 0 1 0 0\nv 1 0 0 1 0 0\nv 0 1 0 1 0 0\nv 0 0 1\nf 1 2 3\nf 1/1 2/2 3/3 4/4\n");
+        let mesh = TriangleMesh::from_obj(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The quad is split into a fan and y up becomes z up.
+        assert_eq!(mesh.triangles.len(), 3);
+        assert_eq!(mesh.triangles[0][2], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.triangles[1][2], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.triangles[2][2], Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(mesh.colors[0], [255, 0, 0]);
+        assert_eq!(mesh.colors[2], DEFAULT_MESH_COLOR);
+    }
+
+    #[test]
+    fn reject_bad_obj_faces() {
+        for face in ["f 1 2 4", "f 0 1 2", "f -4 1 2", "f 1 2 a", "f 1 // 2"] {
+            let path = write_temp_file("bad.obj", format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{face}\n").as_bytes());
+            let result = TriangleMesh::from_obj(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            assert!(result.is_err(), "{face} was accepted");
+        }
+    }
+
+    #[test]
+    fn reject_bad_stl() {
+        let text = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+        let path = write_temp_file("bad.stl", text.as_bytes());
+        let result = TriangleMesh::from_stl(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+
+        // A binary file whose face count does not match its size is not binary and not valid text.
+        let mut data = vec![0xff; 80];
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0xff; 50]);
+        let path = write_temp_file("truncated.stl", &data);
+        let result = TriangleMesh::from_stl(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_binary_stl() {
+        let mut data = vec![0; 80];
+        data.extend_from_slice(&1u32.to_le_bytes());
+        for v in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        // Full red in the 5 bit color of the attribute.
+        data.extend_from_slice(&(0x8000u16 | 31 << 10).to_le_bytes());
+
+        let path = write_temp_file("mesh.stl", &data);
+        let mesh = TriangleMesh::from_stl(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mesh.triangles, vec![[Vec3::ZERO, Vec3::X, Vec3::Y]]);
+        assert_eq!(mesh.colors, vec![[255, 0, 0]]);
+    }
+
+    #[test]
+    fn load_ascii_stl() {
+        let text = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+        let path = write_temp_file("ascii.stl", text.as_bytes());
+        let mesh = TriangleMesh::from_stl(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mesh.triangles, vec![[Vec3::ZERO, Vec3::X, Vec3::Y]]);
+        assert_eq!(mesh.colors, vec![DEFAULT_MESH_COLOR]);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{collections::HashMap, fs::File, io::{BufWriter, Write}, path::Path};
+
+use octa_force::{anyhow::{bail, ensure}, glam::{IVec3, Vec3}, OctaResult};
+
+use crate::{csg::csg_tree::tree::CSGTree, util::{aabb::AABB, math::{get_dag_node_children_i, get_import_voxel_count}, number::Nu, vector::Ve}, voxel::{dag64::{entry::DAG64EntryKey, node::VoxelDAG64Node, parallel::ParallelVoxelDAG64, single::VoxelDAG64, util::get_voxel_size}, grid::offset::OffsetVoxelGrid, palette::{palette::MATERIAL_ID_NONE, Palette}}};
+
+use super::{VolumeBounds, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyPosValue};
+
+// The vox format stores positions in a model as u8.
+pub const VOX_MODEL_MAX_SIZE: i32 = 256;
+const VOX_VERSION: i32 = 150;
+
+// Sampled volumes are first checked in blocks of this size so empty space is skipped.
+const VOX_SAMPLE_BLOCK_SIZE: i32 = 16;
+
+/**
+Collects voxels and writes them as a MagicaVoxel .vox file.
+Everything that does not fit into one 256^3 model is split into several models that are placed with transform nodes.
+The translations follow the importer, so a written file loads back at the same positions.
+Every position should only be added once.
+*/
+#[derive(Debug, Default)]
+pub struct VoxWriter {
+    chunks: HashMap<IVec3, Vec<(IVec3, u8)>>,
+}
+
+impl VoxWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_voxel(&mut self, pos: IVec3, mat: u8) {
+        if mat == MATERIAL_ID_NONE {
+            return;
+        }
+
+        let chunk = pos.div_euclid(IVec3::splat(VOX_MODEL_MAX_SIZE));
+        self.chunks.entry(chunk)
+            .or_default()
+            .push((pos, mat));
+    }
+
+    fn add_cube(&mut self, min: IVec3, size: IVec3, mat: u8) {
+        if mat == MATERIAL_ID_NONE {
+            return;
+        }
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    self.add_voxel(min + IVec3::new(x, y, z), mat);
+                }
+            }
+        }
+    }
+
+    /**
+    Samples every position in the bounds of the model.
+    Blocks that the aabb query reports as full are filled without sampling them.
+    Unbounded volumes like planes and bounds with more voxels than an import allows are rejected.
+    */
+    pub fn add_volume<V: Ve<T, 3>, T: Nu, M: VolumeBounds<V, T, 3> + VolumeQureyPosValue<V, T, 3> + VolumeQureyAABB<V, T, 3>>(&mut self, model: &M) -> OctaResult<()> {
+        let bounds = model.get_bounds();
+        if !bounds.valid() {
+            return Ok(());
+        }
+
+        let min = Vec3::from_array(bounds.min().to_array().map(|v| v.to_f32().floor()));
+        let max = Vec3::from_array(bounds.max().to_array().map(|v| v.to_f32().ceil()));
+        ensure!(min.is_finite() && max.is_finite()
+            && min.cmpgt(Vec3::splat(i32::MIN as f32)).all() && max.cmplt(Vec3::splat(i32::MAX as f32)).all(),
+            "Can not write the volume from {min} to {max}");
+        get_import_voxel_count((max - min + 1.0).as_uvec3())?;
+
+        let min = min.as_ivec3();
+        let max = max.as_ivec3();
+        let to_v = |pos: IVec3| V::new(pos.to_array().map(|v| T::from_f32(v as f32)));
+
+        let block_size = IVec3::splat(VOX_SAMPLE_BLOCK_SIZE);
+        let blocks = (max - min) / block_size + 1;
+        for x in 0..blocks.x {
+            for y in 0..blocks.y {
+                for z in 0..blocks.z {
+                    let block_min = min + IVec3::new(x, y, z) * block_size;
+                    let size = block_size.min(max + 1 - block_min);
+
+                    match model.get_aabb_value(AABB::new(to_v(block_min), to_v(block_min + size))) {
+                        VolumeQureyAABBResult::Full(mat) => self.add_cube(block_min, size, mat),
+                        VolumeQureyAABBResult::Mixed => {
+                            for px in 0..size.x {
+                                for py in 0..size.y {
+                                    for pz in 0..size.z {
+                                        let pos = block_min + IVec3::new(px, py, pz);
+                                        self.add_voxel(pos, model.get_value(to_v(pos)));
+                                    }
+                                }
+                            }
+                        },
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Grids without a transform are copied directly.
+    pub fn add_grid(&mut self, grid: &OffsetVoxelGrid) -> OctaResult<()> {
+        if grid.transform.is_some() {
+            return self.add_volume::<IVec3, i32, _>(grid);
+        }
+
+        let size = grid.grid.size.as_ivec3();
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let pos = IVec3::new(x, y, z);
+                    self.add_voxel(grid.offset + pos, grid.grid.get(pos.as_uvec3()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    Walks the nodes of a dag entry.
+    Every child of a leaf is a uniform block of its level, so leaves above level 1 add whole cubes.
+    */
+    pub fn add_dag_entry(&mut self, get_node: impl Fn(u32) -> VoxelDAG64Node, get_data: impl Fn(u32) -> u8, root_index: u32, levels: u8, offset: IVec3) {
+        self.add_dag_node(&get_node, &get_data, get_node(root_index), levels, offset);
+    }
+
+    fn add_dag_node(&mut self, get_node: &impl Fn(u32) -> VoxelDAG64Node, get_data: &impl Fn(u32) -> u8, node: VoxelDAG64Node, level: u8, offset: IVec3) {
+        if node.is_empty() || level == 0 {
+            return;
+        }
+
+        let child_size = get_voxel_size(level - 1);
+        for (i, pos) in get_dag_node_children_i().into_iter().enumerate() {
+            let Some(index) = node.get_index_for_child(i as u32) else {
+                continue;
+            };
+
+            let child_offset = offset + pos * child_size;
+            if node.is_leaf() {
+                self.add_cube(child_offset, IVec3::splat(child_size), get_data(index));
+            } else {
+                self.add_dag_node(get_node, get_data, get_node(index), level - 1, child_offset);
+            }
+        }
+    }
+
+    pub fn save<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, palette)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /**
+    The materials are numbered in the order they first show up, because the vox palette only has 255 colors.
+    Their colors come from the palette.
+    */
+    pub fn write<P: Palette>(&self, writer: &mut impl Write, palette: &P) -> OctaResult<()> {
+        let mut chunk_keys: Vec<_> = self.chunks.keys().copied().collect();
+        chunk_keys.sort_by_key(|c| c.to_array());
+
+        let mut color_indecies = [0u8; 256];
+        let mut colors = Vec::new();
+        let mut models = vec![];
+        let mut children = vec![];
+
+        for key in chunk_keys.iter() {
+            let voxels = &self.chunks[key];
+
+            let mut min = IVec3::splat(i32::MAX);
+            let mut max = IVec3::splat(i32::MIN);
+            for (pos, mat) in voxels.iter() {
+                min = min.min(*pos);
+                max = max.max(*pos);
+
+                if color_indecies[*mat as usize] == 0 {
+                    ensure!(colors.len() < 255, "The vox palette can only hold 255 materials");
+                    colors.push(palette.get_color(*mat));
+                    color_indecies[*mat as usize] = colors.len() as u8;
+                }
+            }
+
+            let size = max - min + 1;
+            let model_id = children.len() as i32;
+
+            let mut xyzi = vec![];
+            write_i32(&mut xyzi, voxels.len() as i32);
+            for (pos, mat) in voxels.iter() {
+                let local = *pos - min;
+                xyzi.extend_from_slice(&[local.x as u8, local.y as u8, local.z as u8, color_indecies[*mat as usize]]);
+            }
+
+            let mut size_content = vec![];
+            for v in size.to_array() {
+                write_i32(&mut size_content, v);
+            }
+
+            models.push(chunk(b"SIZE", &size_content));
+            models.push(chunk(b"XYZI", &xyzi));
+
+            // The importer places models at translation - size / 2.
+            let translation = min + size / 2;
+            children.push((model_id, translation));
+        }
+
+        if models.is_empty() {
+            bail!("Nothing to write into the vox file");
+        }
+
+        let mut main = models.concat();
+
+        // Scene graph: root transform -> group -> (transform -> shape) per model.
+        let group_id = 1;
+        let mut root = vec![];
+        write_i32(&mut root, 0);
+        write_dict(&mut root, &[]);
+        write_i32(&mut root, group_id);
+        write_i32(&mut root, -1);
+        write_i32(&mut root, -1);
+        write_i32(&mut root, 1);
+        write_dict(&mut root, &[]);
+        main.extend(chunk(b"nTRN", &root));
+
+        let mut group = vec![];
+        write_i32(&mut group, group_id);
+        write_dict(&mut group, &[]);
+        write_i32(&mut group, children.len() as i32);
+        for i in 0..children.len() as i32 {
+            write_i32(&mut group, 2 + i * 2);
+        }
+        main.extend(chunk(b"nGRP", &group));
+
+        for (i, (model_id, translation)) in children.iter().enumerate() {
+            let transform_id = 2 + i as i32 * 2;
+            let t = format!("{} {} {}", translation.x, translation.y, translation.z);
+
+            let mut transform = vec![];
+            write_i32(&mut transform, transform_id);
+            write_dict(&mut transform, &[]);
+            write_i32(&mut transform, transform_id + 1);
+            write_i32(&mut transform, -1);
+            write_i32(&mut transform, 0);
+            write_i32(&mut transform, 1);
+            write_dict(&mut transform, &[("_t", &t)]);
+            main.extend(chunk(b"nTRN", &transform));
+
+            let mut shape = vec![];
+            write_i32(&mut shape, transform_id + 1);
+            write_dict(&mut shape, &[]);
+            write_i32(&mut shape, 1);
+            write_i32(&mut shape, *model_id);
+            write_dict(&mut shape, &[]);
+            main.extend(chunk(b"nSHP", &shape));
+        }
+
+        // Entry i of the palette chunk is the color index i + 1.
+        let mut rgba = vec![0u8; 256 * 4];
+        for (i, color) in colors.iter().enumerate() {
+            rgba[i * 4..i * 4 + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+        }
+        main.extend(chunk(b"RGBA", &rgba));
+
+        writer.write_all(b"VOX ")?;
+        writer.write_all(&VOX_VERSION.to_le_bytes())?;
+        writer.write_all(b"MAIN")?;
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&(main.len() as i32).to_le_bytes())?;
+        writer.write_all(&main)?;
+
+        Ok(())
+    }
+}
+
+impl OffsetVoxelGrid {
+    pub fn save_vox<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let mut writer = VoxWriter::new();
+        writer.add_grid(self)?;
+        writer.save(path, palette)
+    }
+}
+
+impl<V: Ve<T, 3>, T: Nu> CSGTree<u8, V, T, 3> {
+    pub fn save_vox<P: Palette>(&self, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let mut writer = VoxWriter::new();
+        writer.add_volume(self)?;
+        writer.save(path, palette)
+    }
+}
+
+impl VoxelDAG64 {
+    pub fn save_vox<P: Palette>(&self, key: DAG64EntryKey, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let entry = self.get_entry(key);
+
+        let mut writer = VoxWriter::new();
+        writer.add_dag_entry(|i| self.nodes.get(i), |i| self.data.get(i), entry.root_index, entry.levels, entry.offset);
+        writer.save(path, palette)
+    }
+}
+
+impl ParallelVoxelDAG64 {
+    pub fn save_vox<P: Palette>(&self, key: DAG64EntryKey, path: impl AsRef<Path>, palette: &P) -> OctaResult<()> {
+        let entry = self.get_entry(key);
+
+        let mut writer = VoxWriter::new();
+        writer.add_dag_entry(|i| self.nodes.get(i), |i| self.data.get(i), entry.root_index, entry.levels, entry.offset);
+        writer.save(path, palette)
+    }
+}
+
+fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(content.len() + 12);
+    data.extend_from_slice(id);
+    write_i32(&mut data, content.len() as i32);
+    write_i32(&mut data, 0);
+    data.extend_from_slice(content);
+    data
+}
+
+fn write_i32(data: &mut Vec<u8>, v: i32) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+fn write_string(data: &mut Vec<u8>, s: &str) {
+    write_i32(data, s.len() as i32);
+    data.extend_from_slice(s.as_bytes());
+}
+
+fn write_dict(data: &mut Vec<u8>, entries: &[(&str, &str)]) {
+    write_i32(data, entries.len() as i32);
+    for (key, value) in entries {
+        write_string(data, key);
+        write_string(data, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use octa_force::glam::{IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, voxel::palette::{palette::LocalPalette, Palette}};
+
+    use super::VoxWriter;
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    // Voxel positions and colors the way the importer places the models.
+    fn read_back(writer: &VoxWriter, palette: &LocalPalette) -> (usize, HashSet<(IVec3, [u8; 3])>) {
+        let mut bytes = vec![];
+        writer.write(&mut bytes, palette).unwrap();
+        let vox = dot_vox::load_bytes(&bytes).unwrap();
+
+        let mut voxels = HashSet::new();
+        for node in vox.scenes.iter() {
+            let dot_vox::SceneNode::Transform { frames, child, .. } = node else {
+                continue;
+            };
+            let dot_vox::SceneNode::Shape { models, .. } = &vox.scenes[*child as usize] else {
+                continue;
+            };
+
+            let t: Vec<i32> = frames[0].attributes["_t"].split(' ').map(|v| v.parse().unwrap()).collect();
+            let model = &vox.models[models[0].model_id as usize];
+            let size = IVec3::new(model.size.x as i32, model.size.y as i32, model.size.z as i32);
+            let offset = IVec3::new(t[0], t[1], t[2]) - size / 2;
+
+            for v in model.voxels.iter() {
+                let c = vox.palette[v.i as usize];
+                voxels.insert((offset + IVec3::new(v.x as i32, v.y as i32, v.z as i32), [c.r, c.g, c.b]));
+            }
+        }
+
+        (vox.models.len(), voxels)
+    }
+
+    #[test]
+    fn round_trip_split_models() {
+        let mut palette = LocalPalette::new();
+        let white = palette.get_index_simple_color([255, 255, 255]).unwrap();
+        let red = palette.get_index_simple_color([255, 0, 0]).unwrap();
+
+        let mut writer = VoxWriter::new();
+        let expected = [
+            (IVec3::new(0, 0, 0), white, [255, 255, 255]),
+            (IVec3::new(255, 3, 7), red, [255, 0, 0]),
+            (IVec3::new(300, -5, 10), red, [255, 0, 0]),
+            (IVec3::new(-1, 0, 600), white, [255, 255, 255]),
+        ];
+        for (pos, mat, _) in expected {
+            writer.add_voxel(pos, mat);
+        }
+
+        let (models, voxels) = read_back(&writer, &palette);
+        assert_eq!(models, 3);
+        assert_eq!(voxels, expected.iter().map(|(pos, _, color)| (*pos, *color)).collect());
+    }
+
+    #[test]
+    fn add_volume() {
+        let mut palette = LocalPalette::new();
+        let white = palette.get_index_simple_color([255, 255, 255]).unwrap();
+        let csg = Tree::new_sphere_float(Vec3A::new(200.0, 0.0, 0.0), 80.0, white);
+
+        let mut writer = VoxWriter::new();
+        writer.add_volume(&csg).unwrap();
+
+        let (models, voxels) = read_back(&writer, &palette);
+        assert!(models > 1);
+        assert!(voxels.contains(&(IVec3::new(200, 0, 0), [255, 255, 255])));
+        assert!(voxels.contains(&(IVec3::new(270, 0, 0), [255, 255, 255])));
+        assert!(!voxels.iter().any(|(pos, _)| *pos == IVec3::new(290, 0, 0)));
+    }
+
+    #[test]
+    fn reject_huge_volumes() {
+        let mut writer = VoxWriter::new();
+        assert!(writer.add_volume(&Tree::new_sphere_float(Vec3A::ZERO, 2000.0, 1)).is_err());
+        assert!(writer.add_volume(&Tree::new_sphere_float(Vec3A::ZERO, 1e10, 1)).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use std::{collections::HashSet, fs::{self, File}, path::Path};
+
+use memmap2::Mmap;
+use octa_force::{anyhow::{anyhow, bail, ensure}, glam::IVec3, OctaResult};
+use slotmap::SlotMap;
+
+use crate::{util::{byte_reader::ByteReader, parallel_reuse_buffer::ParallelReUseBuffer, reuse_buffer::ReUseBuffer}, voxel::dag64::{entry::{DAG64Entry, DAG64EntryKey}, node::VoxelDAG64Node, parallel::ParallelVoxelDAG64, single::VoxelDAG64}};
+
+pub const DAG64_FILE_VERSION: u32 = 1;
+const DAG64_FILE_MAGIC: [u8; 4] = *b"DG64";
+const DAG64_NODE_SIZE: usize = size_of::<VoxelDAG64Node>();
+// Nodes and leaves point into the buffers with u32 indecies, so no buffer can be bigger.
+const DAG64_MAX_CAPACITY: usize = u32::MAX as usize;
+
+/**
+Binary layout, everything little endian:
+magic, version,
+node capacity, stored nodes, node used range count,
+data capacity, stored data, data used range count, entry count as u64,
+the used ranges as (start, end) u64 pairs, first nodes then data,
+the entries as levels u8, 3 bytes padding, root index u32 and offset 3 x i32,
+then the nodes as they are in memory and at the end the data bytes.
+
+The nodes and data are plain copies of the buffers, so a mapped file is copied over in one go.
+Only the used prefix of the buffers is stored, the rest is filled with defaults up to the capacity again.
+*/
+struct DAG64File<'a> {
+    node_capacity: usize,
+    node_used_ranges: Vec<(usize, usize)>,
+    nodes: &'a [VoxelDAG64Node],
+    data_capacity: usize,
+    data_used_ranges: Vec<(usize, usize)>,
+    data: &'a [u8],
+    entries: Vec<DAG64Entry>,
+}
+
+impl<'a> DAG64File<'a> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = DAG64_FILE_MAGIC.to_vec();
+        bytes.extend_from_slice(&DAG64_FILE_VERSION.to_le_bytes());
+
+        for v in [
+            self.node_capacity, self.nodes.len(), self.node_used_ranges.len(),
+            self.data_capacity, self.data.len(), self.data_used_ranges.len(),
+            self.entries.len(),
+        ] {
+            bytes.extend_from_slice(&(v as u64).to_le_bytes());
+        }
+
+        for (start, end) in self.node_used_ranges.iter().chain(self.data_used_ranges.iter()) {
+            bytes.extend_from_slice(&(*start as u64).to_le_bytes());
+            bytes.extend_from_slice(&(*end as u64).to_le_bytes());
+        }
+
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&[entry.levels, 0, 0, 0]);
+            bytes.extend_from_slice(&entry.root_index.to_le_bytes());
+            for v in entry.offset.to_array() {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        // The node is packed and only made of integers, so its memory is its file layout.
+        let nodes = unsafe {
+            std::slice::from_raw_parts(self.nodes.as_ptr() as *const u8, self.nodes.len() * DAG64_NODE_SIZE)
+        };
+        bytes.extend_from_slice(nodes);
+        bytes.extend_from_slice(self.data);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> OctaResult<Self> {
+        ensure!(cfg!(target_endian = "little"), "DAG64 files can only be loaded on little endian machines");
+
+        let mut reader = ByteReader::new(bytes);
+        if bytes.len() < 8 || reader.array::<4>()? != DAG64_FILE_MAGIC {
+            bail!("Not a DAG64 file");
+        }
+
+        let version = reader.u32_le()?;
+        if version != DAG64_FILE_VERSION {
+            bail!("DAG64 file version {} is not supported. Expected {}", version, DAG64_FILE_VERSION);
+        }
+
+        let mut header = [0; 7];
+        for v in header.iter_mut() {
+            *v = reader.u64_le()? as usize;
+        }
+        let [node_capacity, node_len, node_range_count, data_capacity, data_len, data_range_count, entry_count] = header;
+
+        ensure!(node_len <= node_capacity && data_len <= data_capacity, "DAG64 file stores more than its capacity");
+        ensure!(node_capacity <= DAG64_MAX_CAPACITY && data_capacity <= DAG64_MAX_CAPACITY, "DAG64 file capacity is too large");
+
+        let mut read_ranges = |count: usize, len: usize| -> OctaResult<Vec<(usize, usize)>> {
+            (0..count)
+                .map(|_| {
+                    let (start, end) = (reader.u64_le()? as usize, reader.u64_le()? as usize);
+                    ensure!(start <= end && end <= len, "DAG64 file has an invalid used range");
+                    Ok((start, end))
+                })
+                .collect()
+        };
+        let node_used_ranges = read_ranges(node_range_count, node_len)?;
+        let data_used_ranges = read_ranges(data_range_count, data_len)?;
+
+        let entries = (0..entry_count)
+            .map(|_| {
+                let levels = reader.array::<4>()?[0];
+                let root_index = reader.u32_le()?;
+                let offset = IVec3::new(reader.i32_le()?, reader.i32_le()?, reader.i32_le()?);
+                ensure!((root_index as usize) < node_len, "DAG64 file entry root is out of range");
+
+                Ok(DAG64Entry { levels, root_index, offset })
+            })
+            .collect::<OctaResult<Vec<_>>>()?;
+
+        let node_bytes_len = node_len.checked_mul(DAG64_NODE_SIZE)
+            .ok_or_else(|| anyhow!("DAG64 file node count {} is too large", node_len))?;
+        let node_bytes = reader.bytes(node_bytes_len)?;
+        let nodes = unsafe {
+            std::slice::from_raw_parts(node_bytes.as_ptr() as *const VoxelDAG64Node, node_len)
+        };
+        let data = reader.bytes(data_len)?;
+        ensure!(reader.is_empty(), "DAG64 file has trailing bytes");
+
+        Ok(Self {
+            node_capacity,
+            node_used_ranges,
+            nodes,
+            data_capacity,
+            data_used_ranges,
+            data,
+            entries,
+        })
+    }
+
+    /**
+    The ranges that were pushed into the buffers: the roots, the children of every node and the values of every leaf.
+    Every node is only visited once, so shared subtrees do not blow up the walk.
+    */
+    fn get_pushed_ranges(&self) -> OctaResult<(Vec<std::ops::Range<usize>>, Vec<std::ops::Range<usize>>)> {
+        let mut node_ranges = vec![];
+        let mut data_ranges = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![];
+
+        for entry in self.entries.iter() {
+            let root = entry.root_index as usize;
+            node_ranges.push(root..root + 1);
+            stack.push(entry.root_index);
+        }
+
+        while let Some(index) = stack.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+
+            let node = self.nodes[index as usize];
+            if node.is_empty() {
+                continue;
+            }
+
+            let range = node.range();
+            if node.is_leaf() {
+                ensure!(range.end <= self.data.len(), "DAG64 file leaf {index} points outside of the data");
+                data_ranges.push(range);
+            } else {
+                ensure!(range.end <= self.nodes.len(), "DAG64 file node {index} points outside of the nodes");
+                stack.extend(range.clone().map(|i| i as u32));
+                node_ranges.push(range);
+            }
+        }
+
+        Ok((node_ranges, data_ranges))
+    }
+}
+
+// The buffers are filled up to the capacity, so a failed allocation is an error instead of an abort.
+fn copy_with_capacity<T: Copy>(values: &[T], capacity: usize) -> OctaResult<Vec<T>> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(capacity)
+        .map_err(|_| anyhow!("Could not allocate a DAG64 buffer with a capacity of {}", capacity))?;
+    data.extend_from_slice(values);
+    Ok(data)
+}
+
+/**
+The loaders read the header and copy the buffers straight out of the mapping, the file is never read into memory as a whole.
+The file must not be changed by another process while it is loaded.
+*/
+fn map_file(path: &Path) -> OctaResult<Mmap> {
+    let file = File::open(path)?;
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+impl VoxelDAG64 {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let node_len = self.nodes.used_ranges.last().map_or(0, |r| r.1);
+        let data_len = self.data.used_ranges.last().map_or(0, |r| r.1);
+
+        DAG64File {
+            node_capacity: self.nodes.data.len(),
+            node_used_ranges: self.nodes.used_ranges.clone(),
+            nodes: &self.nodes.data[..node_len],
+            data_capacity: self.data.data.len(),
+            data_used_ranges: self.data.used_ranges.clone(),
+            data: &self.data.data[..data_len],
+            entries: self.entry_points.values().copied().collect(),
+        }.to_bytes()
+    }
+
+    // The keys of the entries in the order they were saved in.
+    pub fn from_bytes(bytes: &[u8]) -> OctaResult<(Self, Vec<DAG64EntryKey>)> {
+        let file = DAG64File::from_bytes(bytes)?;
+        let (node_ranges, data_ranges) = file.get_pushed_ranges()?;
+
+        let mut nodes = ReUseBuffer::from_data(copy_with_capacity(file.nodes, file.node_capacity)?, file.node_capacity, file.node_used_ranges.clone());
+        nodes.rebuild_cache(node_ranges.into_iter());
+
+        let mut data = ReUseBuffer::from_data(copy_with_capacity(file.data, file.data_capacity)?, file.data_capacity, file.data_used_ranges.clone());
+        data.rebuild_cache(data_ranges.into_iter());
+
+        let mut entry_points = SlotMap::with_key();
+        let keys = file.entries.iter()
+            .map(|entry| entry_points.insert(*entry))
+            .collect();
+
+        Ok((Self { nodes, data, entry_points }, keys))
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> OctaResult<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> OctaResult<(Self, Vec<DAG64EntryKey>)> {
+        Self::from_bytes(&map_file(path.as_ref())?)
+    }
+}
+
+// Only the active buffers are stored, the inactive ones are empty after loading.
+impl ParallelVoxelDAG64 {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let node_len = self.nodes.get_write_head();
+        let data_len = self.data.get_write_head();
+
+        DAG64File {
+            node_capacity: self.nodes.data().len(),
+            node_used_ranges: vec![(0, node_len)],
+            nodes: &self.nodes.data()[..node_len],
+            data_capacity: self.data.data().len(),
+            data_used_ranges: vec![(0, data_len)],
+            data: &self.data.data()[..data_len],
+            entries: self.entry_points.lock().values().copied().collect(),
+        }.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> OctaResult<(Self, Vec<DAG64EntryKey>)> {
+        let file = DAG64File::from_bytes(bytes)?;
+        let (node_ranges, data_ranges) = file.get_pushed_ranges()?;
+
+        // A single buffer only writes at its head.
+        let head = |ranges: &[(usize, usize)], len: usize| ranges.last().map_or(0, |r| r.1).max(len);
+
+        let nodes = ParallelReUseBuffer::from_data(copy_with_capacity(file.nodes, file.node_capacity)?, file.node_capacity, head(&file.node_used_ranges, file.nodes.len()));
+        nodes.rebuild_cache(node_ranges.into_iter());
+
+        let data = ParallelReUseBuffer::from_data(copy_with_capacity(file.data, file.data_capacity)?, file.data_capacity, head(&file.data_used_ranges, file.data.len()));
+        data.rebuild_cache(data_ranges.into_iter());
+
+        let mut entry_points = SlotMap::with_key();
+        let keys = file.entries.iter()
+            .map(|entry| entry_points.insert(*entry))
+            .collect();
+
+        Ok((Self {
+            nodes,
+            inactive_nodes: ParallelReUseBuffer::new(file.node_capacity),
+            data,
+            inactive_data: ParallelReUseBuffer::new(file.data_capacity),
+            entry_points: std::sync::Arc::new(parking_lot::Mutex::new(entry_points)),
+        }, keys))
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> OctaResult<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> OctaResult<(Self, Vec<DAG64EntryKey>)> {
+        Self::from_bytes(&map_file(path.as_ref())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, gi::gi_pool_debugger::GINone, voxel::dag64::{lod_heuristic::LODHeuristicNone, parallel::ParallelVoxelDAG64, single::VoxelDAG64}};
+
+    fn new_dag() -> (VoxelDAG64, super::DAG64EntryKey) {
+        let mut csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 10.0, 1);
+        csg.union_sphere(Vec3A::new(8.0, 0.0, 0.0), 5.0, 2);
+
+        let mut dag = VoxelDAG64::new(100000, 64);
+        let key = dag.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+        (dag, key)
+    }
+
+    fn set_u64(bytes: &mut [u8], at: usize, v: u64) {
+        bytes[at..at + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        let (dag, key) = new_dag();
+        let bytes = dag.to_bytes();
+
+        let (loaded, keys) = VoxelDAG64::from_bytes(&bytes).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(loaded == dag);
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        for x in -16..16 {
+            for y in -16..16 {
+                for z in -16..16 {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(dag.get_voxel(key, pos), loaded.get_voxel(keys[0], pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let (mut dag, _) = new_dag();
+        let path = std::env::temp_dir().join(format!("dynamic_voxels_{}_dag.dag64", std::process::id()));
+        dag.save_to_file(&path).unwrap();
+
+        let (mut loaded, _) = VoxelDAG64::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded == dag);
+
+        // With the rebuilt caches the same volume reuses the same nodes in both.
+        let csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 10.0, 1);
+        dag.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+        loaded.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+        assert!(loaded == dag);
+    }
+
+    #[test]
+    fn parallel_round_trip() {
+        let csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 10.0, 1);
+        let mut dag = ParallelVoxelDAG64::new(100000, 1000);
+        dag.add_pos_query_volume(&csg, &LODHeuristicNone {}, GINone);
+        let bytes = dag.to_bytes();
+
+        let (loaded, keys) = ParallelVoxelDAG64::from_bytes(&bytes).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn reject_corrupt_header() {
+        let (dag, _) = new_dag();
+        let bytes = dag.to_bytes();
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(VoxelDAG64::from_bytes(&magic).is_err());
+
+        let mut version = bytes.clone();
+        version[4..8].copy_from_slice(&(super::DAG64_FILE_VERSION + 1).to_le_bytes());
+        assert!(VoxelDAG64::from_bytes(&version).is_err());
+
+        // Stored nodes is at byte 16, node capacity at byte 8.
+        let mut node_len = bytes.clone();
+        set_u64(&mut node_len, 16, u64::MAX);
+        assert!(VoxelDAG64::from_bytes(&node_len).is_err());
+
+        let mut capacity = bytes.clone();
+        set_u64(&mut capacity, 8, u64::MAX);
+        assert!(VoxelDAG64::from_bytes(&capacity).is_err());
+
+        assert!(VoxelDAG64::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(VoxelDAG64::from_bytes(&[]).is_err());
+    }
+}
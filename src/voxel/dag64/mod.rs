@@ -6,4 +6,7 @@ pub mod parallel;
 pub mod util;
 pub mod lod_heuristic;
 pub mod entry;
+pub mod file;
+pub mod ray;
 
+pub mod region;
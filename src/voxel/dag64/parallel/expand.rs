@@ -26,7 +26,8 @@ impl ParallelVoxelDAG64 {
             // middle of the new level.
             let diff: IVec3 = (model_center - tree_aabb.min()).ve_into(); 
             let child_pos = (diff / size) + 2;
-            let child_index = child_pos.as_uvec3().dot(UVec3::new(1, 4, 16));
+            // Same child order as get_dag_node_children and GetNodeCellIndex in the shaders.
+            let child_index = child_pos.as_uvec3().dot(UVec3::new(16, 4, 1));
 
             let new_root = VoxelDAG64Node::single(false, entry_data.root_index, 1 << child_index as u64);
             entry_data.root_index = self.nodes.push(&[new_root]);
@@ -0,0 +1,285 @@
+use octa_force::glam::{IVec3, Mat4, Vec3, Vec3A};
+
+use crate::{util::aabb::AABB3, voxel::dag64::{entry::{DAG64Entry, DAG64EntryKey}, node::VoxelDAG64Node, parallel::ParallelVoxelDAG64, single::VoxelDAG64, util::get_voxel_size}};
+
+#[derive(Debug, Clone, Copy)]
+pub struct DAG64RayHit {
+    // Along the normalized ray, in the space of the origin.
+    pub distance: f32,
+    // Voxel in the space of the entry.
+    pub pos: IVec3,
+    // In the space of the origin. Rays that start inside of a voxel get the negative direction.
+    pub normal: Vec3A,
+    pub material: u8,
+}
+
+/**
+Walks the 4x4x4 cells of every node along the ray in voxel space.
+Cells that are not set in the pop mask are stepped over, so empty space is skipped on every level.
+*/
+struct DAG64Ray<N: Fn(u32) -> VoxelDAG64Node, D: Fn(u32) -> u8> {
+    get_node: N,
+    get_data: D,
+    origin: Vec3A,
+    dir: Vec3A,
+    step: IVec3,
+}
+
+impl<N: Fn(u32) -> VoxelDAG64Node, D: Fn(u32) -> u8> DAG64Ray<N, D> {
+    fn new(get_node: N, get_data: D, origin: Vec3A, dir: Vec3A) -> Self {
+        Self {
+            get_node,
+            get_data,
+            origin,
+            dir,
+            step: IVec3::from_array(dir.to_array().map(|v| if v < 0.0 { -1 } else { 1 })),
+        }
+    }
+
+    // The hit distance is measured in lengths of dir.
+    fn cast(&self, entry: &DAG64Entry, max: f32) -> Option<DAG64RayHit> {
+        let size = entry.get_size() as i32;
+        let min = entry.offset.as_vec3a();
+        let (enter, exit) = AABB3::new(min, min + size as f32).ray_range(self.origin, self.dir)?;
+
+        let t = enter.max(0.0);
+        let end = exit.min(max);
+        if t > end {
+            return None;
+        }
+
+        // Rays that start inside of the entry did not enter through a face.
+        let axis = (enter >= 0.0).then(|| {
+            (0..3)
+                .filter(|i| self.dir[*i] != 0.0)
+                .max_by(|a, b| {
+                    let enter = |i: usize| ((if self.dir[i] > 0.0 { min[i] } else { min[i] + size as f32 }) - self.origin[i]) / self.dir[i];
+                    enter(*a).total_cmp(&enter(*b))
+                })
+        }).flatten();
+
+        self.cast_node((self.get_node)(entry.root_index), entry.levels, entry.offset, t, end, axis)
+    }
+
+    fn cast_node(&self, node: VoxelDAG64Node, level: u8, min: IVec3, enter: f32, exit: f32, mut axis: Option<usize>) -> Option<DAG64RayHit> {
+        if node.is_empty() || level == 0 {
+            return None;
+        }
+
+        let child_size = get_voxel_size(level - 1);
+        let o = (self.origin - min.as_vec3a()) / child_size as f32;
+        let d = self.dir / child_size as f32;
+
+        let t_delta = d.abs().recip();
+        let mut cell = (o + d * enter).floor().as_ivec3().clamp(IVec3::ZERO, IVec3::splat(3));
+        let mut t_next: [f32; 3] = std::array::from_fn(|i| {
+            if d[i] > 0.0 {
+                ((cell[i] + 1) as f32 - o[i]) / d[i]
+            } else if d[i] < 0.0 {
+                (cell[i] as f32 - o[i]) / d[i]
+            } else {
+                f32::INFINITY
+            }
+        });
+
+        let mut t = enter;
+        loop {
+            let i = (cell.x * 16 + cell.y * 4 + cell.z) as u32;
+            let next_axis = (0..3).min_by(|a, b| t_next[*a].total_cmp(&t_next[*b])).unwrap();
+
+            if let Some(index) = node.get_index_for_child(i) {
+                let cell_min = min + cell * child_size;
+                if node.is_leaf() {
+                    return Some(self.hit(t, axis, cell_min, child_size, (self.get_data)(index)));
+                }
+
+                let cell_exit = t_next[next_axis].min(exit);
+                if let Some(hit) = self.cast_node((self.get_node)(index), level - 1, cell_min, t, cell_exit, axis) {
+                    return Some(hit);
+                }
+            }
+
+            t = t_next[next_axis];
+            if t > exit {
+                return None;
+            }
+
+            cell[next_axis] += self.step[next_axis];
+            if cell[next_axis] < 0 || cell[next_axis] > 3 {
+                return None;
+            }
+
+            t_next[next_axis] += t_delta[next_axis];
+            axis = Some(next_axis);
+        }
+    }
+
+    // Leaves above level 1 hold uniform blocks, the hit voxel is the one of the block the ray enters.
+    fn hit(&self, t: f32, axis: Option<usize>, block_min: IVec3, block_size: i32, material: u8) -> DAG64RayHit {
+        let p = self.origin + self.dir * t;
+        let mut pos = p.floor().as_ivec3().clamp(block_min, block_min + block_size - 1);
+
+        let normal = match axis {
+            Some(axis) => {
+                pos[axis] = if self.step[axis] > 0 { block_min[axis] } else { block_min[axis] + block_size - 1 };
+
+                let mut normal = Vec3A::ZERO;
+                normal[axis] = -self.step[axis] as f32;
+                normal
+            },
+            None => -self.dir.normalize_or_zero(),
+        };
+
+        DAG64RayHit {
+            distance: t,
+            pos,
+            normal,
+            material,
+        }
+    }
+}
+
+fn raycast_entry(get_node: impl Fn(u32) -> VoxelDAG64Node, get_data: impl Fn(u32) -> u8, entry: &DAG64Entry, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+    let dir = dir.normalize_or_zero();
+    if dir == Vec3A::ZERO {
+        return None;
+    }
+
+    DAG64Ray::new(get_node, get_data, origin, dir).cast(entry, max_dist)
+}
+
+/**
+The object matrix is the one given to calc_mat, so origin and dir are in the same space as for the renderer.
+The ray is moved into voxel space with the matrix of calc_mat, the distance stays in the space of the origin.
+*/
+fn raycast_entry_with_mat(get_node: impl Fn(u32) -> VoxelDAG64Node, get_data: impl Fn(u32) -> u8, entry: &DAG64Entry, mat: Mat4, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+    let dir = dir.normalize_or_zero();
+    if dir == Vec3A::ZERO {
+        return None;
+    }
+
+    // calc_mat maps into [1, 2) and is transposed for the shaders.
+    let size = entry.get_size() as f32;
+    let to_voxel = Mat4::from_translation(entry.offset.as_vec3())
+        * Mat4::from_scale(Vec3::splat(size))
+        * Mat4::from_translation(Vec3::splat(-1.0))
+        * entry.calc_mat(mat).transpose();
+
+    let local_origin = to_voxel.transform_point3a(origin);
+    let local_dir = to_voxel.transform_vector3a(dir);
+
+    let hit = DAG64Ray::new(get_node, get_data, local_origin, local_dir).cast(entry, max_dist)?;
+    Some(DAG64RayHit {
+        normal: to_voxel.transpose().transform_vector3a(hit.normal).normalize_or_zero(),
+        ..hit
+    })
+}
+
+impl VoxelDAG64 {
+    // Origin and dir are in the voxel space of the entry.
+    pub fn raycast(&self, key: DAG64EntryKey, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+        raycast_entry(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), origin, dir, max_dist)
+    }
+
+    pub fn raycast_with_mat(&self, key: DAG64EntryKey, mat: Mat4, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+        raycast_entry_with_mat(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), mat, origin, dir, max_dist)
+    }
+}
+
+impl ParallelVoxelDAG64 {
+    // Origin and dir are in the voxel space of the entry.
+    pub fn raycast(&self, key: DAG64EntryKey, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+        raycast_entry(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), origin, dir, max_dist)
+    }
+
+    pub fn raycast_with_mat(&self, key: DAG64EntryKey, mat: Mat4, origin: Vec3A, dir: Vec3A, max_dist: f32) -> Option<DAG64RayHit> {
+        raycast_entry_with_mat(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), mat, origin, dir, max_dist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Mat4, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::shader_constants::VOXELS_PER_SHADER_UNIT, voxel::dag64::{entry::DAG64EntryKey, lod_heuristic::LODHeuristicNone, single::VoxelDAG64}};
+
+    fn new_dag() -> (VoxelDAG64, DAG64EntryKey) {
+        let csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 6.0, 1);
+        let mut dag = VoxelDAG64::new(100000, 1000);
+        let key = dag.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+        (dag, key)
+    }
+
+    // The first set voxel on the x axis, found without the ray.
+    fn first_voxel_on_x(dag: &VoxelDAG64, key: DAG64EntryKey) -> i32 {
+        (-64..64).find(|x| dag.get_voxel(key, IVec3::new(*x, 0, 0)) != 0).unwrap()
+    }
+
+    #[test]
+    fn hit_from_outside() {
+        let (dag, key) = new_dag();
+        let x = first_voxel_on_x(&dag, key);
+
+        // The origin lies outside of the root node of the entry.
+        let origin = vec3a(-100.0, 0.5, 0.5);
+        assert!(origin.x < dag.get_entry(key).offset.x as f32);
+
+        let hit = dag.raycast(key, origin, Vec3A::X, 1000.0).unwrap();
+        assert_eq!(hit.pos, IVec3::new(x, 0, 0));
+        assert_eq!(hit.material, 1);
+        assert_eq!(hit.normal, Vec3A::NEG_X);
+        assert!((hit.distance - (x as f32 + 100.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn hit_from_above() {
+        let (dag, key) = new_dag();
+        let z = (-64..64).rev().find(|z| dag.get_voxel(key, IVec3::new(0, 0, *z)) != 0).unwrap();
+
+        let hit = dag.raycast(key, vec3a(0.5, 0.5, 50.0), Vec3A::NEG_Z * 2.0, 1000.0).unwrap();
+        assert_eq!(hit.pos, IVec3::new(0, 0, z));
+        assert_eq!(hit.normal, Vec3A::Z);
+        assert!((hit.distance - (50.0 - (z + 1) as f32)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn miss() {
+        let (dag, key) = new_dag();
+        let x = first_voxel_on_x(&dag, key);
+
+        assert!(dag.raycast(key, vec3a(-100.0, 40.0, 0.5), Vec3A::X, 1000.0).is_none());
+        assert!(dag.raycast(key, vec3a(-100.0, 0.5, 0.5), Vec3A::NEG_X, 1000.0).is_none());
+        assert!(dag.raycast(key, vec3a(-100.0, 0.5, 0.5), Vec3A::ZERO, 1000.0).is_none());
+
+        // Stops before the surface.
+        assert!(dag.raycast(key, vec3a(-100.0, 0.5, 0.5), Vec3A::X, x as f32 + 99.0).is_none());
+    }
+
+    #[test]
+    fn start_inside() {
+        let (dag, key) = new_dag();
+
+        let hit = dag.raycast(key, vec3a(0.5, 0.5, 0.5), Vec3A::Y, 1000.0).unwrap();
+        assert_eq!(hit.pos, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+        assert_eq!(hit.normal, Vec3A::NEG_Y);
+    }
+
+    #[test]
+    fn hit_with_mat() {
+        let (dag, key) = new_dag();
+        let voxel_hit = dag.raycast(key, vec3a(-100.0, 0.5, 0.5), Vec3A::X, 1000.0).unwrap();
+
+        // Without scale one shader unit is VOXELS_PER_SHADER_UNIT voxels.
+        let scale = VOXELS_PER_SHADER_UNIT as f32;
+        let translation = vec3a(3.0, -2.0, 7.0);
+        let mat = Mat4::from_translation(translation.into());
+        let origin = translation + vec3a(-100.0, 0.5, 0.5) / scale;
+
+        let hit = dag.raycast_with_mat(key, mat, origin, Vec3A::X, 1000.0).unwrap();
+        assert_eq!(hit.pos, voxel_hit.pos);
+        assert_eq!(hit.material, voxel_hit.material);
+        assert!(hit.normal.abs_diff_eq(Vec3A::NEG_X, 1e-4));
+        assert!((hit.distance - voxel_hit.distance / scale).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,277 @@
+use octa_force::glam::IVec3;
+
+use crate::{util::aabb::IAABB3, voxel::{dag64::{entry::{DAG64Entry, DAG64EntryKey}, node::VoxelDAG64Node, parallel::ParallelVoxelDAG64, single::VoxelDAG64, util::get_voxel_size}, palette::palette::MATERIAL_ID_NONE}};
+
+// A box of voxels with the same material, already clipped to the queried region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DAG64Brick {
+    pub min: IVec3,
+    pub size: IVec3,
+    pub material: u8,
+}
+
+impl DAG64Brick {
+    pub fn max(&self) -> IVec3 {
+        self.min + self.size
+    }
+
+    pub fn iter_voxels(&self) -> impl Iterator<Item = IVec3> + '_ {
+        let min = self.min;
+        let max = self.max();
+        (min.x..max.x).flat_map(move |x| {
+            (min.y..max.y).flat_map(move |y| (min.z..max.z).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+}
+
+fn get_child_pos(i: u32) -> IVec3 {
+    IVec3::new((i / 16) as i32, (i / 4 % 4) as i32, (i % 4) as i32)
+}
+
+fn get_voxel_in_entry(get_node: impl Fn(u32) -> VoxelDAG64Node, get_data: impl Fn(u32) -> u8, entry: &DAG64Entry, pos: IVec3) -> u8 {
+    let rel = pos - entry.offset;
+    if rel.cmplt(IVec3::ZERO).any() || rel.cmpge(IVec3::splat(entry.get_size() as i32)).any() {
+        return MATERIAL_ID_NONE;
+    }
+
+    let mut node = get_node(entry.root_index);
+    let mut level = entry.levels;
+    let mut min = entry.offset;
+    while level > 0 {
+        let child_size = get_voxel_size(level - 1);
+        let cell = (pos - min) / child_size;
+        let i = (cell.x * 16 + cell.y * 4 + cell.z) as u32;
+
+        let Some(index) = node.get_index_for_child(i) else {
+            return MATERIAL_ID_NONE;
+        };
+
+        if node.is_leaf() {
+            return get_data(index);
+        }
+
+        node = get_node(index);
+        min += cell * child_size;
+        level -= 1;
+    }
+
+    MATERIAL_ID_NONE
+}
+
+struct DAG64RegionStackEntry {
+    node: VoxelDAG64Node,
+    level: u8,
+    min: IVec3,
+    next_child: u32,
+}
+
+/**
+Walks the nodes depth first and yields every occupied child that overlaps the region.
+Children of leaves are uniform blocks, so they come out as one brick.
+On level 1 the voxels of a leaf are merged into runs along z, as long as the material stays the same.
+*/
+pub struct DAG64RegionIter<N: Fn(u32) -> VoxelDAG64Node, D: Fn(u32) -> u8> {
+    get_node: N,
+    get_data: D,
+    region: IAABB3,
+    stack: Vec<DAG64RegionStackEntry>,
+}
+
+impl<N: Fn(u32) -> VoxelDAG64Node, D: Fn(u32) -> u8> DAG64RegionIter<N, D> {
+    fn new(get_node: N, get_data: D, entry: &DAG64Entry, region: IAABB3) -> Self {
+        let mut stack = vec![];
+
+        let entry_aabb = IAABB3::new(entry.offset, entry.offset + entry.get_size() as i32);
+        if entry.levels > 0 && entry_aabb.collides_aabb(region) {
+            stack.push(DAG64RegionStackEntry {
+                node: get_node(entry.root_index),
+                level: entry.levels,
+                min: entry.offset,
+                next_child: 0,
+            });
+        }
+
+        Self {
+            get_node,
+            get_data,
+            region,
+            stack,
+        }
+    }
+
+    fn get_next_occupied(node: &VoxelDAG64Node, from: u32) -> Option<u32> {
+        if from >= 64 {
+            return None;
+        }
+
+        let mask = node.pop_mask >> from;
+        (mask != 0).then(|| from + mask.trailing_zeros())
+    }
+
+    fn clip(&self, min: IVec3, max: IVec3, material: u8) -> DAG64Brick {
+        let min = min.max(self.region.min());
+        let max = max.min(self.region.max());
+
+        DAG64Brick {
+            min,
+            size: max - min,
+            material,
+        }
+    }
+}
+
+impl<N: Fn(u32) -> VoxelDAG64Node, D: Fn(u32) -> u8> Iterator for DAG64RegionIter<N, D> {
+    type Item = DAG64Brick;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let top = self.stack.last_mut()?;
+            let Some(i) = Self::get_next_occupied(&top.node, top.next_child) else {
+                self.stack.pop();
+                continue;
+            };
+            top.next_child = i + 1;
+
+            let node = top.node;
+            let level = top.level;
+            let child_size = get_voxel_size(level - 1);
+            let child_min = top.min + get_child_pos(i) * child_size;
+            let mut child_max = child_min + child_size;
+            if !IAABB3::new(child_min, child_max).collides_aabb(self.region) {
+                continue;
+            }
+
+            let index = node.get_index_in_children_unchecked(i) + node.index();
+            if !node.is_leaf() {
+                self.stack.push(DAG64RegionStackEntry {
+                    node: (self.get_node)(index),
+                    level: level - 1,
+                    min: child_min,
+                    next_child: 0,
+                });
+                continue;
+            }
+
+            let material = (self.get_data)(index);
+            if child_size == 1 {
+                // The data of the next occupied child in the same row directly follows.
+                let mut next = i + 1;
+                let mut next_index = index + 1;
+                while next % 4 != 0
+                    && node.is_occupied(next)
+                    && child_max.z < self.region.max().z
+                    && (self.get_data)(next_index) == material
+                {
+                    child_max.z += 1;
+                    next += 1;
+                    next_index += 1;
+                }
+
+                let top = self.stack.last_mut().unwrap();
+                top.next_child = next;
+            }
+
+            return Some(self.clip(child_min, child_max, material));
+        }
+    }
+}
+
+impl VoxelDAG64 {
+    // MATERIAL_ID_NONE for empty voxels and voxels outside of the entry.
+    pub fn get_voxel(&self, key: DAG64EntryKey, pos: IVec3) -> u8 {
+        get_voxel_in_entry(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), pos)
+    }
+
+    // The region is [min, max) in the voxel space of the entry.
+    pub fn iter_region(&self, key: DAG64EntryKey, region: IAABB3) -> impl Iterator<Item = DAG64Brick> + '_ {
+        DAG64RegionIter::new(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), region)
+    }
+}
+
+impl ParallelVoxelDAG64 {
+    // MATERIAL_ID_NONE for empty voxels and voxels outside of the entry.
+    pub fn get_voxel(&self, key: DAG64EntryKey, pos: IVec3) -> u8 {
+        get_voxel_in_entry(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), pos)
+    }
+
+    // The region is [min, max) in the voxel space of the entry.
+    pub fn iter_region(&self, key: DAG64EntryKey, region: IAABB3) -> impl Iterator<Item = DAG64Brick> + '_ {
+        DAG64RegionIter::new(|i| self.nodes.get(i), |i| self.data.get(i), &self.get_entry(key), region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::aabb::IAABB3, voxel::dag64::{entry::DAG64Entry, lod_heuristic::LODHeuristicNone, node::VoxelDAG64Node, single::VoxelDAG64}};
+
+    use super::{DAG64Brick, DAG64RegionIter};
+
+    // One leaf of 4^3 voxels. Row x = 0, y = 0 has 5 5 7 _, row y = 1 is full of 5 and the last voxel is 9.
+    const DATA: [u8; 8] = [5, 5, 7, 5, 5, 5, 5, 9];
+
+    fn iter_leaf(region: IAABB3) -> Vec<DAG64Brick> {
+        let node = VoxelDAG64Node::single(true, 0, 0b1111_0111 | 1 << 63);
+        let entry = DAG64Entry { levels: 1, root_index: 0, offset: IVec3::ZERO };
+        DAG64RegionIter::new(|_| node, |i| DATA[i as usize], &entry, region).collect()
+    }
+
+    fn brick(min: [i32; 3], size: [i32; 3], material: u8) -> DAG64Brick {
+        DAG64Brick { min: IVec3::from_array(min), size: IVec3::from_array(size), material }
+    }
+
+    #[test]
+    fn merge_runs() {
+        // Runs stop at a new material and at the end of a row, empty voxels are skipped.
+        assert_eq!(iter_leaf(IAABB3::new(IVec3::ZERO, IVec3::splat(4))), vec![
+            brick([0, 0, 0], [1, 1, 2], 5),
+            brick([0, 0, 2], [1, 1, 1], 7),
+            brick([0, 1, 0], [1, 1, 4], 5),
+            brick([3, 3, 3], [1, 1, 1], 9),
+        ]);
+    }
+
+    #[test]
+    fn clip_to_region() {
+        assert_eq!(iter_leaf(IAABB3::new(IVec3::new(0, 0, 1), IVec3::new(4, 4, 3))), vec![
+            brick([0, 0, 1], [1, 1, 1], 5),
+            brick([0, 0, 2], [1, 1, 1], 7),
+            brick([0, 1, 1], [1, 1, 2], 5),
+        ]);
+
+        assert!(iter_leaf(IAABB3::new(IVec3::new(1, 0, 0), IVec3::new(3, 4, 4))).is_empty());
+        assert!(iter_leaf(IAABB3::new(IVec3::splat(4), IVec3::splat(8))).is_empty());
+    }
+
+    #[test]
+    fn matches_get_voxel() {
+        let csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::new(3.0, 1.0, -2.0), 7.0, 1);
+        let mut dag = VoxelDAG64::new(100000, 1000);
+        let key = dag.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+
+        let region = IAABB3::new(IVec3::new(-6, -8, -12), IVec3::new(12, 6, 3));
+        let mut voxels = vec![];
+        for brick in dag.iter_region(key, region) {
+            assert!(brick.size.cmpgt(IVec3::ZERO).all());
+            assert!(brick.min.cmpge(region.min()).all() && brick.max().cmple(region.max()).all(), "{brick:?} is not clipped");
+            voxels.extend(brick.iter_voxels().map(|pos| (pos, brick.material)));
+        }
+
+        let mut expected = vec![];
+        for x in region.min().x..region.max().x {
+            for y in region.min().y..region.max().y {
+                for z in region.min().z..region.max().z {
+                    let pos = IVec3::new(x, y, z);
+                    let mat = dag.get_voxel(key, pos);
+                    if mat != 0 {
+                        expected.push((pos, mat));
+                    }
+                }
+            }
+        }
+
+        voxels.sort_by_key(|(pos, _)| pos.to_array());
+        assert_eq!(voxels, expected);
+    }
+}
@@ -21,7 +21,8 @@ impl VoxelDAG64 {
             // middle of the new level.
             let diff: IVec3 = (model_center - tree_aabb.min()).ve_into(); 
             let child_pos = (diff / size) + 2;
-            let child_index = child_pos.as_uvec3().dot(UVec3::new(1, 4, 16));
+            // Same child order as get_dag_node_children and GetNodeCellIndex in the shaders.
+            let child_index = child_pos.as_uvec3().dot(UVec3::new(16, 4, 1));
 
             let new_root = VoxelDAG64Node::single(false, entry_data.root_index, 1 << child_index as u64);
             entry_data.root_index = self.nodes.push(&[new_root])?;
@@ -35,3 +36,28 @@ impl VoxelDAG64 {
         Ok(entry_data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{vec3a, IVec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, voxel::dag64::{lod_heuristic::LODHeuristicNone, single::VoxelDAG64}};
+
+    #[test]
+    fn expand_keeps_old_voxels() {
+        let mut csg = CSGTree::<u8, IVec3, i32, 3>::new_sphere_float(Vec3A::ZERO, 3.0, 1);
+        let mut dag = VoxelDAG64::new(100000, 1000);
+        let key = dag.add_pos_query_volume(&csg, &LODHeuristicNone {}).unwrap();
+        let levels = dag.get_entry(key).levels;
+
+        // The new sphere lies outside of the entry, so the root has to grow.
+        csg.reset_changed_bounds();
+        csg.union_sphere(vec3a(40.0, 0.0, 0.0), 3.0, 2);
+        let key = dag.update_pos_query_volume(&csg, &LODHeuristicNone {}, key).unwrap();
+
+        assert!(dag.get_entry(key).levels > levels);
+        assert_eq!(dag.get_voxel(key, IVec3::ZERO), 1);
+        assert_eq!(dag.get_voxel(key, IVec3::new(40, 0, 0)), 2);
+        assert_eq!(dag.get_voxel(key, IVec3::new(20, 0, 0)), 0);
+    }
+}
@@ -0,0 +1,223 @@
+use octa_force::glam::{uvec3, UVec3, Vec3A};
+
+use crate::{util::math::to_1d, voxel::palette::palette::MATERIAL_ID_NONE};
+
+use super::VoxelGrid;
+
+// Squared distances are initialized with this instead of infinity so the transform does not produce nans.
+const EDT_FAR: f32 = 1e20;
+
+/**
+Signed euclidean distance of every voxel center to the closest voxel center on the other side of the surface.
+The surface is half a voxel from the centers, so voxels at the surface have a distance of +-0.5.
+Everything outside of the grid counts as empty.
+*/
+#[derive(Clone, Default)]
+pub struct VoxelGridDistance {
+    pub values: Vec<f32>,
+    pub size: UVec3,
+}
+
+impl VoxelGridDistance {
+    pub fn new(data: &[u8], size: UVec3) -> Self {
+        // One empty voxel on every side so solid voxels at the border see the outside.
+        let padded_size = size + 2;
+        let padded = |set: bool| {
+            let mut field = vec![EDT_FAR; padded_size.element_product() as usize];
+            for x in 0..padded_size.x {
+                for y in 0..padded_size.y {
+                    for z in 0..padded_size.z {
+                        let pos = uvec3(x, y, z);
+                        let inside = pos.cmpge(UVec3::ONE).all() && pos.cmple(size).all()
+                            && data[to_1d(pos - 1, size)] != MATERIAL_ID_NONE;
+                        if inside == set {
+                            field[to_1d(pos, padded_size)] = 0.0;
+                        }
+                    }
+                }
+            }
+
+            squared_distance_transform(&mut field, padded_size);
+            field
+        };
+
+        let outside = padded(true);
+        let inside = padded(false);
+
+        let mut values = vec![0.0; size.element_product() as usize];
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let pos = uvec3(x, y, z);
+                    let index = to_1d(pos + 1, padded_size);
+                    values[to_1d(pos, size)] = if data[to_1d(pos, size)] != MATERIAL_ID_NONE {
+                        0.5 - inside[index].sqrt()
+                    } else if outside[index] >= EDT_FAR {
+                        f32::MAX
+                    } else {
+                        outside[index].sqrt() - 0.5
+                    };
+                }
+            }
+        }
+
+        Self { values, size }
+    }
+
+    pub fn get(&self, pos: UVec3) -> f32 {
+        self.values[to_1d(pos, self.size)]
+    }
+
+    /**
+    Trilinear interpolation between the voxel centers in grid space.
+    Outside of the grid the distance to the grid is combined with the distance at the closest border voxel.
+    */
+    pub fn sample(&self, pos: Vec3A) -> f32 {
+        let last = (self.size - 1).as_vec3a();
+        let local = pos - 0.5;
+        let clamped = local.clamp(Vec3A::ZERO, last);
+
+        let a = clamped.floor().as_uvec3();
+        let b = (a + 1).min(self.size - 1);
+        let t = clamped - clamped.floor();
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut p = a;
+            for i in 0..3 {
+                if corner & (1 << i) != 0 {
+                    p[i] = b[i];
+                    weight *= t[i];
+                } else {
+                    weight *= 1.0 - t[i];
+                }
+            }
+
+            if weight > 0.0 {
+                value += self.get(p) * weight;
+            }
+        }
+
+        let outside = (local - clamped).length();
+        if outside > 0.0 {
+            (outside * outside + value.max(0.0).powi(2)).sqrt()
+        } else {
+            value
+        }
+    }
+}
+
+impl VoxelGrid {
+    // Computed on first use and dropped by update_summary.
+    pub fn get_distance_field(&self) -> &VoxelGridDistance {
+        self.distance.get_or_init(|| VoxelGridDistance::new(&self.data, self.size))
+    }
+}
+
+// Separable exact transform after Felzenszwalb and Huttenlocher. Runs the 1D transform along every axis.
+fn squared_distance_transform(field: &mut [f32], size: UVec3) {
+    let max_len = size.max_element() as usize;
+    let mut line = vec![0.0; max_len];
+    let mut out = vec![0.0; max_len];
+    let mut v = vec![0; max_len];
+    let mut z = vec![0.0; max_len + 1];
+
+    for axis in 0..3 {
+        let len = size[axis] as usize;
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for i in 0..size[a] {
+            for j in 0..size[b] {
+                let index = |k: usize| {
+                    let mut pos = UVec3::ZERO;
+                    pos[axis] = k as u32;
+                    pos[a] = i;
+                    pos[b] = j;
+                    to_1d(pos, size)
+                };
+
+                for k in 0..len {
+                    line[k] = field[index(k)];
+                }
+
+                distance_transform_1d(&line[..len], &mut out[..len], &mut v, &mut z);
+
+                for k in 0..len {
+                    field[index(k)] = out[k];
+                }
+            }
+        }
+    }
+}
+
+// Lower envelope of the parabolas rooted at every sample.
+fn distance_transform_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let n = f.len();
+    let parabola = |q: usize| f[q] + (q * q) as f32;
+
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    for q in 1..n {
+        let mut s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f32;
+        while s <= z[k] {
+            k -= 1;
+            s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f32;
+        }
+
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for q in 0..n {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+
+        let diff = q as f32 - v[k] as f32;
+        d[q] = (diff * diff + f[v[k]]).min(EDT_FAR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{uvec3, UVec3, Vec3A};
+
+    use crate::{volume::VolumeQureyDistance, voxel::grid::VoxelGrid};
+
+    #[test]
+    fn voxel_centers() {
+        let grid = VoxelGrid::from_data(UVec3::splat(4), vec![1; 64]);
+        let field = grid.get_distance_field();
+        assert_eq!(field.get(uvec3(0, 0, 0)), -0.5);
+        assert_eq!(field.get(uvec3(1, 1, 1)), -1.5);
+        assert_eq!(field.get(uvec3(3, 1, 2)), -0.5);
+
+        let mut data = vec![0; 64];
+        data[0] = 1;
+        let grid = VoxelGrid::from_data(UVec3::splat(4), data);
+        let field = grid.get_distance_field();
+        assert_eq!(field.get(uvec3(0, 0, 0)), -0.5);
+        assert_eq!(field.get(uvec3(3, 0, 0)), 2.5);
+        assert!((field.get(uvec3(1, 1, 1)) - (3.0_f32.sqrt() - 0.5)).abs() < 1e-5);
+    }
+
+    // Close to the surface the field is exact up to half a voxel.
+    #[test]
+    fn bounded_samples() {
+        let grid = VoxelGrid::from_data(UVec3::splat(4), vec![1; 64]);
+        assert!((grid.get_distance_bounded(Vec3A::new(10.0, 2.0, 2.0), 100.0) - 6.0).abs() <= 0.5);
+        assert!((grid.get_distance_bounded(Vec3A::new(2.0, 2.0, 2.0), 100.0) + 2.0).abs() <= 0.5);
+        assert_eq!(grid.get_distance_bounded(Vec3A::new(10.0, 2.0, 2.0), 3.0), 3.0);
+        assert_eq!(grid.get_distance_bounded(Vec3A::new(2.0, 2.0, 2.0), 1.0), -1.0);
+
+        let grid = VoxelGrid::from_data(UVec3::splat(4), vec![0; 64]);
+        assert_eq!(grid.get_distance_bounded(Vec3A::new(2.0, 2.0, 2.0), 5.0), 5.0);
+    }
+}
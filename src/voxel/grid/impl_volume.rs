@@ -1,8 +1,8 @@
 use octa_force::glam::{IVec3, UVec3, Vec3, Vec3A};
 
-use crate::{util::{aabb::AABB, math::to_1d, math_config::MC, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeQureyPosValue}, voxel::palette::palette::MATERIAL_ID_NONE};
+use crate::{util::{aabb::{AABB, AABB3}, math::to_1d, math_config::MC, number::Nu, vector::Ve}, volume::{VolumeBounds, VolumeGradient, VolumeQureyAABB, VolumeQureyAABBResult, VolumeQureyDistance, VolumeQureyPosValid, VolumeQureyPosValue, VolumeQureyRay, VolumeRayHit}, voxel::palette::palette::MATERIAL_ID_NONE};
 
-use super::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid, VoxelGrid};
+use super::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid, transform::VoxelGridTransform, VoxelGrid};
 
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for VoxelGrid {
     fn calculate_bounds(&mut self) {}
@@ -24,7 +24,7 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for OffsetVoxelGr
     fn calculate_bounds(&mut self) {}
 
     fn get_bounds(&self) -> AABB<V, T, D> {
-        AABB::new(V::ve_from(self.offset), V::ve_from(self.grid.size.as_ivec3() + self.offset - 1))
+        grid_bounds(self.grid.size, self.offset, &self.transform)
     }
 
     fn get_size(&self) -> V {
@@ -40,7 +40,7 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for SharedVoxelGr
     fn calculate_bounds(&mut self) {}
 
     fn get_bounds(&self) -> AABB<V, T, D> {
-        AABB::new(V::ve_from(self.offset), V::ve_from(self.grid.size.as_ivec3() + self.offset - 1))
+        grid_bounds(self.grid.size, self.offset, &self.transform)
     }
 
     fn get_size(&self) -> V {
@@ -52,11 +52,36 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeBounds<V, T, D> for SharedVoxelGr
     }
 }
 
+// The max is inclusive like the bounds of a VoxelGrid.
+fn grid_bounds<V: Ve<T, D>, T: Nu, const D: usize>(size: UVec3, offset: IVec3, transform: &Option<VoxelGridTransform>) -> AABB<V, T, D> {
+    match transform {
+        None => AABB::new(V::ve_from(offset), V::ve_from(size.as_ivec3() + offset - 1)),
+        Some(transform) if T::EPSILON == T::ZERO => {
+            // Integer positions are in the grid when they round into it, see grid_value.
+            let aabb = transform.to_world_aabb(AABB3::new(offset.as_vec3a() - 0.5, (offset + size.as_ivec3()).as_vec3a() - 0.5));
+            AABB::new(V::ve_from(aabb.min().ceil().as_ivec3()), V::ve_from(aabb.max().floor().as_ivec3()))
+        },
+        Some(transform) => {
+            let aabb = transform.to_world_aabb(AABB3::new(offset.as_vec3a(), (offset + size.as_ivec3()).as_vec3a()));
+            AABB::new(V::ve_from(aabb.min().floor().as_ivec3()), V::ve_from(aabb.max().ceil().as_ivec3() - 1))
+        },
+    }
+}
+
+// Same as the CSG tree does before it samples a grid. Integer positions are rounded to the closest voxel, float positions are in the voxel they fall into.
+fn to_grid_voxel<T: Nu>(pos: Vec3A) -> Vec3A {
+    if T::EPSILON == T::ZERO {
+        pos.round()
+    } else {
+        pos.floor()
+    }
+}
+
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for VoxelGrid {
     fn get_value(&self, pos: V) -> u8 {
         if pos.cmplt_any(V::ZERO) || pos.cmpge_any(V::ve_from(self.size)) {
             return MATERIAL_ID_NONE;
-        } 
+        }
 
         self.get(pos.ve_into())
     }
@@ -64,16 +89,365 @@ impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for VoxelG
 
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for OffsetVoxelGrid {
     fn get_value(&self, pos: V) -> u8 {
-        let pos = pos - V::ve_from(self.offset);
-
-        self.grid.get_value(pos)
+        grid_value(&self.grid, self.offset, &self.transform, pos)
     }
 }
 
 impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValue<V, T, D> for SharedVoxelGrid {
     fn get_value(&self, pos: V) -> u8 {
-        let pos = pos - V::ve_from(self.offset);
+        grid_value(&self.grid, self.offset, &self.transform, pos)
+    }
+}
+
+fn grid_value<V: Ve<T, D>, T: Nu, const D: usize>(grid: &VoxelGrid, offset: IVec3, transform: &Option<VoxelGridTransform>, pos: V) -> u8 {
+    match transform {
+        None => <VoxelGrid as VolumeQureyPosValue<V, T, D>>::get_value(grid, pos - V::ve_from(offset)),
+        Some(transform) => {
+            let pos = transform.to_grid_pos(pos.ve_into()) - offset.as_vec3a();
+            <VoxelGrid as VolumeQureyPosValue<Vec3A, f32, 3>>::get_value(grid, to_grid_voxel::<T>(pos))
+        },
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for VoxelGrid {
+    fn is_position_valid(&self, pos: V) -> bool {
+        <Self as VolumeQureyPosValue<V, T, D>>::get_value(self, pos) != MATERIAL_ID_NONE
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for OffsetVoxelGrid {
+    fn is_position_valid(&self, pos: V) -> bool {
+        <Self as VolumeQureyPosValue<V, T, D>>::get_value(self, pos) != MATERIAL_ID_NONE
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyPosValid<V, T, D> for SharedVoxelGrid {
+    fn is_position_valid(&self, pos: V) -> bool {
+        <Self as VolumeQureyPosValue<V, T, D>>::get_value(self, pos) != MATERIAL_ID_NONE
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for VoxelGrid {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        grid_aabb_value(self, IVec3::ZERO, &None, aabb)
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for OffsetVoxelGrid {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        grid_aabb_value(&self.grid, self.offset, &self.transform, aabb)
+    }
+}
+
+impl<V: Ve<T, D>, T: Nu, const D: usize> VolumeQureyAABB<V, T, D> for SharedVoxelGrid {
+    fn get_aabb_value(&self, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+        grid_aabb_value(&self.grid, self.offset, &self.transform, aabb)
+    }
+}
+
+// The aabb covers the voxels in [min, max).
+// With a transform all voxels touched by the aabb in grid space are checked, so the result is conservative.
+fn grid_aabb_value<V: Ve<T, D>, T: Nu, const D: usize>(grid: &VoxelGrid, offset: IVec3, transform: &Option<VoxelGridTransform>, aabb: AABB<V, T, D>) -> VolumeQureyAABBResult {
+    let min: Vec3A = aabb.min().ve_into();
+    let max: Vec3A = aabb.max().ve_into();
+
+    let (min, mut max) = match transform {
+        None => (min.floor().as_ivec3(), max.ceil().as_ivec3()),
+        Some(transform) => {
+            let aabb = transform.to_grid_aabb(AABB3::new(min, max));
+            (to_grid_voxel::<T>(aabb.min()).as_ivec3(), to_grid_voxel::<T>(aabb.max()).as_ivec3() + 1)
+        },
+    };
+
+    // 2D queries look at the slice at z = 0.
+    if D == 2 {
+        max.z = min.z + 1;
+    }
+
+    grid.get_region_value(min - offset, max - offset)
+}
+
+// Central differences of the occupancy. Points from set voxels towards empty ones.
+fn occupancy_gradient<V: Ve<f32, D>, const D: usize>(pos: V, is_set: impl Fn(V) -> bool) -> V {
+    V::new(std::array::from_fn(|i| {
+        let mut offset = [0.0; D];
+        offset[i] = 1.0;
+        let offset = V::new(offset);
+
+        (is_set(pos - offset) as u8 as f32 - is_set(pos + offset) as u8 as f32) * 0.5
+    }))
+}
+
+// With a transform the differences are taken in grid space and the result is mapped back like a normal.
+fn grid_gradient<V: Ve<f32, D>, const D: usize>(grid: &VoxelGrid, offset: IVec3, transform: &Option<VoxelGridTransform>, pos: V) -> V {
+    match transform {
+        None => occupancy_gradient(pos, |pos| grid_value::<V, f32, D>(grid, offset, &None, pos) != MATERIAL_ID_NONE),
+        Some(transform) => {
+            let pos = transform.to_grid_pos(pos.ve_into());
+            let grad = occupancy_gradient(pos, |pos| grid_value::<Vec3A, f32, 3>(grid, offset, &None, pos) != MATERIAL_ID_NONE);
+
+            V::ve_from(transform.to_world_normal(grad))
+        },
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeGradient<V, D> for VoxelGrid {
+    fn get_gradient_at_position(&self, pos: V) -> V {
+        grid_gradient(self, IVec3::ZERO, &None, pos)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeGradient<V, D> for OffsetVoxelGrid {
+    fn get_gradient_at_position(&self, pos: V) -> V {
+        grid_gradient(&self.grid, self.offset, &self.transform, pos)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeGradient<V, D> for SharedVoxelGrid {
+    fn get_gradient_at_position(&self, pos: V) -> V {
+        grid_gradient(&self.grid, self.offset, &self.transform, pos)
+    }
+}
+
+// 3D DDA through the voxels in grid space. The normal is the face the ray entered the hit voxel through.
+fn grid_ray_hit<V: Ve<f32, D>, const D: usize>(grid: &VoxelGrid, offset: IVec3, transform: &Option<VoxelGridTransform>, origin: V, dir: V, min: f32, max: f32) -> Option<VolumeRayHit<V>> {
+    let mut o: Vec3A = origin.ve_into();
+    let mut d: Vec3A = dir.ve_into();
+
+    // 2D rays run through the slice at z = 0.
+    if D == 2 {
+        o.z = 0.5;
+        d.z = 0.0;
+    }
+
+    if let Some(transform) = transform {
+        o = transform.to_grid_pos(o);
+        d = transform.to_grid_dir(d);
+    }
+    o -= offset.as_vec3a();
+
+    let size = grid.size.as_ivec3();
+    let (enter, exit) = AABB3::new(Vec3A::ZERO, size.as_vec3a()).ray_range(o, d)?;
+    let mut t = enter.max(min);
+    let end = exit.min(max);
+    if t > end {
+        return None;
+    }
+
+    let step = IVec3::from_array(d.to_array().map(|v| if v < 0.0 { -1 } else { 1 }));
+    let t_delta = d.abs().recip();
+    let mut cell = (o + d * t).floor().as_ivec3().clamp(IVec3::ZERO, size - 1);
+    let mut t_next: [f32; 3] = std::array::from_fn(|i| {
+        if d[i] > 0.0 {
+            ((cell[i] + 1) as f32 - o[i]) / d[i]
+        } else if d[i] < 0.0 {
+            (cell[i] as f32 - o[i]) / d[i]
+        } else {
+            f32::INFINITY
+        }
+    });
+
+    // Rays that start inside of the grid did not enter through a face.
+    let mut axis = (enter >= min).then(|| {
+        (0..3)
+            .filter(|i| d[*i] != 0.0)
+            .max_by(|a, b| {
+                let enter = |i: usize| ((if d[i] > 0.0 { 0.0 } else { size[i] as f32 }) - o[i]) / d[i];
+                enter(*a).total_cmp(&enter(*b))
+            })
+    }).flatten();
+
+    loop {
+        let value = grid.get(cell.as_uvec3());
+        if value != MATERIAL_ID_NONE {
+            let normal = match axis {
+                Some(axis) => {
+                    let mut normal = Vec3A::ZERO;
+                    normal[axis] = -step[axis] as f32;
+                    normal
+                },
+                None => -d,
+            };
+            let normal = match transform {
+                Some(transform) => transform.to_world_normal(normal),
+                None => normal,
+            };
+
+            return Some(VolumeRayHit {
+                t,
+                pos: origin + dir * t,
+                normal: V::ve_from(normal.normalize_or_zero()),
+                material: value,
+            });
+        }
+
+        let i = (0..3).min_by(|a, b| t_next[*a].total_cmp(&t_next[*b])).unwrap();
+        t = t_next[i];
+        cell[i] += step[i];
+        if t > end || cell[i] < 0 || cell[i] >= size[i] {
+            return None;
+        }
+
+        t_next[i] += t_delta[i];
+        axis = Some(i);
+    }
+}
+
+impl OffsetVoxelGrid {
+    pub fn get_ray_hit_in<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<VolumeRayHit<V>> {
+        grid_ray_hit(&self.grid, self.offset, &self.transform, origin, dir, min, max)
+    }
+}
+
+impl SharedVoxelGrid {
+    pub fn get_ray_hit_in<V: Ve<f32, D>, const D: usize>(&self, origin: V, dir: V, min: f32, max: f32) -> Option<VolumeRayHit<V>> {
+        grid_ray_hit(&self.grid, self.offset, &self.transform, origin, dir, min, max)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyRay<V, D> for VoxelGrid {
+    fn get_ray_hit(&self, origin: V, dir: V, max_t: f32) -> Option<VolumeRayHit<V>> {
+        grid_ray_hit(self, IVec3::ZERO, &None, origin, dir, 0.0, max_t)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyRay<V, D> for OffsetVoxelGrid {
+    fn get_ray_hit(&self, origin: V, dir: V, max_t: f32) -> Option<VolumeRayHit<V>> {
+        self.get_ray_hit_in(origin, dir, 0.0, max_t)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyRay<V, D> for SharedVoxelGrid {
+    fn get_ray_hit(&self, origin: V, dir: V, max_t: f32) -> Option<VolumeRayHit<V>> {
+        self.get_ray_hit_in(origin, dir, 0.0, max_t)
+    }
+}
+
+// The distance field is sampled in grid space and scaled back like the distance of a transformed primitive.
+fn grid_distance<V: Ve<f32, D>, const D: usize>(grid: &VoxelGrid, offset: IVec3, transform: &Option<VoxelGridTransform>, pos: V, max: f32) -> f32 {
+    let mut pos: Vec3A = pos.ve_into();
+
+    // 2D queries look at the slice at z = 0.
+    if D == 2 {
+        pos.z = 0.5;
+    }
+
+    let distance = match transform {
+        None => grid.get_distance_field().sample(pos - offset.as_vec3a()),
+        Some(transform) => transform.to_world_distance(grid.get_distance_field().sample(transform.to_grid_pos(pos) - offset.as_vec3a())),
+    };
+
+    distance.clamp(-max, max)
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for VoxelGrid {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        grid_distance(self, IVec3::ZERO, &None, pos, max)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for OffsetVoxelGrid {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        grid_distance(&self.grid, self.offset, &self.transform, pos, max)
+    }
+}
+
+impl<V: Ve<f32, D>, const D: usize> VolumeQureyDistance<V, D> for SharedVoxelGrid {
+    fn get_distance_bounded(&self, pos: V, max: f32) -> f32 {
+        grid_distance(&self.grid, self.offset, &self.transform, pos, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octa_force::glam::{IVec3, Mat4, Quat, UVec3, Vec3, Vec3A};
+
+    use crate::{csg::csg_tree::tree::CSGTree, util::aabb::AABB, volume::{VolumeBounds, VolumeQureyPosValue}, voxel::grid::{offset::OffsetVoxelGrid, shared::SharedVoxelGrid}};
+
+    type Tree = CSGTree<u8, IVec3, i32, 3>;
+
+    const SIZE: UVec3 = UVec3::new(4, 3, 2);
+    const OFFSET: IVec3 = IVec3::new(1, 2, 3);
+
+    // The material is 1 + x, so rotations can be told apart.
+    fn new_data() -> Vec<u8> {
+        (0..SIZE.element_product()).map(|i| 1 + (i / (SIZE.y * SIZE.z)) as u8).collect()
+    }
+
+    fn new_mat() -> Mat4 {
+        Mat4::from_rotation_translation(Quat::from_rotation_z(0.6), Vec3::new(2.3, -1.0, 0.5))
+    }
+
+    #[test]
+    fn translated_bounds() {
+        let mut grid = OffsetVoxelGrid::from_data(SIZE, new_data(), OFFSET);
+        let bounds: AABB<IVec3, i32, 3> = grid.get_bounds();
+        assert_eq!((bounds.min(), bounds.max()), (IVec3::new(1, 2, 3), IVec3::new(4, 4, 4)));
+
+        grid.set_mat(Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)));
+        let bounds: AABB<IVec3, i32, 3> = grid.get_bounds();
+        assert_eq!((bounds.min(), bounds.max()), (IVec3::new(11, 2, 3), IVec3::new(14, 4, 4)));
+
+        let bounds: AABB<Vec3A, f32, 3> = grid.get_bounds();
+        assert_eq!((bounds.min(), bounds.max()), (Vec3A::new(11.0, 2.0, 3.0), Vec3A::new(14.0, 4.0, 4.0)));
+    }
+
+    #[test]
+    fn rotated_bounds() {
+        for mat in [Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2), new_mat()] {
+            let mut grid = OffsetVoxelGrid::from_data(SIZE, new_data(), OFFSET);
+            grid.set_mat(mat);
+            let bounds: AABB<IVec3, i32, 3> = grid.get_bounds();
+
+            let mut min = IVec3::MAX;
+            let mut max = IVec3::MIN;
+            for x in -12..=12 {
+                for y in -12..=12 {
+                    for z in -12..=12 {
+                        let pos = IVec3::new(x, y, z);
+                        if grid.get_value(pos) != 0 {
+                            min = min.min(pos);
+                            max = max.max(pos);
+                        }
+                    }
+                }
+            }
+
+            assert!(bounds.min().cmple(min).all() && bounds.max().cmpge(max).all(), "{bounds:?} does not contain {min} to {max}");
+            assert!((bounds.max() - max).cmple(IVec3::ONE).all() && (min - bounds.min()).cmple(IVec3::ONE).all(), "{bounds:?} is larger than {min} to {max}");
+        }
+
+        // Quarter turns map voxels onto voxels, so the bounds are exact.
+        let mut grid = OffsetVoxelGrid::from_data(SIZE, new_data(), OFFSET);
+        grid.set_mat(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        let bounds: AABB<IVec3, i32, 3> = grid.get_bounds();
+        assert_eq!((bounds.min(), bounds.max()), (IVec3::new(-4, 1, 3), IVec3::new(-2, 4, 4)));
+        assert_eq!(grid.get_value(IVec3::new(-2, 1, 3)), 1);
+        assert_eq!(grid.get_value(IVec3::new(-2, 4, 3)), 4);
+    }
+
+    #[test]
+    fn same_values_as_transform_node() {
+        let mut grid = SharedVoxelGrid::from_data(SIZE, new_data(), OFFSET);
+        let mut csg = Tree::new_shared_grid(grid.clone());
+        csg.transform_at_index(0, new_mat());
+        csg.calculate_bounds();
+
+        grid.set_mat(new_mat());
+        let mut transformed = Tree::new_shared_grid(grid);
+        transformed.calculate_bounds();
 
-        self.grid.get_value(pos)
+        let mut set = 0;
+        for x in -12..=12 {
+            for y in -12..=12 {
+                for z in -12..=12 {
+                    let pos = IVec3::new(x, y, z);
+                    let value = csg.get_value(pos);
+                    assert_eq!(value, transformed.get_value(pos), "{pos} differs");
+                    set += (value != 0) as usize;
+                }
+            }
+        }
+        assert!(set > 0);
     }
 }
@@ -1,14 +1,20 @@
 //mod from_csg_tree;
+pub mod distance;
 mod impl_volume;
 pub mod offset;
 pub mod shared;
+pub mod summary;
+pub mod transform;
 
-use std::{fmt, usize};
+use std::{fmt, sync::OnceLock, usize};
 
 use octa_force::glam::{uvec3, vec3, UVec3, Vec3, Vec3A};
 
 use crate::{util::math::to_1d};
 
+use distance::VoxelGridDistance;
+use summary::VoxelGridSummary;
+
 use super::palette::palette::MATERIAL_ID_NONE;
 
 const VOXELS_PER_U32: usize = 4;
@@ -17,19 +23,20 @@ const VOXELS_PER_U32: usize = 4;
 pub struct VoxelGrid {
     pub data: Vec<u8>,
     pub size: UVec3,
+    pub summary: VoxelGridSummary,
+    pub distance: OnceLock<VoxelGridDistance>,
 }
 
 impl VoxelGrid {
     pub fn empty(size: UVec3) -> Self {
         let data_length = size.element_product() as usize;
-        VoxelGrid {
-            size,
-            data: vec![MATERIAL_ID_NONE as u8; data_length],
-        }
+        Self::from_data(size, vec![MATERIAL_ID_NONE as u8; data_length])
     }
 
     pub fn from_data(size: UVec3, data: Vec<u8>) -> Self {
         VoxelGrid {
+            summary: VoxelGridSummary::new(&data, size),
+            distance: OnceLock::new(),
             size,
             data,
         }
@@ -54,6 +61,8 @@ impl VoxelGrid {
                 }
             }
         }
+
+        self.update_summary();
     }
 
     pub fn set_corners(&mut self) {
@@ -66,6 +75,8 @@ impl VoxelGrid {
                 }  
             }  
         }  
+
+        self.update_summary();
     }
 
     pub fn get(&self, pos: UVec3) -> u8 {
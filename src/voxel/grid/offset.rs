@@ -1,12 +1,14 @@
-use octa_force::glam::{IVec3, UVec3};
+use octa_force::glam::{IVec3, Mat4, UVec3};
 
-use super::{shared::SharedVoxelGrid, VoxelGrid};
+use super::{transform::VoxelGridTransform, shared::SharedVoxelGrid, VoxelGrid};
 
 
 #[derive(Clone, Debug)]
 pub struct OffsetVoxelGrid {
     pub grid: VoxelGrid,
     pub offset: IVec3,
+    // Applied on top of the offset.
+    pub transform: Option<VoxelGridTransform>,
 }
 
 impl OffsetVoxelGrid {
@@ -14,6 +16,7 @@ impl OffsetVoxelGrid {
         Self {
             grid: VoxelGrid::empty(size),
             offset,
+            transform: None,
         }
     }
 
@@ -21,6 +24,7 @@ impl OffsetVoxelGrid {
         Self {
             grid: VoxelGrid::from_data(size, data),
             offset,
+            transform: None,
         }
     }
 
@@ -28,12 +32,27 @@ impl OffsetVoxelGrid {
         Self {
             grid,
             offset,
+            transform: None,
         }
     }
+
+    pub fn get_mat(&self) -> Mat4 {
+        self.transform.map(|t| t.get_mat()).unwrap_or(Mat4::IDENTITY)
+    }
+
+    pub fn set_mat(&mut self, mat: Mat4) {
+        self.transform = if mat == Mat4::IDENTITY {
+            None
+        } else {
+            Some(VoxelGridTransform::new(mat))
+        };
+    }
 }
 
 impl Into<SharedVoxelGrid> for OffsetVoxelGrid {
     fn into(self) -> SharedVoxelGrid {
-        SharedVoxelGrid::from_grid(self.grid, self.offset)
+        let mut grid = SharedVoxelGrid::from_grid(self.grid, self.offset);
+        grid.transform = self.transform;
+        grid
     }
 }
@@ -1,13 +1,15 @@
 use std::sync::Arc;
-use octa_force::glam::{IVec3, UVec3};
+use octa_force::glam::{IVec3, Mat4, UVec3};
 use parking_lot::Mutex;
 
-use super::{offset::OffsetVoxelGrid, VoxelGrid};
+use super::{transform::VoxelGridTransform, offset::OffsetVoxelGrid, VoxelGrid};
 
 #[derive(Clone, Debug)]
 pub struct SharedVoxelGrid {
     pub grid: Arc<VoxelGrid>,
     pub offset: IVec3,
+    // Applied on top of the offset.
+    pub transform: Option<VoxelGridTransform>,
 }
 
 impl SharedVoxelGrid {
@@ -15,6 +17,7 @@ impl SharedVoxelGrid {
         Self {
             grid: Arc::new(VoxelGrid::empty(size)),
             offset,
+            transform: None,
         }
     }
 
@@ -22,6 +25,7 @@ impl SharedVoxelGrid {
         Self {
             grid: Arc::new(VoxelGrid::from_data(size, data)),
             offset,
+            transform: None,
         }
     }
 
@@ -29,14 +33,29 @@ impl SharedVoxelGrid {
         Self {
             grid: Arc::new(grid),
             offset,
+            transform: None,
         }
     }
+
+    pub fn get_mat(&self) -> Mat4 {
+        self.transform.map(|t| t.get_mat()).unwrap_or(Mat4::IDENTITY)
+    }
+
+    pub fn set_mat(&mut self, mat: Mat4) {
+        self.transform = if mat == Mat4::IDENTITY {
+            None
+        } else {
+            Some(VoxelGridTransform::new(mat))
+        };
+    }
 }
 
 impl Into<OffsetVoxelGrid> for SharedVoxelGrid {
     fn into(self) -> OffsetVoxelGrid {
         let grid = Arc::try_unwrap(self.grid).unwrap();
-        OffsetVoxelGrid::from_grid(grid, self.offset)
+        let mut grid = OffsetVoxelGrid::from_grid(grid, self.offset);
+        grid.transform = self.transform;
+        grid
     }
 }
 
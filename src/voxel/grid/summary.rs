@@ -0,0 +1,117 @@
+use std::sync::OnceLock;
+
+use octa_force::glam::{uvec3, IVec3, UVec3};
+
+use crate::{util::math::to_1d, volume::VolumeQureyAABBResult, voxel::palette::palette::MATERIAL_ID_NONE};
+
+use super::VoxelGrid;
+
+pub const SUMMARY_BLOCK_SIZE: u32 = 4;
+
+// Value of every 4x4x4 block of a grid.
+// Aabb queries only have to look at the voxels of mixed blocks that are partially covered.
+#[derive(Clone, Default)]
+pub struct VoxelGridSummary {
+    pub blocks: Vec<VolumeQureyAABBResult>,
+    pub size: UVec3,
+}
+
+impl VoxelGridSummary {
+    pub fn new(data: &[u8], grid_size: UVec3) -> Self {
+        let size = (grid_size + SUMMARY_BLOCK_SIZE - 1) / SUMMARY_BLOCK_SIZE;
+        let mut blocks = vec![VolumeQureyAABBResult::Mixed; size.element_product() as usize];
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let block = uvec3(x, y, z);
+                    let min = block * SUMMARY_BLOCK_SIZE;
+                    let max = (min + SUMMARY_BLOCK_SIZE).min(grid_size);
+
+                    let mut value = None;
+                    let full = iter_region(min, max)
+                        .all(|pos| merge_value(&mut value, data[to_1d(pos, grid_size)]));
+
+                    if full {
+                        blocks[to_1d(block, size)] = VolumeQureyAABBResult::Full(value.unwrap());
+                    }
+                }
+            }
+        }
+
+        Self { blocks, size }
+    }
+
+    pub fn get(&self, block: UVec3) -> VolumeQureyAABBResult {
+        self.blocks[to_1d(block, self.size)]
+    }
+}
+
+impl VoxelGrid {
+    // Needs to be called after data was changed directly.
+    pub fn update_summary(&mut self) {
+        self.summary = VoxelGridSummary::new(&self.data, self.size);
+        self.distance = OnceLock::new();
+    }
+
+    // Value of all voxels in [min, max). Voxels outside of the grid are empty.
+    pub fn get_region_value(&self, min: IVec3, max: IVec3) -> VolumeQureyAABBResult {
+        let clamped_min = min.max(IVec3::ZERO);
+        let clamped_max = max.min(self.size.as_ivec3());
+        if clamped_min.cmpge(clamped_max).any() {
+            return VolumeQureyAABBResult::Full(MATERIAL_ID_NONE);
+        }
+
+        let mut value = if clamped_min != min || clamped_max != max {
+            Some(MATERIAL_ID_NONE)
+        } else {
+            None
+        };
+
+        let min = clamped_min.as_uvec3();
+        let max = clamped_max.as_uvec3();
+        let block_min = min / SUMMARY_BLOCK_SIZE;
+        let block_max = (max - 1) / SUMMARY_BLOCK_SIZE + 1;
+
+        for block in iter_region(block_min, block_max) {
+            match self.summary.get(block) {
+                VolumeQureyAABBResult::Full(v) => {
+                    if !merge_value(&mut value, v) {
+                        return VolumeQureyAABBResult::Mixed;
+                    }
+                },
+                VolumeQureyAABBResult::Mixed => {
+                    let voxel_min = (block * SUMMARY_BLOCK_SIZE).max(min);
+                    let voxel_max = ((block + 1) * SUMMARY_BLOCK_SIZE).min(max).min(self.size);
+
+                    let same = iter_region(voxel_min, voxel_max)
+                        .all(|pos| merge_value(&mut value, self.get(pos)));
+                    if !same {
+                        return VolumeQureyAABBResult::Mixed;
+                    }
+                },
+            }
+        }
+
+        VolumeQureyAABBResult::Full(value.unwrap())
+    }
+}
+
+fn iter_region(min: UVec3, max: UVec3) -> impl Iterator<Item = UVec3> {
+    (min.x..max.x).flat_map(move |x| {
+        (min.y..max.y).flat_map(move |y| {
+            (min.z..max.z).map(move |z| uvec3(x, y, z))
+        })
+    })
+}
+
+// Returns false if the value differs from the ones before.
+pub(crate) fn merge_value(value: &mut Option<u8>, v: u8) -> bool {
+    match value {
+        Some(old) => *old == v,
+        None => {
+            *value = Some(v);
+            true
+        },
+    }
+}
@@ -0,0 +1,50 @@
+use octa_force::glam::{Mat4, Vec3A};
+
+use crate::{csg::primitves::get_distance_scale, util::{aabb::AABB3, aabb_transformer::AABBTransformer}};
+
+// Transform of a grid on top of its integer offset.
+// Grids without a transform stay on the integer fast path.
+#[derive(Clone, Copy, Debug)]
+pub struct VoxelGridTransform {
+    mat: Mat4,
+    inverse_transfomer: AABBTransformer<Mat4, Vec3A, 3>,
+    distance_scale: f32,
+}
+
+impl VoxelGridTransform {
+    pub fn new(mat: Mat4) -> Self {
+        Self {
+            mat,
+            inverse_transfomer: AABBTransformer::new(mat.inverse()),
+            distance_scale: get_distance_scale::<Vec3A, 3>(&mat),
+        }
+    }
+
+    pub fn get_mat(&self) -> Mat4 {
+        self.mat
+    }
+
+    pub fn to_grid_pos(&self, pos: Vec3A) -> Vec3A {
+        self.inverse_transfomer.transform_pos(pos)
+    }
+
+    pub fn to_grid_dir(&self, dir: Vec3A) -> Vec3A {
+        self.inverse_transfomer.transform_dir(dir)
+    }
+
+    pub fn to_grid_aabb(&self, aabb: AABB3) -> AABB3 {
+        self.inverse_transfomer.transform_aabb(aabb)
+    }
+
+    pub fn to_world_aabb(&self, aabb: AABB3) -> AABB3 {
+        aabb.mul_mat(&self.mat)
+    }
+
+    pub fn to_world_normal(&self, normal: Vec3A) -> Vec3A {
+        self.inverse_transfomer.transform_normal(normal)
+    }
+
+    pub fn to_world_distance(&self, distance: f32) -> f32 {
+        distance * self.distance_scale
+    }
+}
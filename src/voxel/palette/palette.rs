@@ -49,6 +49,26 @@ impl Palette for LocalPalette {
         }
     }
 
+    fn get_index_material(&mut self, material: Material) -> OctaResult<u8> {
+        if material.is_simple_color() {
+            return self.get_index_simple_color(material.color);
+        }
+
+        for i in self.used.iter_ones().skip(1) {
+            if self.materials[i] == material {
+                return Ok(i as u8);
+            }
+        }
+
+        if let Some(i) = self.used.first_zero() {
+            self.materials[i] = material;
+            self.used.set(i, true);
+            return Ok(i as u8);
+        } else {
+            bail!("Palette full!");
+        }
+    }
+
     fn colors(&self) -> Vec<(u8, [u8; 3])> {
         self.used.iter_ones()
             .skip(1)
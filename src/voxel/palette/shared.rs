@@ -4,7 +4,7 @@ use bitvec::{array::BitArray, bitarr, order::Lsb0};
 use octa_force::{anyhow::bail, glam::{vec3, vec4, Vec3A, Vec4}, vulkan::{ash::vk, gpu_allocator::MemoryLocation, Buffer, Context}, OctaResult};
 use parking_lot::{RwLock};
 
-use super::{buffer::PaletteBuffer, palette::LocalPalette, Palette};
+use super::{buffer::PaletteBuffer, material::Material, palette::LocalPalette, Palette};
 
 #[derive(Debug, Clone)]
 pub struct SharedPalette {
@@ -49,6 +49,12 @@ impl Palette for SharedPalette {
         })
     }
 
+    fn get_index_material(&mut self, material: Material) -> OctaResult<u8> {
+        let index = self.palette.write().get_index_material(material)?;
+        self.changed.store(true, Ordering::Relaxed);
+        Ok(index)
+    }
+
     fn colors(&self) -> Vec<(u8, [u8; 3])> {
         let mut palette = self.palette.read();
         palette.colors()